@@ -0,0 +1,345 @@
+//! Pluggable transports for raw collection operations
+//!
+//! [`DriverBackend`] talks the MongoDB wire protocol through the official driver,
+//! [`DataApiBackend`] talks to the Atlas Data API over HTTPS through a user supplied
+//! [`HttpTransport`], for environments where the wire protocol is blocked.
+
+use futures_util::StreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::{Error, Result};
+use mongodb::Database;
+use serde_json::{json, Value};
+
+/// Options accepted by [`Backend::find`]
+#[derive(Debug, Default, Clone)]
+pub struct FindSpec {
+    pub projection: Option<Document>,
+    pub sort: Option<Document>,
+    pub skip: u64,
+    pub limit: i64,
+}
+
+/// Result of [`Backend::update`]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UpdateSummary {
+    pub matched_count: u64,
+    pub modified_count: u64,
+    pub upserted_id: Option<Bson>,
+}
+
+#[allow(async_fn_in_trait)]
+pub trait Backend {
+    async fn find(&self, collection: &str, filter: Document, spec: FindSpec)
+    -> Result<Vec<Document>>;
+
+    async fn insert_one(&self, collection: &str, document: Document) -> Result<Bson>;
+
+    async fn insert_many(&self, collection: &str, documents: Vec<Document>) -> Result<Vec<Bson>>;
+
+    async fn update(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+        many: bool,
+        upsert: bool,
+    ) -> Result<UpdateSummary>;
+
+    async fn delete(&self, collection: &str, filter: Document, many: bool) -> Result<u64>;
+
+    async fn aggregate(&self, collection: &str, pipeline: Vec<Document>) -> Result<Vec<Document>>;
+}
+
+/// Backend using the MongoDB driver
+#[derive(Debug, Clone)]
+pub struct DriverBackend {
+    db: Database,
+}
+
+impl DriverBackend {
+    pub fn new(db: &Database) -> Self {
+        Self { db: db.clone() }
+    }
+}
+
+impl Backend for DriverBackend {
+    async fn find(
+        &self,
+        collection: &str,
+        filter: Document,
+        spec: FindSpec,
+    ) -> Result<Vec<Document>> {
+        let collection = self.db.collection::<Document>(collection);
+        let mut find = collection.find(filter);
+        if let Some(projection) = spec.projection {
+            find = find.projection(projection);
+        }
+        if let Some(sort) = spec.sort {
+            find = find.sort(sort);
+        }
+        if spec.skip > 0 {
+            find = find.skip(spec.skip);
+        }
+        if spec.limit > 0 {
+            find = find.limit(spec.limit);
+        }
+        let mut r = vec![];
+        let mut cursor = find.await?;
+        while let Some(d) = cursor.next().await {
+            r.push(d?);
+        }
+        Ok(r)
+    }
+
+    async fn insert_one(&self, collection: &str, document: Document) -> Result<Bson> {
+        let r = self
+            .db
+            .collection::<Document>(collection)
+            .insert_one(document)
+            .await?;
+        Ok(r.inserted_id)
+    }
+
+    async fn insert_many(&self, collection: &str, documents: Vec<Document>) -> Result<Vec<Bson>> {
+        let r = self
+            .db
+            .collection::<Document>(collection)
+            .insert_many(documents)
+            .await?;
+        let mut ids = r.inserted_ids.into_iter().collect::<Vec<_>>();
+        ids.sort_by_key(|(index, _)| *index);
+        Ok(ids.into_iter().map(|(_, id)| id).collect())
+    }
+
+    async fn update(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+        many: bool,
+        upsert: bool,
+    ) -> Result<UpdateSummary> {
+        let collection = self.db.collection::<Document>(collection);
+        let r = if many {
+            collection.update_many(filter, update).upsert(upsert).await?
+        } else {
+            collection.update_one(filter, update).upsert(upsert).await?
+        };
+        Ok(UpdateSummary {
+            matched_count: r.matched_count,
+            modified_count: r.modified_count,
+            upserted_id: r.upserted_id,
+        })
+    }
+
+    async fn delete(&self, collection: &str, filter: Document, many: bool) -> Result<u64> {
+        let collection = self.db.collection::<Document>(collection);
+        let r = if many {
+            collection.delete_many(filter).await?
+        } else {
+            collection.delete_one(filter).await?
+        };
+        Ok(r.deleted_count)
+    }
+
+    async fn aggregate(&self, collection: &str, pipeline: Vec<Document>) -> Result<Vec<Document>> {
+        let collection = self.db.collection::<Document>(collection);
+        let mut r = vec![];
+        let mut cursor = collection.aggregate(pipeline).await?;
+        while let Some(d) = cursor.next().await {
+            r.push(d?);
+        }
+        Ok(r)
+    }
+}
+
+/// Minimal HTTP client used by [`DataApiBackend`]
+///
+/// Implement it on top of whatever client is available in the target
+/// environment (`fetch` on workers, reqwest, hyper, ...).
+#[allow(async_fn_in_trait)]
+pub trait HttpTransport {
+    /// Sends a POST request and returns the response body
+    async fn post(&self, url: &str, headers: &[(&str, &str)], body: String) -> Result<String>;
+}
+
+/// Backend using the Atlas Data API
+#[derive(Debug, Clone)]
+pub struct DataApiBackend<T: HttpTransport> {
+    transport: T,
+    endpoint: String,
+    api_key: String,
+    data_source: String,
+    database: String,
+}
+
+impl<T: HttpTransport> DataApiBackend<T> {
+    /// # Arguments
+    /// * `endpoint` - Data API base url, e.g. `https://data.mongodb-api.com/app/<app-id>/endpoint/data/v1`
+    /// * `data_source` - Atlas cluster name
+    pub fn new(
+        transport: T,
+        endpoint: &str,
+        api_key: &str,
+        data_source: &str,
+        database: &str,
+    ) -> Self {
+        Self {
+            transport,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            data_source: data_source.to_string(),
+            database: database.to_string(),
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    async fn action(&self, action: &str, collection: &str, body: Document) -> Result<Document> {
+        let mut payload = json!({
+            "dataSource": self.data_source,
+            "database": self.database,
+            "collection": collection,
+        });
+        if let Value::Object(extra) = Bson::Document(body).into_canonical_extjson() {
+            payload.as_object_mut().unwrap().extend(extra);
+        }
+        let url = format!("{}/action/{}", self.endpoint, action);
+        let headers = [
+            ("Content-Type", "application/ejson"),
+            ("Accept", "application/ejson"),
+            ("api-key", self.api_key.as_str()),
+        ];
+        let response = self
+            .transport
+            .post(&url, &headers, payload.to_string())
+            .await?;
+        parse_response(&response)
+    }
+}
+
+fn invalid_response(message: String) -> Error {
+    Error::from(std::io::Error::new(std::io::ErrorKind::InvalidData, message))
+}
+
+fn parse_response(response: &str) -> Result<Document> {
+    let value: Value = serde_json::from_str(response)
+        .map_err(|e| invalid_response(format!("invalid Data API response: {e}")))?;
+    let value = Bson::try_from(value)
+        .map_err(|e| invalid_response(format!("invalid extended json: {e}")))?;
+    match value {
+        Bson::Document(d) => {
+            if let Ok(error) = d.get_str("error") {
+                return Err(invalid_response(format!("Data API error: {error}")));
+            }
+            Ok(d)
+        }
+        other => Err(invalid_response(format!("unexpected Data API response: {other}"))),
+    }
+}
+
+fn documents(mut response: Document, key: &str) -> Result<Vec<Document>> {
+    match response.remove(key) {
+        Some(Bson::Array(items)) => Ok(items
+            .into_iter()
+            .filter_map(|item| match item {
+                Bson::Document(d) => Some(d),
+                _ => None,
+            })
+            .collect()),
+        Some(Bson::Document(d)) => Ok(vec![d]),
+        Some(Bson::Null) | None => Ok(vec![]),
+        Some(other) => Err(invalid_response(format!("unexpected `{key}` value: {other}"))),
+    }
+}
+
+fn count(response: &Document, key: &str) -> u64 {
+    match response.get(key) {
+        Some(Bson::Int32(v)) => *v as u64,
+        Some(Bson::Int64(v)) => *v as u64,
+        Some(Bson::Double(v)) => *v as u64,
+        _ => 0,
+    }
+}
+
+impl<T: HttpTransport> Backend for DataApiBackend<T> {
+    async fn find(
+        &self,
+        collection: &str,
+        filter: Document,
+        spec: FindSpec,
+    ) -> Result<Vec<Document>> {
+        let mut body = doc! {"filter": filter};
+        if let Some(projection) = spec.projection {
+            body.insert("projection", projection);
+        }
+        if let Some(sort) = spec.sort {
+            body.insert("sort", sort);
+        }
+        if spec.skip > 0 {
+            body.insert("skip", spec.skip as i64);
+        }
+        if spec.limit > 0 {
+            body.insert("limit", spec.limit);
+        }
+        documents(self.action("find", collection, body).await?, "documents")
+    }
+
+    async fn insert_one(&self, collection: &str, document: Document) -> Result<Bson> {
+        let mut r = self
+            .action("insertOne", collection, doc! {"document": document})
+            .await?;
+        Ok(r.remove("insertedId").unwrap_or(Bson::Null))
+    }
+
+    async fn insert_many(&self, collection: &str, documents: Vec<Document>) -> Result<Vec<Bson>> {
+        let mut r = self
+            .action("insertMany", collection, doc! {"documents": documents})
+            .await?;
+        match r.remove("insertedIds") {
+            Some(Bson::Array(ids)) => Ok(ids),
+            _ => Ok(vec![]),
+        }
+    }
+
+    async fn update(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+        many: bool,
+        upsert: bool,
+    ) -> Result<UpdateSummary> {
+        let action = if many { "updateMany" } else { "updateOne" };
+        let mut r = self
+            .action(
+                action,
+                collection,
+                doc! {"filter": filter, "update": update, "upsert": upsert},
+            )
+            .await?;
+        Ok(UpdateSummary {
+            matched_count: count(&r, "matchedCount"),
+            modified_count: count(&r, "modifiedCount"),
+            upserted_id: r.remove("upsertedId"),
+        })
+    }
+
+    async fn delete(&self, collection: &str, filter: Document, many: bool) -> Result<u64> {
+        let action = if many { "deleteMany" } else { "deleteOne" };
+        let r = self
+            .action(action, collection, doc! {"filter": filter})
+            .await?;
+        Ok(count(&r, "deletedCount"))
+    }
+
+    async fn aggregate(&self, collection: &str, pipeline: Vec<Document>) -> Result<Vec<Document>> {
+        documents(
+            self.action("aggregate", collection, doc! {"pipeline": pipeline})
+                .await?,
+            "documents",
+        )
+    }
+}
@@ -0,0 +1,130 @@
+//! Synchronous facade for code that isn't async
+//!
+//! Operations run on an internal multi-threaded tokio runtime. The client must
+//! be created with [`connect`] so its background tasks live on that runtime.
+//!
+//! ```ignore
+//! let db = mongodb_ro::blocking::connect("mongodb://localhost:27017")?.database("app");
+//! let user = User::new_model(&db)
+//!     .r#where(doc! {"name": "ali"})
+//!     .blocking()
+//!     .first()?;
+//! ```
+//!
+//! Don't call it from inside an async context, blocking there panics.
+
+use crate::event::Boot;
+use crate::model::{IntoObjectId, Model};
+use crate::page::Page;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::Document;
+use mongodb::error::Result;
+use mongodb::results::InsertOneResult;
+use mongodb::Client;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::OnceLock;
+use tokio::runtime::Runtime;
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// Runtime the blocking operations run on
+pub fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("mongodb-ro-blocking")
+            .build()
+            .expect("failed to start the blocking runtime")
+    })
+}
+
+/// Runs a future to completion on the blocking runtime
+pub fn block_on<F: Future>(f: F) -> F::Output {
+    runtime().block_on(f)
+}
+
+/// Creates a client bound to the blocking runtime
+pub fn connect(uri: &str) -> Result<Client> {
+    block_on(Client::with_uri_str(uri))
+}
+
+/// Synchronous wrapper of a [`Model`], see `Model::blocking`
+pub struct Blocking<'a, M: Boot> {
+    model: Model<'a, M>,
+}
+
+impl<'a, M> Blocking<'a, M>
+where
+    M: Boot,
+    M: Default,
+    M: Serialize,
+    M: DeserializeOwned,
+    M: Send,
+    M: Sync,
+    M: Unpin,
+{
+    pub(crate) fn new(model: Model<'a, M>) -> Blocking<'a, M> {
+        Blocking { model }
+    }
+
+    /// Returns the wrapped model
+    pub fn into_inner(self) -> Model<'a, M> {
+        self.model
+    }
+
+    pub fn get(&self) -> Result<Vec<M>> {
+        block_on(self.model.get())
+    }
+
+    pub fn get_doc(&self) -> Result<Vec<Document>> {
+        block_on(self.model.get_doc())
+    }
+
+    pub fn first(&mut self) -> Result<Option<M>> {
+        block_on(self.model.first())
+    }
+
+    pub fn find_by_id(&mut self, id: impl IntoObjectId) -> Result<Option<M>> {
+        block_on(self.model.find_by_id(id))
+    }
+
+    pub fn exists(&self) -> Result<bool> {
+        block_on(self.model.exists())
+    }
+
+    pub fn paginate(&self, page: u32, per_page: u32) -> Result<Page<M>> {
+        block_on(self.model.paginate(page, per_page))
+    }
+
+    pub fn create(&self) -> Result<InsertOneResult> {
+        block_on(self.model.create())
+    }
+
+    pub fn save(&mut self) -> Result<ObjectId> {
+        block_on(self.model.save())
+    }
+
+    pub fn update(&self, data: Document) -> Result<Document> {
+        block_on(self.model.update(data))
+    }
+
+    pub fn delete(&self) -> Result<Document> {
+        block_on(self.model.delete())
+    }
+}
+
+impl<'a, M: Boot> std::ops::Deref for Blocking<'a, M> {
+    type Target = Model<'a, M>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.model
+    }
+}
+
+impl<'a, M: Boot> std::ops::DerefMut for Blocking<'a, M> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.model
+    }
+}
@@ -0,0 +1,84 @@
+//! Builder-style constructors for models
+//!
+//! [`model_builder!`](crate::model_builder) generates a builder with one setter
+//! per listed field, so models for `fill()` or test factories don't need struct
+//! literals spelling out every field. Fields that aren't listed keep their
+//! `Default` value.
+//!
+//! ```ignore
+//! mongodb_ro::model_builder!(pub UserBuilder for User {
+//!     required { name: String, phone: String }
+//!     optional { age: u8 }
+//! });
+//!
+//! let user = User::builder().name("a").phone("b").build()?;
+//! User::new_model(&db).fill(user).create().await?;
+//! ```
+
+use mongodb::error::Error;
+
+/// Error of a builder missing a required field
+#[doc(hidden)]
+pub fn missing_field(model: &str, field: &str) -> Error {
+    Error::from(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("{field} of {model} is required."),
+    ))
+}
+
+/// Declares a builder for a model
+///
+/// Generates `$builder` with a setter per field and a `$model::builder()`
+/// constructor. `build()` fails when a `required` field wasn't set, `optional`
+/// fields default to the model's `Default`. Both lists may be empty.
+#[macro_export]
+macro_rules! model_builder {
+    ($vis:vis $builder:ident for $model:ident {
+        required { $($req:ident : $req_ty:ty),* $(,)? }
+        optional { $($opt:ident : $opt_ty:ty),* $(,)? }
+    }) => {
+        #[derive(Debug, Clone, Default)]
+        $vis struct $builder {
+            $($req: Option<$req_ty>,)*
+            $($opt: Option<$opt_ty>,)*
+        }
+
+        #[allow(dead_code)]
+        impl $builder {
+            $(
+                pub fn $req(mut self, value: impl Into<$req_ty>) -> $builder {
+                    self.$req = Some(value.into());
+                    self
+                }
+            )*
+            $(
+                pub fn $opt(mut self, value: impl Into<$opt_ty>) -> $builder {
+                    self.$opt = Some(value.into());
+                    self
+                }
+            )*
+
+            pub fn build(self) -> $crate::mongodb::error::Result<$model> {
+                let mut model = <$model as Default>::default();
+                $(
+                    model.$req = self.$req.ok_or_else(|| {
+                        $crate::builder::missing_field(stringify!($model), stringify!($req))
+                    })?;
+                )*
+                $(
+                    if let Some(value) = self.$opt {
+                        model.$opt = value;
+                    }
+                )*
+                Ok(model)
+            }
+        }
+
+        #[allow(dead_code)]
+        impl $model {
+            pub fn builder() -> $builder {
+                $builder::default()
+            }
+        }
+    };
+}
@@ -0,0 +1,624 @@
+//! Bulk writes and their reports
+
+use crate::event::Boot;
+use crate::model::Model;
+use crate::server::ServerInfo;
+use mongodb::action::Action;
+use mongodb::bson::{Bson, Document};
+use mongodb::error::{
+    Error, ErrorKind, IndexedWriteError, Result, WriteConcernError, WriteError, WriteFailure,
+};
+use mongodb::options::{
+    DeleteManyModel, DeleteOneModel, InsertOneModel, ReplaceOneModel, UpdateManyModel,
+    UpdateOneModel, WriteModel,
+};
+use mongodb::results::{InsertManyResult, VerboseBulkWriteResult};
+use mongodb::{ClientSession, Collection, Namespace};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A write error tied to the index of the failed operation
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkWriteFailure {
+    pub index: usize,
+    pub code: i32,
+    pub code_name: Option<String>,
+    pub message: String,
+    /// Offending key of a duplicate key error, e.g. `{ phone: "123" }`
+    pub key_value: Option<String>,
+    pub details: Option<Document>,
+}
+
+impl From<&IndexedWriteError> for BulkWriteFailure {
+    fn from(e: &IndexedWriteError) -> Self {
+        BulkWriteFailure {
+            index: e.index,
+            code: e.code,
+            code_name: e.code_name.clone(),
+            message: e.message.clone(),
+            key_value: duplicate_key(&e.message),
+            details: e.details.clone(),
+        }
+    }
+}
+
+impl BulkWriteFailure {
+    fn from_write_error(index: usize, e: &WriteError) -> Self {
+        BulkWriteFailure {
+            index,
+            code: e.code,
+            code_name: e.code_name.clone(),
+            message: e.message.clone(),
+            key_value: duplicate_key(&e.message),
+            details: e.details.clone(),
+        }
+    }
+}
+
+/// A write concern error reported for the whole batch
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteConcernFailure {
+    pub code: i32,
+    pub code_name: String,
+    pub message: String,
+    pub details: Option<Document>,
+}
+
+impl From<&WriteConcernError> for WriteConcernFailure {
+    fn from(e: &WriteConcernError) -> Self {
+        WriteConcernFailure {
+            code: e.code,
+            code_name: e.code_name.clone(),
+            message: e.message.clone(),
+            details: e.details.clone(),
+        }
+    }
+}
+
+/// Per-operation result of a bulk write
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BulkOutcome {
+    /// Number of operations submitted
+    pub total: usize,
+    /// Indexes of the operations that were applied
+    pub succeeded: Vec<usize>,
+    /// Inserted ids by operation index, when reported by the server
+    pub inserted_ids: BTreeMap<usize, Bson>,
+    pub write_errors: Vec<BulkWriteFailure>,
+    pub write_concern_errors: Vec<WriteConcernFailure>,
+    /// Operations skipped because an ordered batch stopped at an earlier error
+    pub not_attempted: Vec<usize>,
+    /// Upserted ids by operation index
+    pub upserted_ids: BTreeMap<usize, Bson>,
+    pub matched_count: u64,
+    pub modified_count: u64,
+    pub deleted_count: u64,
+}
+
+impl BulkOutcome {
+    /// Builds the report of an `insert_many` call
+    ///
+    /// Write errors are turned into the report, any other error is returned as is.
+    pub fn from_insert_many(
+        total: usize,
+        ordered: bool,
+        result: Result<InsertManyResult>,
+    ) -> Result<BulkOutcome> {
+        let mut outcome = BulkOutcome {
+            total,
+            ..Default::default()
+        };
+        let error = match result {
+            Ok(r) => {
+                outcome.inserted_ids = r.inserted_ids.into_iter().collect();
+                outcome.succeeded = (0..total).collect();
+                return Ok(outcome);
+            }
+            Err(e) => e,
+        };
+        let ErrorKind::InsertMany(insert_error) = error.kind.as_ref() else {
+            return Err(error);
+        };
+        outcome.write_errors = insert_error
+            .write_errors
+            .iter()
+            .flatten()
+            .map(BulkWriteFailure::from)
+            .collect();
+        outcome.write_concern_errors = insert_error
+            .write_concern_error
+            .iter()
+            .map(WriteConcernFailure::from)
+            .collect();
+
+        let first_error = outcome.write_errors.iter().map(|e| e.index).min();
+        for index in 0..total {
+            if outcome.write_errors.iter().any(|e| e.index == index) {
+                continue;
+            }
+            match first_error {
+                Some(first) if ordered && index > first => outcome.not_attempted.push(index),
+                _ => outcome.succeeded.push(index),
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Fills `inserted_ids` of the applied inserts from the `_id` each document was sent with
+    ///
+    /// The driver keeps the ids of a partially failed `insert_many` to itself,
+    /// so documents should get their `_id` before being inserted.
+    pub fn fill_inserted_ids(&mut self, ids: &[Bson]) {
+        for &index in &self.succeeded {
+            if let Some(id) = ids.get(index) {
+                self.inserted_ids.entry(index).or_insert_with(|| id.clone());
+            }
+        }
+    }
+
+    /// Builds the report of a `bulk_write` call
+    ///
+    /// Write errors are turned into the report, any other error is returned as is.
+    pub fn from_bulk_write(
+        total: usize,
+        result: Result<VerboseBulkWriteResult>,
+    ) -> Result<BulkOutcome> {
+        let mut outcome = BulkOutcome {
+            total,
+            ..Default::default()
+        };
+        let partial = match result {
+            Ok(r) => Some(r),
+            Err(error) => {
+                let ErrorKind::BulkWrite(bulk_error) = error.kind.as_ref() else {
+                    return Err(error);
+                };
+                outcome.write_errors = bulk_error
+                    .write_errors
+                    .iter()
+                    .map(|(index, e)| BulkWriteFailure::from_write_error(*index, e))
+                    .collect();
+                outcome.write_errors.sort_by_key(|e| e.index);
+                outcome.write_concern_errors = bulk_error
+                    .write_concern_errors
+                    .iter()
+                    .map(WriteConcernFailure::from)
+                    .collect();
+                match &bulk_error.partial_result {
+                    Some(mongodb::error::PartialBulkWriteResult::Verbose(r)) => Some(r.clone()),
+                    _ => None,
+                }
+            }
+        };
+        if let Some(r) = partial {
+            for (index, insert) in r.insert_results {
+                outcome.inserted_ids.insert(index, insert.inserted_id);
+                outcome.succeeded.push(index);
+            }
+            for (index, update) in r.update_results {
+                outcome.matched_count += update.matched_count;
+                outcome.modified_count += update.modified_count;
+                if let Some(id) = update.upserted_id {
+                    outcome.upserted_ids.insert(index, id);
+                }
+                outcome.succeeded.push(index);
+            }
+            for (index, delete) in r.delete_results {
+                outcome.deleted_count += delete.deleted_count;
+                outcome.succeeded.push(index);
+            }
+        }
+        outcome.succeeded.sort_unstable();
+        outcome.not_attempted = outcome.missing_indexes();
+        Ok(outcome)
+    }
+
+    /// Indexes neither applied nor failed
+    fn missing_indexes(&self) -> Vec<usize> {
+        (0..self.total)
+            .filter(|i| {
+                !self.succeeded.contains(i) && !self.write_errors.iter().any(|e| e.index == *i)
+            })
+            .collect()
+    }
+
+    /// Whether every operation was applied and acknowledged
+    pub fn is_success(&self) -> bool {
+        self.write_errors.is_empty()
+            && self.write_concern_errors.is_empty()
+            && self.not_attempted.is_empty()
+    }
+
+    /// Indexes of the failed and not attempted operations, in order
+    pub fn failed_indexes(&self) -> Vec<usize> {
+        let mut r = self
+            .write_errors
+            .iter()
+            .map(|e| e.index)
+            .chain(self.not_attempted.iter().copied())
+            .collect::<Vec<_>>();
+        r.sort_unstable();
+        r.dedup();
+        r
+    }
+
+    /// Picks the items to submit again from the original batch
+    pub fn retry_subset<T: Clone>(&self, items: &[T]) -> Vec<T> {
+        self.failed_indexes()
+            .into_iter()
+            .filter_map(|i| items.get(i).cloned())
+            .collect()
+    }
+}
+
+fn duplicate_key(message: &str) -> Option<String> {
+    let start = message.find("dup key: ")? + "dup key: ".len();
+    Some(message[start..].trim().to_string())
+}
+
+#[derive(Debug, Clone)]
+enum BulkOp {
+    Insert(Document),
+    Update { filter: Document, update: Document, upsert: bool, many: bool },
+    Replace { filter: Document, replacement: Document, upsert: bool },
+    Delete { filter: Document, many: bool },
+}
+
+impl BulkOp {
+    fn into_write_model(self, ns: &Namespace) -> WriteModel {
+        let ns = ns.clone();
+        match self {
+            BulkOp::Insert(document) => InsertOneModel::builder()
+                .namespace(ns)
+                .document(document)
+                .build()
+                .into(),
+            BulkOp::Update { filter, update, upsert, many: false } => UpdateOneModel::builder()
+                .namespace(ns)
+                .filter(filter)
+                .update(update)
+                .upsert(upsert)
+                .build()
+                .into(),
+            BulkOp::Update { filter, update, upsert, many: true } => UpdateManyModel::builder()
+                .namespace(ns)
+                .filter(filter)
+                .update(update)
+                .upsert(upsert)
+                .build()
+                .into(),
+            BulkOp::Replace { filter, replacement, upsert } => ReplaceOneModel::builder()
+                .namespace(ns)
+                .filter(filter)
+                .replacement(replacement)
+                .upsert(upsert)
+                .build()
+                .into(),
+            BulkOp::Delete { filter, many: false } => DeleteOneModel::builder()
+                .namespace(ns)
+                .filter(filter)
+                .build()
+                .into(),
+            BulkOp::Delete { filter, many: true } => DeleteManyModel::builder()
+                .namespace(ns)
+                .filter(filter)
+                .build()
+                .into(),
+        }
+    }
+}
+
+/// Accumulates typed writes submitted as one `bulk_write`, see `Model::bulk`
+///
+/// Filters and documents go through the model's renaming, timestamps and
+/// compression. Servers older than 8.0 have no `bulk_write` command, the
+/// operations are then run one by one with the same reporting.
+pub struct BulkWriter<'m, 'a, M: Boot> {
+    model: &'m Model<'a, M>,
+    ops: Vec<BulkOp>,
+    ordered: bool,
+    server_info: Option<ServerInfo>,
+    error: Option<Error>,
+}
+
+impl<'m, 'a, M> BulkWriter<'m, 'a, M>
+where
+    M: Boot,
+    M: Default,
+    M: Serialize,
+    M: DeserializeOwned,
+    M: Send,
+    M: Sync,
+    M: Unpin,
+{
+    pub(crate) fn new(model: &'m Model<'a, M>) -> Self {
+        BulkWriter {
+            model,
+            ops: vec![],
+            ordered: true,
+            server_info: None,
+            error: None,
+        }
+    }
+
+    /// Uses `info` to choose between `bulk_write` and sequential writes, instead of detecting
+    /// the server on submit, e.g. the info of [`SessionPool::server_info`](crate::session::SessionPool::server_info)
+    pub fn server_info(mut self, info: ServerInfo) -> Self {
+        self.server_info = Some(info);
+        self
+    }
+
+    /// Stops at the first failed operation when true (the default)
+    pub fn ordered(mut self, ordered: bool) -> Self {
+        self.ordered = ordered;
+        self
+    }
+
+    pub fn insert(mut self, item: &M) -> Self {
+        match self.model.insert_document(item) {
+            Ok(d) => self.ops.push(BulkOp::Insert(d)),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    /// Updates the first document matching `filter`
+    pub fn update(self, filter: Document, data: Document) -> Self {
+        self.push_update(filter, data, false, false)
+    }
+
+    pub fn update_many(self, filter: Document, data: Document) -> Self {
+        self.push_update(filter, data, false, true)
+    }
+
+    /// Updates the first document matching `filter`, or inserts it
+    pub fn upsert(self, filter: Document, data: Document) -> Self {
+        self.push_update(filter, data, true, false)
+    }
+
+    fn push_update(mut self, filter: Document, data: Document, upsert: bool, many: bool) -> Self {
+        let filter = self.model.rename_filter(filter);
+        let checked = if many {
+            self.model.check_bulk_filter("update_many", &filter)
+        } else {
+            Ok(())
+        };
+        let update = checked.and_then(|_| self.model.update_document(data, upsert));
+        match update {
+            Ok(update) => self.ops.push(BulkOp::Update {
+                filter,
+                update,
+                upsert,
+                many,
+            }),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    /// Replaces the first document matching `filter` with `item`
+    ///
+    /// Unlike `Model::replace`, hidden fields and `created_at` are written from `item`.
+    pub fn replace(mut self, filter: Document, item: &M) -> Self {
+        match self.model.replacement_document(item, false) {
+            Ok(replacement) => self.ops.push(BulkOp::Replace {
+                filter: self.model.rename_filter(filter),
+                replacement,
+                upsert: false,
+            }),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    /// Deletes the first document matching `filter`
+    pub fn delete(mut self, filter: Document) -> Self {
+        self.ops.push(BulkOp::Delete {
+            filter: self.model.rename_filter(filter),
+            many: false,
+        });
+        self
+    }
+
+    pub fn delete_many(mut self, filter: Document) -> Self {
+        let filter = self.model.rename_filter(filter);
+        match self.model.check_bulk_filter("delete_many", &filter) {
+            Ok(()) => self.ops.push(BulkOp::Delete { filter, many: true }),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Submits every operation
+    ///
+    /// # Notes
+    /// - Write errors don't fail the call, they are listed in the returned [`BulkOutcome`]
+    /// - Fires one `bulk` event with the outcome summary
+    /// - Detects the server version first, unless `server_info` was given, through the model's
+    ///   `server_info_cache` when set
+    /// - `update_many` and `delete_many` follow the model's guards: an empty filter fails with
+    ///   `require_filter_for_all`, and `max_affected` is counted before the write unless `force()` is set
+    pub async fn submit(self) -> Result<BulkOutcome> {
+        self.run(None).await
+    }
+
+    /// Submits every operation with session
+    pub async fn submit_with_session(self, session: &mut ClientSession) -> Result<BulkOutcome> {
+        self.run(Some(session)).await
+    }
+
+    async fn run(self, mut session: Option<&mut ClientSession>) -> Result<BulkOutcome> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let total = self.ops.len();
+        if total == 0 {
+            return Ok(BulkOutcome::default());
+        }
+        for op in &self.ops {
+            let (operation, filter) = match op {
+                BulkOp::Update { filter, many: true, .. } => ("update_many", filter),
+                BulkOp::Delete { filter, many: true } => ("delete_many", filter),
+                _ => continue,
+            };
+            self.model
+                .check_bulk_affected(operation, filter, session.as_deref_mut())
+                .await?;
+        }
+        let db = self.model.database();
+        let collection = self.model.collection().clone_with_type::<Document>();
+        let native = match self.server_info {
+            Some(info) => info.at_least(8, 0),
+            None => self.model.server_info().await?.at_least(8, 0),
+        };
+        let comment = self.model.command_comment();
+        let outcome = if native {
+            let ns = collection.namespace();
+            let models = self
+                .ops
+                .into_iter()
+                .map(|op| op.into_write_model(&ns))
+                .collect::<Vec<_>>();
+            let write = db
+                .client()
+                .bulk_write(models)
+                .ordered(self.ordered)
+                .optional(collection.write_concern().cloned(), |w, c| w.write_concern(c))
+                .optional(comment, |w, c| w.comment(c))
+                .verbose_results();
+            let r = match session.as_deref_mut() {
+                Some(session) => write.session(session).await,
+                None => write.await,
+            };
+            BulkOutcome::from_bulk_write(total, r)?
+        } else {
+            run_sequential(&collection, self.ops, self.ordered, comment, session.as_deref_mut())
+                .await?
+        };
+        let summary = mongodb::bson::doc! {
+            "total": total as i64,
+            "succeeded": outcome.succeeded.len() as i64,
+            "failed": outcome.failed_indexes().len() as i64,
+        };
+        self.model
+            .finish(self.model.request(), "bulk", Document::new(), summary, session)
+            .await;
+        Ok(outcome)
+    }
+}
+
+/// Runs the operations one at a time, for servers without `bulk_write`
+async fn run_sequential(
+    collection: &Collection<Document>,
+    ops: Vec<BulkOp>,
+    ordered: bool,
+    comment: Option<Bson>,
+    mut session: Option<&mut ClientSession>,
+) -> Result<BulkOutcome> {
+    let mut outcome = BulkOutcome {
+        total: ops.len(),
+        ..Default::default()
+    };
+    for (index, op) in ops.into_iter().enumerate() {
+        let r = match op {
+            BulkOp::Insert(d) => {
+                let action = collection.insert_one(d).optional(comment.clone(), |a, c| a.comment(c));
+                match session.as_deref_mut() {
+                    Some(s) => action.session(s).await,
+                    None => action.await,
+                }
+                .map(|r| {
+                    outcome.inserted_ids.insert(index, r.inserted_id);
+                })
+            }
+            BulkOp::Update { filter, update, upsert, many } => {
+                let action = if many {
+                    collection.update_many(filter, update)
+                } else {
+                    collection.update_one(filter, update)
+                }
+                .upsert(upsert)
+                .optional(comment.clone(), |a, c| a.comment(c));
+                match session.as_deref_mut() {
+                    Some(s) => action.session(s).await,
+                    None => action.await,
+                }
+                .map(|r| {
+                    outcome.matched_count += r.matched_count;
+                    outcome.modified_count += r.modified_count;
+                    if let Some(id) = r.upserted_id {
+                        outcome.upserted_ids.insert(index, id);
+                    }
+                })
+            }
+            BulkOp::Replace { filter, replacement, upsert } => {
+                let action = collection
+                    .replace_one(filter, replacement)
+                    .upsert(upsert)
+                    .optional(comment.clone(), |a, c| a.comment(c));
+                match session.as_deref_mut() {
+                    Some(s) => action.session(s).await,
+                    None => action.await,
+                }
+                .map(|r| {
+                    outcome.matched_count += r.matched_count;
+                    outcome.modified_count += r.modified_count;
+                    if let Some(id) = r.upserted_id {
+                        outcome.upserted_ids.insert(index, id);
+                    }
+                })
+            }
+            BulkOp::Delete { filter, many } => {
+                let action = if many {
+                    collection.delete_many(filter)
+                } else {
+                    collection.delete_one(filter)
+                }
+                .optional(comment.clone(), |a, c| a.comment(c));
+                match session.as_deref_mut() {
+                    Some(s) => action.session(s).await,
+                    None => action.await,
+                }
+                .map(|r| {
+                    outcome.deleted_count += r.deleted_count;
+                })
+            }
+        };
+        match r {
+            Ok(()) => outcome.succeeded.push(index),
+            Err(e) => match e.kind.as_ref() {
+                ErrorKind::Write(WriteFailure::WriteError(we)) => {
+                    outcome.write_errors.push(BulkWriteFailure::from_write_error(index, we));
+                    if ordered {
+                        break;
+                    }
+                }
+                // the operation was applied, an ordered run goes on like `bulk_write` does
+                ErrorKind::Write(WriteFailure::WriteConcernError(wce)) => {
+                    outcome.succeeded.push(index);
+                    outcome.write_concern_errors.push(WriteConcernFailure::from(wce));
+                }
+                _ => return Err(e),
+            },
+        }
+    }
+    outcome.not_attempted = outcome.missing_indexes();
+    Ok(outcome)
+}
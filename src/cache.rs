@@ -0,0 +1,77 @@
+//! Process wide cache of query results
+//!
+//! Entries are keyed by a fingerprint of the database, collection and filter,
+//! and expire after the TTL given when they are read, see `Model::count_cached`.
+//! At most 10 000 counts are kept, expired ones are evicted first, then the oldest.
+
+use mongodb::bson::Document;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+struct Entry {
+    collection: String,
+    count: u64,
+    at: Instant,
+    ttl: Duration,
+}
+
+/// Most counts kept at once, the oldest entry is evicted past this
+const MAX_ENTRIES: usize = 10_000;
+
+static COUNTS: RwLock<Option<HashMap<u64, Entry>>> = RwLock::new(None);
+
+/// Fingerprint of a query, `extra` covers settings such as skip and limit
+pub fn fingerprint(db: &str, collection: &str, filter: &Document, extra: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    db.hash(&mut hasher);
+    collection.hash(&mut hasher);
+    filter.to_string().hash(&mut hasher);
+    extra.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub(crate) fn count(key: u64, ttl: Duration) -> Option<u64> {
+    COUNTS
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|c| c.get(&key))
+        .filter(|e| e.at.elapsed() < ttl)
+        .map(|e| e.count)
+}
+
+pub(crate) fn set_count(key: u64, collection: &str, count: u64, ttl: Duration) {
+    let mut counts = COUNTS.write().unwrap();
+    let counts = counts.get_or_insert_with(HashMap::new);
+    if counts.len() >= MAX_ENTRIES && !counts.contains_key(&key) {
+        counts.retain(|_, e| e.at.elapsed() < e.ttl);
+        if counts.len() >= MAX_ENTRIES
+            && let Some(oldest) = counts.iter().min_by_key(|(_, e)| e.at).map(|(k, _)| *k)
+        {
+            counts.remove(&oldest);
+        }
+    }
+    counts.insert(
+        key,
+        Entry {
+            collection: collection.to_string(),
+            count,
+            at: Instant::now(),
+            ttl,
+        },
+    );
+}
+
+/// Drops the cached counts of `collection`, e.g. after a bulk import
+pub fn invalidate_counts(collection: &str) {
+    if let Some(counts) = COUNTS.write().unwrap().as_mut() {
+        counts.retain(|_, e| e.collection != collection);
+    }
+}
+
+/// Drops every cached count
+pub fn clear() {
+    *COUNTS.write().unwrap() = None;
+}
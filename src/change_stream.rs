@@ -0,0 +1,226 @@
+//! Change streams that survive interruptions
+//!
+//! A [`ResilientStream`] reopens its change stream after errors, resuming
+//! from the last seen event, and waits between attempts according to a
+//! [`Backoff`]. What happens after the watched collection is dropped or
+//! renamed is set with [`OnInvalidate`].
+//!
+//! ```ignore
+//! let mut events = Job::new_model(&db)
+//!     .watch([doc! {"$match": {"operationType": "insert"}}])
+//!     .backoff(Backoff::default().max_retries(10));
+//! while let Some(event) = events.next().await {
+//!     let event = event?;
+//!     log::info!("{:?}", event.document_key);
+//! }
+//! ```
+
+use futures_util::{Stream, StreamExt};
+use mongodb::bson::{Bson, Document};
+use mongodb::change_stream::event::{ChangeStreamEvent, OperationType, ResumeToken};
+use mongodb::change_stream::ChangeStream;
+use mongodb::error::Result;
+use mongodb::options::{ChangeStreamOptions, FullDocumentType};
+use mongodb::Database;
+use std::time::Duration;
+
+/// Delays between reconnection attempts, doubling from `initial` up to `max`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    /// Consecutive failures after which the error is yielded, `None` retries forever
+    pub max_retries: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+            max_retries: None,
+        }
+    }
+}
+
+impl Backoff {
+    pub fn initial(mut self, initial: Duration) -> Backoff {
+        self.initial = initial;
+        self
+    }
+
+    pub fn max(mut self, max: Duration) -> Backoff {
+        self.max = max;
+        self
+    }
+
+    pub fn max_retries(mut self, retries: u32) -> Backoff {
+        self.max_retries = Some(retries);
+        self
+    }
+
+    /// Delay before the attempt following `failures` consecutive failures
+    pub fn delay(&self, failures: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+/// What a [`ResilientStream`] does after an invalidate event, sent when the collection is dropped or renamed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnInvalidate {
+    /// Yields the invalidate event, then ends
+    Stop,
+    /// Keeps watching the namespace after the invalidate event, e.g. to follow a recreated collection
+    #[default]
+    StartAfter,
+}
+
+/// Change event of a model, see `Model::watch_typed`
+///
+/// Field names are the model's and hidden fields are left out, like in reads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelEvent<M> {
+    Insert(M),
+    Update {
+        id: Bson,
+        /// New values of the updated fields, keyed by field name or dotted path
+        changes: Document,
+        removed: Vec<String>,
+    },
+    Replace(M),
+    Delete { id: Bson },
+    /// Any other event, e.g. `drop` or `invalidate`
+    Other(OperationType),
+}
+
+/// Change stream of a collection that reconnects and resumes on its own
+pub struct ResilientStream {
+    db: Database,
+    collection: String,
+    pipeline: Vec<Document>,
+    full_document: Option<FullDocumentType>,
+    backoff: Backoff,
+    on_invalidate: OnInvalidate,
+    token: Option<ResumeToken>,
+    stream: Option<ChangeStream<ChangeStreamEvent<Document>>>,
+    failures: u32,
+    finished: bool,
+}
+
+impl ResilientStream {
+    pub fn new(db: &Database, collection: &str, pipeline: Vec<Document>) -> ResilientStream {
+        ResilientStream {
+            db: db.clone(),
+            collection: collection.to_string(),
+            pipeline,
+            full_document: None,
+            backoff: Backoff::default(),
+            on_invalidate: OnInvalidate::default(),
+            token: None,
+            stream: None,
+            failures: 0,
+            finished: false,
+        }
+    }
+
+    pub fn backoff(mut self, backoff: Backoff) -> ResilientStream {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn on_invalidate(mut self, on_invalidate: OnInvalidate) -> ResilientStream {
+        self.on_invalidate = on_invalidate;
+        self
+    }
+
+    /// Includes the current document in update events
+    pub fn full_document(mut self, full_document: FullDocumentType) -> ResilientStream {
+        self.full_document = Some(full_document);
+        self
+    }
+
+    /// Starts after `token`, e.g. one saved by a previous run
+    pub fn resume_from(mut self, token: ResumeToken) -> ResilientStream {
+        self.token = Some(token);
+        self
+    }
+
+    /// Token of the last seen event, save it to resume after a restart
+    pub fn resume_token(&self) -> Option<ResumeToken> {
+        self.token.clone()
+    }
+
+    async fn open(&self) -> Result<ChangeStream<ChangeStreamEvent<Document>>> {
+        let mut options = ChangeStreamOptions::default();
+        options.full_document = self.full_document.clone();
+        options.start_after = self.token.clone();
+        self.db
+            .collection::<Document>(&self.collection)
+            .watch()
+            .pipeline(self.pipeline.clone())
+            .with_options(options)
+            .await
+    }
+
+    /// Counts a failure and waits, returning the error once the retries are exhausted
+    async fn failed(&mut self, e: mongodb::error::Error) -> Option<mongodb::error::Error> {
+        self.stream = None;
+        self.failures += 1;
+        if self.backoff.max_retries.is_some_and(|max| self.failures > max) {
+            self.failures = 0;
+            return Some(e);
+        }
+        log::warn!("change stream on {} interrupted : {:?}", self.collection, e);
+        tokio::time::sleep(self.backoff.delay(self.failures)).await;
+        None
+    }
+
+    /// Waits for the next event, `None` once stopped by an invalidate event
+    ///
+    /// # Notes
+    /// - Errors are only yielded after `max_retries` consecutive failures, the
+    ///   stream keeps retrying when polled again
+    pub async fn next(&mut self) -> Option<Result<ChangeStreamEvent<Document>>> {
+        loop {
+            if self.finished {
+                return None;
+            }
+            let stream = match &mut self.stream {
+                Some(stream) => stream,
+                None => match self.open().await {
+                    Ok(stream) => self.stream.insert(stream),
+                    Err(e) => match self.failed(e).await {
+                        Some(e) => return Some(Err(e)),
+                        None => continue,
+                    },
+                },
+            };
+            match stream.next().await {
+                Some(Ok(event)) => {
+                    self.failures = 0;
+                    self.token = Some(event.id.clone());
+                    if event.operation_type == OperationType::Invalidate {
+                        self.stream = None;
+                        self.finished = self.on_invalidate == OnInvalidate::Stop;
+                    }
+                    return Some(Ok(event));
+                }
+                Some(Err(e)) => {
+                    if let Some(e) = self.failed(e).await {
+                        return Some(Err(e));
+                    }
+                }
+                None => {
+                    self.stream = None;
+                    tokio::time::sleep(self.backoff.initial).await;
+                }
+            }
+        }
+    }
+
+    /// Turns this into a [`Stream`] of events
+    pub fn into_stream(self) -> impl Stream<Item = Result<ChangeStreamEvent<Document>>> {
+        futures_util::stream::unfold(self, |mut s| async move { s.next().await.map(|e| (e, s)) })
+    }
+}
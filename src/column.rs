@@ -1,6 +1,12 @@
+use mongodb::bson::Document;
 use serde::Deserialize;
 
-#[derive(Debug, Deserialize, Clone)]
+/// Attributes of a model column
+///
+/// Filled from the `#[model(...)]` field attributes. Attributes added after the
+/// derive macro's JSON format default to off, and can also be set from
+/// [`Boot::configure_columns`](crate::event::Boot::configure_columns).
+#[derive(Debug, Deserialize, Clone, Default)]
 pub struct ColumnAttr {
     pub asc: bool,
     pub desc: bool,
@@ -9,10 +15,30 @@ pub struct ColumnAttr {
     pub text: Option<String>,
     pub hidden: bool,
     pub name: Option<String>,
+    /// Per-document expiry date, indexed with `expireAfterSeconds: 0`
+    #[serde(default)]
+    pub expires_at: bool,
+    /// Codec name used to store the value compressed, see [`crate::compress`]
+    #[serde(default)]
+    pub compress: Option<String>,
+    /// Large value left out of list queries unless `Model::with_heavy` is called
+    #[serde(default)]
+    pub heavy: bool,
+    /// Description of the field, see [`crate::schema`]
+    #[serde(default)]
+    pub doc: Option<String>,
+    /// Partial filter expression of the field's index, with model field names
+    ///
+    /// e.g. `{"block": false}` for a unique index ignoring blocked users
+    #[serde(default)]
+    pub partial: Option<Document>,
+    /// Only values the `$jsonSchema` validator accepts, see `Model::provision_collection`
+    #[serde(default)]
+    pub enum_values: Option<Vec<String>>,
 }
 impl ColumnAttr {
     pub fn is_index(&self) -> bool {
-        if self.unique ||self.asc || self.desc || self.sphere2d || self.text.is_some() {
+        if self.unique ||self.asc || self.desc || self.sphere2d || self.text.is_some() || self.expires_at {
             return true;
         }
         false
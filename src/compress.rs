@@ -0,0 +1,117 @@
+//! Transparent compression of large fields
+//!
+//! Columns with `compress = "<codec>"` are stored as BSON binaries
+//! (subtype `0x80`) starting with a small header naming the codec, and are
+//! restored before the document is cast or deserialized. Codecs are
+//! registered by name once at startup:
+//!
+//! ```ignore
+//! struct Zstd;
+//! impl Codec for Zstd {
+//!     fn compress(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+//!         zstd::encode_all(data, 3).map_err(|e| e.to_string())
+//!     }
+//!     fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+//!         zstd::decode_all(data).map_err(|e| e.to_string())
+//!     }
+//! }
+//! mongodb_ro::compress::register_codec("zstd", Zstd);
+//! ```
+//!
+//! Writes fail when the codec fails or isn't registered, nothing is stored
+//! uncompressed. Reads, including `Model::cursor`, return the values decompressed.
+
+use mongodb::bson::spec::BinarySubtype;
+use mongodb::bson::{Binary, Bson};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+const SUBTYPE: u8 = 0x80;
+const MAGIC: &[u8; 3] = b"MRC";
+const KIND_STRING: u8 = 0;
+const KIND_BINARY: u8 = 1;
+
+pub trait Codec: Send + Sync {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, String>;
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, String>;
+}
+
+static CODECS: RwLock<Option<HashMap<String, Arc<dyn Codec>>>> = RwLock::new(None);
+
+/// Registers a codec usable from `compress = "<name>"` columns
+pub fn register_codec(name: &str, codec: impl Codec + 'static) {
+    let mut codecs = CODECS.write().unwrap();
+    codecs
+        .get_or_insert_with(HashMap::new)
+        .insert(name.to_string(), Arc::new(codec));
+}
+
+fn codec(name: &str) -> Result<Arc<dyn Codec>, String> {
+    CODECS
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|c| c.get(name).cloned())
+        .ok_or_else(|| format!("codec `{name}` is not registered"))
+}
+
+/// Compresses a string or binary value, other values are returned unchanged
+pub fn compress(name: &str, value: Bson) -> Result<Bson, String> {
+    let (kind, bytes) = match &value {
+        Bson::String(s) => (KIND_STRING, s.as_bytes()),
+        Bson::Binary(b) if !is_compressed(&value) => (KIND_BINARY, b.bytes.as_slice()),
+        _ => return Ok(value),
+    };
+    let payload = codec(name)?.compress(bytes)?;
+    let mut out = Vec::with_capacity(payload.len() + name.len() + 5);
+    out.extend_from_slice(MAGIC);
+    out.push(kind);
+    out.push(name.len() as u8);
+    out.extend_from_slice(name.as_bytes());
+    out.extend_from_slice(&payload);
+    Ok(Bson::Binary(Binary {
+        subtype: BinarySubtype::UserDefined(SUBTYPE),
+        bytes: out,
+    }))
+}
+
+/// Whether a value was produced by [`compress`]
+pub fn is_compressed(value: &Bson) -> bool {
+    match value {
+        Bson::Binary(b) => {
+            b.subtype == BinarySubtype::UserDefined(SUBTYPE) && b.bytes.starts_with(MAGIC)
+        }
+        _ => false,
+    }
+}
+
+/// Restores a value produced by [`compress`], other values are returned unchanged
+pub fn decompress(value: Bson) -> Result<Bson, String> {
+    if !is_compressed(&value) {
+        return Ok(value);
+    }
+    let Bson::Binary(b) = value else {
+        return Ok(value);
+    };
+    let header = MAGIC.len();
+    let (kind, name_len) = match (b.bytes.get(header), b.bytes.get(header + 1)) {
+        (Some(kind), Some(len)) => (*kind, *len as usize),
+        _ => return Err("truncated compressed value".to_string()),
+    };
+    let start = header + 2;
+    let name = b
+        .bytes
+        .get(start..start + name_len)
+        .and_then(|n| std::str::from_utf8(n).ok())
+        .ok_or_else(|| "invalid codec name".to_string())?;
+    let data = codec(name)?.decompress(&b.bytes[start + name_len..])?;
+    match kind {
+        KIND_STRING => String::from_utf8(data)
+            .map(Bson::String)
+            .map_err(|e| e.to_string()),
+        _ => Ok(Bson::Binary(Binary {
+            subtype: BinarySubtype::Generic,
+            bytes: data,
+        })),
+    }
+}
@@ -0,0 +1,182 @@
+//! Referential checks between related collections
+//!
+//! A [`Rule`] declares that every value of a field references an existing
+//! document of another collection. [`check`] runs the rules and reports the
+//! orphaned documents, [`check_and_fix`] also hands each orphan to a callback.
+//!
+//! ```ignore
+//! let orders = Order::new_model(&db);
+//! let users = User::new_model(&db);
+//! let reports = consistency::check(&db, &[
+//!     Rule::references("order user", &orders, "user_id", &users, "_id").sample(1000),
+//! ]).await?;
+//! for report in reports {
+//!     log::warn!("{report}");
+//! }
+//! ```
+
+use crate::event::Boot;
+use crate::model::Model;
+use futures_util::StreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::Result;
+use mongodb::Database;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    pub collection: String,
+    /// Only documents matching this filter are checked
+    pub filter: Document,
+    /// Database name of the referencing field
+    pub field: String,
+    pub target: String,
+    /// Database name of the referenced field in `target`
+    pub target_field: String,
+    /// Checks a random sample of this size instead of scanning every document
+    pub sample: Option<u32>,
+}
+
+impl Rule {
+    /// Every `field` of the documents matching `model`'s query must exist as `target_field` in `target`
+    ///
+    /// # Notes
+    /// - Field names are renamed to their database names
+    /// - Documents without `field`, or with a null one, are not checked
+    /// - An array `field` is consistent when any of its items exists
+    pub fn references<M, T>(
+        name: &str,
+        model: &Model<'_, M>,
+        field: &str,
+        target: &Model<'_, T>,
+        target_field: &str,
+    ) -> Rule
+    where
+        M: Boot + Default + Serialize + DeserializeOwned + Send + Sync + Unpin,
+        T: Boot + Default + Serialize + DeserializeOwned + Send + Sync + Unpin,
+    {
+        Rule {
+            name: name.to_string(),
+            collection: model.collection_name().to_string(),
+            filter: model.build_find().filter,
+            field: model.db_name(field),
+            target: target.collection_name().to_string(),
+            target_field: target.db_name(target_field),
+            sample: None,
+        }
+    }
+
+    /// Checks `size` random documents instead of the whole collection
+    pub fn sample(mut self, size: u32) -> Rule {
+        self.sample = Some(size.max(1));
+        self
+    }
+
+    fn pipeline(&self) -> Vec<Document> {
+        let mut pipeline = vec![];
+        if !self.filter.is_empty() {
+            pipeline.push(doc! {"$match": self.filter.clone()});
+        }
+        if let Some(size) = self.sample {
+            pipeline.push(doc! {"$sample": {"size": size as i64}});
+        }
+        pipeline.push(doc! {"$match": {&self.field: {"$exists": true, "$ne": Bson::Null}}});
+        pipeline.push(doc! {"$lookup": {
+            "from": &self.target,
+            "localField": &self.field,
+            "foreignField": &self.target_field,
+            "as": "__references",
+        }});
+        pipeline.push(doc! {"$project": {
+            &self.field: 1,
+            "__orphan": {"$eq": [{"$size": "$__references"}, 0]},
+        }});
+        pipeline
+    }
+}
+
+/// A document referencing a missing document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Orphan {
+    /// `_id` of the referencing document
+    pub id: Bson,
+    /// The dangling reference
+    pub value: Bson,
+}
+
+/// Outcome of one rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleReport {
+    pub rule: String,
+    pub collection: String,
+    pub checked: u64,
+    pub orphans: Vec<Orphan>,
+}
+
+impl RuleReport {
+    pub fn is_consistent(&self) -> bool {
+        self.orphans.is_empty()
+    }
+}
+
+impl fmt::Display for RuleReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rule `{}` on `{}`: {} orphans in {} documents",
+            self.rule,
+            self.collection,
+            self.orphans.len(),
+            self.checked
+        )
+    }
+}
+
+/// Runs every rule and reports the orphans
+///
+/// # Notes
+/// - Rules run one after the other, the first failing rule fails the check
+pub async fn check(db: &Database, rules: &[Rule]) -> Result<Vec<RuleReport>> {
+    check_and_fix(db, rules, async |_: &Rule, _: &Orphan| Ok(())).await
+}
+
+/// Runs every rule, calling `fix` for each orphan as it is found
+///
+/// # Notes
+/// - An error returned by `fix` stops the check
+/// - Fixed orphans are still listed in the reports
+pub async fn check_and_fix(
+    db: &Database,
+    rules: &[Rule],
+    mut fix: impl AsyncFnMut(&Rule, &Orphan) -> Result<()>,
+) -> Result<Vec<RuleReport>> {
+    let mut reports = vec![];
+    for rule in rules {
+        let collection = db.collection::<Document>(&rule.collection);
+        let mut cursor = collection.aggregate(rule.pipeline()).await?;
+        let mut report = RuleReport {
+            rule: rule.name.clone(),
+            collection: rule.collection.clone(),
+            checked: 0,
+            orphans: vec![],
+        };
+        while let Some(d) = cursor.next().await {
+            let d = d?;
+            report.checked += 1;
+            if !d.get_bool("__orphan").unwrap_or(false) {
+                continue;
+            }
+            let orphan = Orphan {
+                id: d.get("_id").cloned().unwrap_or(Bson::Null),
+                value: d.get(&rule.field).cloned().unwrap_or(Bson::Null),
+            };
+            fix(rule, &orphan).await?;
+            report.orphans.push(orphan);
+        }
+        reports.push(report);
+    }
+    Ok(reports)
+}
@@ -0,0 +1,70 @@
+//! Copying query results into another database or collection
+
+use mongodb::bson::Document;
+
+type Transform<'c> = Box<dyn Fn(Document) -> Option<Document> + Send + Sync + 'c>;
+
+/// Options of [`Model::copy_to`](crate::model::Model::copy_to)
+pub struct CopyOptions<'c> {
+    /// Target collection, defaults to the model's collection name
+    pub collection: Option<&'c str>,
+    /// Number of documents per `insert_many`
+    pub batch_size: u32,
+    /// Recreate the source indexes on the target before copying
+    pub copy_indexes: bool,
+    /// Rewrites each document, returning `None` skips it
+    pub transform: Option<Transform<'c>>,
+}
+
+impl Default for CopyOptions<'_> {
+    fn default() -> Self {
+        Self {
+            collection: None,
+            batch_size: 1000,
+            copy_indexes: true,
+            transform: None,
+        }
+    }
+}
+
+impl<'c> CopyOptions<'c> {
+    pub fn collection(mut self, name: &'c str) -> Self {
+        self.collection = Some(name);
+        self
+    }
+
+    pub fn batch_size(mut self, size: u32) -> Self {
+        self.batch_size = size;
+        self
+    }
+
+    pub fn copy_indexes(mut self, copy: bool) -> Self {
+        self.copy_indexes = copy;
+        self
+    }
+
+    pub fn transform(
+        mut self,
+        f: impl Fn(Document) -> Option<Document> + Send + Sync + 'c,
+    ) -> Self {
+        self.transform = Some(Box::new(f));
+        self
+    }
+
+    pub(crate) fn apply(&self, doc: Document) -> Option<Document> {
+        match &self.transform {
+            Some(f) => f(doc),
+            None => Some(doc),
+        }
+    }
+}
+
+/// Result of [`Model::copy_to`](crate::model::Model::copy_to)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CopyReport {
+    pub copied: u64,
+    /// Documents dropped by the transform
+    pub skipped: u64,
+    /// Names of the indexes created on the target
+    pub indexes: Vec<String>,
+}
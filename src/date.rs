@@ -0,0 +1,136 @@
+//! Calendar period math for date filters, and the JSON format of dates
+
+use mongodb::bson::DateTime;
+use serde_json::Value;
+
+const MS_PER_MINUTE: i64 = 60_000;
+const MS_PER_HOUR: i64 = 60 * MS_PER_MINUTE;
+const MS_PER_DAY: i64 = 24 * MS_PER_HOUR;
+
+/// Granularity of a date filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatePart {
+    Year,
+    Month,
+    /// Monday based week
+    Week,
+    Day,
+    Hour,
+}
+
+/// Bounds `[start, end)` of the period containing `at`
+///
+/// # Arguments
+/// * `utc_offset_minutes` - Offset of the local time zone, e.g. `210` for UTC+03:30
+pub fn period(at: DateTime, part: DatePart, utc_offset_minutes: i32) -> (DateTime, DateTime) {
+    let offset = utc_offset_minutes as i64 * MS_PER_MINUTE;
+    let local = at.timestamp_millis() + offset;
+    let days = local.div_euclid(MS_PER_DAY);
+    let (y, m, _) = civil_from_days(days);
+
+    let (start, end) = match part {
+        DatePart::Hour => {
+            let start = local.div_euclid(MS_PER_HOUR) * MS_PER_HOUR;
+            (start, start + MS_PER_HOUR)
+        }
+        DatePart::Day => (days * MS_PER_DAY, (days + 1) * MS_PER_DAY),
+        DatePart::Week => {
+            // 1970-01-01 was a Thursday
+            let weekday = (days + 3).rem_euclid(7);
+            let start = days - weekday;
+            (start * MS_PER_DAY, (start + 7) * MS_PER_DAY)
+        }
+        DatePart::Month => {
+            let (ny, nm) = if m == 12 { (y + 1, 1) } else { (y, m + 1) };
+            (
+                days_from_civil(y, m, 1) * MS_PER_DAY,
+                days_from_civil(ny, nm, 1) * MS_PER_DAY,
+            )
+        }
+        DatePart::Year => (
+            days_from_civil(y, 1, 1) * MS_PER_DAY,
+            days_from_civil(y + 1, 1, 1) * MS_PER_DAY,
+        ),
+    };
+    (
+        DateTime::from_millis(start - offset),
+        DateTime::from_millis(end - offset),
+    )
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+pub fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = m as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Proleptic Gregorian date of a day count since 1970-01-01
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+/// How dates appear in the JSON of [`Model::to_json`](crate::model::Model::to_json)
+///
+/// Set it for a model with `options.datetime_json` in
+/// [`Boot::configure`](crate::event::Boot::configure).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateTimeJson {
+    /// Extended JSON, `{"$date": {"$numberLong": "..."}}`
+    #[default]
+    Extended,
+    /// RFC 3339 string, e.g. `"2024-05-01T10:00:00Z"`
+    Rfc3339,
+    /// Milliseconds since the epoch
+    Millis,
+}
+
+fn extended_date(value: &Value) -> Option<DateTime> {
+    let object = value.as_object()?;
+    if object.len() != 1 {
+        return None;
+    }
+    match object.get("$date")? {
+        Value::Object(n) if n.len() == 1 => {
+            n.get("$numberLong")?.as_str()?.parse().ok().map(DateTime::from_millis)
+        }
+        Value::Number(n) => n.as_i64().map(DateTime::from_millis),
+        Value::String(s) => DateTime::parse_rfc3339_str(s).ok(),
+        _ => None,
+    }
+}
+
+/// Rewrites every extended JSON date in `value` to `format`
+pub fn format_json_dates(value: &mut Value, format: DateTimeJson) {
+    if format == DateTimeJson::Extended {
+        return;
+    }
+    if let Some(at) = extended_date(value) {
+        *value = match format {
+            DateTimeJson::Rfc3339 => match at.try_to_rfc3339_string() {
+                Ok(s) => Value::String(s),
+                Err(_) => Value::from(at.timestamp_millis()),
+            },
+            _ => Value::from(at.timestamp_millis()),
+        };
+        return;
+    }
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(|v| format_json_dates(v, format)),
+        Value::Object(object) => object.values_mut().for_each(|v| format_json_dates(v, format)),
+        _ => {}
+    }
+}
@@ -0,0 +1,205 @@
+//! Typed errors raised by this crate
+//!
+//! They are wrapped in [`mongodb::error::Error`] as custom errors, use
+//! `error.get_custom::<GuardError>()` to inspect them.
+
+use crate::query_spec::Op;
+use crate::server::Topology;
+use std::fmt;
+
+/// A query rejected by the model's [`Guards`](crate::options::Guards)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GuardError {
+    /// A list query without limit while `max_limit` is set
+    UnboundedQuery { max_limit: u32 },
+    LimitExceeded { limit: u32, max_limit: u32 },
+    SkipExceeded { skip: u32, max_skip: u32 },
+    /// A multi-document write without any filter condition
+    FilterRequired { operation: String },
+    /// A sort on a field outside `Model::sortable_fields`
+    SortNotAllowed { field: String },
+    /// An `all()` update or delete matching more than `max_affected` documents without `force()`
+    TooManyAffected { operation: String, max_affected: u64 },
+}
+
+impl fmt::Display for GuardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuardError::UnboundedQuery { max_limit } => {
+                write!(f, "query has no limit, at most {max_limit} documents allowed")
+            }
+            GuardError::LimitExceeded { limit, max_limit } => {
+                write!(f, "limit {limit} exceeds the maximum of {max_limit}")
+            }
+            GuardError::SkipExceeded { skip, max_skip } => {
+                write!(f, "skip {skip} exceeds the maximum of {max_skip}")
+            }
+            GuardError::FilterRequired { operation } => {
+                write!(f, "{operation} on all documents requires a filter")
+            }
+            GuardError::SortNotAllowed { field } => {
+                write!(f, "sorting on {field} is not allowed")
+            }
+            GuardError::TooManyAffected { operation, max_affected } => {
+                write!(f, "{operation} would affect more than {max_affected} documents, use force()")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GuardError {}
+
+impl From<GuardError> for mongodb::error::Error {
+    fn from(value: GuardError) -> Self {
+        mongodb::error::Error::custom(value)
+    }
+}
+
+/// A query stopped before completion, see `Model::cancel_on` and `Model::timeout`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupted {
+    Cancelled,
+    TimedOut,
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interrupted::Cancelled => write!(f, "operation cancelled"),
+            Interrupted::TimedOut => write!(f, "operation timed out"),
+        }
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+impl From<Interrupted> for mongodb::error::Error {
+    fn from(value: Interrupted) -> Self {
+        mongodb::error::Error::custom(value)
+    }
+}
+
+/// A list request rejected by its [`QueryRules`](crate::query_spec::QueryRules)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecError {
+    NotFilterable { field: String },
+    NotSortable { field: String },
+    UnknownOperator { field: String, op: String },
+    OperatorNotAllowed { field: String, op: Op },
+    InvalidValue { field: String, value: String },
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecError::NotFilterable { field } => write!(f, "filtering on {field} is not allowed"),
+            SpecError::NotSortable { field } => write!(f, "sorting on {field} is not allowed"),
+            SpecError::UnknownOperator { field, op } => {
+                write!(f, "unknown operator {op} on {field}")
+            }
+            SpecError::OperatorNotAllowed { field, op } => {
+                write!(f, "operator {op:?} is not allowed on {field}")
+            }
+            SpecError::InvalidValue { field, value } => {
+                write!(f, "invalid value {value:?} for {field}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+impl From<SpecError> for mongodb::error::Error {
+    fn from(value: SpecError) -> Self {
+        mongodb::error::Error::custom(value)
+    }
+}
+
+/// A unique index that `Model::add_unique_index` could not introduce
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexMigrationError {
+    /// Documents share the new key and the policy is `DuplicatePolicy::Abort`
+    Duplicates { index: String, groups: u64 },
+    /// The built index is missing or isn't unique
+    VerificationFailed { index: String },
+    /// Documents with a missing or null key field share the key, they aren't merged
+    NullKeys { index: String, groups: u64 },
+}
+
+impl fmt::Display for IndexMigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexMigrationError::Duplicates { index, groups } => {
+                write!(f, "{groups} groups of documents share the key of unique index {index}")
+            }
+            IndexMigrationError::VerificationFailed { index } => {
+                write!(f, "unique index {index} was not found after its build")
+            }
+            IndexMigrationError::NullKeys { index, groups } => {
+                write!(f, "{groups} groups of documents without a key field share the key of unique index {index}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for IndexMigrationError {}
+
+impl From<IndexMigrationError> for mongodb::error::Error {
+    fn from(value: IndexMigrationError) -> Self {
+        mongodb::error::Error::custom(value)
+    }
+}
+
+/// A transaction requested on a deployment without multi-document transactions,
+/// see `SessionPool::transaction`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionsUnsupported {
+    pub topology: Topology,
+    /// Server version, e.g. `7.0.12`
+    pub version: String,
+}
+
+impl fmt::Display for TransactionsUnsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "transactions are not supported on a {:?} server {}",
+            self.topology, self.version
+        )
+    }
+}
+
+impl std::error::Error for TransactionsUnsupported {}
+
+impl From<TransactionsUnsupported> for mongodb::error::Error {
+    fn from(value: TransactionsUnsupported) -> Self {
+        mongodb::error::Error::custom(value)
+    }
+}
+
+/// A write refused by `Model::ensure_invariant`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvariantViolated {
+    pub collection: String,
+    /// Documents matching the invariant's filter
+    pub count: u64,
+    pub max_count: u64,
+}
+
+impl fmt::Display for InvariantViolated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} documents of {} already match, at most {} allowed",
+            self.count, self.collection, self.max_count
+        )
+    }
+}
+
+impl std::error::Error for InvariantViolated {}
+
+impl From<InvariantViolated> for mongodb::error::Error {
+    fn from(value: InvariantViolated) -> Self {
+        mongodb::error::Error::custom(value)
+    }
+}
@@ -1,6 +1,12 @@
+use crate::column::ColumnAttr;
+use crate::options::ModelOptions;
+use crate::repair::Repair;
+use futures::future::LocalBoxFuture;
 use mongodb::bson::Document;
 use mongodb::ClientSession;
+use std::collections::HashMap;
 
+#[allow(async_fn_in_trait)]
 pub trait Boot {
     type Req;
     async fn finish(
@@ -17,4 +23,127 @@ pub trait Boot {
     fn cast(&self, data: Document,_req: &Option<Self::Req>,)->Document{
         data
     }
-}
\ No newline at end of file
+
+    /// Adjusts column attributes when a model is created
+    ///
+    /// Use it for attributes the derive macro can't express yet, e.g.
+    /// `columns.get_mut("expires_at").unwrap().expires_at = true`.
+    fn configure_columns(&self, _columns: &mut HashMap<&str, ColumnAttr>) {}
+
+    /// Sets the default options of the model
+    fn configure(&self, _options: &mut ModelOptions) {}
+
+    /// Role of the request, used for the fields set with `options.visible_to`
+    fn role(&self, _req: &Option<Self::Req>) -> Option<String> {
+        None
+    }
+
+    /// Whether the request also reads drafts of publishable models, e.g. for editors
+    ///
+    /// See [`crate::publish`].
+    fn preview(&self, _req: &Option<Self::Req>) -> bool {
+        false
+    }
+
+    /// Called when a read had to fix a stored document, return `true` to write the fix back
+    ///
+    /// See [`crate::repair`].
+    fn repair(&self, _repair: &Repair) -> bool {
+        false
+    }
+}
+
+/// Object-safe counterpart of [`Boot`]
+///
+/// Every `Boot` implementation is also a `BoxedBoot`, so hooks of different
+/// types can be stored together as `Box<dyn BoxedBoot<Req = R>>`.
+pub trait BoxedBoot {
+    type Req;
+    fn finish<'a>(
+        &'a self,
+        req: &'a Option<Self::Req>,
+        typ: &'a str,
+        old: Document,
+        new: Document,
+        session: Option<&'a mut ClientSession>,
+    ) -> LocalBoxFuture<'a, ()>;
+
+    fn cast(&self, data: Document, req: &Option<Self::Req>) -> Document;
+}
+
+impl<T: Boot> BoxedBoot for T {
+    type Req = T::Req;
+
+    fn finish<'a>(
+        &'a self,
+        req: &'a Option<Self::Req>,
+        typ: &'a str,
+        old: Document,
+        new: Document,
+        session: Option<&'a mut ClientSession>,
+    ) -> LocalBoxFuture<'a, ()> {
+        Box::pin(Boot::finish(self, req, typ, old, new, session))
+    }
+
+    fn cast(&self, data: Document, req: &Option<Self::Req>) -> Document {
+        Boot::cast(self, data, req)
+    }
+}
+
+/// Ordered registry of boxed hooks
+///
+/// `finish` runs every hook in registration order and `cast` pipes the
+/// document through each hook in turn.
+pub struct Hooks<R> {
+    hooks: Vec<Box<dyn BoxedBoot<Req = R>>>,
+}
+
+impl<R> Default for Hooks<R> {
+    fn default() -> Self {
+        Self { hooks: vec![] }
+    }
+}
+
+impl<R> Hooks<R> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a hook to the end of the registry
+    pub fn register(&mut self, hook: Box<dyn BoxedBoot<Req = R>>) -> &mut Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+}
+
+impl<R> Boot for Hooks<R> {
+    type Req = R;
+
+    async fn finish(
+        &self,
+        req: &Option<Self::Req>,
+        typ: &str,
+        old: Document,
+        new: Document,
+        mut session: Option<&mut ClientSession>,
+    ) {
+        for hook in &self.hooks {
+            hook.finish(req, typ, old.clone(), new.clone(), session.as_deref_mut())
+                .await;
+        }
+    }
+
+    fn cast(&self, data: Document, req: &Option<Self::Req>) -> Document {
+        self.hooks
+            .iter()
+            .fold(data, |data, hook| hook.cast(data, req))
+    }
+}
@@ -0,0 +1,39 @@
+//! MongoDB Extended JSON v2 lines
+//!
+//! Used by `Model::export_jsonl` and `Model::import_jsonl`, one document per
+//! line like `mongoexport` and `mongoimport`, so dumps can be exchanged with
+//! the database tools and other drivers.
+
+use mongodb::bson::{Bson, Document};
+use mongodb::error::{Error, Result};
+
+/// Extended JSON v2 mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExtJsonMode {
+    /// Keeps every BSON type, e.g. `{"$numberInt": "1"}`, like `mongoexport --jsonFormat=canonical`
+    Canonical,
+    /// Plain JSON numbers and ISO dates where lossless, the `mongoexport` default
+    #[default]
+    Relaxed,
+}
+
+/// Writes `doc` as one line of extended JSON, without the line break
+pub fn to_line(doc: Document, mode: ExtJsonMode) -> String {
+    let value = match mode {
+        ExtJsonMode::Canonical => Bson::Document(doc).into_canonical_extjson(),
+        ExtJsonMode::Relaxed => Bson::Document(doc).into_relaxed_extjson(),
+    };
+    value.to_string()
+}
+
+/// Reads a document from one line of extended JSON, in either mode
+pub fn from_line(line: &str) -> Result<Document> {
+    let invalid = |message: String| {
+        Error::from(std::io::Error::new(std::io::ErrorKind::InvalidData, message))
+    };
+    let value: serde_json::Value = serde_json::from_str(line).map_err(|e| invalid(e.to_string()))?;
+    match Bson::try_from(value).map_err(|e| invalid(e.to_string()))? {
+        Bson::Document(doc) => Ok(doc),
+        _ => Err(invalid("line is not a document.".to_string())),
+    }
+}
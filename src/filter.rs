@@ -0,0 +1,185 @@
+//! Typed filter DSL
+//!
+//! Field descriptors carry the Rust type of the column so comparisons against a
+//! value of the wrong type, or against a field that does not exist, fail to compile.
+//! Declare the descriptors next to the model with [`model_fields!`](crate::model_fields):
+//!
+//! ```ignore
+//! mongodb_ro::model_fields!(UserFields for User {
+//!     name: String,
+//!     age: u8,
+//! });
+//!
+//! let adults = User::new_model(&db)
+//!     .filter(User::fields().age.gt(18)?.and(User::fields().name.ne("root".to_string())?))
+//!     .get()
+//!     .await?;
+//! ```
+//!
+//! Comparisons fail when the value doesn't serialize to BSON, e.g. a `u64` above `i64::MAX`.
+
+use mongodb::bson::{doc, to_bson, Bson, Document};
+use mongodb::error::Result;
+use serde::Serialize;
+use std::marker::PhantomData;
+
+/// A filter expression built from typed fields
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    doc: Document,
+}
+
+impl Filter {
+    /// Wraps a raw filter document
+    pub fn raw(doc: Document) -> Filter {
+        Filter { doc }
+    }
+
+    /// Both filters must match
+    pub fn and(self, other: Filter) -> Filter {
+        Filter {
+            doc: doc! {"$and": [self.doc, other.doc]},
+        }
+    }
+
+    /// At least one of the filters must match
+    pub fn or(self, other: Filter) -> Filter {
+        Filter {
+            doc: doc! {"$or": [self.doc, other.doc]},
+        }
+    }
+
+    /// None of the filters may match
+    pub fn nor(self, other: Filter) -> Filter {
+        Filter {
+            doc: doc! {"$nor": [self.doc, other.doc]},
+        }
+    }
+
+    pub fn into_document(self) -> Document {
+        self.doc
+    }
+
+    pub fn as_document(&self) -> &Document {
+        &self.doc
+    }
+}
+
+impl From<Filter> for Document {
+    fn from(value: Filter) -> Self {
+        value.doc
+    }
+}
+
+/// Typed descriptor of a model field
+#[derive(Debug)]
+pub struct Field<T> {
+    name: &'static str,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Field<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Field<T> {}
+
+impl<T: Serialize> Field<T> {
+    pub const fn new(name: &'static str) -> Field<T> {
+        Field {
+            name,
+            _type: PhantomData,
+        }
+    }
+
+    /// Rust name of the field (renames are applied by the model)
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn op(&self, op: &str, value: Bson) -> Filter {
+        Filter {
+            doc: doc! {self.name: {op: value}},
+        }
+    }
+
+    pub fn eq(&self, value: T) -> Result<Filter> {
+        Ok(Filter {
+            doc: doc! {self.name: to_bson(&value)?},
+        })
+    }
+
+    pub fn ne(&self, value: T) -> Result<Filter> {
+        Ok(self.op("$ne", to_bson(&value)?))
+    }
+
+    pub fn gt(&self, value: T) -> Result<Filter> {
+        Ok(self.op("$gt", to_bson(&value)?))
+    }
+
+    pub fn gte(&self, value: T) -> Result<Filter> {
+        Ok(self.op("$gte", to_bson(&value)?))
+    }
+
+    pub fn lt(&self, value: T) -> Result<Filter> {
+        Ok(self.op("$lt", to_bson(&value)?))
+    }
+
+    pub fn lte(&self, value: T) -> Result<Filter> {
+        Ok(self.op("$lte", to_bson(&value)?))
+    }
+
+    pub fn is_in(&self, values: impl IntoIterator<Item = T>) -> Result<Filter> {
+        let values = values.into_iter().map(|v| to_bson(&v)).collect::<std::result::Result<_, _>>()?;
+        Ok(self.op("$in", Bson::Array(values)))
+    }
+
+    pub fn not_in(&self, values: impl IntoIterator<Item = T>) -> Result<Filter> {
+        let values = values.into_iter().map(|v| to_bson(&v)).collect::<std::result::Result<_, _>>()?;
+        Ok(self.op("$nin", Bson::Array(values)))
+    }
+
+    pub fn exists(&self, exists: bool) -> Filter {
+        self.op("$exists", Bson::Boolean(exists))
+    }
+}
+
+impl Field<String> {
+    /// Matches a regular expression with the given options (`i`, `m`, `x`, `s`)
+    pub fn regex(&self, pattern: &str, options: &str) -> Filter {
+        Filter {
+            doc: doc! {self.name: {"$regex": pattern, "$options": options}},
+        }
+    }
+}
+
+/// Declares typed field descriptors for a model
+///
+/// Generates `$fields` with one [`Field`] per listed column and a
+/// `$model::fields()` constructor. Each entry is checked against the model
+/// struct, so a typo or a wrong type is a compile error.
+#[macro_export]
+macro_rules! model_fields {
+    ($fields:ident for $model:ty { $($name:ident : $ty:ty),* $(,)? }) => {
+        #[allow(dead_code)]
+        #[derive(Debug, Clone, Copy)]
+        pub struct $fields {
+            $(pub $name: $crate::filter::Field<$ty>,)*
+        }
+
+        #[allow(dead_code)]
+        impl $model {
+            pub fn fields() -> $fields {
+                $fields {
+                    $($name: $crate::filter::Field::new(stringify!($name)),)*
+                }
+            }
+        }
+
+        const _: fn(&$model) = |model| {
+            $(let _: &$ty = &model.$name;)*
+        };
+    };
+}
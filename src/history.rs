@@ -0,0 +1,176 @@
+//! Versioned history of documents, for time-travel reads
+//!
+//! A [`Revisions`] recorder stores every change event of a collection as a
+//! revision, so writes of other services and `all()` writes are covered too.
+//! `Model::as_of` then rebuilds the documents as they were at a given time.
+//!
+//! ```ignore
+//! impl Boot for Order {
+//!     type Req = ();
+//!
+//!     fn configure(&self, options: &mut ModelOptions) {
+//!         options.versioned("order_revisions");
+//!     }
+//! }
+//!
+//! // background task
+//! let revisions = Order::new_model(&db).revisions()?;
+//! let mut events = Order::new_model(&db).watch([]).full_document(FullDocumentType::UpdateLookup);
+//! while let Some(event) = events.next().await {
+//!     revisions.record(event?).await?;
+//! }
+//!
+//! // "state as of March 1"
+//! let orders = Order::new_model(&db)
+//!     .r#where(doc! {"customer": id})
+//!     .as_of(DateTime::parse_rfc3339_str("2024-03-01T00:00:00Z")?)
+//!     .await?;
+//! ```
+//!
+//! # Notes
+//! - Documents unchanged since recording started have no revision and aren't found
+//! - `UpdateLookup` reads the document after the event, use `FullDocumentType::Required`
+//!   with pre- and post-images enabled on the collection for exact revisions
+
+use mongodb::bson::{doc, to_bson, Bson, DateTime, Document};
+use mongodb::change_stream::event::{ChangeStreamEvent, OperationType};
+use mongodb::error::{Error, Result};
+use mongodb::{Collection, Database, IndexModel};
+
+/// Recorder of the revisions of a collection
+#[derive(Debug, Clone)]
+pub struct Revisions {
+    collection: Collection<Document>,
+}
+
+impl Revisions {
+    /// # Arguments
+    /// * `name` - Collection of the revisions
+    pub fn new(db: &Database, name: &str) -> Revisions {
+        Revisions {
+            collection: db.collection(name),
+        }
+    }
+
+    /// Creates the index of `as_of` reads
+    pub async fn ensure_index(&self) -> Result<()> {
+        let index = IndexModel::builder().keys(doc! {"doc_id": 1, "ts": -1}).build();
+        self.collection.create_index(index).await?;
+        Ok(())
+    }
+
+    /// Revision of the document `doc_id` as it was at `at`, e.g. to replay what followed
+    /// with [`Replay::from_version`](crate::replay::Replay::from_version)
+    pub async fn version(&self, doc_id: impl Into<Bson>, at: DateTime) -> Result<Option<Document>> {
+        self.collection
+            .find_one(doc! {"doc_id": doc_id.into(), "at": {"$lte": at}})
+            .sort(doc! {"ts": -1, "at": -1})
+            .await
+    }
+
+    /// Stores the revision of a change event
+    ///
+    /// # Notes
+    /// - Returns whether the event changed a document, other events aren't stored
+    /// - Recording the same event again is a no-op, so replays are safe
+    /// - Updates need the full document, see the [module docs](self)
+    pub async fn record(&self, event: ChangeStreamEvent<Document>) -> Result<bool> {
+        let op = match event.operation_type {
+            OperationType::Insert => "insert",
+            OperationType::Update => "update",
+            OperationType::Replace => "replace",
+            OperationType::Delete => "delete",
+            _ => return Ok(false),
+        };
+        let Some(id) = event.document_key.as_ref().and_then(|k| k.get("_id")) else {
+            return Ok(false);
+        };
+        let document = match (op, event.full_document) {
+            ("delete", _) => None,
+            (_, Some(document)) => Some(document),
+            (_, None) => {
+                return Err(Error::from(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "revisions need the full document of change events.",
+                )));
+            }
+        };
+        let at = match (event.wall_time, event.cluster_time) {
+            (Some(at), _) => at,
+            (None, Some(ts)) => DateTime::from_millis(ts.time as i64 * 1000),
+            (None, None) => DateTime::now(),
+        };
+        let revision = doc! {
+            "doc_id": id.clone(),
+            "op": op,
+            "at": at,
+            "ts": event.cluster_time,
+            "doc": document,
+        };
+        self.collection
+            .update_one(doc! {"_id": to_bson(&event.id)?}, doc! {"$setOnInsert": revision})
+            .upsert(true)
+            .await?;
+        Ok(true)
+    }
+}
+
+/// Pipeline on the revisions rebuilding the documents matching `filter` as they were at `at`
+///
+/// The leading `$match` keeps the documents with a revision matching `filter`, their
+/// latest revision is then looked up in `collection` and matched again, since only
+/// that one tells whether the document matched at `at`.
+pub(crate) fn as_of_pipeline(collection: &str, at: DateTime, filter: Document) -> Vec<Document> {
+    let Some(revision_filter) = on_revisions(&filter).filter(|f| !f.is_empty()) else {
+        let mut pipeline = vec![
+            doc! {"$match": {"at": {"$lte": at}}},
+            doc! {"$sort": {"doc_id": 1, "ts": -1, "at": -1}},
+            doc! {"$group": {"_id": "$doc_id", "op": {"$first": "$op"}, "doc": {"$first": "$doc"}}},
+            doc! {"$match": {"op": {"$ne": "delete"}}},
+            doc! {"$replaceRoot": {"newRoot": "$doc"}},
+        ];
+        if !filter.is_empty() {
+            pipeline.push(doc! {"$match": filter});
+        }
+        return pipeline;
+    };
+    vec![
+        doc! {"$match": {"$and": [{"at": {"$lte": at}}, revision_filter]}},
+        doc! {"$group": {"_id": "$doc_id"}},
+        doc! {"$lookup": {
+            "from": collection,
+            "let": {"id": "$_id"},
+            "pipeline": [
+                {"$match": {"$expr": {"$eq": ["$doc_id", "$$id"]}, "at": {"$lte": at}}},
+                {"$sort": {"ts": -1, "at": -1}},
+                {"$limit": 1},
+            ],
+            "as": "latest",
+        }},
+        doc! {"$unwind": "$latest"},
+        doc! {"$match": {"latest.op": {"$ne": "delete"}}},
+        doc! {"$replaceRoot": {"newRoot": "$latest.doc"}},
+        doc! {"$match": filter},
+    ]
+}
+
+/// `filter` on the `doc` field of the revisions, `None` when it has operators like `$expr`
+fn on_revisions(filter: &Document) -> Option<Document> {
+    filter
+        .iter()
+        .map(|(key, value)| match (key.as_str(), value) {
+            ("$and" | "$or" | "$nor", Bson::Array(items)) => {
+                let items = items
+                    .iter()
+                    .map(|item| match item {
+                        Bson::Document(d) => on_revisions(d).map(Bson::Document),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                Some((key.clone(), Bson::Array(items)))
+            }
+            _ if key.starts_with('$') => None,
+            _ => Some((format!("doc.{key}"), value.clone())),
+        })
+        .collect()
+}
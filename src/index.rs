@@ -0,0 +1,218 @@
+//! Declared vs live index comparison
+
+use mongodb::bson::{Bson, Document};
+use mongodb::IndexModel;
+use std::fmt;
+
+/// Comparable description of an index
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexSpec {
+    pub name: String,
+    pub keys: Document,
+    pub unique: bool,
+    pub expire_after_secs: Option<u64>,
+    pub text: bool,
+    /// Partial filter expression, with database field names
+    pub partial: Option<Document>,
+}
+
+impl IndexSpec {
+    pub fn from_model(index: &IndexModel) -> IndexSpec {
+        let options = index.options.as_ref();
+        let text = index
+            .keys
+            .iter()
+            .any(|(k, v)| k == "_fts" || v.as_str() == Some("text"));
+        let name = match options.and_then(|o| o.name.clone()) {
+            Some(name) => name,
+            None => default_name(&index.keys),
+        };
+        IndexSpec {
+            name,
+            keys: index.keys.clone(),
+            unique: options.and_then(|o| o.unique).unwrap_or(false),
+            expire_after_secs: options
+                .and_then(|o| o.expire_after)
+                .map(|d| d.as_secs()),
+            text,
+            partial: options.and_then(|o| o.partial_filter_expression.clone()),
+        }
+    }
+}
+
+impl fmt::Display for IndexSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.keys)?;
+        if self.unique {
+            write!(f, " unique")?;
+        }
+        if let Some(secs) = self.expire_after_secs {
+            write!(f, " expireAfterSeconds={secs}")?;
+        }
+        if let Some(partial) = &self.partial {
+            write!(f, " partial={partial}")?;
+        }
+        Ok(())
+    }
+}
+
+/// What `Model::add_unique_index` does with documents sharing the new key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Fails without changing anything
+    #[default]
+    Abort,
+    /// Keeps the document with the smallest `_id` of each group, deleting the others
+    KeepOldest,
+    /// Keeps the document with the largest `_id` of each group, deleting the others
+    KeepNewest,
+}
+
+/// Step reached by `Model::add_unique_index`
+#[derive(Debug, Clone, PartialEq)]
+pub enum UniqueIndexStep {
+    /// Looking for documents sharing the new key
+    Scanning,
+    /// Deleting the duplicates of `groups` groups of documents
+    Merging { groups: u64 },
+    /// Building the index, hidden from the query planner
+    Building,
+    /// Checking the built index
+    Verifying,
+    /// Making the index visible to the query planner
+    Unhiding,
+    Done,
+}
+
+/// Outcome of `Model::add_unique_index`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UniqueIndexReport {
+    pub name: String,
+    /// Groups of documents that shared the new key
+    pub duplicate_groups: u64,
+    /// Documents deleted to merge the duplicates
+    pub removed: u64,
+}
+
+/// Name the server gives an index created without an explicit name
+pub fn default_name(keys: &Document) -> String {
+    keys.iter()
+        .map(|(k, v)| {
+            let v = match v {
+                Bson::Int32(i) => i.to_string(),
+                Bson::Int64(i) => i.to_string(),
+                Bson::Double(d) => (*d as i64).to_string(),
+                Bson::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            format!("{k}_{v}")
+        })
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Differences between declared and live indexes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexDrift {
+    pub collection: String,
+    /// Declared but not present in the collection
+    pub missing: Vec<IndexSpec>,
+    /// Present in the collection but not declared
+    pub unexpected: Vec<IndexSpec>,
+    /// Present under the same name with different keys or options: (declared, live)
+    pub mismatched: Vec<(IndexSpec, IndexSpec)>,
+}
+
+impl IndexDrift {
+    pub fn compare(collection: &str, declared: Vec<IndexSpec>, live: Vec<IndexSpec>) -> IndexDrift {
+        let mut drift = IndexDrift {
+            collection: collection.to_string(),
+            ..Default::default()
+        };
+        let mut live = live
+            .into_iter()
+            .filter(|i| i.name != "_id_")
+            .collect::<Vec<_>>();
+        for expected in declared {
+            match live.iter().position(|i| i.name == expected.name) {
+                None => drift.missing.push(expected),
+                Some(pos) => {
+                    let found = live.remove(pos);
+                    let same_keys = expected.text || keys_eq(&expected.keys, &found.keys);
+                    if !same_keys
+                        || expected.unique != found.unique
+                        || expected.expire_after_secs != found.expire_after_secs
+                        || expected.partial != found.partial
+                    {
+                        drift.mismatched.push((expected, found));
+                    }
+                }
+            }
+        }
+        drift.unexpected = live;
+        drift
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+fn keys_eq(a: &Document, b: &Document) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b.iter()).all(|((ka, va), (kb, vb))| {
+            ka == kb
+                && match (va.as_str(), vb.as_str()) {
+                    (Some(x), Some(y)) => x == y,
+                    (None, None) => as_i64(va) == as_i64(vb),
+                    _ => false,
+                }
+        })
+}
+
+fn as_i64(v: &Bson) -> Option<i64> {
+    match v {
+        Bson::Int32(i) => Some(*i as i64),
+        Bson::Int64(i) => Some(*i),
+        Bson::Double(d) => Some(*d as i64),
+        _ => None,
+    }
+}
+
+impl fmt::Display for IndexDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "indexes of `{}` match the model", self.collection);
+        }
+        writeln!(f, "index drift on `{}`:", self.collection)?;
+        for index in &self.missing {
+            writeln!(f, "  - missing    {index}")?;
+        }
+        for index in &self.unexpected {
+            writeln!(f, "  + unexpected {index}")?;
+        }
+        for (expected, found) in &self.mismatched {
+            writeln!(f, "  ~ expected   {expected}")?;
+            writeln!(f, "    found      {found}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Asserts that the live indexes of a model's collection match its declaration
+///
+/// ```ignore
+/// mongodb_ro::assert_indexes!(User, &db);
+/// ```
+#[macro_export]
+macro_rules! assert_indexes {
+    ($model:ty, $db:expr) => {{
+        let drift = <$model>::new_model($db)
+            .index_drift()
+            .await
+            .expect("failed to list indexes");
+        if !drift.is_empty() {
+            panic!("{}", drift);
+        }
+    }};
+}
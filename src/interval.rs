@@ -0,0 +1,106 @@
+//! Time intervals stored as millis, with overlap filters
+//!
+//! An [`Interval`] field is stored as `{start, end}` in milliseconds since the
+//! epoch, and is half-open: `end` itself isn't part of it, so back to back
+//! bookings don't overlap. Filters on interval fields are built from its
+//! [`Field`](crate::filter::Field) descriptor:
+//!
+//! ```ignore
+//! mongodb_ro::model_fields!(BookingFields for Booking { slot: Interval });
+//!
+//! let conflicts = Booking::new_model(&db)
+//!     .filter(Booking::fields().slot.overlaps(from, to))
+//!     .get()
+//!     .await?;
+//! ```
+
+use crate::filter::{Field, Filter};
+use mongodb::bson::{doc, DateTime};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Half-open time interval `[start, end)`, in milliseconds since the epoch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Interval {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Interval {
+    pub fn new(start: DateTime, end: DateTime) -> Interval {
+        Interval {
+            start: start.timestamp_millis(),
+            end: end.timestamp_millis(),
+        }
+    }
+
+    /// Interval of `duration` starting at `start`
+    pub fn starting_at(start: DateTime, duration: Duration) -> Interval {
+        let start = start.timestamp_millis();
+        Interval {
+            start,
+            end: start.saturating_add(duration.as_millis().min(i64::MAX as u128) as i64),
+        }
+    }
+
+    pub fn start(&self) -> DateTime {
+        DateTime::from_millis(self.start)
+    }
+
+    pub fn end(&self) -> DateTime {
+        DateTime::from_millis(self.end)
+    }
+
+    /// Length of the interval, zero when `end` isn't after `start`
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.end.saturating_sub(self.start).max(0) as u64)
+    }
+
+    /// Whether the interval holds no instant
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
+    pub fn contains(&self, at: DateTime) -> bool {
+        let at = at.timestamp_millis();
+        self.start <= at && at < self.end
+    }
+
+    /// Whether both intervals share at least one instant
+    pub fn overlaps(&self, other: &Interval) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+impl Field<Interval> {
+    /// Intervals sharing at least one instant with `[from, to)`
+    ///
+    /// # Notes
+    /// - Touching intervals, one ending when the other starts, don't overlap
+    pub fn overlaps(&self, from: DateTime, to: DateTime) -> Filter {
+        let name = self.name();
+        Filter::raw(doc! {
+            format!("{name}.start"): {"$lt": to.timestamp_millis()},
+            format!("{name}.end"): {"$gt": from.timestamp_millis()},
+        })
+    }
+
+    /// Intervals holding the instant `at`
+    pub fn contains(&self, at: DateTime) -> Filter {
+        let name = self.name();
+        let at = at.timestamp_millis();
+        Filter::raw(doc! {
+            format!("{name}.start"): {"$lte": at},
+            format!("{name}.end"): {"$gt": at},
+        })
+    }
+
+    /// Intervals entirely inside `[from, to)`
+    pub fn within(&self, from: DateTime, to: DateTime) -> Filter {
+        let name = self.name();
+        Filter::raw(doc! {
+            format!("{name}.start"): {"$gte": from.timestamp_millis()},
+            format!("{name}.end"): {"$lte": to.timestamp_millis()},
+        })
+    }
+}
@@ -0,0 +1,201 @@
+//! Reading back the JSON written by `Model::to_json`
+//!
+//! `to_json` turns ObjectIds and dates into plain strings or numbers, see
+//! [`format_json_ids`](crate::public_id::format_json_ids) and
+//! [`format_json_dates`](crate::date::format_json_dates). [`from_json`] undoes
+//! that for the values deserialized as BSON, i.e. `ObjectId`, `DateTime`, `Bson`
+//! and `Document` fields, while `String` and integer fields keep the value as is.
+
+use crate::date::DateTimeJson;
+use crate::public_id::ObjectIdJson;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{DeserializeOwned, Deserializer, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use serde_json::{json, Error, Value};
+
+/// Deserializes `value`, reading ids and dates in the given formats back as BSON
+pub fn from_json<T: DeserializeOwned>(
+    value: Value,
+    ids: ObjectIdJson,
+    dates: DateTimeJson,
+) -> Result<T, Error> {
+    T::deserialize(JsonIn { value, ids, dates })
+}
+
+struct JsonIn {
+    value: Value,
+    ids: ObjectIdJson,
+    dates: DateTimeJson,
+}
+
+impl JsonIn {
+    /// Extended JSON of a formatted id or date, `None` for other values
+    fn extended(&self) -> Option<Value> {
+        let millis = match (&self.value, self.dates) {
+            (Value::String(s), DateTimeJson::Rfc3339) => {
+                DateTime::parse_rfc3339_str(s).ok().map(|at| at.timestamp_millis())
+            }
+            (Value::Number(n), DateTimeJson::Millis) => n.as_i64(),
+            _ => None,
+        };
+        if let Some(millis) = millis {
+            return Some(json!({"$date": {"$numberLong": millis.to_string()}}));
+        }
+        match &self.value {
+            Value::String(s) if self.ids == ObjectIdJson::Hex && ObjectId::parse_str(s).is_ok() => {
+                Some(json!({"$oid": s}))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for JsonIn {
+    type Deserializer = JsonIn;
+
+    fn into_deserializer(self) -> JsonIn {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for JsonIn {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if let Some(extended) = self.extended() {
+            return extended.deserialize_any(visitor);
+        }
+        let (ids, dates) = (self.ids, self.dates);
+        let child = move |value| JsonIn { value, ids, dates };
+        match self.value {
+            Value::Array(items) => {
+                let mut seq = SeqDeserializer::new(items.into_iter().map(child));
+                let r = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(r)
+            }
+            // extended JSON values are read as they are
+            Value::Object(object) if !object.keys().any(|k| k.starts_with('$')) => {
+                let mut map = MapDeserializer::new(object.into_iter().map(move |(k, v)| (k, child(v))));
+                let r = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(r)
+            }
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_bool(visitor)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_i8(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_i16(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_i32(visitor)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_i64(visitor)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_u8(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_u16(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_u32(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_u64(visitor)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_f32(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_f64(visitor)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_char(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_str(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_string(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_unit(visitor)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.value.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.value.deserialize_identifier(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 seq tuple tuple_struct map struct
+    }
+}
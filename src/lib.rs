@@ -7,11 +7,60 @@
 //! - Transactions
 //! - Aggregation
 //!
+//! # Features
+//! - `rt-tokio` (default): helpers that need the tokio runtime directly, such as
+//!   timeouts, throttled batching and background tasks. Disabling it only drops
+//!   this crate's own tokio dependency and those helpers; the MongoDB driver still
+//!   requires tokio, so the crate isn't runtime-agnostic and doesn't run on wasm.
+//! - `blocking`: synchronous wrappers for code that isn't async, see [`blocking`].
+//!
 
 pub mod model;
-mod column;
+pub mod column;
 pub mod event;
-mod query_builder;
+pub mod backend;
+pub mod filter;
+pub mod interval;
+pub mod stream;
+pub mod index;
+pub mod page;
+pub mod bulk;
+pub mod builder;
+pub mod compress;
+pub mod stats;
+pub mod report;
+pub mod date;
+pub mod copy;
+pub mod two_phase;
+pub mod preflight;
+pub mod server;
+pub mod watcher;
+pub mod public_id;
+pub mod consistency;
+pub mod ext_json;
+pub mod json;
+pub mod recorder;
+pub mod publish;
+pub mod repair;
+pub mod repository;
+pub mod schema;
+pub mod history;
+pub mod cache;
+#[cfg(feature = "rt-tokio")]
+pub mod session;
+#[cfg(feature = "rt-tokio")]
+pub mod change_stream;
+#[cfg(feature = "rt-tokio")]
+pub mod replay;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod error;
+pub mod options;
+pub mod query_builder;
+pub mod query_spec;
 
 pub use mongodb_ro_derive::*;
+/// The driver the crate is built on, used by the macros so callers don't need the same version
+pub use mongodb;
+pub use preflight::preflight;
 
@@ -1,24 +1,213 @@
+use crate::bulk::{BulkOutcome, BulkWriter};
+use crate::backend::{Backend, FindSpec, UpdateSummary};
 use crate::column::ColumnAttr;
+use crate::cache;
+#[cfg(feature = "rt-tokio")]
+use crate::change_stream::{ModelEvent, ResilientStream};
+#[cfg(feature = "rt-tokio")]
+use mongodb::change_stream::event::{ChangeStreamEvent, OperationType};
+use crate::compress;
+use crate::public_id::{self, format_json_ids};
+use crate::copy::{CopyOptions, CopyReport};
+use crate::ext_json::{self, ExtJsonMode};
+use crate::json;
 use crate::event::Boot;
-use crate::query_builder::QueryBuilder;
-use futures_util::StreamExt;
+use crate::error::{GuardError, IndexMigrationError, InvariantViolated};
+use crate::filter::Filter;
+use crate::index::{
+    default_name, DuplicatePolicy, IndexDrift, IndexSpec, UniqueIndexReport, UniqueIndexStep,
+};
+use crate::options::{DecodeErrorPolicy, Guards, ModelOptions};
+use crate::preflight::PreflightTarget;
+use crate::publish;
+use crate::repair::Repair;
+use crate::schema::{self, FieldSchema};
+use crate::history::{self, Revisions};
+use crate::server::{ServerInfo, ServerInfoCache};
+use crate::page::{decode_cursor, encode_cursor, KeysetPage, Page};
+use crate::date::{format_json_dates, period, DatePart};
+use crate::stats::FieldStats;
+use crate::query_spec::QuerySpec;
+use crate::query_builder::{
+    and_condition, apply, push_or, ArrayElement, FindQuery, Order, QueryBuilder, UpdateQuery, Verbosity,
+    WhereGroup,
+};
+use futures_util::{Stream, StreamExt};
 use log::error;
-use mongodb::action::{EstimatedDocumentCount, Find};
+use mongodb::action::{Action, Aggregate, Find};
 use mongodb::bson::{doc, to_document, Document};
 use mongodb::bson::{Bson, DateTime};
 use mongodb::error::{Error, Result};
-use mongodb::options::{CountOptions, IndexOptions};
+use mongodb::bson::oid::ObjectId;
+use mongodb::options::{
+    AggregateOptions, CollectionOptions, CountOptions, CursorType, DeleteOptions,
+    FindOneAndDeleteOptions, FindOneAndUpdateOptions, FindOptions, Hint, IndexOptions,
+    ReadConcern, ReturnDocument, UpdateOptions, WriteConcern,
+};
 use mongodb::results::{InsertManyResult, InsertOneResult};
-use mongodb::{bson, ClientSession, Collection, Cursor, Database, IndexModel, SessionCursor};
+use mongodb::{bson, ClientSession, Collection, Database, IndexModel};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
-use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
+use std::future::IntoFuture;
+use std::time::Instant;
+use std::ops::{Deref, DerefMut};
 
 pub type MongodbResult<T> = Result<T>;
 
+/// Collection of the lock documents of `Model::ensure_invariant_with_session`
+pub const INVARIANT_LOCKS: &str = "invariant_locks";
+
+/// Returns `$call` run with the current [`SessionPool`](crate::session::SessionPool) session, if any
+macro_rules! in_current_session {
+    ($session:ident => $call:expr) => {
+        #[cfg(feature = "rt-tokio")]
+        if let Some(shared) = crate::session::current() {
+            let mut guard = shared.lock().await;
+            let $session: &mut ClientSession = &mut guard;
+            return crate::session::detached($call).await;
+        }
+    };
+}
+
+fn undecodable() -> Error {
+    Error::from(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "document skipped by the decode error policy.",
+    ))
+}
+
+/// Direction of a sort value, `None` for anything but 1 and -1, e.g. a `$meta` expression
+fn sort_direction(value: &Bson) -> Option<i32> {
+    match value {
+        Bson::Int32(n) if n.abs() == 1 => Some(*n),
+        Bson::Int64(n) if n.abs() == 1 => Some(*n as i32),
+        Bson::Double(n) if *n == 1.0 || *n == -1.0 => Some(*n as i32),
+        _ => None,
+    }
+}
+
+/// Whether a sort value is a `$meta` expression such as the text score
+fn is_meta(value: &Bson) -> bool {
+    value.as_document().is_some_and(|d| d.contains_key("$meta"))
+}
+
+/// Decompresses the values of `fields` written by [`compress::compress`]
+fn decompress_fields(mut data: Document, fields: &[String]) -> Document {
+    for key in fields {
+        if let Some(value) = data.get(key).filter(|v| compress::is_compressed(v)) {
+            match compress::decompress(value.clone()) {
+                Ok(value) => {
+                    data.insert(key, value);
+                }
+                Err(e) => error!("Can't decompress {key} : {e}"),
+            }
+        }
+    }
+    data
+}
+
+fn negate(n: Bson) -> Result<Bson> {
+    let overflow = || {
+        Error::from(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "decrement amount overflows when negated.",
+        ))
+    };
+    match n {
+        Bson::Int32(n) => n.checked_neg().map(Bson::Int32).ok_or_else(overflow),
+        Bson::Int64(n) => n.checked_neg().map(Bson::Int64).ok_or_else(overflow),
+        Bson::Double(n) => Ok(Bson::Double(-n)),
+        Bson::Decimal128(n) => {
+            let mut bytes = n.bytes();
+            bytes[15] ^= 0x80;
+            Ok(Bson::Decimal128(bson::Decimal128::from_bytes(bytes)))
+        }
+        _ => Err(Error::from(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "increment amount must be numeric.",
+        ))),
+    }
+}
+
+/// Ids accepted by the `*_by_id` helpers, an `ObjectId` or its hex string
+pub trait IntoObjectId {
+    fn into_object_id(self) -> Result<ObjectId>;
+}
+
+impl IntoObjectId for ObjectId {
+    fn into_object_id(self) -> Result<ObjectId> {
+        Ok(self)
+    }
+}
+
+impl IntoObjectId for &ObjectId {
+    fn into_object_id(self) -> Result<ObjectId> {
+        Ok(*self)
+    }
+}
+
+impl IntoObjectId for &str {
+    fn into_object_id(self) -> Result<ObjectId> {
+        ObjectId::parse_str(self).map_err(|_| {
+            Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid id.",
+            ))
+        })
+    }
+}
+
+impl IntoObjectId for &String {
+    fn into_object_id(self) -> Result<ObjectId> {
+        self.as_str().into_object_id()
+    }
+}
+
+/// Progress reported by [`Model::rebuild_indexes`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexRebuildProgress {
+    /// Name of the rebuilt index, the field name for single field indexes
+    pub field: String,
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Documents updated by [`Model::backfill_defaults`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BackfillReport {
+    /// Updated documents per field
+    pub fields: Vec<(String, u64)>,
+}
+
+impl BackfillReport {
+    pub fn total(&self) -> u64 {
+        self.fields.iter().map(|(_, n)| n).sum()
+    }
+}
+
+/// Output of one prefix of a pipeline, see [`Model::debug_stages`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageSample {
+    /// Position of the last stage run, starting at 1
+    pub stage: usize,
+    /// Operator of the last stage run, e.g. `$match`
+    pub operator: String,
+    /// Number of documents out of the stage
+    pub count: u64,
+    /// First documents out of the stage
+    pub sample: Vec<Document>,
+}
+
+/// A document with the documents joined by [`Model::get_with`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Joined<M, O> {
+    pub item: M,
+    pub joined: Vec<O>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Model<'a, M>
 where
@@ -37,6 +226,10 @@ where
     columns: HashMap<&'a str, ColumnAttr>,
     #[serde(skip)]
     query_builder: QueryBuilder,
+    #[serde(skip)]
+    options: ModelOptions,
+    #[serde(skip)]
+    server_info: Option<Arc<ServerInfoCache>>,
 }
 
 impl<'a, T: 'a + Boot> Deref for Model<'a, T> {
@@ -71,7 +264,7 @@ where
     ) -> Model<'a, M> {
         let columns = serde_json::from_str(columns).unwrap();
 
-        let model = Model {
+        let mut model = Model {
             inner: Box::<M>::default(),
             req: None,
             db: db.clone(),
@@ -79,8 +272,11 @@ where
             columns,
             add_times,
             query_builder: Default::default(),
+            options: Default::default(),
+            server_info: None,
         };
-
+        model.inner.configure_columns(&mut model.columns);
+        model.inner.configure(&mut model.options);
         model
     }
 
@@ -96,13 +292,8 @@ where
             self.columns.insert(
                 name,
                 ColumnAttr {
-                    asc: false,
-                    desc: false,
-                    unique: false,
-                    sphere2d: false,
-                    text: None,
-                    hidden: false,
                     name: Some(name.to_string()),
+                    ..Default::default()
                 },
             );
         }
@@ -113,9 +304,151 @@ where
         self.collection_name
     }
 
-    /// Gets a handle to the MongoDB collection
+    /// Gets a handle to the MongoDB collection, with the model's read and write concerns
     pub fn collection(&self) -> Collection<M> {
-        self.db.collection::<M>(self.collection_name)
+        self.coll::<M>()
+    }
+
+    fn coll<T: Send + Sync>(&self) -> Collection<T> {
+        let options = CollectionOptions::builder()
+            .read_concern(self.options.read_concern.clone())
+            .write_concern(self.options.write_concern.clone())
+            .build();
+        self.db.collection_with_options(self.collection_name, options)
+    }
+    /// Watches the collection, reconnecting and resuming after interruptions
+    ///
+    /// # Arguments
+    /// * `pipeline` - Stages applied to the change events, with database field names
+    ///
+    /// # Notes
+    /// - Change streams need a replica set or a sharded cluster
+    #[cfg(feature = "rt-tokio")]
+    pub fn watch(&self, pipeline: impl IntoIterator<Item = Document>) -> ResilientStream {
+        ResilientStream::new(&self.db, self.collection_name, pipeline.into_iter().collect())
+    }
+    /// Watches the collection like `watch`, yielding typed model events
+    ///
+    /// # Arguments
+    /// * `pipeline` - Stages applied to the change events, with database field names
+    ///
+    /// # Notes
+    /// - Full documents go through renames, casts and hidden fields like `get()`
+    /// - Documents that don't decode are handled by the decode error policy, skipped events aren't yielded
+    #[cfg(feature = "rt-tokio")]
+    pub fn watch_typed(
+        &self,
+        pipeline: impl IntoIterator<Item = Document>,
+    ) -> impl Stream<Item = Result<ModelEvent<M>>> + '_ {
+        let hidden_fields = self.hidden_fields();
+        self.watch(pipeline).into_stream().filter_map(move |event| {
+            let event = event.and_then(|event| self.model_event(event, &hidden_fields));
+            futures::future::ready(event.transpose())
+        })
+    }
+
+    #[cfg(feature = "rt-tokio")]
+    fn model_event(
+        &self,
+        event: ChangeStreamEvent<Document>,
+        hidden_fields: &[String],
+    ) -> Result<Option<ModelEvent<M>>> {
+        let id = event
+            .document_key
+            .as_ref()
+            .and_then(|k| k.get("_id").cloned())
+            .unwrap_or(Bson::Null);
+        let full = event.full_document.unwrap_or_default();
+        Ok(match event.operation_type {
+            OperationType::Insert => self.decode(full, hidden_fields)?.map(ModelEvent::Insert),
+            OperationType::Replace => self.decode(full, hidden_fields)?.map(ModelEvent::Replace),
+            OperationType::Delete => Some(ModelEvent::Delete { id }),
+            OperationType::Update => {
+                let (updated, removed) = event
+                    .update_description
+                    .map(|d| (d.updated_fields, d.removed_fields))
+                    .unwrap_or_default();
+                let mut changes = Document::new();
+                for (path, value) in self.hydrate(updated) {
+                    if let Some(path) = self.model_path(&path, hidden_fields) {
+                        changes.insert(path, value);
+                    }
+                }
+                let removed = removed
+                    .iter()
+                    .filter_map(|path| self.model_path(path, hidden_fields))
+                    .collect();
+                Some(ModelEvent::Update { id, changes, removed })
+            }
+            other => Some(ModelEvent::Other(other)),
+        })
+    }
+
+    /// Model name of a database field path, `None` for hidden fields
+    #[cfg(feature = "rt-tokio")]
+    fn model_path(&self, path: &str, hidden_fields: &[String]) -> Option<String> {
+        let (top, rest) = match path.split_once('.') {
+            Some((top, rest)) => (top, Some(rest)),
+            None => (path, None),
+        };
+        let name = self
+            .columns
+            .keys()
+            .find(|name| self.db_name(name) == top)
+            .map_or(top, |name| *name);
+        if hidden_fields.iter().any(|h| h == name) {
+            return None;
+        }
+        Some(match rest {
+            Some(rest) => format!("{name}.{rest}"),
+            None => name.to_string(),
+        })
+    }
+
+    /// Replays past change events of the collection, e.g. to rebuild derived data
+    ///
+    /// # Arguments
+    /// * `pipeline` - Stages applied to the change events, with database field names
+    ///
+    /// # Notes
+    /// - Set where it starts with `from_token`, `from_version`, `from_time` or `checkpoint`
+    #[cfg(feature = "rt-tokio")]
+    pub fn replay(&self, pipeline: impl IntoIterator<Item = Document>) -> crate::replay::Replay {
+        crate::replay::Replay::new(&self.db, self.collection_name, pipeline.into_iter().collect())
+    }
+    /// Wraps the model in a synchronous facade
+    #[cfg(feature = "blocking")]
+    pub fn blocking(self) -> crate::blocking::Blocking<'a, M> {
+        crate::blocking::Blocking::new(self)
+    }
+
+    /// Detects the capabilities of the server
+    ///
+    /// # Notes
+    /// - Reuses the info of the cache set with `server_info_cache`
+    /// - Runs `hello` and `buildInfo` on every call without one
+    pub async fn server_info(&self) -> Result<ServerInfo> {
+        match &self.server_info {
+            Some(cache) => cache.get(&self.db).await,
+            None => ServerInfo::detect(&self.db).await,
+        }
+    }
+    /// Sets the [`ServerInfoCache`] of the model's client, e.g. [`SessionPool::server_info_cache`](crate::session::SessionPool::server_info_cache)
+    pub fn server_info_cache(mut self, cache: Arc<ServerInfoCache>) -> Model<'a, M> {
+        self.server_info = Some(cache);
+        self
+    }
+    /// Gets the database of the model
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+    /// Gets the request set with `set_request`
+    pub fn request(&self) -> &Option<M::Req> {
+        &self.req
+    }
+    /// Starts a bulk write of typed operations on the collection
+    pub fn bulk(&self) -> BulkWriter<'_, 'a, M> {
+        BulkWriter::new(self)
     }
     /// Changes the collection name for this model
     pub fn set_collection(mut self, name: &'a str) -> Model<'a, M> {
@@ -130,25 +463,45 @@ where
     /// 2. Remove indexes for fields that no longer exist in the model
     /// 3. Create new indexes for fields marked as indexes in column attributes
     pub async fn register_indexes(&self) {
-        let coll = self.db.collection::<M>(self.collection_name);
+        if let Err(error) = self.provision_capped().await {
+            error!("Can't create capped collection : {:?}", error);
+        }
+        let coll = self.coll::<M>();
         let previous_indexes = coll.list_indexes().await;
         let mut attrs = vec![];
         for (name, attr) in &self.columns {
             if attr.is_index() {
-                attrs.push(name)
+                attrs.push(*name)
             }
         }
+        attrs.extend(self.options.index_exprs.iter().map(|e| e.name.as_str()));
+        let mut uniques = self.unique_index_models();
 
         let mut keys_to_remove = Vec::new();
-        if previous_indexes.is_ok() {
-            let foreach_future = previous_indexes.unwrap().for_each(|pr| {
+        if let Ok(previous_indexes) = previous_indexes {
+            let foreach_future = previous_indexes.for_each(|pr| {
                 match pr {
                     Ok(index_model) => {
+                        if let Some(pos) = uniques.iter().position(|u| u.keys == index_model.keys) {
+                            // means a declared unique index exists, keep it
+                            uniques.remove(pos);
+                            return futures::future::ready(());
+                        }
                         index_model.keys.iter().for_each(|key| {
                             if key.0 != "_id" {
-                                if let Some(pos) = attrs.iter().position(|k| k == &key.0) {
-                                    // means attribute exists in struct and database and not need to create it
-                                    attrs.remove(pos);
+                                if let Some(pos) = attrs.iter().position(|k| *k == key.0) {
+                                    let live = index_model
+                                        .options
+                                        .as_ref()
+                                        .and_then(|o| o.partial_filter_expression.as_ref());
+                                    if live != self.partial_filter(key.0).as_ref() {
+                                        // means the partial filter changed, recreate the index
+                                        let name = index_model.options.as_ref().and_then(|o| o.name.clone());
+                                        keys_to_remove.push(name.or_else(|| Some(default_name(&index_model.keys))));
+                                    } else {
+                                        // means attribute exists in struct and database and not need to create it
+                                        attrs.remove(pos);
+                                    }
                                 } else if let Some(rw) = &index_model.options {
                                     // means the attribute must remove because not exists in struct
                                     match rw.default_language {
@@ -157,7 +510,7 @@ where
                                             None => keys_to_remove.push(rw.name.clone()),
                                             Some(name) => {
                                                 if let Some(pos) =
-                                                    attrs.iter().position(|k| k == &name)
+                                                    attrs.iter().position(|k| *k == name)
                                                 {
                                                     attrs.remove(pos);
                                                 } else {
@@ -181,40 +534,8 @@ where
 
         let attrs = attrs
             .iter()
-            .map(|name| {
-                let key = name.to_string();
-                let attr = &self.columns.get(key.as_str()).unwrap();
-
-                if let Some(lang) = &attr.text {
-                    let opts = IndexOptions::builder()
-                        .unique(attr.unique)
-                        .name(key.clone())
-                        .default_language(lang.to_string())
-                        .build();
-                    IndexModel::builder()
-                        .keys(doc! {
-                            key : "text"
-                        })
-                        .options(opts)
-                        .build()
-                } else if attr.sphere2d {
-                    let opts = IndexOptions::builder().unique(attr.unique).build();
-                    IndexModel::builder()
-                        .keys(doc! { key: "2dsphere" })
-                        .options(opts)
-                        .build()
-                } else {
-                    let sort = if attr.desc { -1 } else { 1 };
-                    let opts = IndexOptions::builder().unique(attr.unique).build();
-
-                    IndexModel::builder()
-                        .keys(doc! {
-                            key : sort
-                        })
-                        .options(opts)
-                        .build()
-                }
-            })
+            .map(|name| self.index_model(name))
+            .chain(uniques)
             .collect::<Vec<IndexModel>>();
 
         for name in keys_to_remove {
@@ -229,126 +550,1398 @@ where
         }
     }
 
-    /// Reset all filters
-    pub fn reset(mut self) -> Model<'a, M> {
-        self.query_builder = Default::default();
-        self
+    /// Indexes declared with `ModelOptions::unique_index`
+    fn unique_index_models(&self) -> Vec<IndexModel> {
+        self.options
+            .unique_indexes
+            .iter()
+            .map(|fields| self.unique_index_model(fields))
+            .collect()
     }
-    /// Adds a filter condition to the query
-    pub fn r#where(mut self, data: Document) -> Model<'a, M> {
-        self.query_builder.r#where.push(data);
-        self
+
+    fn unique_index_model(&self, fields: &[String]) -> IndexModel {
+        let keys = fields
+            .iter()
+            .map(|f| (self.db_name(f), Bson::Int32(1)))
+            .collect::<Document>();
+        let options = IndexOptions::builder()
+            .name(default_name(&keys))
+            .unique(true)
+            .build();
+        IndexModel::builder().keys(keys).options(options).build()
     }
-    /// Sets the number of documents to skip
-    pub fn skip(mut self, count: u32) -> Model<'a, M> {
-        self.query_builder.skip = count;
-        self
+
+    fn index_model(&self, name: &str) -> IndexModel {
+        let mut index = self.column_index_model(name);
+        if let Some(partial) = self.partial_filter(name) {
+            let options = index.options.get_or_insert_with(IndexOptions::default);
+            options.partial_filter_expression = Some(partial);
+        }
+        index
     }
-    /// Gets distinct values for a field
-    pub async fn distinct(&self, name: &str) -> Result<Vec<Bson>> {
-        let whr = &self.query_builder.r#where;
-        let filter = if whr.is_empty() {
-            doc! {}
-        } else {
-            doc! {"$and":whr}
-        };
-        let collection = self.db.collection::<Document>(self.collection_name);
-        collection.distinct(&name, filter).await
+
+    /// Partial filter expression of the index of `name`, with database field names
+    fn partial_filter(&self, name: &str) -> Option<Document> {
+        let partial = self.columns.get(name)?.partial.clone()?;
+        Some(self.rename_filter(partial))
     }
-    /// Sets the maximum number of documents to return
-    pub fn limit(mut self, count: u32) -> Model<'a, M> {
-        self.query_builder.limit = count;
-        self
+
+    fn column_index_model(&self, name: &str) -> IndexModel {
+        let key = name.to_string();
+        if self.options.index_exprs.iter().any(|e| e.name == key) {
+            let opts = IndexOptions::builder().name(key.clone()).build();
+            return IndexModel::builder()
+                .keys(doc! { key: 1 })
+                .options(opts)
+                .build();
+        }
+        let attr = &self.columns.get(key.as_str()).unwrap();
+
+        if let Some(lang) = &attr.text {
+            let opts = IndexOptions::builder()
+                .unique(attr.unique)
+                .name(key.clone())
+                .default_language(lang.to_string())
+                .build();
+            IndexModel::builder()
+                .keys(doc! {
+                    key : "text"
+                })
+                .options(opts)
+                .build()
+        } else if attr.expires_at {
+            let opts = IndexOptions::builder()
+                .unique(attr.unique)
+                .expire_after(std::time::Duration::ZERO)
+                .build();
+            IndexModel::builder()
+                .keys(doc! { key: 1 })
+                .options(opts)
+                .build()
+        } else if attr.sphere2d {
+            let opts = IndexOptions::builder().unique(attr.unique).build();
+            IndexModel::builder()
+                .keys(doc! { key: "2dsphere" })
+                .options(opts)
+                .build()
+        } else {
+            let sort = if attr.desc { -1 } else { 1 };
+            let opts = IndexOptions::builder().unique(attr.unique).build();
+
+            IndexModel::builder()
+                .keys(doc! {
+                    key : sort
+                })
+                .options(opts)
+                .build()
+        }
     }
-    /// The number of documents the server should return per cursor batch.
-    pub fn batch_size(mut self, value: u32) -> Model<'a, M> {
-        self.query_builder.batch_size = value;
-        self
+
+    /// Fields of the model with their column attributes and descriptions, sorted by name
+    pub fn schema(&self) -> Vec<FieldSchema> {
+        let defaults = to_document(&M::default()).unwrap_or_default();
+        let mut fields = self
+            .columns
+            .iter()
+            .map(|(name, attr)| {
+                let (kind, format) = defaults.get(*name).map(schema::kind_of).unwrap_or_default();
+                FieldSchema {
+                    name: name.to_string(),
+                    db_name: self.db_name(name),
+                    doc: attr.doc.clone(),
+                    kind,
+                    format,
+                    hidden: attr.hidden,
+                    unique: attr.unique,
+                    indexed: attr.is_index(),
+                    heavy: attr.heavy,
+                }
+            })
+            .collect::<Vec<_>>();
+        fields.sort_by(|a, b| a.name.cmp(&b.name));
+        fields
     }
-    /// Sets the sort order
-    pub fn sort(mut self, data: Document) -> Model<'a, M> {
-        self.query_builder.sort = data;
-        self
+
+    /// `$jsonSchema` of the stored documents, derived from the default values of the model
+    ///
+    /// # Notes
+    /// - Fields are required unless their default is `None` or left out by serde
+    /// - Fields with `enum_values` only accept those, compressed fields any type
+    /// - Other fields stay allowed
+    pub fn json_schema(&self) -> Document {
+        let defaults = to_document(&M::default()).unwrap_or_default();
+        let mut columns = self.columns.iter().collect::<Vec<_>>();
+        columns.sort_by_key(|(name, _)| **name);
+        let mut properties = Document::new();
+        let mut required = vec![];
+        for (name, attr) in columns {
+            let key = self.db_name(name);
+            let default = defaults.get(*name);
+            let mut property = Document::new();
+            if let Some(values) = &attr.enum_values {
+                property.insert("enum", values.clone());
+            } else if let Some(kind) = default
+                .filter(|_| attr.compress.is_none())
+                .and_then(schema::bson_type_of)
+            {
+                property.insert("bsonType", kind);
+            }
+            if let Some(doc) = &attr.doc {
+                property.insert("description", doc.clone());
+            }
+            if default.is_some_and(|v| *v != Bson::Null) && key != "_id" {
+                required.push(key.clone());
+            }
+            properties.insert(key, property);
+        }
+        if self.add_times {
+            for field in ["created_at", "updated_at"] {
+                properties.insert(field, doc! {"bsonType": "date"});
+            }
+        }
+        let mut schema = doc! {"bsonType": "object", "properties": properties};
+        if !required.is_empty() {
+            schema.insert("required", required);
+        }
+        schema
     }
-    /// Sets whether to affect all matching documents (for update/delete)
-    pub fn all(mut self) -> Model<'a, M> {
-        self.query_builder.all = true;
-        self
+
+    /// Creates the collection with the [`Model::json_schema`] validator, or updates the validator
+    ///
+    /// Makes the server reject bad writes, including those of other services.
+    ///
+    /// # Notes
+    /// - Returns whether the collection was created, as capped when declared so
+    /// - Level and action come from `ModelOptions::validation_level` and `validation_action`
+    /// - Documents already stored aren't checked
+    pub async fn provision_collection(&self) -> Result<bool> {
+        if self.options.view.is_some() {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is a view, views have no validator.", self.collection_name),
+            )));
+        }
+        let validator = doc! {"$jsonSchema": self.json_schema()};
+        let existing = self
+            .db
+            .list_collection_names()
+            .filter(doc! {"name": self.collection_name})
+            .await?;
+        if existing.is_empty() {
+            let capped = self.options.capped;
+            self.db
+                .create_collection(self.collection_name)
+                .validator(validator)
+                .optional(self.options.validation_level.clone(), |a, l| a.validation_level(l))
+                .optional(self.options.validation_action.clone(), |a, v| a.validation_action(v))
+                .optional(capped.map(|c| c.size), |a, size| a.capped(true).size(size))
+                .optional(capped.and_then(|c| c.max), |a, max| a.max(max))
+                .await?;
+            return Ok(true);
+        }
+        let mut command = doc! {"collMod": self.collection_name, "validator": validator};
+        if let Some(level) = &self.options.validation_level {
+            command.insert("validationLevel", mongodb::bson::to_bson(level)?);
+        }
+        if let Some(action) = &self.options.validation_action {
+            command.insert("validationAction", mongodb::bson::to_bson(action)?);
+        }
+        self.db.run_command(command).await?;
+        Ok(false)
     }
-    /// Sets the projection (field selection)
-    pub fn select(mut self, data: Document) -> Model<'a, M> {
-        self.query_builder.select = Some(data);
-        self
+
+    /// OpenAPI schema object of the model's JSON output, with the field descriptions
+    pub fn openapi_schema(&self) -> serde_json::Value {
+        schema::openapi(&self.schema())
     }
-    /// Sets which fields should be visible (overrides hidden fields)
-    pub fn visible(mut self, data: Vec<&str>) -> Model<'a, M> {
-        self.query_builder.visible_fields = data.iter().map(|a| a.to_string()).collect();
-        self
+
+    /// Creates a read-only view `name` of this model's collection
+    ///
+    /// # Arguments
+    /// * `pipeline` - Stages applied to the documents, with database field names
+    pub async fn create_view(
+        &self,
+        name: &str,
+        pipeline: impl IntoIterator<Item = Document>,
+    ) -> Result<()> {
+        self.db
+            .create_collection(name)
+            .view_on(self.collection_name.to_string())
+            .pipeline(pipeline.into_iter().collect::<Vec<_>>())
+            .await
     }
-    /// Sets whether to upsert on update
-    pub fn upsert(mut self) -> Model<'a, M> {
-        self.query_builder.upsert = true;
-        self
+
+    /// Creates the view declared with [`ModelOptions::view_of`] unless it exists
+    ///
+    /// # Notes
+    /// - Returns whether the view was created
+    /// - Writes through a view-backed model are rejected by the server
+    pub async fn provision_view(&self) -> Result<bool> {
+        let Some(view) = &self.options.view else {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not declared as a view.", self.collection_name),
+            )));
+        };
+        let existing = self
+            .db
+            .list_collection_names()
+            .filter(doc! {"name": self.collection_name})
+            .await?;
+        if !existing.is_empty() {
+            return Ok(false);
+        }
+        self.db
+            .create_collection(self.collection_name)
+            .view_on(view.view_of.clone())
+            .pipeline(view.pipeline.clone())
+            .await?;
+        Ok(true)
     }
 
-    fn hidden_fields(&self) -> Vec<String> {
-        let mut r = vec![];
-        for (name, attr) in &self.columns {
-            if attr.hidden
-                && !self
-                    .query_builder
-                    .visible_fields
-                    .contains(&name.to_string())
-            {
-                r.push(name.to_string())
-            }
+    /// Creates the model's collection as declared in its options and registers its indexes
+    ///
+    /// # Notes
+    /// - Returns whether the collection was created
+    /// - Views come from [`ModelOptions::view_of`], capped collections from
+    ///   [`ModelOptions::capped`], other collections are left to the first write
+    pub async fn provision(&self) -> Result<bool> {
+        if self.options.view.is_some() {
+            return self.provision_view().await;
         }
-        r
+        let created = self.provision_capped().await?;
+        self.register_indexes().await;
+        Ok(created)
     }
-    fn clear(&self, data: Document, hidden_fields: &Vec<String>) -> M {
-        let data = data;
-        let mut default = to_document(&M::default()).unwrap();
-        for (name, attr) in &self.columns {
-            if hidden_fields.contains(&name.to_string()) {
-                continue;
-            }
-            let rename = match attr.name.clone() {
-                None => name.to_string(),
-                Some(a) => a,
-            };
-            if data.contains_key(&rename) {
-                default.insert(name.to_string(), data.get(&rename).unwrap());
+
+    /// Creates the capped collection declared with [`ModelOptions::capped`] unless it exists
+    async fn provision_capped(&self) -> Result<bool> {
+        let Some(capped) = self.options.capped else {
+            return Ok(false);
+        };
+        let existing = self
+            .db
+            .list_collections()
+            .filter(doc! {"name": self.collection_name})
+            .await?
+            .collect::<Vec<_>>()
+            .await;
+        if let Some(spec) = existing.into_iter().next() {
+            if spec?.options.capped != Some(true) {
+                log::warn!("{} exists and is not capped", self.collection_name);
             }
+            return Ok(false);
         }
+        self.db
+            .create_collection(self.collection_name)
+            .capped(true)
+            .size(capped.size)
+            .optional(capped.max, |a, max| a.max(max))
+            .await?;
+        Ok(true)
+    }
 
-        bson::from_document(default).unwrap()
+    /// Names of the views reading from this model's collection
+    pub async fn views(&self) -> Result<Vec<String>> {
+        let mut names = self
+            .db
+            .list_collection_names()
+            .filter(doc! {"type": "view", "options.viewOn": self.collection_name})
+            .await?;
+        names.sort();
+        Ok(names)
     }
-}
 
-impl<'a, M> Model<'a, M>
-where
-    M: Boot,
-    M: Default,
-    M: Serialize,
-{
-    /// this method takes the inner and gives you ownership of inner then
-    /// replace it with default value
-    pub fn take_inner(&mut self) -> M {
-        std::mem::take(&mut *self.inner)
+    /// Drops the view `name`, refusing to drop anything that isn't a view
+    pub async fn drop_view(&self, name: &str) -> Result<()> {
+        let views = self
+            .db
+            .list_collection_names()
+            .filter(doc! {"type": "view", "name": name})
+            .await?;
+        if views.is_empty() {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{name} is not a view."),
+            )));
+        }
+        self.db.collection::<Document>(name).drop().await
     }
 
-    pub fn inner_ref(&self) -> &M {
-        self.inner.as_ref()
+    /// Indexes declared by the model's column attributes and options, as `register_indexes` creates them
+    pub fn declared_indexes(&self) -> Vec<IndexSpec> {
+        self.declared_index_models()
+            .iter()
+            .map(IndexSpec::from_model)
+            .collect()
     }
 
-    pub fn inner_mut(&mut self) -> &mut M {
-        self.inner.as_mut()
+    /// Column, `index_expr` and `unique_index` indexes of the model
+    fn declared_index_models(&self) -> Vec<IndexModel> {
+        let mut names = self
+            .columns
+            .iter()
+            .filter(|(_, attr)| attr.is_index())
+            .map(|(name, _)| *name)
+            .collect::<Vec<&str>>();
+        names.sort();
+        names.extend(self.options.index_exprs.iter().map(|e| e.name.as_str()));
+        names
+            .into_iter()
+            .map(|name| self.index_model(name))
+            .chain(self.unique_index_models())
+            .collect()
     }
 
-    pub fn inner_to_doc(&self) -> MongodbResult<Document> {
-        let mut re = to_document(&self.inner)?;
-        self.rename_field(&mut re, false);
-        Ok(re)
+    /// Compares the declared indexes with the ones present in the collection
+    pub async fn index_drift(&self) -> Result<IndexDrift> {
+        let coll = self.coll::<Document>();
+        let mut live = vec![];
+        let mut cursor = coll.list_indexes().await?;
+        while let Some(index) = cursor.next().await {
+            live.push(IndexSpec::from_model(&index?));
+        }
+        Ok(IndexDrift::compare(
+            self.collection_name,
+            self.declared_indexes(),
+            live,
+        ))
+    }
+
+    /// Drops and recreates every declared index one at a time
+    ///
+    /// # Arguments
+    /// * `throttle` - Pause between two rebuilt indexes
+    /// * `progress` - Called after each index has been rebuilt
+    ///
+    /// # Notes
+    /// - Useful after index corruption or when options changed and need a rebuild
+    /// - Stops at the first failing index
+    /// - Rebuilds the same indexes as `register_indexes`: column, `index_expr` and `unique_index` ones
+    /// - Only drops the live index with the keys or the name of each declared one,
+    ///   undeclared indexes are left as they are
+    #[cfg(feature = "rt-tokio")]
+    pub async fn rebuild_indexes(
+        &self,
+        throttle: std::time::Duration,
+        mut progress: impl FnMut(&IndexRebuildProgress),
+    ) -> Result<()> {
+        const INDEX_NOT_FOUND: i32 = 27;
+        let coll = self.coll::<Document>();
+        let models = self.declared_index_models();
+
+        let mut existing = vec![];
+        let mut cursor = coll.list_indexes().await?;
+        while let Some(index) = cursor.next().await {
+            existing.push(index?);
+        }
+
+        let total = models.len();
+        for (i, model) in models.into_iter().enumerate() {
+            let declared_name = model.options.as_ref().and_then(|o| o.name.clone());
+            let field = declared_name.clone().unwrap_or_else(|| default_name(&model.keys));
+            for index in &existing {
+                let Some(index_name) = index.options.as_ref().and_then(|o| o.name.clone()) else {
+                    continue;
+                };
+                let same = index.keys == model.keys || Some(&index_name) == declared_name.as_ref();
+                if !same || index_name == "_id_" {
+                    continue;
+                }
+                // already gone, e.g. dropped for an earlier field or concurrently
+                if let Err(e) = coll.drop_index(index_name).await
+                    && !matches!(e.kind.as_ref(), mongodb::error::ErrorKind::Command(c) if c.code == INDEX_NOT_FOUND)
+                {
+                    return Err(e);
+                }
+            }
+            coll.create_index(model).await?;
+            progress(&IndexRebuildProgress {
+                field,
+                done: i + 1,
+                total,
+            });
+            if i + 1 < total && !throttle.is_zero() {
+                tokio::time::sleep(throttle).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Introduces a unique index on a live collection
+    ///
+    /// Looks for documents sharing the new key and merges them according to
+    /// `policy`, builds the index hidden from the query planner, checks it,
+    /// then makes it visible.
+    ///
+    /// # Arguments
+    /// * `fields` - Indexed fields, with model names
+    /// * `progress` - Called when each step starts
+    ///
+    /// # Notes
+    /// - Hidden indexes need MongoDB 4.4 or later
+    /// - A duplicate written during the build makes it fail with a duplicate key error,
+    ///   the index isn't created and the call can be retried
+    /// - Declare the fields with [`ModelOptions::unique_index`] too, otherwise the next
+    ///   `register_indexes` drops the index
+    /// - Documents with a missing or null key field are never merged, the call fails with
+    ///   `IndexMigrationError::NullKeys` when several of them share the key
+    pub async fn add_unique_index(
+        &self,
+        fields: &[&str],
+        policy: DuplicatePolicy,
+        mut progress: impl FnMut(&UniqueIndexStep),
+    ) -> Result<UniqueIndexReport> {
+        let coll = self.coll::<Document>();
+        let mut keys = Document::new();
+        let mut group = Document::new();
+        let mut present = Document::new();
+        let mut names = vec![];
+        for (i, field) in fields.iter().enumerate() {
+            let name = self.db_name(field);
+            // dotted names can't be keys of a `$group` id
+            group.insert(format!("k{i}"), doc! {"$ifNull": [format!("${name}"), Bson::Null]});
+            present.insert(name.as_str(), doc! {"$ne": Bson::Null});
+            keys.insert(name.as_str(), 1);
+            names.push(name);
+        }
+        let name = default_name(&keys);
+        let keep = match policy {
+            DuplicatePolicy::KeepNewest => doc! {"$max": "$_id"},
+            _ => doc! {"$min": "$_id"},
+        };
+
+        progress(&UniqueIndexStep::Scanning);
+        // the index gives a missing key field the null key, such documents aren't merged
+        let pipeline = vec![
+            doc! {"$match": {"$nor": [present.clone()]}},
+            doc! {"$group": {"_id": group.clone(), "count": {"$sum": 1}}},
+            doc! {"$match": {"count": {"$gt": 1}}},
+            doc! {"$count": "groups"},
+        ];
+        let mut cursor = coll.aggregate(pipeline).allow_disk_use(true).await?;
+        if let Some(counted) = cursor.next().await {
+            let groups = match counted?.get("groups") {
+                Some(Bson::Int32(n)) => *n as u64,
+                Some(Bson::Int64(n)) => *n as u64,
+                _ => 0,
+            };
+            return Err(IndexMigrationError::NullKeys { index: name, groups }.into());
+        }
+
+        let pipeline = vec![
+            doc! {"$match": present},
+            doc! {"$group": {"_id": group, "keep": keep, "count": {"$sum": 1}}},
+            doc! {"$match": {"count": {"$gt": 1}}},
+        ];
+        let mut duplicates = vec![];
+        let mut cursor = coll.aggregate(pipeline).allow_disk_use(true).await?;
+        while let Some(group) = cursor.next().await {
+            let group = group?;
+            if let (Ok(key), Some(keep)) = (group.get_document("_id"), group.get("keep")) {
+                duplicates.push((key.clone(), keep.clone()));
+            }
+        }
+        let mut report = UniqueIndexReport {
+            name: name.clone(),
+            duplicate_groups: duplicates.len() as u64,
+            removed: 0,
+        };
+
+        if !duplicates.is_empty() {
+            if policy == DuplicatePolicy::Abort {
+                return Err(IndexMigrationError::Duplicates {
+                    index: name,
+                    groups: report.duplicate_groups,
+                }
+                .into());
+            }
+            progress(&UniqueIndexStep::Merging {
+                groups: report.duplicate_groups,
+            });
+            for (key, keep) in duplicates {
+                let same_key = names
+                    .iter()
+                    .zip(key.values())
+                    .map(|(name, value)| doc! {"$eq": [format!("${name}"), {"$literal": value}]})
+                    .collect::<Vec<_>>();
+                let filter = doc! {"_id": {"$ne": keep}, "$expr": {"$and": same_key}};
+                let r = coll.delete_many(filter).await?;
+                report.removed += r.deleted_count;
+            }
+        }
+
+        progress(&UniqueIndexStep::Building);
+        let options = IndexOptions::builder()
+            .name(name.clone())
+            .unique(true)
+            .hidden(true)
+            .build();
+        coll.create_index(IndexModel::builder().keys(keys).options(options).build())
+            .await?;
+
+        progress(&UniqueIndexStep::Verifying);
+        let mut built = None;
+        let mut cursor = coll.list_indexes().await?;
+        while let Some(index) = cursor.next().await {
+            let index = index?;
+            if index.options.as_ref().and_then(|o| o.name.as_deref()) == Some(name.as_str()) {
+                built = Some(index);
+            }
+        }
+        let unique = built
+            .and_then(|i| i.options)
+            .and_then(|o| o.unique)
+            .unwrap_or(false);
+        if !unique {
+            return Err(IndexMigrationError::VerificationFailed { index: name }.into());
+        }
+
+        progress(&UniqueIndexStep::Unhiding);
+        self.db
+            .run_command(doc! {
+                "collMod": self.collection_name,
+                "index": {"name": name.as_str(), "hidden": false},
+            })
+            .await?;
+        progress(&UniqueIndexStep::Done);
+        Ok(report)
+    }
+
+    /// Reset all filters
+    pub fn reset(mut self) -> Model<'a, M> {
+        self.query_builder = Default::default();
+        self
+    }
+    /// Adds a filter condition to the query
+    ///
+    /// # Notes
+    /// - Strings of conditions on `index_expr` fields are normalized, see [`ModelOptions::index_expr`]
+    pub fn r#where(mut self, data: Document) -> Model<'a, M> {
+        let data = self.route_index_exprs(data);
+        self.query_builder.r#where.push(data);
+        self
+    }
+    /// Starts a new alternative (`$or`) with the given condition
+    ///
+    /// `r#where(a).r#where(b).or_where(c).r#where(d)` matches `(a AND b) OR (c AND d)`
+    pub fn or_where(mut self, data: Document) -> Model<'a, M> {
+        let data = self.route_index_exprs(data);
+        let qb = &mut self.query_builder;
+        push_or(&mut qb.r#where, &mut qb.or_where, data);
+        self
+    }
+    /// Adds a nested group of conditions to the query
+    pub fn where_group(mut self, f: impl FnOnce(WhereGroup) -> WhereGroup) -> Model<'a, M> {
+        let group = self.route_index_exprs(f(WhereGroup::default()).into_document());
+        if !group.is_empty() {
+            self.query_builder.r#where.push(group);
+        }
+        self
+    }
+    /// Matches documents whose date `field` falls in the same period as `at`
+    ///
+    /// # Arguments
+    /// * `part` - Period granularity (day, month, ...)
+    /// * `utc_offset_minutes` - Time zone the period boundaries are computed in
+    pub fn where_date(
+        self,
+        field: &str,
+        part: DatePart,
+        at: DateTime,
+        utc_offset_minutes: i32,
+    ) -> Model<'a, M> {
+        let (start, end) = period(at, part, utc_offset_minutes);
+        self.between_dates(field, start, end)
+    }
+    /// Matches documents whose date `field` is in `[from, to)`
+    pub fn between_dates(mut self, field: &str, from: DateTime, to: DateTime) -> Model<'a, M> {
+        let key = self.db_name(field);
+        self.query_builder
+            .r#where
+            .push(doc! {key: {"$gte": from, "$lt": to}});
+        self
+    }
+    /// Adds a typed filter condition to the query
+    ///
+    /// # Notes
+    /// - Field names are renamed to their database names
+    pub fn filter(mut self, filter: Filter) -> Model<'a, M> {
+        let data = self.rename_filter(filter.into_document());
+        self.query_builder.r#where.push(data);
+        self
+    }
+    /// Adds the filter of the scope `name`, declared with [`ModelOptions::scope`]
+    ///
+    /// # Notes
+    /// - Fails when the model has no scope `name`, a typo shouldn't widen a query
+    pub fn scope(mut self, name: &str) -> Result<Model<'a, M>> {
+        let Some(filter) = self.options.scopes.get(name).cloned() else {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} has no scope {name}.", self.collection_name),
+            )));
+        };
+        let filter = self.rename_filter(filter);
+        self.query_builder.r#where.push(filter);
+        Ok(self)
+    }
+    /// Adds the conditions, sort and page of a validated [`QuerySpec`]
+    ///
+    /// # Notes
+    /// - Field names are renamed to their database names
+    /// - Sets skip/limit from the page, `paginate(spec.page, spec.per_page)` works too
+    pub fn apply_spec(mut self, spec: &QuerySpec) -> Model<'a, M> {
+        let filter = spec.filter();
+        if !filter.is_empty() {
+            let filter = self.rename_filter(filter);
+            self.query_builder.r#where.push(filter);
+        }
+        if !spec.sort.is_empty() {
+            self.query_builder.sort = self.rename_filter(spec.sort_document());
+        }
+        self.query_builder.skip = (spec.page.max(1) - 1).saturating_mul(spec.per_page);
+        self.query_builder.limit = spec.per_page;
+        self
+    }
+    /// Sets the number of documents to skip
+    pub fn skip(mut self, count: u32) -> Model<'a, M> {
+        self.query_builder.skip = count;
+        self
+    }
+    /// Gets distinct values for a field
+    ///
+    /// # Notes
+    /// - `name` is the model field name, renamed to its database name
+    pub async fn distinct(&self, name: &str) -> Result<Vec<Bson>> {
+        in_current_session!(s => self.distinct_with_session(name, s));
+        let filter = self.read_filter();
+        let collection = self.coll::<Document>();
+        collection
+            .distinct(self.db_name(name), filter)
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .await
+    }
+    /// Gets distinct values for a field with session
+    pub async fn distinct_with_session(&self, name: &str, session: &mut ClientSession) -> Result<Vec<Bson>> {
+        let filter = self.read_filter();
+        let collection = self.coll::<Document>();
+        collection
+            .distinct(self.db_name(name), filter)
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .session(session)
+            .await
+    }
+    /// Gets distinct values for a field, deserialized into `T`
+    ///
+    /// # Notes
+    /// - Fails when a value doesn't deserialize, e.g. a field holding mixed types
+    pub async fn distinct_as<T: DeserializeOwned>(&self, name: &str) -> Result<Vec<T>> {
+        let mut r = vec![];
+        for value in self.distinct(name).await? {
+            r.push(bson::from_bson(value)?);
+        }
+        Ok(r)
+    }
+    /// Gets distinct values for several fields in one `$facet` aggregation
+    ///
+    /// # Notes
+    /// - Keys of the map are the given field names, renames are applied to the query
+    /// - Array fields contribute their items, like `distinct`
+    pub async fn distinct_many(&self, fields: &[&str]) -> Result<HashMap<String, Vec<Bson>>> {
+        let mut r = HashMap::new();
+        if fields.is_empty() {
+            return Ok(r);
+        }
+        let mut facet = Document::new();
+        for (i, field) in fields.iter().enumerate() {
+            let path = format!("${}", self.db_name(field));
+            facet.insert(
+                format!("f{i}"),
+                vec![
+                    doc! {"$unwind": &path},
+                    doc! {"$group": {"_id": &path}},
+                ],
+            );
+        }
+        let pipeline = vec![
+            doc! {"$match": self.read_filter()},
+            doc! {"$facet": facet},
+        ];
+        let result = self
+            .aggregate_documents(&self.coll(), pipeline, self.aggregate_options())
+            .await?
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        for (i, field) in fields.iter().enumerate() {
+            let values = match result.get_array(format!("f{i}")) {
+                Ok(groups) => groups
+                    .iter()
+                    .filter_map(|g| g.as_document().and_then(|g| g.get("_id")).cloned())
+                    .collect(),
+                Err(_) => vec![],
+            };
+            r.insert(field.to_string(), values);
+        }
+        Ok(r)
+    }
+    /// Counts the matching documents per value of `field`, largest groups first
+    pub async fn group_count(&self, field: &str) -> Result<Vec<(Bson, i64)>> {
+        let groups = self.group_by(field, doc! {"$sum": 1}).await?;
+        Ok(groups
+            .into_iter()
+            .map(|(key, value)| {
+                let count = match value {
+                    Bson::Int32(n) => n as i64,
+                    Bson::Int64(n) => n,
+                    _ => 0,
+                };
+                (key, count)
+            })
+            .collect())
+    }
+    /// Sums `sum_field` of the matching documents per value of `group_field`, largest sums first
+    ///
+    /// # Notes
+    /// - Non numeric values of `sum_field` are ignored
+    pub async fn group_sum(&self, group_field: &str, sum_field: &str) -> Result<Vec<(Bson, f64)>> {
+        let path = format!("${}", self.db_name(sum_field));
+        let groups = self.group_by(group_field, doc! {"$sum": path}).await?;
+        Ok(groups
+            .into_iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Bson::Int32(n) => n as f64,
+                    Bson::Int64(n) => n as f64,
+                    Bson::Double(n) => n,
+                    Bson::Decimal128(n) => n.to_string().parse().unwrap_or(0.0),
+                    _ => 0.0,
+                };
+                (key, value)
+            })
+            .collect())
+    }
+    async fn group_by(&self, field: &str, accumulator: Document) -> Result<Vec<(Bson, Bson)>> {
+        let pipeline = vec![
+            doc! {"$match": self.read_filter()},
+            doc! {"$group": {"_id": format!("${}", self.db_name(field)), "value": accumulator}},
+            doc! {"$sort": {"value": -1, "_id": 1}},
+        ];
+        let docs = self
+            .aggregate_documents(&self.coll(), pipeline, self.aggregate_options())
+            .await?;
+        let mut r = vec![];
+        for mut d in docs {
+            let key = d.remove("_id").unwrap_or(Bson::Null);
+            r.push((key, d.remove("value").unwrap_or(Bson::Null)));
+        }
+        Ok(r)
+    }
+    /// Sets the maximum number of documents to return
+    pub fn limit(mut self, count: u32) -> Model<'a, M> {
+        self.query_builder.limit = count;
+        self
+    }
+    /// The number of documents the server should return per cursor batch.
+    ///
+    /// Applies to find, cursor, stream and aggregate operations.
+    pub fn batch_size(mut self, value: u32) -> Model<'a, M> {
+        self.query_builder.batch_size = value;
+        self
+    }
+    /// Sets the sort order
+    pub fn sort(mut self, data: Document) -> Model<'a, M> {
+        self.query_builder.sort = data;
+        self
+    }
+    /// Forces the index with the given keys
+    ///
+    /// # Notes
+    /// - Applies to find, count, update and delete operations
+    pub fn hint(mut self, keys: Document) -> Model<'a, M> {
+        self.query_builder.hint = Some(Hint::Keys(keys));
+        self
+    }
+    /// Forces the index with the given name, like `hint`
+    pub fn hint_name(mut self, name: &str) -> Model<'a, M> {
+        self.query_builder.hint = Some(Hint::Name(name.to_string()));
+        self
+    }
+    /// Lets an `all()` update or delete affect more documents than the `max_affected` guard allows
+    pub fn force(mut self) -> Model<'a, M> {
+        self.query_builder.force = true;
+        self
+    }
+    /// Reads `heavy` columns too, list queries leave them out by default
+    ///
+    /// # Notes
+    /// - `first`, `find_by_id` and the other single document reads always include them
+    /// - Models read without them hold default values, don't `save()` them back
+    pub fn with_heavy(mut self) -> Model<'a, M> {
+        self.query_builder.with_heavy = true;
+        self
+    }
+    /// Reads drafts of a publishable model too, see [`crate::publish`]
+    pub fn with_drafts(mut self) -> Model<'a, M> {
+        self.query_builder.with_drafts = true;
+        self
+    }
+    /// Restricts sorting to `fields`, e.g. the indexed ones a client may sort on
+    ///
+    /// # Notes
+    /// - Reads sorting on other fields fail with [`GuardError::SortNotAllowed`]
+    /// - Appends `_id` to the sort as a tiebreaker, so pages are stable
+    pub fn sortable_fields(mut self, fields: &[&str]) -> Model<'a, M> {
+        let fields = fields.iter().map(|f| self.db_name(f)).collect();
+        self.query_builder.sortable = Some(fields);
+        self
+    }
+    /// Sorts in natural (insertion on capped collections) order
+    pub fn natural_order(mut self, order: Order) -> Model<'a, M> {
+        self.query_builder.sort = doc! {"$natural": order.value()};
+        self
+    }
+
+    /// Adjusts the driver's find options, after the ones built from the query settings
+    ///
+    /// An escape hatch for options this crate doesn't wrap, also used by `build_find`.
+    pub fn with_find_options(
+        mut self,
+        f: impl Fn(&mut FindOptions) + Send + Sync + 'static,
+    ) -> Model<'a, M> {
+        self.query_builder.overrides.find.push(Arc::new(f));
+        self
+    }
+    /// Adjusts the driver's count options
+    pub fn with_count_options(
+        mut self,
+        f: impl Fn(&mut CountOptions) + Send + Sync + 'static,
+    ) -> Model<'a, M> {
+        self.query_builder.overrides.count.push(Arc::new(f));
+        self
+    }
+    /// Adjusts the driver's aggregate options
+    pub fn with_aggregate_options(
+        mut self,
+        f: impl Fn(&mut AggregateOptions) + Send + Sync + 'static,
+    ) -> Model<'a, M> {
+        self.query_builder.overrides.aggregate.push(Arc::new(f));
+        self
+    }
+    /// Adjusts the driver's update options of `all()` updates
+    pub fn with_update_options(
+        mut self,
+        f: impl Fn(&mut UpdateOptions) + Send + Sync + 'static,
+    ) -> Model<'a, M> {
+        self.query_builder.overrides.update.push(Arc::new(f));
+        self
+    }
+    /// Adjusts the driver's options of single document updates
+    ///
+    /// # Notes
+    /// - `upsert` and `return_document` forced by a method, e.g. `update_or_create`, still win
+    pub fn with_find_one_and_update_options(
+        mut self,
+        f: impl Fn(&mut FindOneAndUpdateOptions) + Send + Sync + 'static,
+    ) -> Model<'a, M> {
+        self.query_builder.overrides.find_one_and_update.push(Arc::new(f));
+        self
+    }
+    /// Adjusts the driver's delete options of `all()` deletes
+    pub fn with_delete_options(
+        mut self,
+        f: impl Fn(&mut DeleteOptions) + Send + Sync + 'static,
+    ) -> Model<'a, M> {
+        self.query_builder.overrides.delete.push(Arc::new(f));
+        self
+    }
+    /// Adjusts the driver's options of single document deletes
+    pub fn with_find_one_and_delete_options(
+        mut self,
+        f: impl Fn(&mut FindOneAndDeleteOptions) + Send + Sync + 'static,
+    ) -> Model<'a, M> {
+        self.query_builder.overrides.find_one_and_delete.push(Arc::new(f));
+        self
+    }
+    /// Overrides the decode error policy of the model for this query
+    pub fn on_decode_error(mut self, policy: DecodeErrorPolicy) -> Model<'a, M> {
+        self.options.on_decode_error = policy;
+        self
+    }
+    /// Overrides the read concern of the model for this query
+    pub fn read_concern(mut self, concern: ReadConcern) -> Model<'a, M> {
+        self.options.read_concern = Some(concern);
+        self
+    }
+    /// Overrides the write concern of the model for this query
+    ///
+    /// # Notes
+    /// - Writes in a transaction use the transaction's write concern instead
+    pub fn write_concern(mut self, concern: WriteConcern) -> Model<'a, M> {
+        self.options.write_concern = Some(concern);
+        self
+    }
+    /// Overrides the comment attached to the commands of this query, see [`ModelOptions::comment`]
+    ///
+    /// # Notes
+    /// - Applies to finds, counts, aggregations, inserts, updates and deletes
+    pub fn comment(mut self, comment: &str) -> Model<'a, M> {
+        self.options.comment = Some(comment.to_string());
+        self
+    }
+    /// Overrides the query guards of the model for this query
+    pub fn guards(mut self, guards: Guards) -> Model<'a, M> {
+        self.options.guards = guards;
+        self
+    }
+    /// Sets whether to affect all matching documents (for update/delete)
+    pub fn all(mut self) -> Model<'a, M> {
+        self.query_builder.all = true;
+        self
+    }
+    /// Filters documents whose `field` is within `max_meters` of a point, nearest first
+    ///
+    /// # Notes
+    /// - `field` must be a `sphere2d` column
+    /// - `$near` sorts the results itself, it can't be combined with `count_documents` or `$or`
+    pub fn near(self, field: &str, lng: f64, lat: f64, max_meters: f64) -> Result<Model<'a, M>> {
+        let point = doc! {"type": "Point", "coordinates": [lng, lat]};
+        self.geo_where(field, doc! {"$near": {"$geometry": point, "$maxDistance": max_meters}})
+    }
+    /// Filters documents whose `field` lies within a polygon of `(lng, lat)` points
+    ///
+    /// # Notes
+    /// - `field` must be a `sphere2d` column
+    /// - The ring is closed when the last point differs from the first
+    pub fn geo_within_polygon(self, field: &str, points: &[(f64, f64)]) -> Result<Model<'a, M>> {
+        let mut ring = points.iter().map(|(lng, lat)| vec![*lng, *lat]).collect::<Vec<_>>();
+        if let (Some(first), Some(last)) = (ring.first(), ring.last())
+            && first != last
+        {
+            ring.push(first.clone());
+        }
+        let polygon = doc! {"type": "Polygon", "coordinates": [ring]};
+        self.geo_where(field, doc! {"$geoWithin": {"$geometry": polygon}})
+    }
+    /// Filters documents whose `field` intersects a GeoJSON geometry
+    ///
+    /// # Arguments
+    /// * `geometry` - e.g. `doc! {"type": "LineString", "coordinates": [[0.0, 0.0], [1.0, 1.0]]}`
+    pub fn geo_intersects(self, field: &str, geometry: Document) -> Result<Model<'a, M>> {
+        self.geo_where(field, doc! {"$geoIntersects": {"$geometry": geometry}})
+    }
+    fn geo_where(mut self, field: &str, condition: Document) -> Result<Model<'a, M>> {
+        if !self.columns.get(field).is_some_and(|attr| attr.sphere2d) {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{field} is not a sphere2d column."),
+            )));
+        }
+        self.query_builder.r#where.push(doc! {self.db_name(field): condition});
+        Ok(self)
+    }
+    /// Filters with a `$text` search of `query`
+    ///
+    /// # Arguments
+    /// * `by_relevance` - Sorts by relevance, before any other sort, and adds the score as `text_score`
+    ///
+    /// # Notes
+    /// - Fails when no column has a `text` index
+    /// - The score can be read with `get_doc`, models ignore it
+    pub fn search_text(mut self, query: &str, by_relevance: bool) -> Result<Model<'a, M>> {
+        if !self.columns.values().any(|attr| attr.text.is_some()) {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "no text index column.",
+            )));
+        }
+        self.query_builder.r#where.push(doc! {"$text": {"$search": query}});
+        if by_relevance {
+            let score = doc! {"$meta": "textScore"};
+            self.query_builder
+                .select
+                .get_or_insert_with(Document::new)
+                .insert("text_score", score.clone());
+            let mut sort = doc! {"text_score": score};
+            sort.extend(std::mem::take(&mut self.query_builder.sort));
+            self.query_builder.sort = sort;
+        }
+        Ok(self)
+    }
+    /// Sets the projection (field selection)
+    pub fn select(mut self, data: Document) -> Model<'a, M> {
+        self.query_builder.select = Some(data);
+        self
+    }
+    /// Sets which fields should be visible (overrides hidden fields)
+    pub fn visible(mut self, data: Vec<&str>) -> Model<'a, M> {
+        self.query_builder.visible_fields = data.iter().map(|a| a.to_string()).collect();
+        self
+    }
+    /// Shows every hidden field, including role restricted ones, e.g. for internal jobs
+    pub fn visible_all(mut self) -> Model<'a, M> {
+        self.query_builder.visible_all = true;
+        self
+    }
+    /// Hides `fields` for this query, on top of the model's hidden fields
+    ///
+    /// # Notes
+    /// - Takes precedence over `visible` and `visible_all`
+    /// - Hidden fields keep their default values and aren't written by `save()`
+    pub fn hide(mut self, fields: &[&str]) -> Model<'a, M> {
+        self.query_builder.hide.extend(fields.iter().map(|f| f.to_string()));
+        self
+    }
+    /// Aborts long reads when `token` is cancelled
+    ///
+    /// # Notes
+    /// - Applies to `get`, `get_doc`, `aggregate`, `aggregate_doc`, `stream`, `chunk`,
+    ///   `copy_to` and `backfill_defaults`
+    /// - The open cursor is dropped, which makes the driver issue `killCursors`
+    /// - The operation fails with [`Interrupted::Cancelled`]
+    #[cfg(feature = "rt-tokio")]
+    pub fn cancel_on(mut self, token: tokio_util::sync::CancellationToken) -> Model<'a, M> {
+        self.query_builder.cancel = Some(token);
+        self
+    }
+    /// Aborts long reads after `duration`, like `cancel_on`
+    ///
+    /// The operation fails with [`Interrupted::TimedOut`].
+    #[cfg(feature = "rt-tokio")]
+    pub fn timeout(mut self, duration: std::time::Duration) -> Model<'a, M> {
+        self.query_builder.timeout = Some(duration);
+        self
+    }
+    /// Lets the server abort the operation after `duration`
+    ///
+    /// # Notes
+    /// - Sent as `maxTimeMS` on find, aggregate, count and single document update/delete
+    /// - `all()` updates and deletes don't support it
+    /// - Unlike `timeout`, the server stops working on the query too
+    pub fn max_time(mut self, duration: std::time::Duration) -> Model<'a, M> {
+        self.query_builder.max_time = Some(duration);
+        self
+    }
+    /// Lets the server write temporary files for sorts and groupings over its memory limit
+    ///
+    /// # Notes
+    /// - Applies to find and aggregate
+    pub fn allow_disk_use(mut self, allow: bool) -> Model<'a, M> {
+        self.query_builder.allow_disk_use = Some(allow);
+        self
+    }
+    /// Keeps the cursor open after the last document of a capped collection,
+    /// so later inserts can still be read from it
+    pub fn tailable(mut self) -> Model<'a, M> {
+        self.query_builder.cursor_type = Some(CursorType::Tailable);
+        self
+    }
+    /// Makes a tailable cursor wait on the server for new documents instead of
+    /// returning empty batches
+    pub fn await_data(mut self) -> Model<'a, M> {
+        self.query_builder.cursor_type = Some(CursorType::TailableAwait);
+        self
+    }
+    /// Makes the written document expire after `duration`
+    ///
+    /// # Notes
+    /// - Sets the column marked `expires_at` on `create` and `update`
+    pub fn expire_in(mut self, duration: std::time::Duration) -> Model<'a, M> {
+        let at = DateTime::from_millis(
+            DateTime::now().timestamp_millis() + duration.as_millis() as i64,
+        );
+        self.query_builder.expire = Some(Some(at));
+        self
+    }
+    /// Removes the expiry date of the written document
+    pub fn never_expire(mut self) -> Model<'a, M> {
+        self.query_builder.expire = Some(None);
+        self
+    }
+    /// Sets whether to upsert on update
+    pub fn upsert(mut self) -> Model<'a, M> {
+        self.query_builder.upsert = true;
+        self
+    }
+
+    pub(crate) fn db_name(&self, name: &str) -> String {
+        match self.columns.get(name).and_then(|attr| attr.name.clone()) {
+            None => name.to_string(),
+            Some(rename) => rename,
+        }
+    }
+
+    pub(crate) fn rename_filter(&self, data: Document) -> Document {
+        let mut r = Document::new();
+        for (key, value) in data {
+            let value = match value {
+                Bson::Array(items) if key.starts_with('$') => Bson::Array(
+                    items
+                        .into_iter()
+                        .map(|item| match item {
+                            Bson::Document(d) => Bson::Document(self.rename_filter(d)),
+                            other => other,
+                        })
+                        .collect(),
+                ),
+                other => other,
+            };
+            if key.starts_with('$') {
+                r.insert(key, value);
+            } else if let Some(e) = self.options.index_exprs.iter().find(|e| e.name == key) {
+                r.insert(key, e.route(value));
+            } else if let Some((top, rest)) = key.split_once('.') {
+                r.insert(format!("{}.{rest}", self.db_name(top)), value);
+            } else {
+                r.insert(self.db_name(&key), value);
+            }
+        }
+        r
+    }
+
+    /// Normalizes the conditions on `index_expr` shadow fields of a raw filter, keeping the other keys
+    fn route_index_exprs(&self, data: Document) -> Document {
+        if self.options.index_exprs.is_empty() {
+            return data;
+        }
+        data.into_iter()
+            .map(|(key, value)| match value {
+                Bson::Array(items) if key.starts_with('$') => {
+                    let items = items
+                        .into_iter()
+                        .map(|item| match item {
+                            Bson::Document(d) => Bson::Document(self.route_index_exprs(d)),
+                            other => other,
+                        })
+                        .collect();
+                    (key, Bson::Array(items))
+                }
+                value => match self.options.index_exprs.iter().find(|e| e.name == key) {
+                    Some(e) => (key, e.route(value)),
+                    None => (key, value),
+                },
+            })
+            .collect()
+    }
+
+    /// Sets the shadow fields of `data`, stored fields, whose source field is set
+    fn set_index_exprs(&self, data: &mut Document) {
+        for e in &self.options.index_exprs {
+            if let Some(value) = data.get(self.db_name(&e.source)).and_then(|v| e.apply(v)) {
+                data.insert(e.name.clone(), value);
+            }
+        }
+    }
+
+    fn expires_at_field(&self) -> Option<String> {
+        self.columns
+            .iter()
+            .find(|(_, attr)| attr.expires_at)
+            .map(|(name, _)| self.db_name(name))
+    }
+
+    /// Compresses the columns with a codec, failing when the codec does
+    fn compress_fields(&self, data: &mut Document) -> Result<()> {
+        for (name, attr) in &self.columns {
+            let Some(codec) = &attr.compress else {
+                continue;
+            };
+            let key = self.db_name(name);
+            if let Some(value) = data.get_mut(&key) {
+                *value = compress::compress(codec, std::mem::take(value)).map_err(|e| {
+                    Error::from(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("can't compress {key}: {e}."),
+                    ))
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn hydrate(&self, data: Document) -> Document {
+        decompress_fields(data, &self.compressed_fields())
+    }
+
+    /// Database names of the compressed columns
+    fn compressed_fields(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .filter(|(_, attr)| attr.compress.is_some())
+            .map(|(name, _)| self.db_name(name))
+            .collect()
+    }
+
+    fn hidden_fields(&self) -> Vec<String> {
+        let mut r = vec![];
+        let role = self.inner.role(&self.req);
+        for (name, attr) in &self.columns {
+            let hidden = attr.hidden
+                || self
+                    .options
+                    .field_roles
+                    .get(*name)
+                    .is_some_and(|roles| !role.as_ref().is_some_and(|role| roles.contains(role)));
+            let shown = self.query_builder.visible_all
+                || self.query_builder.visible_fields.contains(&name.to_string());
+            if (hidden && !shown) || self.query_builder.hide.contains(&name.to_string()) {
+                r.push(name.to_string())
+            }
+        }
+        r
+    }
+    /// Hydrates, casts and decodes a stored document, `None` when the decode error policy skips it
+    fn decode(&self, data: Document, hidden_fields: &[String]) -> Result<Option<M>> {
+        self.clear(self.cast(self.hydrate(data), &self.req), hidden_fields)
+    }
+    fn clear(&self, data: Document, hidden_fields: &[String]) -> Result<Option<M>> {
+        let default = to_document(&M::default())?;
+        let mut fields = vec![];
+        for (name, attr) in &self.columns {
+            if hidden_fields.contains(&name.to_string()) {
+                continue;
+            }
+            let rename = match attr.name.clone() {
+                None => name.to_string(),
+                Some(a) => a,
+            };
+            if let Some(value) = data.get(&rename) {
+                fields.push((name.to_string(), value.clone()));
+            }
+        }
+        let missing = self
+            .columns
+            .iter()
+            .filter(|(name, _)| default.get(**name).is_some_and(|v| *v != Bson::Null))
+            .filter(|(name, _)| !data.contains_key(self.db_name(name)))
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<_>>();
+        let mut full = default.clone();
+        for (name, value) in &fields {
+            full.insert(name.clone(), value.clone());
+        }
+        let error = match bson::from_document(full) {
+            Ok(m) => {
+                self.repair(&data, missing, vec![]);
+                return Ok(Some(m));
+            }
+            Err(e) => e,
+        };
+        let id = data.get("_id").cloned().unwrap_or(Bson::Null);
+        match self.options.on_decode_error {
+            DecodeErrorPolicy::Fail => Err(error.into()),
+            DecodeErrorPolicy::SkipDocument => {
+                log::warn!("Skipping {} {id} : {error}", self.collection_name);
+                Ok(None)
+            }
+            DecodeErrorPolicy::UseDefaultAndLog => {
+                let mut partial = default;
+                let mut defaulted = vec![];
+                for (name, value) in fields {
+                    let mut candidate = partial.clone();
+                    candidate.insert(name.clone(), value);
+                    if bson::from_document::<M>(candidate.clone()).is_ok() {
+                        partial = candidate;
+                    } else {
+                        error!("Can't decode {name} of {} {id}, using default", self.collection_name);
+                        defaulted.push(name);
+                    }
+                }
+                let m = bson::from_document(partial)?;
+                self.repair(&data, missing, defaulted);
+                Ok(Some(m))
+            }
+        }
+    }
+
+    /// Offers the fixes of a read to `Boot::repair`, writing them back in the background when accepted
+    fn repair(&self, data: &Document, missing: Vec<String>, defaulted: Vec<String>) {
+        if missing.is_empty() && defaulted.is_empty() || self.projection().is_some() {
+            return;
+        }
+        let Some(id) = data.get("_id") else {
+            return;
+        };
+        let Ok(stored) = self.insert_document(&M::default()) else {
+            return;
+        };
+        let mut fixes = Document::new();
+        let mut filter = doc! {"_id": id.clone()};
+        let fields = missing.iter().map(|n| (n, true));
+        for (name, is_missing) in fields.chain(defaulted.iter().map(|n| (n, false))) {
+            let key = self.db_name(name);
+            let Some(value) = stored.get(&key) else {
+                continue;
+            };
+            fixes.insert(key.clone(), value.clone());
+            let condition = if is_missing {
+                Bson::Document(doc! {"$exists": false})
+            } else {
+                data.get(&key).cloned().unwrap_or(Bson::Null)
+            };
+            filter.insert(key, condition);
+        }
+        if fixes.is_empty() {
+            return;
+        }
+        let repair = Repair {
+            collection: self.collection_name.to_string(),
+            id: id.clone(),
+            missing,
+            defaulted,
+            fixes,
+            filter,
+        };
+        if !self.inner.repair(&repair) {
+            return;
+        }
+        #[cfg(feature = "rt-tokio")]
+        {
+            let per_second = self.options.max_repairs_per_second.unwrap_or(10);
+            let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+                return;
+            };
+            if !crate::repair::allow(self.collection_name, per_second) {
+                return;
+            }
+            let coll = self.coll::<Document>();
+            runtime.spawn(async move {
+                let update = doc! {"$set": repair.fixes};
+                if let Err(e) = coll.update_one(repair.filter, update).await {
+                    error!("Can't repair {} {} : {e}", repair.collection, repair.id);
+                }
+            });
+        }
+        #[cfg(not(feature = "rt-tokio"))]
+        log::debug!("repair of {} {} not written, it needs the rt-tokio feature", repair.collection, repair.id);
+    }
+}
+
+impl<'a, M> Model<'a, M>
+where
+    M: Boot,
+    M: Default,
+    M: Serialize,
+{
+    /// this method takes the inner and gives you ownership of inner then
+    /// replace it with default value
+    pub fn take_inner(&mut self) -> M {
+        std::mem::take(&mut *self.inner)
+    }
+
+    pub fn inner_ref(&self) -> &M {
+        self.inner.as_ref()
+    }
+
+    pub fn inner_mut(&mut self) -> &mut M {
+        self.inner.as_mut()
+    }
+
+    pub fn inner_to_doc(&self) -> MongodbResult<Document> {
+        let mut re = to_document(&self.inner)?;
+        self.rename_field(&mut re, false);
+        Ok(re)
     }
 
     fn rename_field(&self, doc: &mut Document, is_opt: bool) {
@@ -365,593 +1958,2834 @@ where
                             }
                         }
                     }
-                } else {
-                    match doc.get(name) {
-                        None => {}
-                        Some(b) => {
-                            doc.insert(a.clone(), b.clone());
-                            doc.remove(name);
-                        }
+                } else {
+                    match doc.get(name) {
+                        None => {}
+                        Some(b) => {
+                            doc.insert(a.clone(), b.clone());
+                            doc.remove(name);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn fill(mut self, inner: M) -> Model<'a, M> {
+        *self.inner = inner;
+        self
+    }
+}
+
+impl<'a, M> Model<'a, M>
+where
+    M: Boot,
+    M: Default,
+    M: Serialize,
+    M: DeserializeOwned,
+    M: Send,
+    M: Sync,
+    M: Unpin,
+{
+    /// Get Documents count with filters
+    pub async fn count_documents(self) -> Result<u64> {
+        in_current_session!(s => self.count_documents_with_session(s));
+        let collection = self.coll::<Document>();
+        let filter = self.read_filter();
+
+        let options = self.count_options();
+
+        collection
+            .count_documents(filter)
+            .with_options(options)
+            .await
+    }
+
+    /// Get Documents count with filters and session
+    pub async fn count_documents_with_session(self, session: &mut ClientSession) -> Result<u64> {
+        let collection = self.coll::<Document>();
+        let filter = self.read_filter();
+
+        let options = self.count_options();
+
+        collection
+            .count_documents(filter)
+            .with_options(options)
+            .session(session)
+            .await
+    }
+
+    /// Checks that fewer than `max_count` documents match `filter` before a write adding one
+    ///
+    /// E.g. "a user has at most 3 active sessions" is checked with
+    /// `ensure_invariant(doc! {"user_id": id, "active": true}, 3)` before
+    /// inserting a session, both in the same transaction.
+    ///
+    /// # Arguments
+    /// * `filter` - Documents counted by the invariant, with model field names
+    ///
+    /// # Notes
+    /// - Fails with [`InvariantViolated`] when the invariant would break
+    /// - Runs in the current session, see [`crate::session`]
+    /// - Only safe inside a transaction, see `ensure_invariant_with_session`
+    pub async fn ensure_invariant(&self, filter: Document, max_count: u64) -> Result<u64> {
+        in_current_session!(s => self.ensure_invariant_with_session(filter, max_count, s));
+        let filter = self.rename_filter(filter);
+        let count = self.coll::<Document>().count_documents(filter).await?;
+        self.invariant_holds(count, max_count)
+    }
+
+    /// Checks that fewer than `max_count` documents match `filter` in `session`
+    ///
+    /// # Notes
+    /// - Also bumps a lock document of the invariant in [`INVARIANT_LOCKS`], so
+    ///   concurrent transactions checking the same invariant conflict and one
+    ///   of them is retried or aborted, instead of both passing the check
+    /// - Returns the number of matching documents
+    pub async fn ensure_invariant_with_session(
+        &self,
+        filter: Document,
+        max_count: u64,
+        session: &mut ClientSession,
+    ) -> Result<u64> {
+        let filter = self.rename_filter(filter);
+        let key = cache::fingerprint(self.db.name(), self.collection_name, &filter, "invariant");
+        self.db
+            .collection::<Document>(INVARIANT_LOCKS)
+            .update_one(
+                doc! {"_id": format!("{}:{key:x}", self.collection_name)},
+                doc! {"$inc": {"writes": 1_i64}},
+            )
+            .upsert(true)
+            .session(&mut *session)
+            .await?;
+        let count = self
+            .coll::<Document>()
+            .count_documents(filter)
+            .session(&mut *session)
+            .await?;
+        self.invariant_holds(count, max_count)
+    }
+
+    fn invariant_holds(&self, count: u64, max_count: u64) -> Result<u64> {
+        if count >= max_count {
+            return Err(InvariantViolated {
+                collection: self.collection_name.to_string(),
+                count,
+                max_count,
+            }
+            .into());
+        }
+        Ok(count)
+    }
+
+    /// Fast count of all documents read from the collection metadata
+    ///
+    /// Takes constant time whatever the collection size, use it for totals shown
+    /// to users or dashboards. Prefer `count_documents` when the exact number matters.
+    ///
+    /// # Notes
+    /// - Falls back to `count_documents` when a filter is set, or when drafts
+    ///   of a publishable model are hidden
+    /// - Can be off after an unclean shutdown or while orphaned documents
+    ///   exist on a sharded cluster
+    /// - Ignores skip/limit and the current session
+    pub async fn estimated_count(self) -> Result<u64> {
+        if self.query_builder.has_filter() || self.hides_drafts() {
+            return self.count_documents().await;
+        }
+        self.coll::<Document>()
+            .estimated_document_count()
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .optional(self.query_builder.max_time, |c, t| c.max_time(t))
+            .await
+    }
+
+    /// Get Documents count with filters, reusing a count cached less than `ttl` ago
+    ///
+    /// # Notes
+    /// - Cached per database, collection, filter and skip/limit, for the whole process
+    /// - Totals can be stale by up to `ttl`, use [`cache::invalidate_counts`] after large writes
+    pub async fn count_cached(self, ttl: std::time::Duration) -> Result<u64> {
+        let filter = self.read_filter();
+        let extra = format!("{}:{}", self.query_builder.skip, self.query_builder.limit);
+        let key = cache::fingerprint(self.db.name(), self.collection_name, &filter, &extra);
+        if let Some(count) = cache::count(key, ttl) {
+            return Ok(count);
+        }
+        let collection_name = self.collection_name;
+        let count = self.count_documents().await?;
+        cache::set_count(key, collection_name, count, ttl);
+        Ok(count)
+    }
+
+    fn add_times_to_data(&self, data: Document) -> Result<Document> {
+        let mut data = data;
+        if data.get_object_id("_id").is_err() {
+            data.remove("_id");
+        }
+        self.set_index_exprs(&mut data);
+        self.set_draft_status(&mut data);
+        if self.add_times {
+            if !data.contains_key("updated_at") || data.get_datetime("updated_at").is_err() {
+                data.insert("updated_at", DateTime::now());
+            }
+            if !data.contains_key("created_at") || data.get_datetime("created_at").is_err() {
+                data.insert("created_at", DateTime::now());
+            }
+        }
+        if let Some(expire) = self.query_builder.expire {
+            match self.expires_at_field() {
+                None => log::warn!("expiry ignored, no column marked expires_at"),
+                Some(field) => match expire {
+                    Some(at) => {
+                        data.insert(field, at);
+                    }
+                    None => {
+                        data.remove(&field);
+                    }
+                },
+            }
+        }
+        self.compress_fields(&mut data)?;
+        Ok(data)
+    }
+    /// Creates a new document in the collection
+    ///
+    /// # Notes
+    /// - Automatically adds timestamps if configured
+    /// - Documents of a publishable model without a valid `status` are created as drafts
+    pub async fn create(&self) -> Result<InsertOneResult> {
+        in_current_session!(s => self.create_with_session(s));
+        let mut data = self.add_times_to_data(self.inner_to_doc()?)?;
+
+        match self
+            .coll::<Document>()
+            .insert_one(data.clone())
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .await{
+            Ok(r) => {
+                data.insert("_id",r.inserted_id.clone());
+                self.finish(&self.req, "create", Document::new(), data, None)
+                    .await;
+                Ok(r)
+            }
+            Err(e) => {Err(e)}
+        }
+    }
+
+    /// Creates a new document in the collection wit session
+    ///
+    /// # Arguments
+    /// * `session` -  MongoDB transaction session
+    ///
+    /// # Notes
+    /// - Automatically adds timestamps if configured
+    pub async fn create_with_session(
+        &self,
+        session: &mut ClientSession,
+    ) -> Result<InsertOneResult> {
+        let mut data = self.add_times_to_data(self.inner_to_doc()?)?;
+        match self
+            .coll::<Document>()
+            .insert_one(data.clone())
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .session(&mut *session)
+            .await{
+            Ok(r) => {
+                data.insert("_id",r.inserted_id.clone());
+                self.finish(&self.req, "create", Document::new(), data, Some(session))
+                    .await;
+                Ok(r)
+            }
+            Err(e) => {Err(e)}
+        }
+    }
+
+    /// Update of an existing document for `save()`, `None` when `_id` isn't set
+    fn prepare_save(&self) -> Result<Option<(ObjectId, Document)>> {
+        let mut data = self.inner_to_doc()?;
+        let Ok(id) = data.get_object_id("_id") else {
+            return Ok(None);
+        };
+        data.remove("_id");
+        for name in self.hidden_fields() {
+            data.remove(self.db_name(&name));
+        }
+        let mut update = doc! {};
+        if self.add_times {
+            data.remove("created_at");
+            data.insert("updated_at", DateTime::now());
+            update.insert("$setOnInsert", doc! {"created_at": DateTime::now()});
+        }
+        self.set_index_exprs(&mut data);
+        self.compress_fields(&mut data)?;
+        update.insert("$set", data);
+        Ok(Some((id, update)))
+    }
+    fn set_inner_id(&mut self, id: Bson) -> Result<()> {
+        let mut data = to_document(&self.inner)?;
+        data.insert("_id", id);
+        *self.inner = bson::from_document(data)?;
+        Ok(())
+    }
+
+    /// Inserts the model when `_id` is not set, updates the document with that `_id` otherwise
+    ///
+    /// # Notes
+    /// - Sets `_id` on the model after an insert, so saving again updates it
+    /// - Keeps `created_at` of existing documents and refreshes `updated_at`
+    /// - Updates skip hidden fields unless made visible, since reads return them empty
+    /// - Recreates the document if it was deleted meanwhile
+    pub async fn save(&mut self) -> Result<ObjectId> {
+        in_current_session!(s => self.save_with_session(s));
+        let Some((id, update)) = self.prepare_save()? else {
+            let r = self.create().await?;
+            self.set_inner_id(r.inserted_id.clone())?;
+            return r.inserted_id.as_object_id().ok_or_else(|| {
+                Error::custom("inserted id is not an ObjectId")
+            });
+        };
+        self.coll::<Document>()
+            .update_one(doc! {"_id": id}, update.clone())
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .upsert(true)
+            .await?;
+        self.finish(&self.req, "update", doc! {"_id": id}, update, None)
+            .await;
+        Ok(id)
+    }
+
+    /// Inserts or updates the model based on `_id` with session
+    pub async fn save_with_session(&mut self, session: &mut ClientSession) -> Result<ObjectId> {
+        let Some((id, update)) = self.prepare_save()? else {
+            let r = self.create_with_session(session).await?;
+            self.set_inner_id(r.inserted_id.clone())?;
+            return r.inserted_id.as_object_id().ok_or_else(|| {
+                Error::custom("inserted id is not an ObjectId")
+            });
+        };
+        self.coll::<Document>()
+            .update_one(doc! {"_id": id}, update.clone())
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .upsert(true)
+            .session(&mut *session)
+            .await?;
+        self.finish(&self.req, "update", doc! {"_id": id}, update, Some(session))
+            .await;
+        Ok(id)
+    }
+
+    /// Creates a new document from raw BSON
+    pub async fn create_doc(&self, data: Document) -> Result<InsertOneResult> {
+        in_current_session!(s => self.create_doc_with_session(data, s));
+        let mut data = self.add_times_to_data(data)?;
+
+        match self
+            .coll::<Document>()
+            .insert_one(data.clone())
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .await{
+            Ok(r) => {
+                data.insert("_id",r.inserted_id.clone());
+                self.finish(&self.req, "create", Document::new(), data, None)
+                    .await;
+                Ok(r)
+            }
+            Err(e) => {Err(e)}
+        }
+    }
+
+    /// Creates a new document from raw BSON with session
+    pub async fn create_doc_with_session(
+        &self,
+        data: Document,
+        session: &mut ClientSession,
+    ) -> Result<InsertOneResult> {
+        let mut data = self.add_times_to_data(data)?;
+
+        match self
+            .coll::<Document>()
+            .insert_one(data.clone())
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .session(&mut *session)
+            .await{
+            Ok(r) => {
+                data.insert("_id",r.inserted_id.clone());
+                self.finish(&self.req, "create", Document::new(), data, Some(session))
+                    .await;
+                Ok(r)
+            }
+            Err(e) => {Err(e)}
+        }
+    }
+
+    /// Creates many documents from models
+    ///
+    /// # Notes
+    /// - Applies renames, timestamps and compression to every model
+    /// - Fires one `create_many` event, `inserted_ids` are keyed by the index in `items`
+    pub async fn create_many(&self, items: Vec<M>) -> Result<InsertManyResult> {
+        self.create_many_doc(self.models_to_docs(&items)?).await
+    }
+    /// Creates many documents from models with session
+    pub async fn create_many_with_session(
+        &self,
+        items: Vec<M>,
+        session: &mut ClientSession,
+    ) -> Result<InsertManyResult> {
+        self.create_many_doc_with_session(self.models_to_docs(&items)?, session)
+            .await
+    }
+    fn models_to_docs(&self, items: &[M]) -> Result<Vec<Document>> {
+        items
+            .iter()
+            .map(|item| {
+                let mut data = to_document(item)?;
+                self.rename_field(&mut data, false);
+                Ok(data)
+            })
+            .collect()
+    }
+    /// Creates many document from raw BSON
+    pub async fn create_many_doc(&self, data: Vec<Document>) -> Result<InsertManyResult> {
+        in_current_session!(s => self.create_many_doc_with_session(data, s));
+        let mut d=vec![];
+        for item in data {
+            d.push(self.add_times_to_data(item)?);
+        }
+
+        match self
+            .coll::<Document>()
+            .insert_many(d)
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .await{
+            Ok(r) => {
+                let inserted_ids: HashMap<String, Bson> = r.inserted_ids.clone()
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v))
+                    .collect();
+                self.finish(&self.req, "create_many", Document::new(), doc! {"_ids": Bson::Document(Document::from_iter(inserted_ids))}, None)
+                    .await;
+                Ok(r)
+            }
+            Err(e) => {Err(e)}
+        }
+    }
+    /// Creates many document from raw BSON with session
+    pub async fn create_many_doc_with_session(&self, data: Vec<Document>,session: &mut ClientSession,) -> Result<InsertManyResult> {
+        let mut d=vec![];
+        for item in data {
+            d.push(self.add_times_to_data(item)?);
+        }
+
+        match self
+            .coll::<Document>()
+            .insert_many(d)
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .session(&mut *session)
+            .await{
+            Ok(r) => {
+                let inserted_ids: HashMap<String, Bson> = r.inserted_ids.clone()
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v))
+                    .collect();
+                self.finish(&self.req, "create_many", Document::new(),
+                            doc! {"_ids": Bson::Document(Document::from_iter(inserted_ids))},
+                            Some(session))
+                    .await;
+                Ok(r)
+            }
+            Err(e) => {Err(e)}
+        }
+    }
+    /// Creates many documents from raw BSON and reports the outcome of each one
+    ///
+    /// # Arguments
+    /// * `ordered` - Stop at the first failing document
+    ///
+    /// # Notes
+    /// - Write errors don't fail the call, they are listed in the returned [`BulkOutcome`]
+    /// - Use [`BulkOutcome::retry_subset`] to resubmit only the failed documents
+    /// - `_id`s are set before the insert, so `inserted_ids` lists the applied
+    ///   documents after a partial failure too
+    pub async fn create_many_doc_report(
+        &self,
+        data: Vec<Document>,
+        ordered: bool,
+    ) -> Result<BulkOutcome> {
+        in_current_session!(s => self.create_many_doc_report_with_session(data, ordered, s));
+        let total = data.len();
+        let (d, ids) = self.report_documents(data)?;
+        let r = self
+            .coll::<Document>()
+            .insert_many(d)
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .ordered(ordered)
+            .await;
+        let mut outcome = BulkOutcome::from_insert_many(total, ordered, r)?;
+        outcome.fill_inserted_ids(&ids);
+        self.finish_many_report(&outcome, None).await;
+        Ok(outcome)
+    }
+
+    /// Creates many documents from raw BSON with session and reports the outcome of each one
+    pub async fn create_many_doc_report_with_session(
+        &self,
+        data: Vec<Document>,
+        ordered: bool,
+        session: &mut ClientSession,
+    ) -> Result<BulkOutcome> {
+        let total = data.len();
+        let (d, ids) = self.report_documents(data)?;
+        let r = self
+            .coll::<Document>()
+            .insert_many(d)
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .ordered(ordered)
+            .session(&mut *session)
+            .await;
+        let mut outcome = BulkOutcome::from_insert_many(total, ordered, r)?;
+        outcome.fill_inserted_ids(&ids);
+        self.finish_many_report(&outcome, Some(session)).await;
+        Ok(outcome)
+    }
+
+    /// Stored form of the documents of a report insert, with their `_id`s set beforehand
+    fn report_documents(&self, data: Vec<Document>) -> Result<(Vec<Document>, Vec<Bson>)> {
+        let mut documents = Vec::with_capacity(data.len());
+        let mut ids = Vec::with_capacity(data.len());
+        for item in data {
+            let mut item = self.add_times_to_data(item)?;
+            let id = item.get("_id").cloned().unwrap_or_else(|| ObjectId::new().into());
+            item.insert("_id", id.clone());
+            ids.push(id);
+            documents.push(item);
+        }
+        Ok((documents, ids))
+    }
+
+    async fn finish_many_report(&self, outcome: &BulkOutcome, session: Option<&mut ClientSession>) {
+        if outcome.succeeded.is_empty() {
+            return;
+        }
+        let inserted_ids = outcome
+            .inserted_ids
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect::<Document>();
+        self.finish(
+            &self.req,
+            "create_many",
+            Document::new(),
+            doc! {"_ids": inserted_ids, "failed": outcome.failed_indexes().len() as i64},
+            session,
+        )
+        .await;
+    }
+    fn prepare_first_or_create(&self) -> Result<(Document, Document, Bson)> {
+        if !self.query_builder.has_filter() {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "where not set.",
+            )));
+        }
+        let filter = self.query_builder.filter();
+        let mut data = self.add_times_to_data(self.inner_to_doc()?)?;
+        // values from the filter take precedence over the inner model, the
+        // server copies its equality conditions into the inserted document
+        let conditions = std::iter::once(&filter)
+            .chain(filter.get_array("$and").into_iter().flatten().filter_map(Bson::as_document));
+        let mut filter_id = None;
+        for condition in conditions {
+            for (key, value) in condition {
+                data.remove(key);
+                if key == "_id" && !value.as_document().is_some_and(|d| d.keys().any(|k| k.starts_with('$'))) {
+                    filter_id = Some(value.clone());
+                }
+            }
+        }
+        let id = match filter_id {
+            Some(id) => id,
+            None => {
+                let id = data.get("_id").cloned().unwrap_or_else(|| ObjectId::new().into());
+                data.insert("_id", id.clone());
+                id
+            }
+        };
+        Ok((filter, doc! {"$setOnInsert": data}, id))
+    }
+
+    /// Gets the first matching document or creates it from the inner model
+    ///
+    /// # Notes
+    /// - Runs as a single upsert, so concurrent calls don't create duplicates
+    /// - The created document is the inner model merged with the filter fields
+    pub async fn first_or_create(&self) -> Result<M> {
+        in_current_session!(s => self.first_or_create_with_session(s));
+        let (filter, data, id) = self.prepare_first_or_create()?;
+        let collection = self.coll::<Document>();
+        let existing = collection
+            .find_one_and_update(filter, data)
+            .with_options(self.find_one_and_update_options())
+            .upsert(true)
+            .await?;
+        let hidden_fields = self.hidden_fields();
+        let doc = match existing {
+            Some(doc) => doc,
+            None => {
+                let doc = collection
+                    .find_one(doc! {"_id": id})
+                    .await?
+                    .unwrap_or_default();
+                self.finish(&self.req, "create", Document::new(), doc.clone(), None)
+                    .await;
+                doc
+            }
+        };
+        self.decode(doc, &hidden_fields)?.ok_or_else(undecodable)
+    }
+
+    /// Gets the first matching document or creates it from the inner model with session
+    pub async fn first_or_create_with_session(&self, session: &mut ClientSession) -> Result<M> {
+        let (filter, data, id) = self.prepare_first_or_create()?;
+        let collection = self.coll::<Document>();
+        let existing = collection
+            .find_one_and_update(filter, data)
+            .with_options(self.find_one_and_update_options())
+            .upsert(true)
+            .session(&mut *session)
+            .await?;
+        let hidden_fields = self.hidden_fields();
+        let doc = match existing {
+            Some(doc) => doc,
+            None => {
+                let doc = collection
+                    .find_one(doc! {"_id": id})
+                    .session(&mut *session)
+                    .await?
+                    .unwrap_or_default();
+                self.finish(&self.req, "create", Document::new(), doc.clone(), Some(session))
+                    .await;
+                doc
+            }
+        };
+        self.decode(doc, &hidden_fields)?.ok_or_else(undecodable)
+    }
+
+    fn prepare_update(&self, data: Document) -> Result<(Document, Document)> {
+        self.prepare_update_as(data, self.query_builder.upsert)
+    }
+
+    fn prepare_update_as(&self, data: Document, upsert: bool) -> Result<(Document, Document)> {
+        self.check_write("update")?;
+        let data = self.update_document(data, upsert)?;
+        if !self.query_builder.has_filter() {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "where not set.",
+            )));
+        }
+        let filter = self.query_builder.filter();
+        Ok((data, filter))
+    }
+
+    /// Renames fields, adds timestamps and expiry, and compresses columns of an update
+    pub(crate) fn update_document(&self, data: Document, upsert: bool) -> Result<Document> {
+        let mut data = data;
+        let mut is_opt = false;
+        for (a, _) in data.iter() {
+            if a.starts_with("$") {
+                is_opt = true;
+            }
+        }
+
+        self.rename_field(&mut data, is_opt);
+        if !is_opt {
+            data = doc! {"$set":data};
+        }
+        for op in ["$set", "$setOnInsert"] {
+            if let Some(Bson::Document(set)) = data.get_mut(op) {
+                self.set_index_exprs(set);
+            }
+        }
+        if let Some(Bson::Document(unset)) = data.get_mut("$unset") {
+            for e in &self.options.index_exprs {
+                if unset.contains_key(self.db_name(&e.source)) {
+                    unset.insert(e.name.clone(), "");
+                }
+            }
+        }
+        if self.add_times {
+            if !data.contains_key("$set") {
+                data.insert("$set", doc! {});
+            }
+            let set = data.get_mut("$set").unwrap().as_document_mut().unwrap();
+            set.insert("updated_at", DateTime::now());
+        }
+
+        if upsert && self.add_times {
+            if !data.contains_key("$setOnInsert") {
+                data.insert("$setOnInsert", doc! {});
+            }
+            let set = data
+                .get_mut("$setOnInsert")
+                .unwrap()
+                .as_document_mut()
+                .unwrap();
+            set.insert("created_at", DateTime::now());
+        }
+        if let Some(expire) = self.query_builder.expire {
+            match self.expires_at_field() {
+                None => log::warn!("expiry ignored, no column marked expires_at"),
+                Some(field) => {
+                    let (op, value) = match expire {
+                        Some(at) => ("$set", Bson::DateTime(at)),
+                        None => ("$unset", Bson::String(String::new())),
+                    };
+                    if !data.contains_key(op) {
+                        data.insert(op, doc! {});
                     }
+                    let ops = data.get_mut(op).unwrap().as_document_mut().unwrap();
+                    ops.insert(field, value);
+                }
+            }
+        }
+        for op in ["$set", "$setOnInsert"] {
+            if let Some(Bson::Document(set)) = data.get_mut(op) {
+                self.compress_fields(set)?;
+            }
+        }
+        Ok(data)
+    }
+    /// Updates documents in the collection
+    ///
+    /// # Arguments
+    /// * `data` - Update operations
+    ///
+    /// # Notes
+    /// - Automatically adds updated_at timestamp if configured
+    /// - Handles both single and multi-document updates based on `all()` setting
+    /// - Supports upsert if configured
+    pub async fn update(&self, data: Document) -> Result<Document> {
+        in_current_session!(s => self.update_with_session(data, s));
+        let (data, filter) = self.prepare_update(data)?;
+
+        let r = self.coll::<Document>();
+
+        if self.query_builder.all {
+            self.check_affected("update_many", &filter, None).await?;
+            let r = r
+                .update_many(filter, data.clone())
+                .with_options(self.update_options())
+                .await;
+            match r {
+                Ok(old) => {
+                    let res = doc! {"modified_count":old.modified_count.to_string()};
+                    self.finish(&self.req, "update_many", res.clone(), data, None)
+                        .await;
+                    Ok(res)
+                }
+                Err(e) => Err(e),
+            }
+        } else {
+            let r = r
+                .find_one_and_update(filter, data.clone())
+                .with_options(self.find_one_and_update_options())
+                .await;
+            match r {
+                Ok(old) => {
+                    let res = old.unwrap_or(Document::new());
+                    self.finish(&self.req, "update", res.clone(), data, None)
+                        .await;
+                    Ok(res)
+                }
+                Err(e) => Err(e),
+            }
+        }
+    }
+
+    /// Updates the first matching document and returns it as it is after the update
+    ///
+    /// # Arguments
+    /// * `data` - Update operations
+    ///
+    /// # Notes
+    /// - Applies renames and hides hidden fields unless made visible
+    /// - Returns `None` when nothing matched, fails when `all()` is set
+    pub async fn update_returning(&self, data: Document) -> Result<Option<M>> {
+        self.update_returning_inner(data, None).await
+    }
+
+    /// Updates the first matching document with session and returns it as it is after the update
+    pub async fn update_returning_with_session(
+        &self,
+        data: Document,
+        session: &mut ClientSession,
+    ) -> Result<Option<M>> {
+        self.update_returning_inner(data, Some(session)).await
+    }
+
+    async fn update_returning_inner(
+        &self,
+        data: Document,
+        session: Option<&mut ClientSession>,
+    ) -> Result<Option<M>> {
+        if session.is_none() {
+            in_current_session!(s => Box::pin(self.update_returning_inner(data, Some(s))));
+        }
+        self.check_single("update_returning")?;
+        let (data, filter) = self.prepare_update(data)?;
+        let (old, new, session) = self.update_images(filter, data.clone(), session).await?;
+        self.finish(&self.req, "update", old, data, session).await;
+        self.typed(new)
+    }
+
+    /// Updates the first document matching `filter`, returning it before and after the update
+    ///
+    /// The previous state comes from the update itself, the new one is read back by
+    /// `_id`, or by `filter` when the update upserted. Both are empty when nothing
+    /// matched.
+    async fn update_images<'s>(
+        &self,
+        filter: Document,
+        data: Document,
+        mut session: Option<&'s mut ClientSession>,
+    ) -> Result<(Document, Document, Option<&'s mut ClientSession>)> {
+        let collection = self.coll::<Document>();
+        let update = collection
+            .find_one_and_update(filter.clone(), data)
+            .with_options(self.find_one_and_update_options())
+            .return_document(ReturnDocument::Before);
+        let old = match session.as_deref_mut() {
+            Some(s) => update.session(s).await?,
+            None => update.await?,
+        }
+        .unwrap_or_default();
+        if old.is_empty() && !self.query_builder.upsert {
+            return Ok((old, Document::new(), session));
+        }
+        let lookup = match old.get("_id") {
+            Some(id) => doc! {"_id": id.clone()},
+            None => filter,
+        };
+        let find = collection
+            .find_one(lookup)
+            .sort(self.sort_document())
+            .optional(self.command_comment(), |a, c| a.comment(c));
+        let new = match session.as_deref_mut() {
+            Some(s) => find.session(s).await?,
+            None => find.await?,
+        }
+        .unwrap_or_default();
+        Ok((old, new, session))
+    }
+
+    /// Updates the first matching document or creates it, returning the result
+    ///
+    /// # Arguments
+    /// * `data` - Update operations
+    ///
+    /// # Notes
+    /// - Always upserts, sets `created_at` on insert if configured
+    /// - Returns the document as it is after the update
+    /// - `finish` gets an empty old document, the previous state isn't read
+    pub async fn update_or_create(&self, data: Document) -> Result<M> {
+        in_current_session!(s => self.update_or_create_with_session(data, s));
+        let (data, filter) = self.prepare_update_as(data, true)?;
+        let r = self
+            .coll::<Document>()
+            .find_one_and_update(filter, data.clone())
+            .with_options(self.find_one_and_update_options())
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await?
+            .unwrap_or_default();
+        self.finish(&self.req, "update_or_create", Document::new(), data, None)
+            .await;
+        let hidden_fields = self.hidden_fields();
+        self.decode(r, &hidden_fields)?.ok_or_else(undecodable)
+    }
+
+    /// Updates the first matching document or creates it with session, returning the result
+    pub async fn update_or_create_with_session(
+        &self,
+        data: Document,
+        session: &mut ClientSession,
+    ) -> Result<M> {
+        let (data, filter) = self.prepare_update_as(data, true)?;
+        let r = self
+            .coll::<Document>()
+            .find_one_and_update(filter, data.clone())
+            .with_options(self.find_one_and_update_options())
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .session(&mut *session)
+            .await?
+            .unwrap_or_default();
+        self.finish(&self.req, "update_or_create", Document::new(), data, Some(session))
+            .await;
+        let hidden_fields = self.hidden_fields();
+        self.decode(r, &hidden_fields)?.ok_or_else(undecodable)
+    }
+
+    /// Adds `n` to a numeric field of the first matching document
+    ///
+    /// # Arguments
+    /// * `field` - Model field name, renamed like other updates
+    /// * `n` - Int32, Int64, Double or Decimal128 amount
+    ///
+    /// # Notes
+    /// - Refreshes `updated_at`, creates the document when upsert is set
+    /// - Returns the document as it is after the update
+    /// - `finish` gets the document before and after the update
+    pub async fn increment(&self, field: &str, n: impl Into<Bson>) -> Result<Option<M>> {
+        self.inc(field, n.into(), None).await
+    }
+
+    /// Subtracts `n` from a numeric field of the first matching document
+    pub async fn decrement(&self, field: &str, n: impl Into<Bson>) -> Result<Option<M>> {
+        self.inc(field, negate(n.into())?, None).await
+    }
+
+    /// Adds `n` to a numeric field of the first matching document with session
+    pub async fn increment_with_session(
+        &self,
+        field: &str,
+        n: impl Into<Bson>,
+        session: &mut ClientSession,
+    ) -> Result<Option<M>> {
+        self.inc(field, n.into(), Some(session)).await
+    }
+
+    /// Subtracts `n` from a numeric field of the first matching document with session
+    pub async fn decrement_with_session(
+        &self,
+        field: &str,
+        n: impl Into<Bson>,
+        session: &mut ClientSession,
+    ) -> Result<Option<M>> {
+        self.inc(field, negate(n.into())?, Some(session)).await
+    }
+
+    async fn inc(
+        &self,
+        field: &str,
+        n: Bson,
+        session: Option<&mut ClientSession>,
+    ) -> Result<Option<M>> {
+        if session.is_none() {
+            in_current_session!(s => Box::pin(self.inc(field, n, Some(s))));
+        }
+        let (data, filter) = self.prepare_update(doc! {"$inc": {field: n}})?;
+        let (old, new, session) = self.update_images(filter, data, session).await?;
+        self.finish(&self.req, "update", old, new.clone(), session).await;
+        self.typed(new)
+    }
+
+    /// Appends `value` to an array field
+    ///
+    /// # Notes
+    /// - Same as `update(doc! {"$push": {field: value}})`, so `all()`, upsert and timestamps apply
+    /// - Use `doc! {"$each": [...]}` as value to append several items
+    pub async fn push(&self, field: &str, value: impl Into<Bson>) -> Result<Document> {
+        self.update(doc! {"$push": {field: value.into()}}).await
+    }
+
+    /// Removes the items matching `condition` from an array field
+    ///
+    /// # Arguments
+    /// * `condition` - A value, or a query document such as `doc! {"$gte": 6}`
+    pub async fn pull(&self, field: &str, condition: impl Into<Bson>) -> Result<Document> {
+        self.update(doc! {"$pull": {field: condition.into()}}).await
+    }
+
+    /// Appends `value` to an array field unless already present
+    pub async fn add_to_set(&self, field: &str, value: impl Into<Bson>) -> Result<Document> {
+        self.update(doc! {"$addToSet": {field: value.into()}}).await
+    }
+
+    /// Appends `value` to an array field with session
+    pub async fn push_with_session(
+        &self,
+        field: &str,
+        value: impl Into<Bson>,
+        session: &mut ClientSession,
+    ) -> Result<Document> {
+        self.update_with_session(doc! {"$push": {field: value.into()}}, session)
+            .await
+    }
+
+    /// Removes the items matching `condition` from an array field with session
+    pub async fn pull_with_session(
+        &self,
+        field: &str,
+        condition: impl Into<Bson>,
+        session: &mut ClientSession,
+    ) -> Result<Document> {
+        self.update_with_session(doc! {"$pull": {field: condition.into()}}, session)
+            .await
+    }
+
+    /// Appends `value` to an array field unless already present with session
+    pub async fn add_to_set_with_session(
+        &self,
+        field: &str,
+        value: impl Into<Bson>,
+        session: &mut ClientSession,
+    ) -> Result<Document> {
+        self.update_with_session(doc! {"$addToSet": {field: value.into()}}, session)
+            .await
+    }
+
+    /// Updates the items of an array field matching conditions
+    ///
+    /// Generates the `$[ident]` positional operator and its `arrayFilters`:
+    ///
+    /// ```ignore
+    /// Order::new_model(&db)
+    ///     .r#where(doc! {"_id": id})
+    ///     .update_array_element("items", |item| item.where_("sku", "A1").inc("qty", 1))
+    ///     .await?;
+    /// ```
+    ///
+    /// # Notes
+    /// - Without `where_` conditions, every item is updated with `$[]`
+    /// - Runs `update()`, so `all()`, upsert and timestamps apply
+    pub async fn update_array_element(
+        mut self,
+        field: &str,
+        f: impl FnOnce(ArrayElement) -> ArrayElement,
+    ) -> Result<Document> {
+        let element = f(ArrayElement::default());
+        if element.is_empty() {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "no change set on the array items.",
+            )));
+        }
+        let ident = format!("elem{}", self.query_builder.array_filters.len());
+        let path = match field.split_once('.') {
+            Some((top, rest)) => format!("{}.{rest}", self.db_name(top)),
+            None => self.db_name(field),
+        };
+        let (update, filter) = element.build(&path, &ident);
+        self.query_builder.array_filters.extend(filter);
+        self.update(update).await
+    }
+
+    /// Replacement document and filter for `replace()`
+    fn prepare_replace(&self, new: &M) -> Result<(Document, Document)> {
+        if !self.query_builder.has_filter() {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "where not set.",
+            )));
+        }
+        let data = self.replacement_document(new, self.query_builder.upsert)?;
+        Ok((data, self.query_builder.filter()))
+    }
+
+    /// Pipeline update replacing a document with `data`
+    ///
+    /// Hidden fields and `created_at` keep their stored values, like `save()`, and
+    /// take the ones of `data` when the document has none, e.g. on insert.
+    fn replace_pipeline(&self, data: &Document) -> Vec<Document> {
+        let mut kept = self
+            .hidden_fields()
+            .iter()
+            .map(|name| self.db_name(name))
+            .collect::<Vec<_>>();
+        if self.add_times {
+            kept.push("created_at".to_string());
+        }
+        let mut replacement = doc! {"_id": "$_id"};
+        for (key, value) in data {
+            let value = doc! {"$literal": value.clone()};
+            if kept.contains(key) {
+                replacement.insert(key, doc! {"$ifNull": [format!("${key}"), value]});
+            } else {
+                replacement.insert(key, value);
+            }
+        }
+        vec![doc! {"$replaceWith": replacement}]
+    }
+
+    /// Stored form of a replacement, with a new `_id` for upserts
+    pub(crate) fn replacement_document(&self, new: &M, upsert: bool) -> Result<Document> {
+        let mut data = to_document(new)?;
+        self.rename_field(&mut data, false);
+        if self.add_times {
+            data.insert("updated_at", DateTime::now());
+        }
+        let mut data = self.add_times_to_data(data)?;
+        if upsert && !data.contains_key("_id") {
+            data.insert("_id", ObjectId::new());
+        }
+        Ok(data)
+    }
+
+    /// Stored form of a new document
+    pub(crate) fn insert_document(&self, item: &M) -> Result<Document> {
+        let mut data = to_document(item)?;
+        self.rename_field(&mut data, false);
+        self.add_times_to_data(data)
+    }
+
+    /// Creates the filled document as a draft of a publishable model
+    ///
+    /// # Notes
+    /// - Sets `status` to draft and clears `published_at`, see [`crate::publish`]
+    pub async fn draft(&self) -> Result<InsertOneResult> {
+        self.create_doc(self.draft_document()?).await
+    }
+    /// Creates the filled document as a draft with session
+    pub async fn draft_with_session(&self, session: &mut ClientSession) -> Result<InsertOneResult> {
+        self.create_doc_with_session(self.draft_document()?, session)
+            .await
+    }
+    /// Makes new documents of a publishable model drafts unless they carry a valid status
+    fn set_draft_status(&self, data: &mut Document) {
+        if !self.options.publishable {
+            return;
+        }
+        let status = self.db_name(publish::STATUS);
+        if matches!(data.get_str(&status), Ok(publish::DRAFT | publish::PUBLISHED)) {
+            return;
+        }
+        data.insert(status, publish::DRAFT);
+        data.insert(self.db_name(publish::PUBLISHED_AT), Bson::Null);
+    }
+    fn draft_document(&self) -> Result<Document> {
+        let mut data = self.inner_to_doc()?;
+        data.insert(self.db_name(publish::STATUS), publish::DRAFT);
+        data.insert(self.db_name(publish::PUBLISHED_AT), Bson::Null);
+        Ok(data)
+    }
+
+    /// Publishes the matching documents, setting `published_at` to now
+    ///
+    /// # Notes
+    /// - Runs `update`, so filters, `all()` and hooks apply as usual
+    pub async fn publish(&self) -> Result<Document> {
+        self.update(doc! {publish::STATUS: publish::PUBLISHED, publish::PUBLISHED_AT: DateTime::now()})
+            .await
+    }
+    /// Publishes the matching documents with session
+    pub async fn publish_with_session(&self, session: &mut ClientSession) -> Result<Document> {
+        self.update_with_session(
+            doc! {publish::STATUS: publish::PUBLISHED, publish::PUBLISHED_AT: DateTime::now()},
+            session,
+        )
+        .await
+    }
+
+    /// Turns the matching documents back into drafts, clearing `published_at`
+    pub async fn unpublish(&self) -> Result<Document> {
+        self.update(doc! {publish::STATUS: publish::DRAFT, publish::PUBLISHED_AT: Bson::Null})
+            .await
+    }
+    /// Turns the matching documents back into drafts with session
+    pub async fn unpublish_with_session(&self, session: &mut ClientSession) -> Result<Document> {
+        self.update_with_session(
+            doc! {publish::STATUS: publish::DRAFT, publish::PUBLISHED_AT: Bson::Null},
+            session,
+        )
+        .await
+    }
+
+    /// Replaces the first matching document
+    ///
+    /// # Arguments
+    /// * `new` - Replacement document
+    ///
+    /// # Notes
+    /// - Refreshes `updated_at`, keeps `created_at` and hidden fields like `save()`
+    ///   unless made visible
+    /// - Inserts `new` when nothing matches and upsert is set
+    /// - Returns the replaced document, or the inserted one after an upsert
+    pub async fn replace(&self, new: M) -> Result<Option<M>> {
+        in_current_session!(s => self.replace_with_session(new, s));
+        let (data, filter) = self.prepare_replace(&new)?;
+        let r = self
+            .coll::<Document>()
+            .find_one_and_update(filter, self.replace_pipeline(&data))
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .upsert(self.query_builder.upsert)
+            .sort(self.query_builder.sort.clone())
+            .await?;
+        self.finish(&self.req, "replace", r.clone().unwrap_or_default(), data.clone(), None)
+            .await;
+        self.replaced(r, data)
+    }
+
+    /// Replaces the first matching document with session
+    pub async fn replace_with_session(
+        &self,
+        new: M,
+        session: &mut ClientSession,
+    ) -> Result<Option<M>> {
+        let (data, filter) = self.prepare_replace(&new)?;
+        let r = self
+            .coll::<Document>()
+            .find_one_and_update(filter, self.replace_pipeline(&data))
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .upsert(self.query_builder.upsert)
+            .sort(self.query_builder.sort.clone())
+            .session(&mut *session)
+            .await?;
+        self.finish(
+            &self.req,
+            "replace",
+            r.clone().unwrap_or_default(),
+            data.clone(),
+            Some(session),
+        )
+        .await;
+        self.replaced(r, data)
+    }
+
+    fn replaced(&self, old: Option<Document>, data: Document) -> Result<Option<M>> {
+        match old {
+            Some(old) => self.typed(old),
+            None if self.query_builder.upsert => self.typed(data),
+            None => Ok(None),
+        }
+    }
+
+    /// Updates documents in the collection with session
+    ///
+    /// # Arguments
+    /// * `data` - Update operations
+    /// * `session` - MongoDB transaction session
+    ///
+    /// # Notes
+    /// - Automatically adds updated_at timestamp if configured
+    /// - Handles both single and multi-document updates based on `all()` setting
+    /// - Supports upsert if configured
+    pub async fn update_with_session(
+        &self,
+        data: Document,
+        session: &mut ClientSession,
+    ) -> Result<Document> {
+        let (data, filter) = self.prepare_update(data)?;
+
+        let r = self.coll::<Document>();
+        if self.query_builder.all {
+            self.check_affected("update_many", &filter, Some(&mut *session)).await?;
+            let r = r
+                .update_many(filter, data.clone())
+                .with_options(self.update_options())
+                .session(&mut *session)
+                .await;
+            match r {
+                Ok(old) => {
+                    let res = doc! {"modified_count":old.modified_count.to_string()};
+                    self.finish(&self.req, "update_many", res.clone(), data, Some(session))
+                        .await;
+                    Ok(res)
+                }
+                Err(e) => Err(e),
+            }
+        } else {
+            let r = r
+                .find_one_and_update(filter, data.clone())
+                .with_options(self.find_one_and_update_options())
+                .session(&mut *session)
+                .await;
+            match r {
+                Ok(old) => {
+                    let res = old.unwrap_or(Document::new());
+                    self.finish(&self.req, "update", res.clone(), data, Some(session))
+                        .await;
+                    Ok(res)
                 }
+                Err(e) => Err(e),
             }
         }
     }
 
-    pub fn fill(mut self, inner: M) -> Model<'a, M> {
-        *self.inner = inner;
-        self
+    /// Deletes documents from the collection
+    ///
+    ///
+    /// # Notes
+    /// - Handles both single and multi-document deletes based on `all()` setting
+    pub async fn delete(&self) -> Result<Document> {
+        in_current_session!(s => self.delete_with_session(s));
+        self.check_write("delete")?;
+        if !self.query_builder.has_filter() {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "where not set.",
+            )));
+        }
+        let filter = self.query_builder.filter();
+
+        let r = self.coll::<Document>();
+        if self.query_builder.all {
+            self.check_affected("delete_many", &filter, None).await?;
+            let r = r.delete_many(filter).with_options(self.delete_options()).await;
+            match r {
+                Ok(old) => {
+                    let res = doc! {"deleted_count":old.deleted_count.to_string()};
+                    self.finish(&self.req, "delete_many", res.clone(), doc! {}, None)
+                        .await;
+                    Ok(res)
+                }
+                Err(e) => Err(e),
+            }
+        } else {
+            let r = r
+                .find_one_and_delete(filter)
+                .with_options(self.find_one_and_delete_options())
+                .await;
+            match r {
+                Ok(old) => {
+                    let res = old.unwrap_or(Document::new());
+                    self.finish(&self.req, "delete", res.clone(), doc! {}, None)
+                        .await;
+                    Ok(res)
+                }
+                Err(e) => Err(e),
+            }
+        }
     }
-}
 
-impl<'a, M> Model<'a, M>
-where
-    M: Boot,
-    M: Default,
-    M: Serialize,
-    M: DeserializeOwned,
-    M: Send,
-    M: Sync,
-    M: Unpin,
-{
-    /// Get Documents count with filters
-    pub async fn count_documents(self) -> Result<u64> {
-        let whr = &self.query_builder.r#where;
-        let collection = self.db.collection::<Document>(self.collection_name);
-        let filter = if whr.is_empty() {
-            doc! {}
+    /// Deletes the first matching document and returns it as a model
+    ///
+    /// # Notes
+    /// - Applies renames and hides hidden fields unless made visible
+    /// - Returns `None` when nothing matched, fails when `all()` is set
+    pub async fn delete_typed(&self) -> Result<Option<M>> {
+        self.check_single("delete_typed")?;
+        let r = self.delete().await?;
+        self.typed(r)
+    }
+
+    /// Deletes the first matching document with session and returns it as a model
+    pub async fn delete_typed_with_session(&self, session: &mut ClientSession) -> Result<Option<M>> {
+        self.check_single("delete_typed")?;
+        let r = self.delete_with_session(session).await?;
+        self.typed(r)
+    }
+
+    fn check_single(&self, operation: &str) -> Result<()> {
+        if self.query_builder.all {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{operation} works on a single document, remove all()."),
+            )));
+        }
+        Ok(())
+    }
+
+    /// Deletes documents from the collection with session
+    ///
+    /// # Arguments
+    /// * `session` - Optional MongoDB transaction session
+    ///
+    /// # Notes
+    /// - Handles both single and multi-document deletes based on `all()` setting
+    pub async fn delete_with_session(&self, session: &mut ClientSession) -> Result<Document> {
+        self.check_write("delete")?;
+        if !self.query_builder.has_filter() {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "where not set.",
+            )));
+        }
+        let filter = self.query_builder.filter();
+
+        let r = self.coll::<Document>();
+        if self.query_builder.all {
+            self.check_affected("delete_many", &filter, Some(&mut *session)).await?;
+            let r = r
+                .delete_many(filter)
+                .with_options(self.delete_options())
+                .session(&mut *session)
+                .await;
+            match r {
+                Ok(old) => {
+                    let res = doc! {"deleted_count":old.deleted_count.to_string()};
+                    self.finish(
+                        &self.req,
+                        "delete_many",
+                        res.clone(),
+                        doc! {},
+                        Some(session),
+                    )
+                    .await;
+                    Ok(res)
+                }
+                Err(e) => Err(e),
+            }
         } else {
-            doc! { "$and": whr }
+            let r = r
+                .find_one_and_delete(filter)
+                .with_options(self.find_one_and_delete_options())
+                .session(&mut *session)
+                .await;
+            match r {
+                Ok(old) => {
+                    let res = old.unwrap_or(Document::new());
+                    self.finish(&self.req, "delete", res.clone(), doc! {}, Some(session))
+                        .await;
+                    Ok(res)
+                }
+                Err(e) => Err(e),
+            }
+        }
+    }
+    /// Awaits `f` unless the query is cancelled or its timeout since `started` elapsed
+    #[cfg(feature = "rt-tokio")]
+    async fn interruptible<T>(&self, started: Instant, f: impl IntoFuture<Output = T>) -> Result<T> {
+        use crate::error::Interrupted;
+        use futures::future::{select, Either};
+
+        let cancelled = async {
+            match &self.query_builder.cancel {
+                Some(token) => token.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        let timed_out = async {
+            match self.query_builder.timeout {
+                Some(timeout) => tokio::time::sleep(timeout.saturating_sub(started.elapsed())).await,
+                None => std::future::pending().await,
+            }
+        };
+        let f = std::pin::pin!(f.into_future());
+        let (cancelled, timed_out) = (std::pin::pin!(cancelled), std::pin::pin!(timed_out));
+        match select(f, select(cancelled, timed_out)).await {
+            Either::Left((r, _)) => Ok(r),
+            Either::Right((Either::Left(_), _)) => Err(Interrupted::Cancelled.into()),
+            Either::Right((Either::Right(_), _)) => Err(Interrupted::TimedOut.into()),
+        }
+    }
+
+    #[cfg(not(feature = "rt-tokio"))]
+    async fn interruptible<T>(&self, _started: Instant, f: impl IntoFuture<Output = T>) -> Result<T> {
+        Ok(f.await)
+    }
+
+    /// Applies the limit/skip guards to a list query returning at most `limit` documents
+    fn check_read(&self, limit: u32, skip: u32) -> Result<()> {
+        let guards = &self.options.guards;
+        if let Some(max_limit) = guards.max_limit {
+            if limit == 0 {
+                return Err(GuardError::UnboundedQuery { max_limit }.into());
+            }
+            if limit > max_limit {
+                return Err(GuardError::LimitExceeded { limit, max_limit }.into());
+            }
+        }
+        if let Some(max_skip) = guards.max_skip
+            && skip > max_skip
+        {
+            return Err(GuardError::SkipExceeded { skip, max_skip }.into());
+        }
+        if let Some(sortable) = &self.query_builder.sortable
+            && let Some(field) = self
+                .query_builder
+                .sort
+                .iter()
+                .find(|(k, v)| *k != "_id" && !is_meta(v) && !sortable.contains(k))
+                .map(|(k, _)| k)
+        {
+            return Err(GuardError::SortNotAllowed {
+                field: field.to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Counts the documents an `all()` write would affect against the `max_affected` guard
+    ///
+    /// The count runs before the write, so concurrent inserts can still slip through.
+    async fn check_affected(
+        &self,
+        operation: &str,
+        filter: &Document,
+        session: Option<&mut ClientSession>,
+    ) -> Result<()> {
+        let Some(max_affected) = self.options.guards.max_affected else {
+            return Ok(());
+        };
+        if !self.query_builder.all || self.query_builder.force {
+            return Ok(());
+        }
+        self.count_affected(operation, filter, max_affected, session).await
+    }
+
+    async fn count_affected(
+        &self,
+        operation: &str,
+        filter: &Document,
+        max_affected: u64,
+        session: Option<&mut ClientSession>,
+    ) -> Result<()> {
+        let collection = self.coll::<Document>();
+        let count = collection.count_documents(filter.clone()).limit(max_affected + 1);
+        let count = match session {
+            Some(session) => count.session(session).await?,
+            None => count.await?,
+        };
+        if count > max_affected {
+            return Err(GuardError::TooManyAffected {
+                operation: operation.to_string(),
+                max_affected,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Filter guard of a multi-document operation of a [`BulkWriter`], queued with `filter`
+    pub(crate) fn check_bulk_filter(&self, operation: &str, filter: &Document) -> Result<()> {
+        if filter.is_empty() && self.options.guards.require_filter_for_all {
+            return Err(GuardError::FilterRequired {
+                operation: operation.to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// `max_affected` guard of a multi-document operation of a [`BulkWriter`], unless `force()` is set
+    pub(crate) async fn check_bulk_affected(
+        &self,
+        operation: &str,
+        filter: &Document,
+        session: Option<&mut ClientSession>,
+    ) -> Result<()> {
+        match self.options.guards.max_affected {
+            Some(max_affected) if !self.query_builder.force => {
+                self.count_affected(operation, filter, max_affected, session).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// `check_affected` counting through `backend`
+    async fn check_affected_via(
+        &self,
+        backend: &impl Backend,
+        operation: &str,
+        filter: &Document,
+    ) -> Result<()> {
+        let Some(max_affected) = self.options.guards.max_affected else {
+            return Ok(());
+        };
+        if !self.query_builder.all || self.query_builder.force {
+            return Ok(());
+        }
+        let pipeline = vec![
+            doc! {"$match": filter.clone()},
+            doc! {"$limit": (max_affected + 1) as i64},
+            doc! {"$count": "count"},
+        ];
+        let counted = backend.aggregate(self.collection_name, pipeline).await?;
+        let count = match counted.first().and_then(|d| d.get("count")) {
+            Some(Bson::Int32(n)) => *n as u64,
+            Some(Bson::Int64(n)) => *n as u64,
+            _ => 0,
+        };
+        if count > max_affected {
+            return Err(GuardError::TooManyAffected {
+                operation: operation.to_string(),
+                max_affected,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Applies the filter guard to `all()` updates and deletes
+    fn check_write(&self, operation: &str) -> Result<()> {
+        let qb = &self.query_builder;
+        let unfiltered = qb.r#where.iter().chain(qb.or_where.iter().flatten()).all(|d| d.is_empty());
+        if qb.all && self.options.guards.require_filter_for_all && unfiltered {
+            return Err(GuardError::FilterRequired {
+                operation: operation.to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn prepare_get(&self) -> (Document, Vec<String>) {
+        let filter = self.read_filter();
+        let hidden_fields = self.hidden_fields();
+        (filter, hidden_fields)
+    }
+
+    /// Filter of reads, limited to published documents unless drafts are visible
+    fn read_filter(&self) -> Document {
+        let filter = self.query_builder.filter();
+        if !self.hides_drafts() {
+            return filter;
+        }
+        let published = doc! {self.db_name(publish::STATUS): publish::PUBLISHED};
+        and_condition(filter, published)
+    }
+
+    /// Whether reads are limited to published documents of a publishable model
+    fn hides_drafts(&self) -> bool {
+        self.options.publishable
+            && !self.query_builder.with_drafts
+            && !self.inner.preview(&self.req)
+    }
+
+    /// Sort of the query, with the `_id` tiebreaker when sortable fields are set
+    fn sort_document(&self) -> Document {
+        let mut sort = self.query_builder.sort.clone();
+        if self.query_builder.sortable.is_none() || sort.is_empty() || sort.contains_key("_id") {
+            return sort;
+        }
+        let Some(direction) = sort.iter().last().and_then(|(_, v)| sort_direction(v)) else {
+            return sort;
         };
+        sort.insert("_id", direction);
+        sort
+    }
+
+    /// Projection of reads, the select or else the exclusion of heavy columns
+    fn projection(&self) -> Option<Document> {
+        if let Some(select) = &self.query_builder.select {
+            return Some(select.clone());
+        }
+        if self.query_builder.with_heavy {
+            return None;
+        }
+        let mut heavy = self
+            .columns
+            .iter()
+            .filter(|(_, attr)| attr.heavy)
+            .map(|(name, _)| self.db_name(name))
+            .collect::<Vec<_>>();
+        heavy.sort();
+        let projection = heavy.into_iter().map(|name| (name, Bson::Int32(0))).collect::<Document>();
+        (!projection.is_empty()).then_some(projection)
+    }
+
+    fn find_options(&self) -> FindOptions {
+        let mut options = FindOptions::default();
+        options.sort = Some(self.sort_document());
+
+        if self.query_builder.skip > 0 {
+            options.skip = Some(self.query_builder.skip as u64);
+        }
+        if self.query_builder.limit > 0 {
+            options.limit = Some(self.query_builder.limit as i64);
+        }
+        if self.query_builder.batch_size > 0 {
+            options.batch_size = Some(self.query_builder.batch_size);
+        }
+        options.projection = self.projection();
+        options.hint = self.query_builder.hint.clone();
+        options.max_time = self.query_builder.max_time;
+        options.allow_disk_use = self.query_builder.allow_disk_use;
+        options.cursor_type = self.query_builder.cursor_type;
+        options.comment = self.command_comment();
+        apply(&self.query_builder.overrides.find, options)
+    }
+
+    fn count_options(&self) -> CountOptions {
+        let mut options = CountOptions::default();
+        if self.query_builder.skip > 0 {
+            options.skip = Some(self.query_builder.skip as u64);
+        }
+        if self.query_builder.limit > 0 {
+            options.limit = Some(self.query_builder.limit as u64);
+        }
+        options.hint = self.query_builder.hint.clone();
+        options.max_time = self.query_builder.max_time;
+        options.comment = self.command_comment();
+        apply(&self.query_builder.overrides.count, options)
+    }
+
+    fn update_options(&self) -> UpdateOptions {
+        let mut options = UpdateOptions::default();
+        options.upsert = Some(self.query_builder.upsert);
+        options.hint = self.query_builder.hint.clone();
+        options.array_filters = self.array_filters();
+        options.comment = self.command_comment();
+        apply(&self.query_builder.overrides.update, options)
+    }
+
+    fn find_one_and_update_options(&self) -> FindOneAndUpdateOptions {
+        let mut options = FindOneAndUpdateOptions::default();
+        options.upsert = Some(self.query_builder.upsert);
+        options.sort = Some(self.sort_document());
+        options.hint = self.query_builder.hint.clone();
+        options.array_filters = self.array_filters();
+        options.max_time = self.query_builder.max_time;
+        options.comment = self.command_comment();
+        apply(&self.query_builder.overrides.find_one_and_update, options)
+    }
 
-        let options = CountOptions::builder()
-            .skip(if self.query_builder.skip > 0 {
-                Some(self.query_builder.skip as u64)
-            } else {
-                None
-            })
-            .limit(if self.query_builder.limit > 0 {
-                Some(self.query_builder.limit as u64)
-            } else {
-                None
-            })
-            .build();
+    /// Comment attached to the commands, see `Model::comment`
+    pub(crate) fn command_comment(&self) -> Option<Bson> {
+        self.options.comment.clone().map(Bson::String)
+    }
 
-        collection
-            .count_documents(filter)
-            .with_options(options)
-            .await
+    fn array_filters(&self) -> Option<Vec<Document>> {
+        let filters = &self.query_builder.array_filters;
+        (!filters.is_empty()).then(|| filters.clone())
     }
 
-    /// Get Documents count with filters and session
-    pub async fn count_documents_with_session(self, session: &mut ClientSession) -> Result<u64> {
-        let whr = &self.query_builder.r#where;
-        let collection = self.db.collection::<Document>(self.collection_name);
-        let filter = if whr.is_empty() {
-            doc! {}
-        } else {
-            doc! { "$and": whr }
-        };
+    fn delete_options(&self) -> DeleteOptions {
+        let mut options = DeleteOptions::default();
+        options.hint = self.query_builder.hint.clone();
+        options.comment = self.command_comment();
+        apply(&self.query_builder.overrides.delete, options)
+    }
 
-        let options = CountOptions::builder()
-            .skip(if self.query_builder.skip > 0 {
-                Some(self.query_builder.skip as u64)
-            } else {
-                None
-            })
-            .limit(if self.query_builder.limit > 0 {
-                Some(self.query_builder.limit as u64)
-            } else {
-                None
-            })
-            .build();
+    fn find_one_and_delete_options(&self) -> FindOneAndDeleteOptions {
+        let mut options = FindOneAndDeleteOptions::default();
+        options.sort = Some(self.sort_document());
+        options.hint = self.query_builder.hint.clone();
+        options.max_time = self.query_builder.max_time;
+        options.comment = self.command_comment();
+        apply(&self.query_builder.overrides.find_one_and_delete, options)
+    }
 
-        collection
-            .count_documents(filter)
-            .with_options(options)
-            .session(session)
-            .await
+    /// Options of aggregations built from the query settings
+    fn aggregate_options(&self) -> AggregateOptions {
+        let mut options = AggregateOptions::default();
+        if self.query_builder.batch_size > 0 {
+            options.batch_size = Some(self.query_builder.batch_size);
+        }
+        options.max_time = self.query_builder.max_time;
+        options.allow_disk_use = self.query_builder.allow_disk_use;
+        options.comment = self.command_comment();
+        apply(&self.query_builder.overrides.aggregate, options)
     }
 
-    fn add_times_to_data(&self, data: Document) -> Document {
-        let mut data = data;
-        if data.get_object_id("_id").is_err() {
-            data.remove("_id");
+    fn prepare_aggregate<'b>(&self, aggregate: Aggregate<'b>) -> Aggregate<'b> {
+        aggregate.with_options(self.aggregate_options())
+    }
+
+    fn prepare_find<'b>(&self, find: Find<'b, Document>) -> Find<'b, Document> {
+        find.with_options(self.find_options())
+    }
+
+    /// Runs a find in the current session if any, collecting the raw documents
+    async fn find_documents(
+        &self,
+        filter: Document,
+        options: FindOptions,
+        started: Instant,
+    ) -> Result<Vec<Document>> {
+        in_current_session!(s => self.find_documents_with_session(filter, options, started, s));
+        let collection = self.coll::<Document>();
+        let find = collection.find(filter).with_options(options);
+        let mut docs = vec![];
+        let mut cursor = self.interruptible(started, find).await??;
+        while let Some(d) = self.interruptible(started, cursor.next()).await? {
+            docs.push(d?);
         }
-        if self.add_times {
-            if !data.contains_key("updated_at") || !data.get_datetime("updated_at").is_ok() {
-                data.insert("updated_at", DateTime::now());
-            }
-            if !data.contains_key("created_at") || !data.get_datetime("created_at").is_ok() {
-                data.insert("created_at", DateTime::now());
-            }
+        Ok(docs)
+    }
+    #[cfg(feature = "rt-tokio")]
+    async fn find_documents_with_session(
+        &self,
+        filter: Document,
+        options: FindOptions,
+        started: Instant,
+        session: &mut ClientSession,
+    ) -> Result<Vec<Document>> {
+        let collection = self.coll::<Document>();
+        let find = collection.find(filter).with_options(options).session(&mut *session);
+        let mut docs = vec![];
+        let mut cursor = self.interruptible(started, find).await??;
+        while let Some(d) = self.interruptible(started, cursor.next(&mut *session)).await? {
+            docs.push(d?);
         }
-        data
+        Ok(docs)
     }
-    /// Creates a new document in the collection
+
+    /// Runs an aggregation in the current session if any, collecting the raw documents
+    async fn aggregate_documents(
+        &self,
+        collection: &Collection<Document>,
+        pipeline: Vec<Document>,
+        options: AggregateOptions,
+    ) -> Result<Vec<Document>> {
+        in_current_session!(s => self.aggregate_documents_with_session(collection, pipeline, options, s));
+        let started = Instant::now();
+        let aggregate = collection.aggregate(pipeline).with_options(options);
+        let mut docs = vec![];
+        let mut cursor = self.interruptible(started, aggregate).await??;
+        while let Some(d) = self.interruptible(started, cursor.next()).await? {
+            docs.push(d?);
+        }
+        Ok(docs)
+    }
+    #[cfg(feature = "rt-tokio")]
+    async fn aggregate_documents_with_session(
+        &self,
+        collection: &Collection<Document>,
+        pipeline: Vec<Document>,
+        options: AggregateOptions,
+        session: &mut ClientSession,
+    ) -> Result<Vec<Document>> {
+        let started = Instant::now();
+        let aggregate = collection
+            .aggregate(pipeline)
+            .with_options(options)
+            .session(&mut *session);
+        let mut docs = vec![];
+        let mut cursor = self.interruptible(started, aggregate).await??;
+        while let Some(d) = self.interruptible(started, cursor.next(&mut *session)).await? {
+            docs.push(d?);
+        }
+        Ok(docs)
+    }
+
+    /// Builds the find command without executing it
+    ///
+    /// The filter and options are exactly what `get()` would send, so they can be
+    /// passed to driver APIs this crate does not wrap or checked in unit tests.
+    pub fn build_find(&self) -> FindQuery {
+        FindQuery {
+            filter: self.read_filter(),
+            options: self.find_options(),
+        }
+    }
+
+    /// Builds the update command without executing it
     ///
     /// # Notes
-    /// - Automatically adds timestamps if configured
-    pub async fn create(&self) -> Result<InsertOneResult> {
-        let mut data = self.add_times_to_data(self.inner_to_doc()?);
+    /// - Applies field renames and timestamps like `update()`
+    /// - Fails when no filter is set
+    pub fn build_update(&self, data: Document) -> Result<UpdateQuery> {
+        let (update, filter) = self.prepare_update(data)?;
+        Ok(UpdateQuery {
+            filter,
+            update,
+            upsert: self.query_builder.upsert,
+            many: self.query_builder.all,
+            sort: self.sort_document(),
+        })
+    }
 
-        match self
-            .db
-            .collection(self.collection_name)
-            .insert_one(data.clone())
-            .await{
-            Ok(r) => {
-                data.insert("_id",r.inserted_id.clone());
-                self.finish(&self.req, "create", Document::new(), data, None)
-                    .await;
-                Ok(r)
-            }
-            Err(e) => {Err(e)}
+    /// Queries documents from the collection
+    ///
+    ///
+    /// # Notes
+    /// - Respects skip/limit/sort/select settings
+    /// - Filters out hidden fields unless explicitly made visible
+    pub async fn get(&self) -> Result<Vec<M>> {
+        in_current_session!(s => self.get_with_session(s));
+        self.check_read(self.query_builder.limit, self.query_builder.skip)?;
+        let (filter, hidden_fields) = self.prepare_get();
+        let collection = self.coll::<Document>();
+        let mut find = collection.find(filter);
+        find = self.prepare_find(find);
+
+        let mut r = vec![];
+        let started = Instant::now();
+        let mut cursor = self.interruptible(started, find).await??;
+        while let Some(d) = self.interruptible(started, cursor.next()).await? {
+            r.extend(self.decode(d?, &hidden_fields)?)
         }
+        Ok(r)
     }
 
-    /// Creates a new document in the collection wit session
+    /// Queries documents from the collection with session
     ///
     /// # Arguments
-    /// * `session` -  MongoDB transaction session
+    /// * `session` - Optional MongoDB transaction session
     ///
     /// # Notes
-    /// - Automatically adds timestamps if configured
-    pub async fn create_with_session(
-        &self,
-        session: &mut ClientSession,
-    ) -> Result<InsertOneResult> {
-        let mut data = self.add_times_to_data(self.inner_to_doc()?);
-        match self
-            .db
-            .collection(self.collection_name)
-            .insert_one(data.clone())
-            .session(&mut *session)
-            .await{
-            Ok(r) => {
-                data.insert("_id",r.inserted_id.clone());
-                self.finish(&self.req, "create", Document::new(), data, Some(session))
-                    .await;
-                Ok(r)
-            }
-            Err(e) => {Err(e)}
+    /// - Respects skip/limit/sort/select settings
+    /// - Filters out hidden fields unless explicitly made visible
+    pub async fn get_with_session(&self, session: &mut ClientSession) -> Result<Vec<M>> {
+        self.check_read(self.query_builder.limit, self.query_builder.skip)?;
+        let (filter, hidden_fields) = self.prepare_get();
+        let collection = self.coll::<Document>();
+        let mut find = collection.find(filter);
+        find = self.prepare_find(find);
+
+        let mut r = vec![];
+        let started = Instant::now();
+        let mut cursor = self.interruptible(started, find.session(&mut *session)).await??;
+        while let Some(d) = self.interruptible(started, cursor.next(&mut *session)).await? {
+            r.extend(self.decode(d?, &hidden_fields)?)
         }
+        Ok(r)
     }
 
-    /// Creates a new document from raw BSON
-    pub async fn create_doc(&self, data: Document) -> Result<InsertOneResult> {
-        let mut data = self.add_times_to_data(data);
-
-        match self
-            .db
-            .collection(self.collection_name)
-            .insert_one(data.clone())
-            .await{
-            Ok(r) => {
-                data.insert("_id",r.inserted_id.clone());
-                self.finish(&self.req, "create", Document::new(), data, None)
-                    .await;
-                Ok(r)
-            }
-            Err(e) => {Err(e)}
-        }
+    /// Explains the find `get()` would run, returning the server's plan
+    ///
+    /// # Notes
+    /// - With [`Verbosity::ExecutionStats`] and above the query is executed
+    pub async fn explain(&self, verbosity: Verbosity) -> Result<Document> {
+        let command = self.build_find().command(self.collection_name);
+        self.db
+            .run_command(doc! {"explain": command, "verbosity": verbosity.value()})
+            .await
     }
 
-    /// Creates a new document from raw BSON with session
-    pub async fn create_doc_with_session(
+    /// Explains an aggregation pipeline, returning the server's plan
+    pub async fn explain_aggregate(
         &self,
-        data: Document,
-        session: &mut ClientSession,
-    ) -> Result<InsertOneResult> {
-        let mut data = self.add_times_to_data(data);
-
-        match self
-            .db
-            .collection(self.collection_name)
-            .insert_one(data.clone())
-            .session(&mut *session)
-            .await{
-            Ok(r) => {
-                data.insert("_id",r.inserted_id.clone());
-                self.finish(&self.req, "create", Document::new(), data, Some(session))
-                    .await;
-                Ok(r)
-            }
-            Err(e) => {Err(e)}
+        pipeline: impl IntoIterator<Item = Document>,
+        verbosity: Verbosity,
+    ) -> Result<Document> {
+        let mut command = doc! {
+            "aggregate": self.collection_name,
+            "pipeline": pipeline.into_iter().collect::<Vec<_>>(),
+            "cursor": {},
+        };
+        if let Some(max_time) = self.query_builder.max_time {
+            command.insert("maxTimeMS", max_time.as_millis() as i64);
         }
+        if let Some(allow) = self.query_builder.allow_disk_use {
+            command.insert("allowDiskUse", allow);
+        }
+        if let Some(comment) = self.command_comment() {
+            command.insert("comment", comment);
+        }
+        self.db
+            .run_command(doc! {"explain": command, "verbosity": verbosity.value()})
+            .await
     }
 
-    /// Creates many document from raw BSON
-    pub async fn create_many_doc(&self, data: Vec<Document>) -> Result<InsertManyResult> {
-        let mut d=vec![];
-        for item in data {
-            d.push(self.add_times_to_data(item));
+    /// Recorder of the model's revisions, declared with [`ModelOptions::versioned`]
+    pub fn revisions(&self) -> Result<Revisions> {
+        match &self.options.revisions {
+            Some(name) => Ok(Revisions::new(&self.db, name)),
+            None => Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not versioned.", self.collection_name),
+            ))),
         }
+    }
 
-        match self
-            .db
-            .collection(self.collection_name)
-            .insert_many(d)
-            .await{
-            Ok(r) => {
-                let inserted_ids: HashMap<String, Bson> = r.inserted_ids.clone()
-                    .into_iter()
-                    .map(|(k, v)| (k.to_string(), v))
-                    .collect();
-                self.finish(&self.req, "create_many", Document::new(), doc! {"_ids": Bson::Document(Document::from_iter(inserted_ids))}, None)
-                    .await;
-                Ok(r)
-            }
-            Err(e) => {Err(e)}
+    /// Matching documents as they were at `at`, rebuilt from the revisions
+    ///
+    /// # Notes
+    /// - The filter and sort apply to the rebuilt documents, skip and limit too
+    /// - Documents deleted before `at` aren't returned
+    /// - Follows the read guards and aggregate options like `get`, e.g. `max_time` and `cancel`
+    /// - See [`crate::history`] for recording the revisions
+    pub async fn as_of(&self, at: DateTime) -> Result<Vec<M>> {
+        let Some(revisions) = &self.options.revisions else {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not versioned.", self.collection_name),
+            )));
+        };
+        self.check_read(self.query_builder.limit, self.query_builder.skip)?;
+        let (filter, hidden_fields) = self.prepare_get();
+        let mut pipeline = history::as_of_pipeline(revisions, at, filter);
+        let sort = self.sort_document();
+        if !sort.is_empty() {
+            pipeline.push(doc! {"$sort": sort});
         }
-    }
-    /// Creates many document from raw BSON with session
-    pub async fn create_many_doc_with_session(&self, data: Vec<Document>,session: &mut ClientSession,) -> Result<InsertManyResult> {
-        let mut d=vec![];
-        for item in data {
-            d.push(self.add_times_to_data(item));
+        if self.query_builder.skip > 0 {
+            pipeline.push(doc! {"$skip": self.query_builder.skip as i64});
+        }
+        if self.query_builder.limit > 0 {
+            pipeline.push(doc! {"$limit": self.query_builder.limit as i64});
+        }
+        let docs = self
+            .aggregate_documents(
+                &self.db.collection(revisions),
+                pipeline,
+                self.aggregate_options(),
+            )
+            .await?;
+        let mut r = vec![];
+        for d in docs {
+            r.extend(self.decode(d, &hidden_fields)?);
         }
+        Ok(r)
+    }
 
-        match self
-            .db
-            .collection(self.collection_name)
-            .insert_many(d)
-            .session(&mut *session)
-            .await{
-            Ok(r) => {
-                let inserted_ids: HashMap<String, Bson> = r.inserted_ids.clone()
-                    .into_iter()
-                    .map(|(k, v)| (k.to_string(), v))
-                    .collect();
-                self.finish(&self.req, "create_many", Document::new(),
-                            doc! {"_ids": Bson::Document(Document::from_iter(inserted_ids))},
-                            Some(session))
-                    .await;
-                Ok(r)
-            }
-            Err(e) => {Err(e)}
+    /// Runs the pipeline stage by stage, sampling the documents out of each stage
+    ///
+    /// Runs stage 1, then stages 1-2 and so on, to see where documents disappear
+    /// or change shape.
+    ///
+    /// # Arguments
+    /// * `sample_n` - Documents kept per stage, at least 1
+    ///
+    /// # Notes
+    /// - One aggregation per stage, counts run the whole prefix, mind large collections
+    /// - `$out` and `$merge` stages are rejected, debugging doesn't write
+    /// - A select is applied to the samples
+    pub async fn debug_stages(
+        &self,
+        pipeline: impl IntoIterator<Item = Document>,
+        sample_n: u32,
+    ) -> Result<Vec<StageSample>> {
+        let pipeline = pipeline.into_iter().collect::<Vec<_>>();
+        if let Some(stage) = pipeline
+            .iter()
+            .find(|s| s.contains_key("$out") || s.contains_key("$merge"))
+        {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("can't debug a pipeline writing its output: {stage}."),
+            )));
+        }
+        let mut r = vec![];
+        for (i, stage) in pipeline.iter().enumerate() {
+            let (sample, count) = self
+                .facet_page(pipeline[..=i].to_vec(), 1, sample_n)
+                .await?;
+            r.push(StageSample {
+                stage: i + 1,
+                operator: stage.keys().next().cloned().unwrap_or_default(),
+                count,
+                sample,
+            });
         }
+        Ok(r)
     }
-    fn prepare_update(&self, data: Document) -> Result<(Document, Document)> {
-        let mut data = data;
-        let mut is_opt = false;
-        for (a, _) in data.iter() {
-            if a.starts_with("$") {
-                is_opt = true;
-            }
+
+    /// Runs an aggregation pipeline and returns one page of its results with the total count
+    ///
+    /// # Arguments
+    /// * `page` - Page number, starting at 1
+    ///
+    /// # Notes
+    /// - Items and total come from a single `$facet` roundtrip
+    /// - Results are deserialized into `T` like `aggregate_as`, a select is applied to the items
+    pub async fn facet_paginate<T: DeserializeOwned>(
+        &self,
+        pipeline: impl IntoIterator<Item = Document>,
+        page: u32,
+        per_page: u32,
+    ) -> Result<Page<T>> {
+        let pipeline = pipeline.into_iter().collect();
+        let (docs, total) = self.facet_page(pipeline, page, per_page).await?;
+        let items = docs
+            .into_iter()
+            .map(|d| bson::from_document(self.hydrate(d)).map_err(Error::from))
+            .collect::<Result<Vec<T>>>()?;
+        Ok(Page::new(items, total, page.max(1), per_page))
+    }
+
+    /// Runs `pipeline` and returns one page of its output plus the total count in a single `$facet`
+    async fn facet_page(
+        &self,
+        mut pipeline: Vec<Document>,
+        page: u32,
+        per_page: u32,
+    ) -> Result<(Vec<Document>, u64)> {
+        let page = page.max(1);
+        let mut items = vec![
+            doc! {"$skip": ((page - 1) as i64) * per_page as i64},
+            doc! {"$limit": per_page.max(1) as i64},
+        ];
+        if let Some(projection) = self.projection() {
+            items.push(doc! {"$project": projection});
         }
+        pipeline.push(doc! {"$facet": {
+            "items": items,
+            "total": [{"$count": "count"}],
+        }});
+
+        let docs = self
+            .aggregate_documents(&self.coll(), pipeline, self.aggregate_options())
+            .await?;
+        let Some(mut result) = docs.into_iter().next() else {
+            return Ok((vec![], 0));
+        };
+        let total = match result.get_array("total").ok().and_then(|t| t.first()) {
+            Some(Bson::Document(d)) => match d.get("count") {
+                Some(Bson::Int32(c)) => *c as u64,
+                Some(Bson::Int64(c)) => *c as u64,
+                _ => 0,
+            },
+            _ => 0,
+        };
+        let items = match result.remove("items") {
+            Some(Bson::Array(items)) => items
+                .into_iter()
+                .filter_map(|item| match item {
+                    Bson::Document(d) => Some(d),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        };
+        Ok((items, total))
+    }
 
-        self.rename_field(&mut data, is_opt);
-        if !is_opt {
-            data = doc! {"$set":data};
-        }
-        if self.add_times {
-            if !data.contains_key("$set") {
-                data.insert("$set", doc! {});
-            }
-            let set = data.get_mut("$set").unwrap().as_document_mut().unwrap();
-            set.insert("updated_at", DateTime::now());
+    /// Offset pagination
+    ///
+    /// # Arguments
+    /// * `page` - Page number, starting at 1
+    /// * `per_page` - Number of documents per page
+    ///
+    /// # Notes
+    /// - Items and total count are fetched in one `$facet` aggregation
+    /// - Respects sort/select settings, ignores skip/limit
+    /// - Filters out hidden fields unless explicitly made visible
+    pub async fn paginate(&self, page: u32, per_page: u32) -> Result<Page<M>> {
+        self.check_read(per_page, (page.max(1) - 1).saturating_mul(per_page))?;
+        let (filter, hidden_fields) = self.prepare_get();
+        let mut pipeline = vec![doc! {"$match": filter}];
+        let sort = self.sort_document();
+        if !sort.is_empty() {
+            pipeline.push(doc! {"$sort": sort});
         }
+        let (docs, total) = self.facet_page(pipeline, page, per_page).await?;
+        let items = docs
+            .into_iter()
+            .filter_map(|d| self.decode(d, &hidden_fields).transpose())
+            .collect::<Result<Vec<M>>>()?;
+        Ok(Page::new(items, total, page.max(1), per_page))
+    }
 
-        if self.query_builder.upsert {
-            if self.add_times {
-                if !data.contains_key("$setOnInsert") {
-                    data.insert("$setOnInsert", doc! {});
+    /// Writes the default value of `fields` to the matching documents missing them
+    ///
+    /// The usual follow-up to adding fields to the model, documents written
+    /// before keep failing to decode or filter on the new fields until then.
+    ///
+    /// # Arguments
+    /// * `fields` - Model field names
+    ///
+    /// # Notes
+    /// - Defaults are the values of `M::default()`, stored like `create` stores them
+    /// - Updates at most `batch_size` documents per write, 1000 when unset
+    /// - Documents holding the field with a `null` value are left as they are
+    /// - Stops between batches when cancelled or timed out, the batches written are kept
+    pub async fn backfill_defaults(&self, fields: &[&str]) -> Result<BackfillReport> {
+        let started = Instant::now();
+        let defaults = self.insert_document(&M::default())?;
+        let batch_size = match self.query_builder.batch_size {
+            0 => 1000,
+            size => size as i64,
+        };
+        let filter = self.query_builder.filter();
+        let collection = self.coll::<Document>();
+        let mut report = BackfillReport::default();
+        for field in fields {
+            let key = self.db_name(field);
+            let Some(default) = defaults.get(&key).cloned() else {
+                return Err(Error::from(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("field {field} has no default value."),
+                )));
+            };
+            let mut missing = doc! {key.as_str(): {"$exists": false}};
+            if !filter.is_empty() {
+                missing = doc! {"$and": [filter.clone(), missing]};
+            }
+            let mut updated = 0;
+            loop {
+                let find = async {
+                    collection
+                        .find(missing.clone())
+                        .projection(doc! {"_id": 1})
+                        .limit(batch_size)
+                        .await?
+                        .map(|d| d.map(|d| d.get("_id").cloned().unwrap_or_default()))
+                        .collect::<Vec<_>>()
+                        .await
+                        .into_iter()
+                        .collect::<Result<Vec<_>>>()
+                };
+                let ids = self.interruptible(started, find).await??;
+                if ids.is_empty() {
+                    break;
                 }
-                let set = data
-                    .get_mut("$setOnInsert")
-                    .unwrap()
-                    .as_document_mut()
-                    .unwrap();
-                set.insert("created_at", DateTime::now());
+                let update = collection.update_many(
+                    doc! {"_id": {"$in": ids}, key.as_str(): {"$exists": false}},
+                    doc! {"$set": {key.as_str(): default.clone()}},
+                );
+                let r = self.interruptible(started, update).await??;
+                updated += r.modified_count;
             }
+            report.fields.push((field.to_string(), updated));
         }
-        let whr = &self.query_builder.r#where;
-        if whr.is_empty() {
-            return Err(Error::from(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "where not set.",
-            )));
-        }
-        let filter = doc! {"$and":whr};
-        Ok((data, filter))
+        Ok(report)
     }
-    /// Updates documents in the collection
+
+    /// Recomputes the shadow fields of `ModelOptions::index_exprs` on the matching documents
     ///
-    /// # Arguments
-    /// * `data` - Update operations
+    /// Needed once after declaring an index expression, for the documents stored before.
+    /// Returns the number of documents modified.
     ///
     /// # Notes
-    /// - Automatically adds updated_at timestamp if configured
-    /// - Handles both single and multi-document updates based on `all()` setting
-    /// - Supports upsert if configured
-    pub async fn update(&self, data: Document) -> Result<Document> {
-        let (data, filter) = self.prepare_update(data)?;
+    /// - Runs server-side with a pipeline update, documents aren't read
+    pub async fn refresh_index_exprs(&self) -> Result<u64> {
+        let mut set = Document::new();
+        for e in &self.options.index_exprs {
+            set.insert(e.name.clone(), e.aggregation(&self.db_name(&e.source)));
+        }
+        if set.is_empty() {
+            return Ok(0);
+        }
+        let r = self
+            .coll::<Document>()
+            .update_many(self.query_builder.filter(), vec![doc! {"$set": set}])
+            .await?;
+        Ok(r.modified_count)
+    }
 
-        let r = self.db.collection::<Document>(self.collection_name);
+    /// Processes every matching document in batches of `size`
+    ///
+    /// # Notes
+    /// - Walks `_id` ranges, so documents are read in `_id` order and sort/skip/limit are ignored
+    /// - `_id` is always read, even when `select` leaves it out
+    /// - Stops at the first error returned by `f`
+    /// - Returns the number of processed documents
+    pub async fn chunk(
+        &self,
+        size: u32,
+        mut f: impl AsyncFnMut(Vec<M>) -> Result<()>,
+    ) -> Result<u64> {
+        self.check_read(size, 0)?;
+        let started = Instant::now();
+        let (filter, hidden_fields) = self.prepare_get();
+        let mut last_id: Option<Bson> = None;
+        let mut processed = 0;
+        loop {
+            let mut conditions = vec![];
+            if !filter.is_empty() {
+                conditions.push(filter.clone());
+            }
+            if let Some(id) = &last_id {
+                conditions.push(doc! {"_id": {"$gt": id}});
+            }
+            let filter = if conditions.is_empty() {
+                doc! {}
+            } else {
+                doc! {"$and": conditions}
+            };
+            let mut options = self.find_options();
+            options.sort = Some(doc! {"_id": 1});
+            options.skip = None;
+            options.limit = Some(size.max(1) as i64);
+            if let Some(projection) = options.projection.as_mut() {
+                projection.remove("_id");
+            }
 
-        if self.query_builder.all {
-            let r = r
-                .update_many(filter, data.clone())
-                .upsert(self.query_builder.upsert)
-                .await;
-            match r {
-                Ok(old) => {
-                    let res = doc! {"modified_count":old.modified_count.to_string()};
-                    self.finish(&self.req, "update_many", res.clone(), data, None)
-                        .await;
-                    Ok(res)
-                }
-                Err(e) => Err(e),
+            let mut items = vec![];
+            let mut read = 0;
+            for d in self.find_documents(filter, options, started).await? {
+                read += 1;
+                last_id = d.get("_id").cloned();
+                items.extend(self.decode(d, &hidden_fields)?);
             }
-        } else {
-            let r = r
-                .find_one_and_update(filter, data.clone())
-                .upsert(self.query_builder.upsert)
-                .sort(self.query_builder.sort.clone())
-                .await;
-            match r {
-                Ok(old) => {
-                    let res = old.unwrap_or(Document::new());
-                    self.finish(&self.req, "update", res.clone(), data, None)
-                        .await;
-                    Ok(res)
-                }
-                Err(e) => Err(e),
+            if read == 0 {
+                break;
+            }
+            processed += items.len() as u64;
+            let done = read < size.max(1) as usize;
+            if !items.is_empty() {
+                f(items).await?;
+            }
+            if done {
+                break;
             }
         }
+        Ok(processed)
     }
 
-    /// Updates documents in the collection with session
+    /// Keyset (cursor based) pagination
     ///
     /// # Arguments
-    /// * `data` - Update operations
-    /// * `session` - MongoDB transaction session
+    /// * `after` - `next` token of the previous page, `None` for the first page
+    /// * `per_page` - Maximum number of documents per page
     ///
     /// # Notes
-    /// - Automatically adds updated_at timestamp if configured
-    /// - Handles both single and multi-document updates based on `all()` setting
-    /// - Supports upsert if configured
-    pub async fn update_with_session(
-        &self,
-        data: Document,
-        session: &mut ClientSession,
-    ) -> Result<Document> {
-        let (data, filter) = self.prepare_update(data)?;
+    /// - Paginates on the single field set with `sort()` (with `_id` as tiebreaker), or on `_id` ascending
+    /// - Ignores skip/limit settings
+    /// - Filters out hidden fields unless explicitly made visible
+    pub async fn paginate_after(&self, after: Option<&str>, per_page: u32) -> Result<KeysetPage<M>> {
+        self.check_read(per_page, 0)?;
+        let (filter, hidden_fields) = self.prepare_get();
+        let (key, dir) = match self.query_builder.sort.iter().next() {
+            Some((k, v)) if self.query_builder.sort.len() == 1 => {
+                let desc = sort_direction(v) == Some(-1);
+                (k.clone(), if desc { -1 } else { 1 })
+            }
+            _ => ("_id".to_string(), 1),
+        };
+        let op = if dir < 0 { "$lt" } else { "$gt" };
 
-        let r = self.db.collection::<Document>(self.collection_name);
-        if self.query_builder.all {
-            let r = r
-                .update_many(filter, data.clone())
-                .upsert(self.query_builder.upsert)
-                .session(&mut *session)
-                .await;
-            match r {
-                Ok(old) => {
-                    let res = doc! {"modified_count":old.modified_count.to_string()};
-                    self.finish(&self.req, "update_many", res.clone(), data, Some(session))
-                        .await;
-                    Ok(res)
-                }
-                Err(e) => Err(e),
+        let mut conditions = vec![];
+        if !filter.is_empty() {
+            conditions.push(filter);
+        }
+        if let Some(token) = after {
+            let (token_key, value, id) = decode_cursor(token)?;
+            if token_key != key {
+                return Err(Error::from(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "pagination cursor does not match the sort key.",
+                )));
             }
+            if key == "_id" {
+                conditions.push(doc! {"_id": {op: id}});
+            } else {
+                conditions.push(doc! {"$or": [
+                    {&key: {op: value.clone()}},
+                    {&key: value, "_id": {op: id}},
+                ]});
+            }
+        }
+        let filter = if conditions.is_empty() {
+            doc! {}
         } else {
-            let r = r
-                .find_one_and_update(filter, data.clone())
-                .upsert(self.query_builder.upsert)
-                .sort(self.query_builder.sort.clone())
-                .session(&mut *session)
-                .await;
-            match r {
-                Ok(old) => {
-                    let res = old.unwrap_or(Document::new());
-                    self.finish(&self.req, "update", res.clone(), data, Some(session))
-                        .await;
-                    Ok(res)
-                }
-                Err(e) => Err(e),
+            doc! {"$and": conditions}
+        };
+        let sort = if key == "_id" {
+            doc! {"_id": dir}
+        } else {
+            doc! {&key: dir, "_id": dir}
+        };
+
+        let mut options = self.find_options();
+        options.sort = Some(sort);
+        options.skip = None;
+        options.limit = Some(per_page as i64 + 1);
+        let mut docs = self.find_documents(filter, options, Instant::now()).await?;
+        let mut next = None;
+        if docs.len() > per_page as usize {
+            docs.truncate(per_page as usize);
+            if let Some(last) = docs.last() {
+                let value = last.get(&key).cloned().unwrap_or(Bson::Null);
+                let id = last.get("_id").cloned().unwrap_or(Bson::Null);
+                next = Some(encode_cursor(&key, value, id));
             }
         }
+        let items = docs
+            .into_iter()
+            .filter_map(|d| self.decode(d, &hidden_fields).transpose())
+            .collect::<Result<Vec<M>>>()?;
+        Ok(KeysetPage { items, next })
     }
 
-    /// Deletes documents from the collection
-    ///
+    /// Extracts a single field from every matching document
     ///
     /// # Notes
-    /// - Handles both single and multi-document deletes based on `all()` setting
-    pub async fn delete(&self) -> Result<Document> {
-        let whr = &self.query_builder.r#where;
-        if whr.is_empty() {
-            return Err(Error::from(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "where not set.",
-            )));
-        }
-        let filter = doc! {"$and":whr};
+    /// - `field` is the model field name, renames are applied
+    /// - Respects skip/limit/sort settings
+    /// - Documents without the field are skipped
+    pub async fn pluck<T: DeserializeOwned>(&self, field: &str) -> Result<Vec<T>> {
+        in_current_session!(s => self.pluck_with_session(field, s));
+        let (filter, _) = self.prepare_get();
+        let key = self.db_name(field);
+        let collection = self.coll::<Document>();
+        let mut find = self.prepare_find(collection.find(filter));
+        find = find.projection(doc! {&key: 1});
 
-        let r = self.db.collection::<Document>(self.collection_name);
-        if self.query_builder.all {
-            let r = r.delete_many(filter).await;
-            match r {
-                Ok(old) => {
-                    let res = doc! {"deleted_count":old.deleted_count.to_string()};
-                    self.finish(&self.req, "delete_many", res.clone(), doc! {}, None)
-                        .await;
-                    Ok(res)
-                }
-                Err(e) => Err(e),
+        let mut r = vec![];
+        let mut cursor = find.await?;
+        while let Some(d) = cursor.next().await {
+            if let Some(value) = self.hydrate(d?).remove(&key) {
+                r.push(bson::from_bson(value)?);
             }
-        } else {
-            let r = r
-                .find_one_and_delete(filter)
-                .sort(self.query_builder.sort.clone())
-                .await;
-            match r {
-                Ok(old) => {
-                    let res = old.unwrap_or(Document::new());
-                    self.finish(&self.req, "delete", res.clone(), doc! {}, None)
-                        .await;
-                    Ok(res)
-                }
-                Err(e) => Err(e),
+        }
+        Ok(r)
+    }
+    /// Extracts a single field from every matching document with session
+    pub async fn pluck_with_session<T: DeserializeOwned>(
+        &self,
+        field: &str,
+        session: &mut ClientSession,
+    ) -> Result<Vec<T>> {
+        let (filter, _) = self.prepare_get();
+        let key = self.db_name(field);
+        let collection = self.coll::<Document>();
+        let mut find = self.prepare_find(collection.find(filter));
+        find = find.projection(doc! {&key: 1});
+
+        let mut r = vec![];
+        let mut cursor = find.session(&mut *session).await?;
+        while let Some(d) = cursor.next(&mut *session).await {
+            if let Some(value) = self.hydrate(d?).remove(&key) {
+                r.push(bson::from_bson(value)?);
             }
         }
+        Ok(r)
     }
 
-    /// Deletes documents from the collection with session
+    /// Samples documents and computes statistics of one field
     ///
     /// # Arguments
-    /// * `session` - Optional MongoDB transaction session
+    /// * `field` - Model field name, renames are applied
+    /// * `sample_size` - Number of random documents to inspect
     ///
     /// # Notes
-    /// - Handles both single and multi-document deletes based on `all()` setting
-    pub async fn delete_with_session(&self, session: &mut ClientSession) -> Result<Document> {
-        let whr = &self.query_builder.r#where;
-        if whr.is_empty() {
-            return Err(Error::from(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "where not set.",
-            )));
+    /// - Respects the current filters
+    /// - Cardinality and ratios are estimates from the sample
+    pub async fn field_stats(&self, field: &str, sample_size: u32) -> Result<FieldStats> {
+        let (filter, _) = self.prepare_get();
+        let path = format!("${}", self.db_name(field));
+        let pipeline = vec![
+            doc! {"$match": filter},
+            doc! {"$sample": {"size": sample_size.max(1) as i64}},
+            doc! {"$group": {
+                "_id": Bson::Null,
+                "count": {"$sum": 1},
+                "nulls": {"$sum": {"$cond": [{"$eq": [{"$ifNull": [&path, Bson::Null]}, Bson::Null]}, 1, 0]}},
+                "min": {"$min": &path},
+                "max": {"$max": &path},
+                "avg_size": {"$avg": {"$cond": [
+                    {"$eq": [{"$type": &path}, "missing"]},
+                    0,
+                    {"$subtract": [{"$bsonSize": {"v": &path}}, 8]},
+                ]}},
+                "values": {"$addToSet": &path},
+            }},
+            doc! {"$project": {
+                "count": 1, "nulls": 1, "min": 1, "max": 1, "avg_size": 1,
+                "distinct": {"$size": "$values"},
+            }},
+        ];
+        let d = self
+            .aggregate_documents(&self.coll(), pipeline, self.aggregate_options())
+            .await?
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        Ok(FieldStats::from_document(field, &d))
+    }
+
+    /// Checks whether any document matches the filters
+    ///
+    /// # Notes
+    /// - Runs a limit 1 query projecting only `_id`
+    pub async fn exists(&self) -> Result<bool> {
+        in_current_session!(s => self.exists_with_session(s));
+        let (filter, _) = self.prepare_get();
+        let collection = self.coll::<Document>();
+        let r = collection
+            .find_one(filter)
+            .projection(doc! {"_id": 1})
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .await?;
+        Ok(r.is_some())
+    }
+    /// Checks whether any document matches the filters with session
+    pub async fn exists_with_session(&self, session: &mut ClientSession) -> Result<bool> {
+        let (filter, _) = self.prepare_get();
+        let collection = self.coll::<Document>();
+        let r = collection
+            .find_one(filter)
+            .projection(doc! {"_id": 1})
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .session(session)
+            .await?;
+        Ok(r.is_some())
+    }
+
+    /// Returns which of `ids` belong to a matching document
+    ///
+    /// # Notes
+    /// - Runs a single `_id $in` query projecting only `_id`, e.g. to validate
+    ///   the references of a bulk import
+    /// - Other filters apply too, so ids of documents filtered out aren't returned
+    pub async fn exists_many(
+        &self,
+        ids: impl IntoIterator<Item = ObjectId>,
+    ) -> Result<HashSet<ObjectId>> {
+        in_current_session!(s => self.exists_many_with_session(ids, s));
+        let Some(filter) = self.exists_many_filter(ids) else {
+            return Ok(HashSet::new());
+        };
+        let mut cursor = self
+            .coll::<Document>()
+            .find(filter)
+            .projection(doc! {"_id": 1})
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .await?;
+        let mut found = HashSet::new();
+        while let Some(d) = cursor.next().await {
+            found.extend(d?.get_object_id("_id").ok());
+        }
+        Ok(found)
+    }
+    /// Returns which of `ids` belong to a matching document with session
+    pub async fn exists_many_with_session(
+        &self,
+        ids: impl IntoIterator<Item = ObjectId>,
+        session: &mut ClientSession,
+    ) -> Result<HashSet<ObjectId>> {
+        let Some(filter) = self.exists_many_filter(ids) else {
+            return Ok(HashSet::new());
+        };
+        let mut cursor = self
+            .coll::<Document>()
+            .find(filter)
+            .projection(doc! {"_id": 1})
+            .optional(self.command_comment(), |a, c| a.comment(c))
+            .session(&mut *session)
+            .await?;
+        let mut found = HashSet::new();
+        while let Some(d) = cursor.next(&mut *session).await {
+            found.extend(d?.get_object_id("_id").ok());
         }
-        let filter = doc! {"$and":whr};
+        Ok(found)
+    }
+    fn exists_many_filter(&self, ids: impl IntoIterator<Item = ObjectId>) -> Option<Document> {
+        let ids = ids.into_iter().collect::<HashSet<_>>();
+        if ids.is_empty() {
+            return None;
+        }
+        let (filter, _) = self.prepare_get();
+        let ids = doc! {"_id": {"$in": ids.into_iter().collect::<Vec<_>>()}};
+        Some(if filter.is_empty() {
+            ids
+        } else {
+            doc! {"$and": [filter, ids]}
+        })
+    }
 
-        let r = self.db.collection::<Document>(self.collection_name);
-        if self.query_builder.all {
-            let r = r.delete_many(filter).session(&mut *session).await;
-            match r {
-                Ok(old) => {
-                    let res = doc! {"deleted_count":old.deleted_count.to_string()};
-                    self.finish(
-                        &self.req,
-                        "delete_many",
-                        res.clone(),
-                        doc! {},
-                        Some(session),
-                    )
-                    .await;
-                    Ok(res)
-                }
-                Err(e) => Err(e),
-            }
+    /// Gets the first matching document
+    pub async fn first(&mut self) -> Result<Option<M>> {
+        self.query_builder.with_heavy = true;
+        self.query_builder.limit = 1;
+        let r = self.get().await?;
+        Ok(r.into_iter().next())
+    }
+    fn recency_sort(&self, order: Order) -> Document {
+        if self.add_times {
+            doc! {"created_at": order.value(), "_id": order.value()}
         } else {
-            let r = r
-                .find_one_and_delete(filter)
-                .sort(self.query_builder.sort.clone())
-                .session(&mut *session)
-                .await;
-            match r {
-                Ok(old) => {
-                    let res = old.unwrap_or(Document::new());
-                    self.finish(&self.req, "delete", res.clone(), doc! {}, Some(session))
-                        .await;
-                    Ok(res)
-                }
-                Err(e) => Err(e),
-            }
+            doc! {"_id": order.value()}
+        }
+    }
+    /// Gets the most recently created matching document
+    ///
+    /// # Notes
+    /// - Sorts by `created_at` when timestamps are enabled, by `_id` otherwise
+    pub async fn latest(&mut self) -> Result<Option<M>> {
+        self.query_builder.sort = self.recency_sort(Order::Desc);
+        self.first().await
+    }
+    /// Gets the oldest matching document
+    pub async fn oldest(&mut self) -> Result<Option<M>> {
+        self.query_builder.sort = self.recency_sort(Order::Asc);
+        self.first().await
+    }
+    /// Gets the most recently created matching document with session
+    pub async fn latest_with_session(&mut self, session: &mut ClientSession) -> Result<Option<M>> {
+        self.query_builder.sort = self.recency_sort(Order::Desc);
+        self.first_with_session(session).await
+    }
+    /// Gets the oldest matching document with session
+    pub async fn oldest_with_session(&mut self, session: &mut ClientSession) -> Result<Option<M>> {
+        self.query_builder.sort = self.recency_sort(Order::Asc);
+        self.first_with_session(session).await
+    }
+    /// Gets the first matching document with session
+    pub async fn first_with_session(&mut self, session: &mut ClientSession) -> Result<Option<M>> {
+        self.query_builder.with_heavy = true;
+        self.query_builder.limit = 1;
+        let r = self.get_with_session(session).await?;
+        Ok(r.into_iter().next())
+    }
+
+    fn where_id(&mut self, id: impl IntoObjectId) -> Result<()> {
+        let id = id.into_object_id()?;
+        self.query_builder.and_all(doc! {"_id": id});
+        self.query_builder.all = false;
+        Ok(())
+    }
+    fn typed(&self, data: Document) -> Result<Option<M>> {
+        if data.is_empty() {
+            return Ok(None);
         }
+        self.decode(data, &self.hidden_fields())
     }
-    fn prepare_get(&self) -> (Document, Vec<String>) {
-        let whr = &self.query_builder.r#where;
-        let filter = if whr.is_empty() {
-            doc! {}
-        } else {
-            doc! {"$and":whr}
-        };
-        let hidden_fields = self.hidden_fields();
-        (filter, hidden_fields)
+    /// Gets a document by `_id`
+    ///
+    /// # Arguments
+    /// * `id` - `ObjectId` or its hex string
+    pub async fn find_by_id(&mut self, id: impl IntoObjectId) -> Result<Option<M>> {
+        self.where_id(id)?;
+        self.first().await
     }
-
-    fn prepare_find<'b>(&self, mut find: Find<'b, Document>) -> Find<'b, Document> {
-        find = find.sort(self.query_builder.sort.clone());
-
-        if self.query_builder.skip > 0 {
-            find = find.skip(self.query_builder.skip as u64);
+    /// Gets a document by `_id` with session
+    pub async fn find_by_id_with_session(
+        &mut self,
+        id: impl IntoObjectId,
+        session: &mut ClientSession,
+    ) -> Result<Option<M>> {
+        self.where_id(id)?;
+        self.first_with_session(session).await
+    }
+    /// Gets the obfuscated public id of `id`, `None` when the model has no public id salt
+    pub fn public_id(&self, id: &ObjectId) -> Option<String> {
+        let salt = self.options.public_id.as_deref()?;
+        Some(public_id::encode(salt, id))
+    }
+    /// Gets a document by its public id
+    ///
+    /// # Notes
+    /// - Malformed or foreign public ids find nothing
+    /// - Fails when the model has no public id salt
+    pub async fn find_by_public_id(&mut self, public_id: &str) -> Result<Option<M>> {
+        let Some(salt) = self.options.public_id.clone() else {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "public_id not configured.",
+            )));
+        };
+        match public_id::decode(&salt, public_id) {
+            Some(id) => self.find_by_id(id).await,
+            None => Ok(None),
         }
-        if self.query_builder.limit > 0 {
-            find = find.limit(self.query_builder.limit as i64);
+    }
+    /// Converts a model to JSON, replacing `_id` with its public id when configured
+    ///
+    /// Dates and ObjectIds are written in the model's `datetime_json` and `object_id_json` formats.
+    pub fn to_json(&self, item: &M) -> Result<serde_json::Value> {
+        let mut value = serde_json::to_value(item).map_err(Error::custom)?;
+        let id = to_document(item).ok().and_then(|d| d.get_object_id("_id").ok());
+        if let (Some(salt), Some(id), Some(object)) =
+            (&self.options.public_id, id, value.as_object_mut())
+        {
+            object.insert("_id".to_string(), public_id::encode(salt, &id).into());
         }
-        if self.query_builder.batch_size > 0 {
-            find = find.batch_size(self.query_builder.batch_size);
+        format_json_dates(&mut value, self.options.datetime_json);
+        format_json_ids(&mut value, self.options.object_id_json);
+        Ok(value)
+    }
+    /// Fills the model from JSON, e.g. written by `to_json` or sent by a client
+    ///
+    /// # Notes
+    /// - A string `_id` is read as a public id when configured, as a hex ObjectId otherwise
+    /// - Fails on an `_id` that can't be decoded
+    /// - Reads ids and dates in the formats `to_json` writes, see [`crate::json`]
+    pub fn fill_from_json(self, mut value: serde_json::Value) -> Result<Model<'a, M>> {
+        if let Some(object) = value.as_object_mut()
+            && let Some(serde_json::Value::String(id)) = object.get("_id")
+        {
+            let id = match &self.options.public_id {
+                Some(salt) => public_id::decode(salt, id),
+                None => ObjectId::parse_str(id).ok(),
+            };
+            let Some(id) = id else {
+                return Err(Error::from(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "invalid id.",
+                )));
+            };
+            object.insert("_id".to_string(), serde_json::json!({"$oid": id.to_hex()}));
         }
-        if let Some(select) = self.query_builder.select.clone() {
-            find = find.projection(select);
+        let inner = json::from_json(value, self.options.object_id_json, self.options.datetime_json)
+            .map_err(Error::custom)?;
+        Ok(self.fill(inner))
+    }
+    /// Deletes a document by `_id`, returning the deleted document
+    pub async fn delete_by_id(&mut self, id: impl IntoObjectId) -> Result<Option<M>> {
+        self.where_id(id)?;
+        let r = self.delete().await?;
+        self.typed(r)
+    }
+    /// Deletes a document by `_id` with session, returning the deleted document
+    pub async fn delete_by_id_with_session(
+        &mut self,
+        id: impl IntoObjectId,
+        session: &mut ClientSession,
+    ) -> Result<Option<M>> {
+        self.where_id(id)?;
+        let r = self.delete_with_session(session).await?;
+        self.typed(r)
+    }
+    /// Updates a document by `_id`, returning the document as it is after the update
+    ///
+    /// # Arguments
+    /// * `id` - `ObjectId` or its hex string
+    /// * `data` - Update operations
+    pub async fn update_by_id(&mut self, id: impl IntoObjectId, data: Document) -> Result<Option<M>> {
+        in_current_session!(s => self.update_by_id_with_session(id, data, s));
+        self.where_id(id)?;
+        let (data, filter) = self.prepare_update(data)?;
+        let (old, new, _) = self.update_images(filter, data.clone(), None).await?;
+        self.finish(&self.req, "update", old, data, None).await;
+        self.typed(new)
+    }
+    /// Updates a document by `_id` with session, returning the document as it is after the update
+    pub async fn update_by_id_with_session(
+        &mut self,
+        id: impl IntoObjectId,
+        data: Document,
+        session: &mut ClientSession,
+    ) -> Result<Option<M>> {
+        self.where_id(id)?;
+        let (data, filter) = self.prepare_update(data)?;
+        let (old, new, session) = self.update_images(filter, data.clone(), Some(session)).await?;
+        self.finish(&self.req, "update", old, data, session).await;
+        self.typed(new)
+    }
+
+    /// Runs an aggregation pipeline
+    pub async fn aggregate(
+        &mut self,
+        pipeline: impl IntoIterator<Item = Document>,
+    ) -> Result<Vec<M>> {
+        in_current_session!(s => self.aggregate_with_session(pipeline, s));
+        let collection = self.coll::<Document>();
+        let res = self.prepare_aggregate(collection.aggregate(pipeline));
+        let hidden_fields = self.hidden_fields();
+        let mut r = vec![];
+        let started = Instant::now();
+        let mut cursor = self.interruptible(started, res).await??;
+        while let Some(d) = self.interruptible(started, cursor.next()).await? {
+            r.extend(self.decode(d?, &hidden_fields)?)
         }
-        find
+        Ok(r)
     }
 
-    /// Queries documents from the collection
-    ///
+    /// Gets up to `n` random matching documents
     ///
     /// # Notes
-    /// - Respects skip/limit/sort/select settings
-    /// - Filters out hidden fields unless explicitly made visible
-    pub async fn get(&self) -> Result<Vec<M>> {
+    /// - Runs a `$sample` aggregation after the filter, the same document isn't returned twice
+    /// - Sort, skip and limit settings are ignored, select and hidden fields apply
+    pub async fn sample(&self, n: u32) -> Result<Vec<M>> {
+        self.check_read(n, 0)?;
         let (filter, hidden_fields) = self.prepare_get();
-        let collection = self.db.collection::<Document>(self.collection_name);
-        let mut find = collection.find(filter);
-        find = self.prepare_find(find);
-
+        let mut pipeline = vec![
+            doc! {"$match": filter},
+            doc! {"$sample": {"size": n.max(1) as i64}},
+        ];
+        if let Some(projection) = self.projection() {
+            pipeline.push(doc! {"$project": projection});
+        }
+        let docs = self
+            .aggregate_documents(&self.coll(), pipeline, self.aggregate_options())
+            .await?;
         let mut r = vec![];
-        let mut cursor = find.await?;
-        while let Some(d) = cursor.next().await {
-            r.push(self.clear(self.cast(d?, &self.req), &hidden_fields))
+        for d in docs {
+            r.extend(self.decode(d, &hidden_fields)?)
         }
         Ok(r)
     }
 
-    /// Queries documents from the collection with session
+    /// Queries documents and joins the documents of `other` whose `foreign_field` equals `local_field`
     ///
     /// # Arguments
-    /// * `session` - Optional MongoDB transaction session
+    /// * `other` - Model of the joined collection, its renames and hidden fields apply to the joined documents
     ///
     /// # Notes
-    /// - Respects skip/limit/sort/select settings
-    /// - Filters out hidden fields unless explicitly made visible
-    pub async fn get_with_session(&self, session: &mut ClientSession) -> Result<Vec<M>> {
+    /// - Runs one aggregation with a `$lookup` stage
+    /// - Respects skip/limit/sort settings, select is ignored
+    pub async fn get_with<O>(
+        &self,
+        other: &Model<'_, O>,
+        local_field: &str,
+        foreign_field: &str,
+    ) -> Result<Vec<Joined<M, O>>>
+    where
+        O: Boot + Default + Serialize + DeserializeOwned + Send + Sync + Unpin,
+    {
+        self.check_read(self.query_builder.limit, self.query_builder.skip)?;
         let (filter, hidden_fields) = self.prepare_get();
-        let collection = self.db.collection::<Document>(self.collection_name);
-        let mut find = collection.find(filter);
-        find = self.prepare_find(find);
+        let mut pipeline = self.join_stages(filter);
+        pipeline.push(doc! {"$lookup": {
+            "from": other.collection_name,
+            "localField": self.db_name(local_field),
+            "foreignField": other.db_name(foreign_field),
+            "as": "__joined",
+        }});
+        self.aggregate_joined(pipeline, other, &hidden_fields).await
+    }
 
-        let mut r = vec![];
-        let mut cursor = find.session(&mut *session).await?;
-        while let Some(d) = cursor.next(&mut *session).await {
-            r.push(self.clear(self.cast(d?, &self.req), &hidden_fields))
+    /// Queries documents and joins the documents of `other` reached recursively from them
+    ///
+    /// Starting from the `other` documents whose `connect_to` equals `start_with`,
+    /// follows `connect_from` to `connect_to` again, e.g. the reports of a manager
+    /// or the subcategories of a category.
+    ///
+    /// # Arguments
+    /// * `other` - Model of the traversed collection, can be this model's collection
+    /// * `start_with` - Field of this model the traversal starts from
+    /// * `connect_from` - Field of `other` holding the value of the next hop
+    /// * `connect_to` - Field of `other` matched against `start_with` and `connect_from`
+    /// * `max_depth` - Maximum number of hops after the first, `None` for no limit
+    ///
+    /// # Notes
+    /// - Runs one aggregation with a `$graphLookup` stage
+    /// - Respects skip/limit/sort settings, select is ignored
+    /// - The joined documents are in no particular order
+    pub async fn graph_lookup<O>(
+        &self,
+        other: &Model<'_, O>,
+        start_with: &str,
+        connect_from: &str,
+        connect_to: &str,
+        max_depth: Option<u32>,
+    ) -> Result<Vec<Joined<M, O>>>
+    where
+        O: Boot + Default + Serialize + DeserializeOwned + Send + Sync + Unpin,
+    {
+        self.check_read(self.query_builder.limit, self.query_builder.skip)?;
+        let (filter, hidden_fields) = self.prepare_get();
+        let mut pipeline = self.join_stages(filter);
+        let mut stage = doc! {
+            "from": other.collection_name,
+            "startWith": format!("${}", self.db_name(start_with)),
+            "connectFromField": other.db_name(connect_from),
+            "connectToField": other.db_name(connect_to),
+            "as": "__joined",
+        };
+        if let Some(max_depth) = max_depth {
+            stage.insert("maxDepth", max_depth as i64);
         }
-        Ok(r)
+        pipeline.push(doc! {"$graphLookup": stage});
+        self.aggregate_joined(pipeline, other, &hidden_fields).await
     }
 
-    /// Gets the first matching document
-    pub async fn first(&mut self) -> Result<Option<M>> {
-        self.query_builder.limit = 1;
-        let r = self.get().await?;
-        for item in r {
-            return Ok(Some(item));
+    /// `$match`, `$sort`, `$skip` and `$limit` stages selecting the documents to join to
+    fn join_stages(&self, filter: Document) -> Vec<Document> {
+        let mut pipeline = vec![doc! {"$match": filter}];
+        let sort = self.sort_document();
+        if !sort.is_empty() {
+            pipeline.push(doc! {"$sort": sort});
         }
-        Ok(None)
-    }
-    /// Gets the first matching document with session
-    pub async fn first_with_session(&mut self, session: &mut ClientSession) -> Result<Option<M>> {
-        self.query_builder.limit = 1;
-        let r = self.get_with_session(session).await?;
-        for item in r {
-            return Ok(Some(item));
+        if self.query_builder.skip > 0 {
+            pipeline.push(doc! {"$skip": self.query_builder.skip as i64});
+        }
+        if self.query_builder.limit > 0 {
+            pipeline.push(doc! {"$limit": self.query_builder.limit as i64});
         }
-        Ok(None)
+        pipeline
     }
 
-    /// Runs an aggregation pipeline
-    pub async fn aggregate(
-        &mut self,
-        pipeline: impl IntoIterator<Item = Document>,
-    ) -> Result<Vec<M>> {
-        let collection = self.db.collection::<Document>(self.collection_name);
-        let res = collection.aggregate(pipeline);
-        let hidden_fields = self.hidden_fields();
+    /// Runs a pipeline whose documents hold their joined `other` documents in `__joined`
+    async fn aggregate_joined<O>(
+        &self,
+        pipeline: Vec<Document>,
+        other: &Model<'_, O>,
+        hidden_fields: &[String],
+    ) -> Result<Vec<Joined<M, O>>>
+    where
+        O: Boot + Default + Serialize + DeserializeOwned + Send + Sync + Unpin,
+    {
+        let other_hidden_fields = other.hidden_fields();
+        let docs = self
+            .aggregate_documents(&self.coll(), pipeline, self.aggregate_options())
+            .await?;
         let mut r = vec![];
-        let mut cursor = res.await?;
-        while let Some(d) = cursor.next().await {
-            r.push(self.clear(self.cast(d?, &self.req), &hidden_fields))
+        for mut d in docs {
+            let joined = match d.remove("__joined") {
+                Some(Bson::Array(items)) => items,
+                _ => vec![],
+            };
+            let Some(item) = self.decode(d, hidden_fields)? else {
+                continue;
+            };
+            let mut items = vec![];
+            for j in joined {
+                if let Bson::Document(j) = j {
+                    items.extend(other.decode(j, &other_hidden_fields)?);
+                }
+            }
+            r.push(Joined { item, joined: items });
         }
         Ok(r)
     }
@@ -962,13 +4796,14 @@ where
         pipeline: impl IntoIterator<Item = Document>,
         session: &mut ClientSession,
     ) -> Result<Vec<M>> {
-        let collection = self.db.collection::<Document>(self.collection_name);
-        let res = collection.aggregate(pipeline);
+        let collection = self.coll::<Document>();
+        let res = self.prepare_aggregate(collection.aggregate(pipeline));
         let hidden_fields = self.hidden_fields();
         let mut r = vec![];
-        let mut cursor = res.session(&mut *session).await?;
-        while let Some(d) = cursor.next(&mut *session).await {
-            r.push(self.clear(self.cast(d?, &self.req), &hidden_fields))
+        let started = Instant::now();
+        let mut cursor = self.interruptible(started, res.session(&mut *session)).await??;
+        while let Some(d) = self.interruptible(started, cursor.next(&mut *session)).await? {
+            r.extend(self.decode(d?, &hidden_fields)?)
         }
         Ok(r)
     }
@@ -980,15 +4815,18 @@ where
     /// - Respects skip/limit/sort/select settings
     /// - Filters out hidden fields unless explicitly made visible
     pub async fn get_doc(&self) -> Result<Vec<Document>> {
+        in_current_session!(s => self.get_doc_with_session(s));
+        self.check_read(self.query_builder.limit, self.query_builder.skip)?;
         let (filter, _) = self.prepare_get();
-        let collection = self.db.collection::<Document>(self.collection_name);
+        let collection = self.coll::<Document>();
         let mut find = collection.find(filter);
         find = self.prepare_find(find);
 
         let mut r = vec![];
-        let mut cursor = find.await?;
-        while let Some(d) = cursor.next().await {
-            r.push(self.cast(d?, &self.req))
+        let started = Instant::now();
+        let mut cursor = self.interruptible(started, find).await??;
+        while let Some(d) = self.interruptible(started, cursor.next()).await? {
+            r.push(self.cast(self.hydrate(d?), &self.req))
         }
         Ok(r)
     }
@@ -1002,39 +4840,37 @@ where
     /// - Respects skip/limit/sort/select settings
     /// - Filters out hidden fields unless explicitly made visible
     pub async fn get_doc_with_session(&self, session: &mut ClientSession) -> Result<Vec<Document>> {
+        self.check_read(self.query_builder.limit, self.query_builder.skip)?;
         let (filter, _) = self.prepare_get();
-        let collection = self.db.collection::<Document>(self.collection_name);
+        let collection = self.coll::<Document>();
         let mut find = collection.find(filter);
         find = self.prepare_find(find);
 
         let mut r = vec![];
-        let mut cursor = find.session(&mut *session).await?;
-        while let Some(d) = cursor.next(&mut *session).await {
-            r.push(self.cast(d?, &self.req))
+        let started = Instant::now();
+        let mut cursor = self.interruptible(started, find.session(&mut *session)).await??;
+        while let Some(d) = self.interruptible(started, cursor.next(&mut *session)).await? {
+            r.push(self.cast(self.hydrate(d?), &self.req))
         }
         Ok(r)
     }
 
     /// Gets the first matching document
     pub async fn first_doc(&mut self) -> Result<Option<Document>> {
+        self.query_builder.with_heavy = true;
         self.query_builder.limit = 1;
         let r = self.get_doc().await?;
-        for item in r {
-            return Ok(Some(item));
-        }
-        Ok(None)
+        Ok(r.into_iter().next())
     }
     /// Gets the first matching document with session
     pub async fn first_doc_with_session(
         &mut self,
         session: &mut ClientSession,
     ) -> Result<Option<Document>> {
+        self.query_builder.with_heavy = true;
         self.query_builder.limit = 1;
         let r = self.get_doc_with_session(session).await?;
-        for item in r {
-            return Ok(Some(item));
-        }
-        Ok(None)
+        Ok(r.into_iter().next())
     }
 
     /// Runs an aggregation pipeline
@@ -1042,12 +4878,14 @@ where
         &mut self,
         pipeline: impl IntoIterator<Item = Document>,
     ) -> Result<Vec<Document>> {
-        let collection = self.db.collection::<Document>(self.collection_name);
-        let res = collection.aggregate(pipeline);
+        in_current_session!(s => self.aggregate_doc_with_session(pipeline, s));
+        let collection = self.coll::<Document>();
+        let res = self.prepare_aggregate(collection.aggregate(pipeline));
         let mut r = vec![];
-        let mut cursor = res.await?;
-        while let Some(d) = cursor.next().await {
-            r.push(self.cast(d?, &self.req))
+        let started = Instant::now();
+        let mut cursor = self.interruptible(started, res).await??;
+        while let Some(d) = self.interruptible(started, cursor.next()).await? {
+            r.push(self.cast(self.hydrate(d?), &self.req))
         }
         Ok(r)
     }
@@ -1058,47 +4896,447 @@ where
         pipeline: impl IntoIterator<Item = Document>,
         session: &mut ClientSession,
     ) -> Result<Vec<Document>> {
-        let collection = self.db.collection::<Document>(self.collection_name);
-        let res = collection.aggregate(pipeline);
+        let collection = self.coll::<Document>();
+        let res = self.prepare_aggregate(collection.aggregate(pipeline));
         let mut r = vec![];
-        let mut cursor = res.session(&mut *session).await?;
-        while let Some(d) = cursor.next(&mut *session).await {
-            r.push(self.cast(d?, &self.req))
+        let started = Instant::now();
+        let mut cursor = self.interruptible(started, res.session(&mut *session)).await??;
+        while let Some(d) = self.interruptible(started, cursor.next(&mut *session)).await? {
+            r.push(self.cast(self.hydrate(d?), &self.req))
+        }
+        Ok(r)
+    }
+
+    /// Runs an aggregation pipeline, deserializing each result into `T`
+    ///
+    /// # Notes
+    /// - For pipelines that reshape documents, field names are the database names
+    /// - Compressed fields are decompressed, casts and hidden fields don't apply
+    pub async fn aggregate_as<T: DeserializeOwned>(
+        &mut self,
+        pipeline: impl IntoIterator<Item = Document>,
+    ) -> Result<Vec<T>> {
+        in_current_session!(s => self.aggregate_as_with_session(pipeline, s));
+        let collection = self.coll::<Document>();
+        let res = self.prepare_aggregate(collection.aggregate(pipeline));
+        let mut r = vec![];
+        let started = Instant::now();
+        let mut cursor = self.interruptible(started, res).await??;
+        while let Some(d) = self.interruptible(started, cursor.next()).await? {
+            r.push(bson::from_document(self.hydrate(d?))?)
+        }
+        Ok(r)
+    }
+
+    /// Runs an aggregation pipeline with session, deserializing each result into `T`
+    pub async fn aggregate_as_with_session<T: DeserializeOwned>(
+        &mut self,
+        pipeline: impl IntoIterator<Item = Document>,
+        session: &mut ClientSession,
+    ) -> Result<Vec<T>> {
+        let collection = self.coll::<Document>();
+        let res = self.prepare_aggregate(collection.aggregate(pipeline));
+        let mut r = vec![];
+        let started = Instant::now();
+        let mut cursor = self.interruptible(started, res.session(&mut *session)).await??;
+        while let Some(d) = self.interruptible(started, cursor.next(&mut *session)).await? {
+            r.push(bson::from_document(self.hydrate(d?))?)
         }
         Ok(r)
     }
 
+    /// Streams matching documents as typed models
+    ///
+    /// # Notes
+    /// - Respects skip/limit/sort/select/batch_size settings
+    /// - Filters out hidden fields unless explicitly made visible, like `get()`
+    /// - Ends with an [`Interrupted`](crate::error::Interrupted) error when cancelled
+    ///   or when the timeout elapses, counted from this call
+    pub async fn stream(&self) -> Result<impl Stream<Item = Result<M>> + '_> {
+        let started = Instant::now();
+        self.check_read(self.query_builder.limit, self.query_builder.skip)?;
+        let (filter, hidden_fields) = self.prepare_get();
+        let collection = self.coll::<Document>();
+        let find = self.prepare_find(collection.find(filter));
+        let cursor = self.interruptible(started, find).await??;
+        Ok(futures::stream::unfold(
+            Some((cursor, hidden_fields)),
+            move |state| async move {
+                let (mut cursor, hidden_fields) = state?;
+                loop {
+                    let d = match self.interruptible(started, cursor.next()).await {
+                        Ok(d) => d?,
+                        Err(e) => return Some((Err(e), None)),
+                    };
+                    if let Some(item) = d.and_then(|d| self.decode(d, &hidden_fields)).transpose() {
+                        return Some((item, Some((cursor, hidden_fields))));
+                    }
+                }
+            },
+        ))
+    }
+
+    /// Streams matching documents as typed models with session
+    pub async fn stream_with_session<'s>(
+        &'s self,
+        session: &'s mut ClientSession,
+    ) -> Result<impl Stream<Item = Result<M>> + 's> {
+        let started = Instant::now();
+        self.check_read(self.query_builder.limit, self.query_builder.skip)?;
+        let (filter, hidden_fields) = self.prepare_get();
+        let collection = self.coll::<Document>();
+        let find = self.prepare_find(collection.find(filter));
+        let cursor = self.interruptible(started, find.session(&mut *session)).await??;
+        Ok(futures::stream::unfold(
+            Some((cursor, session, hidden_fields)),
+            move |state| async move {
+                let (mut cursor, session, hidden_fields) = state?;
+                loop {
+                    let d = match self.interruptible(started, cursor.next(&mut *session)).await {
+                        Ok(d) => d?,
+                        Err(e) => return Some((Err(e), None)),
+                    };
+                    if let Some(item) = d.and_then(|d| self.decode(d, &hidden_fields)).transpose() {
+                        return Some((item, Some((cursor, session, hidden_fields))));
+                    }
+                }
+            },
+        ))
+    }
+
+    /// Follows the documents inserted in a capped collection, like `tail -f`
+    ///
+    /// Yields the matching documents already there, then the new ones as they
+    /// are inserted. The stream never ends, drop it to stop.
+    ///
+    /// # Notes
+    /// - Only works on capped collections, the server rejects tailable cursors on others
+    /// - Uses `await_data()` unless `tailable()` was called
+    /// - Reopens the cursor after the last read `_id` when the server closes it,
+    ///   e.g. while the collection is empty
+    /// - Sort and skip are ignored, documents come in insertion order
+    #[cfg(feature = "rt-tokio")]
+    pub fn tail_stream(&self) -> impl Stream<Item = Result<M>> + '_ {
+        let (filter, hidden_fields) = self.prepare_get();
+        let collection = self.coll::<Document>();
+        let mut options = self.find_options();
+        options.sort = None;
+        options.skip = None;
+        options.cursor_type = options.cursor_type.or(Some(CursorType::TailableAwait));
+        let state = (None::<mongodb::Cursor<Document>>, None::<Bson>);
+        futures::stream::unfold(state, move |(mut cursor, mut last_id)| {
+            let (filter, hidden_fields, collection, options) =
+                (filter.clone(), hidden_fields.clone(), collection.clone(), options.clone());
+            async move {
+                loop {
+                    let open = match cursor.as_mut() {
+                        Some(open) => open,
+                        None => {
+                            let filter = match &last_id {
+                                None => filter.clone(),
+                                Some(id) => doc! {"$and": [filter.clone(), {"_id": {"$gt": id}}]},
+                            };
+                            match collection.find(filter).with_options(options.clone()).await {
+                                Ok(open) => cursor.insert(open),
+                                Err(e) => return Some((Err(e), (None, last_id))),
+                            }
+                        }
+                    };
+                    match open.next().await {
+                        Some(Ok(d)) => {
+                            last_id = d.get("_id").cloned();
+                            if let Some(item) = self.decode(d, &hidden_fields).transpose() {
+                                return Some((item, (cursor, last_id)));
+                            }
+                        }
+                        Some(Err(e)) => return Some((Err(e), (None, last_id))),
+                        None => {
+                            cursor = None;
+                            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                        }
+                    }
+                }
+            }
+        })
+    }
+
     /// Creates a cursor for iterating over documents in the collection.
     ///
     ///
     /// # Returns
-    /// - `Result<Cursor<Document>>`: A MongoDB cursor that can be used to iterate over documents
+    /// - A stream of the documents, with compressed fields decompressed
     /// - Returns an error if the query execution fails
     ///
     /// # Example
-    /// ```rust
+    /// ```ignore
     /// let cursor = User::new_model(db).cursor().await?;
     /// while let Some(doc) = cursor.next().await {
     ///     // process document
     /// }
     /// ```
-    pub async fn cursor(&self) -> Result<Cursor<Document>> {
+    pub async fn cursor(&self) -> Result<impl Stream<Item = Result<Document>> + Unpin + use<M>> {
+        self.check_read(self.query_builder.limit, self.query_builder.skip)?;
         let (filter, _) = self.prepare_get();
-        let collection = self.db.collection::<Document>(self.collection_name);
+        let collection = self.coll::<Document>();
         let mut find = collection.find(filter);
         find = self.prepare_find(find).no_cursor_timeout(true);
         let cursor = find.await?;
-        Ok(cursor)
+        let fields = self.compressed_fields();
+        Ok(cursor.map(move |d| d.map(|d| decompress_fields(d, &fields))))
     }
-    pub async fn cursor_with_session(
-        &self,
-        session: &mut ClientSession,
-    ) -> Result<SessionCursor<Document>> {
+    /// Creates a cursor for iterating over documents with session, see `cursor`
+    pub async fn cursor_with_session<'s>(
+        &'s self,
+        session: &'s mut ClientSession,
+    ) -> Result<impl Stream<Item = Result<Document>> + 's> {
+        self.check_read(self.query_builder.limit, self.query_builder.skip)?;
         let (filter, _) = self.prepare_get();
-        let collection = self.db.collection::<Document>(self.collection_name);
+        let collection = self.coll::<Document>();
         let mut find = collection.find(filter);
         find = self.prepare_find(find).no_cursor_timeout(true);
-        let cursor = find.session(session).await?;
-        Ok(cursor)
+        let cursor = find.session(&mut *session).await?;
+        Ok(futures::stream::unfold(
+            (cursor, session),
+            move |(mut cursor, session)| async move {
+                let d = cursor.next(&mut *session).await?;
+                Some((d.map(|d| self.hydrate(d)), (cursor, session)))
+            },
+        ))
+    }
+
+    /// Copies the matching documents into another database
+    ///
+    /// # Arguments
+    /// * `db` - Target database, may live on another cluster
+    /// * `options` - Target collection, batching, index replication and transform
+    ///
+    /// # Notes
+    /// - Respects skip/limit/sort/select settings
+    /// - Documents are copied as stored, hidden and compressed fields included
+    /// - Events are not fired for the inserted documents
+    pub async fn copy_to(&self, db: &Database, options: CopyOptions<'_>) -> Result<CopyReport> {
+        let started = Instant::now();
+        let source = self.coll::<Document>();
+        let target = db.collection::<Document>(options.collection.unwrap_or(self.collection_name));
+        let mut report = CopyReport::default();
+
+        if options.copy_indexes {
+            let mut indexes = vec![];
+            let mut cursor = source.list_indexes().await?;
+            while let Some(index) = cursor.next().await {
+                let index = index?;
+                let name = index.options.as_ref().and_then(|o| o.name.clone());
+                if name.as_deref() != Some("_id_") {
+                    report.indexes.extend(name);
+                    indexes.push(index);
+                }
+            }
+            if !indexes.is_empty() {
+                target.create_indexes(indexes).await?;
+            }
+        }
+
+        let batch_size = options.batch_size.max(1) as usize;
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut find_options = self.find_options();
+        find_options.projection = self.query_builder.select.clone();
+        let find = source.find(self.query_builder.filter()).with_options(find_options);
+        let mut cursor = self.interruptible(started, find).await??;
+        while let Some(d) = self.interruptible(started, cursor.next()).await? {
+            match options.apply(d?) {
+                Some(d) => batch.push(d),
+                None => report.skipped += 1,
+            }
+            if batch.len() >= batch_size {
+                target.insert_many(std::mem::take(&mut batch)).await?;
+                report.copied += batch_size as u64;
+            }
+        }
+        if !batch.is_empty() {
+            report.copied += batch.len() as u64;
+            target.insert_many(batch).await?;
+        }
+        Ok(report)
+    }
+
+    /// Writes the matching documents to `writer` as Extended JSON lines
+    ///
+    /// # Notes
+    /// - Respects skip/limit/sort/select settings
+    /// - Documents are written as stored, hidden and compressed fields included
+    /// - The output can be read by `mongoimport`
+    pub async fn export_jsonl(
+        &self,
+        writer: &mut impl std::io::Write,
+        mode: ExtJsonMode,
+    ) -> Result<u64> {
+        let started = Instant::now();
+        let collection = self.coll::<Document>();
+        let mut find_options = self.find_options();
+        find_options.projection = self.query_builder.select.clone();
+        let find = collection.find(self.query_builder.filter()).with_options(find_options);
+        let mut written = 0;
+        let mut cursor = self.interruptible(started, find).await??;
+        while let Some(d) = self.interruptible(started, cursor.next()).await? {
+            writeln!(writer, "{}", ext_json::to_line(d?, mode))?;
+            written += 1;
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// Inserts the Extended JSON lines of `reader`, e.g. written by `export_jsonl` or `mongoexport`
+    ///
+    /// # Arguments
+    /// * `batch_size` - Documents per `insert_many`
+    ///
+    /// # Notes
+    /// - Both canonical and relaxed lines are accepted, blank lines are skipped
+    /// - Documents are inserted as read, timestamps and events don't apply
+    /// - Fails on the first invalid line, earlier batches stay inserted
+    pub async fn import_jsonl(&self, reader: impl std::io::BufRead, batch_size: u32) -> Result<u64> {
+        let collection = self.coll::<Document>();
+        let batch_size = batch_size.max(1) as usize;
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut imported = 0;
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            batch.push(ext_json::from_line(&line)?);
+            if batch.len() >= batch_size {
+                collection.insert_many(std::mem::take(&mut batch)).await?;
+                imported += batch_size as u64;
+            }
+        }
+        if !batch.is_empty() {
+            imported += batch.len() as u64;
+            collection.insert_many(batch).await?;
+        }
+        Ok(imported)
+    }
+
+    /// Queries documents through an alternate [`Backend`]
+    ///
+    /// # Notes
+    /// - Respects skip/limit/sort/select settings
+    /// - Filters out hidden fields unless explicitly made visible
+    pub async fn get_via(&self, backend: &impl Backend) -> Result<Vec<M>> {
+        self.check_read(self.query_builder.limit, self.query_builder.skip)?;
+        let (filter, hidden_fields) = self.prepare_get();
+        let spec = FindSpec {
+            projection: self.query_builder.select.clone(),
+            sort: Some(self.sort_document()),
+            skip: self.query_builder.skip as u64,
+            limit: self.query_builder.limit as i64,
+        };
+        let docs = backend.find(self.collection_name, filter, spec).await?;
+        docs.into_iter()
+            .filter_map(|d| self.decode(d, &hidden_fields).transpose())
+            .collect()
+    }
+
+    /// Creates a new document through an alternate [`Backend`]
+    pub async fn create_via(&self, backend: &impl Backend) -> Result<Bson> {
+        let mut data = self.add_times_to_data(self.inner_to_doc()?)?;
+        let id = backend
+            .insert_one(self.collection_name, data.clone())
+            .await?;
+        data.insert("_id", id.clone());
+        self.finish(&self.req, "create", Document::new(), data, None)
+            .await;
+        Ok(id)
+    }
+
+    /// Updates documents through an alternate [`Backend`]
+    ///
+    /// # Notes
+    /// - Handles both single and multi-document updates based on `all()` setting
+    /// - The `max_affected` guard counts the matching documents through `backend` too
+    pub async fn update_via(&self, backend: &impl Backend, data: Document) -> Result<UpdateSummary> {
+        let (data, filter) = self.prepare_update(data)?;
+        self.check_affected_via(backend, "update_many", &filter).await?;
+        let r = backend
+            .update(
+                self.collection_name,
+                filter,
+                data.clone(),
+                self.query_builder.all,
+                self.query_builder.upsert,
+            )
+            .await?;
+        let typ = if self.query_builder.all { "update_many" } else { "update" };
+        let res = doc! {"modified_count": r.modified_count.to_string()};
+        self.finish(&self.req, typ, res, data, None).await;
+        Ok(r)
+    }
+
+    /// Deletes documents through an alternate [`Backend`]
+    ///
+    /// # Notes
+    /// - Handles both single and multi-document deletes based on `all()` setting
+    /// - The `max_affected` guard counts the matching documents through `backend` too
+    pub async fn delete_via(&self, backend: &impl Backend) -> Result<u64> {
+        self.check_write("delete")?;
+        if !self.query_builder.has_filter() {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "where not set.",
+            )));
+        }
+        let filter = self.query_builder.filter();
+        self.check_affected_via(backend, "delete_many", &filter).await?;
+        let deleted = backend
+            .delete(self.collection_name, filter, self.query_builder.all)
+            .await?;
+        let typ = if self.query_builder.all { "delete_many" } else { "delete" };
+        self.finish(&self.req, typ, doc! {"deleted_count": deleted.to_string()}, doc! {}, None)
+            .await;
+        Ok(deleted)
+    }
+
+    /// Runs an aggregation pipeline through an alternate [`Backend`]
+    pub async fn aggregate_via(
+        &self,
+        backend: &impl Backend,
+        pipeline: impl IntoIterator<Item = Document>,
+    ) -> Result<Vec<M>> {
+        let hidden_fields = self.hidden_fields();
+        let docs = backend
+            .aggregate(self.collection_name, pipeline.into_iter().collect())
+            .await?;
+        docs.into_iter()
+            .filter_map(|d| self.decode(d, &hidden_fields).transpose())
+            .collect()
+    }
+}
+
+impl<'a, M> PreflightTarget for Model<'a, M>
+where
+    M: Boot,
+    M: Default,
+    M: Serialize,
+    M: DeserializeOwned,
+    M: Send,
+    M: Sync,
+    M: Unpin,
+{
+    fn collection_name(&self) -> &str {
+        self.collection_name
+    }
+
+    fn declared_indexes(&self) -> Vec<IndexSpec> {
+        Model::declared_indexes(self)
+    }
+
+    fn field_names(&self) -> Vec<String> {
+        let mut r = vec!["_id".to_string()];
+        if self.add_times {
+            r.push("created_at".to_string());
+            r.push("updated_at".to_string());
+        }
+        r.extend(self.columns.keys().map(|name| self.db_name(name)));
+        r
     }
 }
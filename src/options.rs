@@ -0,0 +1,270 @@
+//! Per-model options
+//!
+//! Defaults come from [`Boot::configure`](crate::event::Boot::configure) and can be
+//! overridden per query on the model builder.
+
+use crate::date::DateTimeJson;
+use crate::public_id::ObjectIdJson;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::{ReadConcern, ValidationAction, ValidationLevel, WriteConcern};
+use std::collections::HashMap;
+
+/// Sanity limits applied to queries
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Guards {
+    /// Maximum `limit` of list queries; queries without limit are rejected when set
+    pub max_limit: Option<u32>,
+    pub max_skip: Option<u32>,
+    /// Reject `all()` updates and deletes without any filter condition
+    pub require_filter_for_all: bool,
+    /// Reject `all()` updates and deletes matching more documents, unless `Model::force` is set
+    pub max_affected: Option<u64>,
+}
+
+/// What reads do with a stored document that doesn't decode into the model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecodeErrorPolicy {
+    /// Fail the whole read
+    #[default]
+    Fail,
+    /// Leave the document out of the results, logging a warning
+    SkipDocument,
+    /// Keep the default value of every field that doesn't decode, logging an error
+    UseDefaultAndLog,
+}
+
+/// Options shared by every query of a model
+#[derive(Debug, Clone, Default)]
+pub struct ModelOptions {
+    pub guards: Guards,
+    /// Salt of the obfuscated public ids, see [`public_id`](crate::public_id)
+    pub public_id: Option<String>,
+    pub on_decode_error: DecodeErrorPolicy,
+    /// Format of dates in `to_json`
+    pub datetime_json: DateTimeJson,
+    /// Format of ObjectIds in `to_json`
+    pub object_id_json: ObjectIdJson,
+    /// Fields only read for the listed roles, see [`ModelOptions::visible_to`]
+    pub field_roles: HashMap<String, Vec<String>>,
+    /// Read concern of every query, the client's when `None`
+    pub read_concern: Option<ReadConcern>,
+    /// Write concern of every write, the client's when `None`, e.g. `WriteConcern::majority()`
+    pub write_concern: Option<WriteConcern>,
+    /// Makes the model's collection a read-only view, see [`ModelOptions::view_of`]
+    pub view: Option<ViewSpec>,
+    /// Creates the model's collection as capped, see [`ModelOptions::capped`]
+    pub capped: Option<CappedSpec>,
+    /// Write-behind repairs per second, see [`crate::repair`], 10 when `None`
+    pub max_repairs_per_second: Option<u32>,
+    /// Validation level of the `$jsonSchema` validator, the server's (strict) when `None`
+    pub validation_level: Option<ValidationLevel>,
+    /// Validation action of the `$jsonSchema` validator, the server's (error) when `None`
+    pub validation_action: Option<ValidationAction>,
+    /// Comment attached to the model's commands, e.g. the service name, shown in
+    /// `system.profile`, slow query logs and `currentOp`
+    pub comment: Option<String>,
+    /// Collection of the revisions read by `Model::as_of`, see [`crate::history`]
+    pub revisions: Option<String>,
+    /// Reads only return published documents, see [`crate::publish`]
+    pub publishable: bool,
+    /// Named filters applied with `Model::scope`, see [`ModelOptions::scope`]
+    pub scopes: HashMap<String, Document>,
+    /// Unique indexes over several fields, see [`ModelOptions::unique_index`]
+    pub unique_indexes: Vec<Vec<String>>,
+    /// Indexed fields computed from other fields on write, see [`ModelOptions::index_expr`]
+    pub index_exprs: Vec<IndexExpr>,
+}
+
+/// Size limits of a capped collection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CappedSpec {
+    /// Maximum size in bytes
+    pub size: u64,
+    /// Maximum number of documents
+    pub max: Option<u64>,
+}
+
+/// String normalization of an [`IndexExpr`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalize {
+    /// Lowercases ASCII letters, other characters are kept as the server's `$toLower` does
+    Lower,
+    /// Uppercases ASCII letters, other characters are kept as the server's `$toUpper` does
+    Upper,
+    Trim,
+}
+
+/// Hidden field holding a normalized copy of another field, with its own index
+///
+/// Lets exact matches on the shadow field stand in for case-insensitive
+/// queries on servers without collation support.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexExpr {
+    /// Name of the shadow field, in the database and in filters
+    pub name: String,
+    /// Field the value is computed from, as named in the struct
+    pub source: String,
+    /// Normalizations, innermost first
+    pub steps: Vec<Normalize>,
+}
+
+impl IndexExpr {
+    /// Parses `expr`, nested calls of `toLower`, `toUpper` and `trim` around a field name
+    ///
+    /// e.g. `toLower(email)` or `toLower(trim(email))`
+    pub fn parse(name: &str, expr: &str) -> Option<IndexExpr> {
+        let mut steps = vec![];
+        let mut rest = expr.trim();
+        while let Some((func, inner)) = rest.split_once('(') {
+            let step = match func.trim() {
+                "toLower" => Normalize::Lower,
+                "toUpper" => Normalize::Upper,
+                "trim" => Normalize::Trim,
+                _ => return None,
+            };
+            steps.push(step);
+            rest = inner.strip_suffix(')')?.trim();
+        }
+        if steps.is_empty() || rest.is_empty() || rest.contains(')') {
+            return None;
+        }
+        steps.reverse();
+        Some(IndexExpr {
+            name: name.to_string(),
+            source: rest.to_string(),
+            steps,
+        })
+    }
+
+    /// Normalized `value`, `None` unless it's a string
+    pub fn apply(&self, value: &Bson) -> Option<Bson> {
+        let mut value = value.as_str()?.to_string();
+        for step in &self.steps {
+            value = match step {
+                Normalize::Lower => value.to_ascii_lowercase(),
+                Normalize::Upper => value.to_ascii_uppercase(),
+                Normalize::Trim => value.trim().to_string(),
+            };
+        }
+        Some(Bson::String(value))
+    }
+
+    /// Aggregation expression computing the shadow field from `field`, its database name
+    pub fn aggregation(&self, field: &str) -> Bson {
+        let mut expr = Bson::String(format!("${field}"));
+        for step in &self.steps {
+            expr = match step {
+                Normalize::Lower => Bson::Document(doc! {"$toLower": expr}),
+                Normalize::Upper => Bson::Document(doc! {"$toUpper": expr}),
+                Normalize::Trim => Bson::Document(doc! {"$trim": {"input": expr}}),
+            };
+        }
+        expr
+    }
+
+    /// `condition` of a filter on the shadow field, with its strings normalized
+    pub(crate) fn route(&self, condition: Bson) -> Bson {
+        match condition {
+            Bson::String(_) => self.apply(&condition).unwrap_or(condition),
+            Bson::Document(d) => Bson::Document(
+                d.into_iter()
+                    .map(|(op, value)| match (op.as_str(), value) {
+                        ("$eq" | "$ne", value) => (op, self.route(value)),
+                        ("$in" | "$nin", Bson::Array(items)) => {
+                            (op, Bson::Array(items.into_iter().map(|v| self.route(v)).collect()))
+                        }
+                        (_, value) => (op, value),
+                    })
+                    .collect(),
+            ),
+            other => other,
+        }
+    }
+}
+
+/// Definition of a view-backed model
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ViewSpec {
+    /// Collection or view the view reads from
+    pub view_of: String,
+    /// Stages applied to the source documents, with database field names
+    pub pipeline: Vec<Document>,
+}
+
+impl ModelOptions {
+    /// Hides `field` unless the role of the request is one of `roles`
+    ///
+    /// # Notes
+    /// - The role comes from [`Boot::role`](crate::event::Boot::role)
+    /// - `Model::visible` still shows the field regardless of the role
+    pub fn visible_to(&mut self, field: &str, roles: &[&str]) -> &mut ModelOptions {
+        self.field_roles.insert(
+            field.to_string(),
+            roles.iter().map(|r| r.to_string()).collect(),
+        );
+        self
+    }
+
+    /// Keeps the revisions of the model's documents in `collection`, see [`crate::history`]
+    pub fn versioned(&mut self, collection: &str) -> &mut ModelOptions {
+        self.revisions = Some(collection.to_string());
+        self
+    }
+
+    /// Declares a reusable filter, applied with `Model::scope(name)`
+    ///
+    /// # Notes
+    /// - Field names are the model's, renamed to their database names when applied
+    pub fn scope(&mut self, name: &str, filter: Document) -> &mut ModelOptions {
+        self.scopes.insert(name.to_string(), filter);
+        self
+    }
+
+    /// Declares a unique index over `fields`, with model names, e.g. one added by `Model::add_unique_index`
+    ///
+    /// # Notes
+    /// - `register_indexes` creates it when missing and keeps it, undeclared indexes are dropped
+    pub fn unique_index(&mut self, fields: &[&str]) -> &mut ModelOptions {
+        self.unique_indexes
+            .push(fields.iter().map(|f| f.to_string()).collect());
+        self
+    }
+
+    /// Declares the model's collection as capped, a ring buffer dropping its oldest documents
+    ///
+    /// # Notes
+    /// - Created by `Model::provision` and `Model::register_indexes` when it doesn't exist,
+    ///   an existing collection isn't converted
+    pub fn capped(&mut self, size: u64, max: Option<u64>) -> &mut ModelOptions {
+        self.capped = Some(CappedSpec { size, max });
+        self
+    }
+
+    /// Maintains the `name` field as `expr` of another field, e.g. `toLower(email)`, and indexes it
+    ///
+    /// # Notes
+    /// - Strings of filters on `name` are normalized the same way, so
+    ///   `where(doc! {"email_lower": "Foo@Example.com"})` finds `foo@example.com`
+    /// - Documents stored before need `Model::refresh_index_exprs`
+    /// - An invalid `expr` is logged and ignored
+    pub fn index_expr(&mut self, name: &str, expr: &str) -> &mut ModelOptions {
+        match IndexExpr::parse(name, expr) {
+            Some(index_expr) => self.index_exprs.push(index_expr),
+            None => log::error!("invalid index expression of {name} : {expr}"),
+        }
+        self
+    }
+
+    /// Declares the model's collection as a view of `source`, created by `Model::provision_view`
+    pub fn view_of(
+        &mut self,
+        source: &str,
+        pipeline: impl IntoIterator<Item = Document>,
+    ) -> &mut ModelOptions {
+        self.view = Some(ViewSpec {
+            view_of: source.to_string(),
+            pipeline: pipeline.into_iter().collect(),
+        });
+        self
+    }
+}
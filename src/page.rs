@@ -0,0 +1,94 @@
+//! Pagination results
+
+use mongodb::bson::{Bson, Document};
+use mongodb::error::{Error, Result};
+use serde::Serialize;
+
+/// One page of a keyset (cursor based) pagination
+#[derive(Debug, Clone, Serialize)]
+pub struct KeysetPage<T> {
+    pub items: Vec<T>,
+    /// Token to pass to the next `paginate_after` call, `None` on the last page
+    pub next: Option<String>,
+}
+
+/// One page of an offset pagination
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: u64,
+    /// Current page, starting at 1
+    pub page: u32,
+    pub per_page: u32,
+    pub total_pages: u32,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, total: u64, page: u32, per_page: u32) -> Page<T> {
+        let total_pages = if per_page == 0 {
+            0
+        } else {
+            total.div_ceil(per_page as u64) as u32
+        };
+        Page {
+            items,
+            total,
+            page,
+            per_page,
+            total_pages,
+        }
+    }
+
+    pub fn has_next(&self) -> bool {
+        self.page < self.total_pages
+    }
+}
+
+/// Encodes the position of the last item of a page
+pub(crate) fn encode_cursor(key: &str, value: Bson, id: Bson) -> String {
+    let mut doc = Document::new();
+    doc.insert("k", key);
+    doc.insert("v", value);
+    doc.insert("id", id);
+    let bytes = mongodb::bson::to_vec(&doc).unwrap_or_default();
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Decodes a token produced by [`encode_cursor`] into `(key, value, id)`
+pub(crate) fn decode_cursor(token: &str) -> Result<(String, Bson, Bson)> {
+    let invalid = || {
+        Error::from(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "invalid pagination cursor.",
+        ))
+    };
+    if !token.len().is_multiple_of(2) || !token.is_ascii() {
+        return Err(invalid());
+    }
+    let bytes = (0..token.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&token[i..i + 2], 16))
+        .collect::<std::result::Result<Vec<u8>, _>>()
+        .map_err(|_| invalid())?;
+    let mut doc = Document::from_reader(bytes.as_slice()).map_err(|_| invalid())?;
+    let key = doc.get_str("k").map_err(|_| invalid())?.to_string();
+    let value = doc.remove("v").ok_or_else(invalid)?;
+    let id = doc.remove("id").ok_or_else(invalid)?;
+    // tokens come from clients, values go into filters and must not carry operators
+    if !is_plain(&value) || !is_plain(&id) {
+        return Err(invalid());
+    }
+    Ok((key, value, id))
+}
+
+/// Whether `value` is a scalar that only matches itself in a filter
+fn is_plain(value: &Bson) -> bool {
+    !matches!(
+        value,
+        Bson::Document(_)
+            | Bson::Array(_)
+            | Bson::RegularExpression(_)
+            | Bson::JavaScriptCode(_)
+            | Bson::JavaScriptCodeWithScope(_)
+    )
+}
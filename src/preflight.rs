@@ -0,0 +1,224 @@
+//! Startup self-check of the collections used by an application
+//!
+//! ```ignore
+//! let users = User::new_model(&db);
+//! let posts = Post::new_model(&db);
+//! let report = mongodb_ro::preflight(&db, &[&users, &posts]).await;
+//! if !report.is_ok() {
+//!     panic!("{report}");
+//! }
+//! ```
+
+use crate::index::{IndexDrift, IndexSpec};
+use futures_util::StreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::Database;
+use std::fmt;
+
+/// Actions a model needs on its collection
+pub const REQUIRED_ACTIONS: [&str; 6] =
+    ["find", "insert", "update", "remove", "createIndex", "listIndexes"];
+
+/// What [`preflight`] needs to know about a model, implemented by `Model`
+pub trait PreflightTarget {
+    fn collection_name(&self) -> &str;
+    fn declared_indexes(&self) -> Vec<IndexSpec>;
+    /// Stored names of the model fields
+    fn field_names(&self) -> Vec<String>;
+}
+
+/// Result of the checks of one collection
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollectionCheck {
+    pub collection: String,
+    pub exists: bool,
+    /// Entries of [`REQUIRED_ACTIONS`] not granted to the connected user
+    pub missing_actions: Vec<String>,
+    pub index_drift: IndexDrift,
+    /// Validator rules the model can't satisfy
+    pub validator_issues: Vec<String>,
+    /// Errors raised while checking
+    pub errors: Vec<String>,
+}
+
+impl CollectionCheck {
+    /// Missing or mismatched indexes, permissions, validator issues or errors fail the check
+    pub fn is_ok(&self) -> bool {
+        self.missing_actions.is_empty()
+            && self.index_drift.missing.is_empty()
+            && self.index_drift.mismatched.is_empty()
+            && self.validator_issues.is_empty()
+            && self.errors.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PreflightReport {
+    /// Error of the initial `ping`, `None` when connected
+    pub connection_error: Option<String>,
+    pub collections: Vec<CollectionCheck>,
+}
+
+impl PreflightReport {
+    pub fn is_ok(&self) -> bool {
+        self.connection_error.is_none() && self.collections.iter().all(|c| c.is_ok())
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(e) = &self.connection_error {
+            return writeln!(f, "preflight failed, can't connect: {e}");
+        }
+        for check in &self.collections {
+            if check.is_ok() {
+                writeln!(f, "`{}` ok", check.collection)?;
+                continue;
+            }
+            writeln!(f, "`{}` failed:", check.collection)?;
+            if !check.missing_actions.is_empty() {
+                writeln!(f, "  missing actions: {}", check.missing_actions.join(", "))?;
+            }
+            if !check.index_drift.missing.is_empty() || !check.index_drift.mismatched.is_empty() {
+                write!(f, "  {}", check.index_drift)?;
+            }
+            for issue in check.validator_issues.iter().chain(&check.errors) {
+                writeln!(f, "  {issue}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks connectivity, then permissions, indexes and validator of every model's collection
+pub async fn preflight(db: &Database, models: &[&dyn PreflightTarget]) -> PreflightReport {
+    let mut report = PreflightReport::default();
+    if let Err(e) = db.run_command(doc! {"ping": 1}).await {
+        report.connection_error = Some(e.to_string());
+        return report;
+    }
+    let privileges = match privileges(db).await {
+        Ok(p) => p,
+        Err(e) => {
+            report.connection_error = Some(format!("can't read privileges: {e}"));
+            return report;
+        }
+    };
+    for model in models {
+        report.collections.push(check(db, *model, privileges.as_deref()).await);
+    }
+    report
+}
+
+/// Privileges of the connected user, `None` when access control is disabled
+async fn privileges(db: &Database) -> mongodb::error::Result<Option<Vec<Document>>> {
+    let status = db
+        .run_command(doc! {"connectionStatus": 1, "showPrivileges": true})
+        .await?;
+    let auth = status.get_document("authInfo").cloned().unwrap_or_default();
+    let authenticated = auth
+        .get_array("authenticatedUsers")
+        .map(|u| !u.is_empty())
+        .unwrap_or(false);
+    if !authenticated {
+        return Ok(None);
+    }
+    Ok(Some(
+        auth.get_array("authenticatedUserPrivileges")
+            .map(|p| p.iter().filter_map(|p| p.as_document().cloned()).collect())
+            .unwrap_or_default(),
+    ))
+}
+
+/// Whether a privilege resource covers `db.collection`
+fn covers(resource: &Document, db: &str, collection: &str) -> bool {
+    if resource.get_bool("anyResource").unwrap_or(false) {
+        return true;
+    }
+    let matches = |key: &str, value: &str| match resource.get_str(key) {
+        Ok(v) => v.is_empty() || v == value,
+        Err(_) => false,
+    };
+    matches("db", db) && matches("collection", collection)
+}
+
+pub(crate) fn missing_actions(privileges: &[Document], db: &str, collection: &str) -> Vec<String> {
+    REQUIRED_ACTIONS
+        .iter()
+        .filter(|action| {
+            !privileges.iter().any(|p| {
+                p.get_document("resource")
+                    .map(|r| covers(r, db, collection))
+                    .unwrap_or(false)
+                    && p.get_array("actions")
+                        .map(|a| a.iter().any(|x| x.as_str() == Some(action)))
+                        .unwrap_or(false)
+            })
+        })
+        .map(|a| a.to_string())
+        .collect()
+}
+
+/// Required fields of a `$jsonSchema` validator that the model doesn't have
+pub(crate) fn validator_issues(validator: &Document, fields: &[String]) -> Vec<String> {
+    let Ok(schema) = validator.get_document("$jsonSchema") else {
+        return vec![];
+    };
+    schema
+        .get_array("required")
+        .map(|r| r.iter().filter_map(Bson::as_str).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|f| !fields.iter().any(|x| x == f))
+        .map(|f| format!("validator requires `{f}` which the model doesn't define"))
+        .collect()
+}
+
+async fn check(
+    db: &Database,
+    model: &dyn PreflightTarget,
+    privileges: Option<&[Document]>,
+) -> CollectionCheck {
+    let name = model.collection_name();
+    let mut check = CollectionCheck {
+        collection: name.to_string(),
+        ..Default::default()
+    };
+    if let Some(privileges) = privileges {
+        check.missing_actions = missing_actions(privileges, db.name(), name);
+    }
+
+    match db.list_collections().filter(doc! {"name": name}).await {
+        Ok(mut cursor) => {
+            if let Some(spec) = cursor.next().await {
+                match spec {
+                    Ok(spec) => {
+                        check.exists = true;
+                        if let Some(validator) = spec.options.validator {
+                            check.validator_issues = validator_issues(&validator, &model.field_names());
+                        }
+                    }
+                    Err(e) => check.errors.push(e.to_string()),
+                }
+            }
+        }
+        Err(e) => check.errors.push(e.to_string()),
+    }
+
+    let mut live = vec![];
+    if check.exists {
+        match db.collection::<Document>(name).list_indexes().await {
+            Ok(mut cursor) => {
+                while let Some(index) = cursor.next().await {
+                    match index {
+                        Ok(index) => live.push(IndexSpec::from_model(&index)),
+                        Err(e) => check.errors.push(e.to_string()),
+                    }
+                }
+            }
+            Err(e) => check.errors.push(e.to_string()),
+        }
+    }
+    check.index_drift = IndexDrift::compare(name, model.declared_indexes(), live);
+    check
+}
@@ -0,0 +1,133 @@
+//! Obfuscated public identifiers
+//!
+//! ObjectIds embed their creation time and a counter, so exposing them leaks
+//! how many and when documents were created. A public id is the `_id` run
+//! through a 4 round Feistel network keyed by a per-model salt, then encoded
+//! in base58. It is reversible with the same salt only.
+//!
+//! Enable it for a model with `options.public_id = Some("<salt>".into())` in
+//! [`Boot::configure`](crate::event::Boot::configure).
+//!
+//! Plain ObjectIds can also be written as hex strings instead of extended
+//! JSON, see [`ObjectIdJson`].
+
+use mongodb::bson::oid::ObjectId;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const ROUNDS: u8 = 4;
+
+fn round(salt: &str, i: u8, half: &[u8; 6]) -> [u8; 6] {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update([i]);
+    hasher.update(half);
+    let hash = hasher.finalize();
+    let mut r = [0; 6];
+    r.copy_from_slice(&hash[..6]);
+    r
+}
+
+fn xor(a: &[u8; 6], b: &[u8; 6]) -> [u8; 6] {
+    let mut r = [0; 6];
+    for i in 0..6 {
+        r[i] = a[i] ^ b[i];
+    }
+    r
+}
+
+fn split(bytes: [u8; 12]) -> ([u8; 6], [u8; 6]) {
+    let mut left = [0; 6];
+    let mut right = [0; 6];
+    left.copy_from_slice(&bytes[..6]);
+    right.copy_from_slice(&bytes[6..]);
+    (left, right)
+}
+
+fn join(left: [u8; 6], right: [u8; 6]) -> [u8; 12] {
+    let mut r = [0; 12];
+    r[..6].copy_from_slice(&left);
+    r[6..].copy_from_slice(&right);
+    r
+}
+
+/// Public id of `id` for a salt
+pub fn encode(salt: &str, id: &ObjectId) -> String {
+    let (mut left, mut right) = split(id.bytes());
+    for i in 0..ROUNDS {
+        (left, right) = (right, xor(&left, &round(salt, i, &right)));
+    }
+    base58(&join(left, right))
+}
+
+/// `_id` of a public id produced with the same salt, `None` if it's malformed
+pub fn decode(salt: &str, public_id: &str) -> Option<ObjectId> {
+    let (mut left, mut right) = split(unbase58(public_id)?);
+    for i in (0..ROUNDS).rev() {
+        (left, right) = (xor(&right, &round(salt, i, &left)), left);
+    }
+    Some(ObjectId::from_bytes(join(left, right)))
+}
+
+fn base58(bytes: &[u8; 12]) -> String {
+    let mut n = u128::from_be_bytes({
+        let mut b = [0; 16];
+        b[4..].copy_from_slice(bytes);
+        b
+    });
+    let mut r = vec![];
+    while n > 0 {
+        r.push(ALPHABET[(n % 58) as usize]);
+        n /= 58;
+    }
+    r.reverse();
+    String::from_utf8(r).unwrap_or_default()
+}
+
+fn unbase58(s: &str) -> Option<[u8; 12]> {
+    if s.is_empty() || s.len() > 17 {
+        return None;
+    }
+    let mut n: u128 = 0;
+    for c in s.bytes() {
+        let digit = ALPHABET.iter().position(|a| *a == c)? as u128;
+        n = n.checked_mul(58)?.checked_add(digit)?;
+    }
+    let bytes = n.to_be_bytes();
+    if bytes[..4].iter().any(|b| *b != 0) {
+        return None;
+    }
+    let mut r = [0; 12];
+    r.copy_from_slice(&bytes[4..]);
+    Some(r)
+}
+
+/// How ObjectIds appear in the JSON of [`Model::to_json`](crate::model::Model::to_json)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ObjectIdJson {
+    /// Extended JSON, `{"$oid": "..."}`
+    #[default]
+    Extended,
+    /// Plain hex string
+    Hex,
+}
+
+/// Rewrites every extended JSON ObjectId in `value` to `format`
+pub fn format_json_ids(value: &mut Value, format: ObjectIdJson) {
+    if format == ObjectIdJson::Extended {
+        return;
+    }
+    if let Value::Object(object) = value
+        && object.len() == 1
+        && let Some(Value::String(hex)) = object.get("$oid")
+    {
+        *value = Value::String(hex.clone());
+        return;
+    }
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(|v| format_json_ids(v, format)),
+        Value::Object(object) => object.values_mut().for_each(|v| format_json_ids(v, format)),
+        _ => {}
+    }
+}
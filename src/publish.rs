@@ -0,0 +1,38 @@
+//! Draft and published workflows
+//!
+//! A publishable model has a `status` field, [`DRAFT`] or [`PUBLISHED`], and a
+//! `published_at` date. Reads only return published documents, unless the
+//! request is a preview, see [`Boot::preview`](crate::event::Boot::preview), or
+//! the query calls `Model::with_drafts`.
+//!
+//! ```ignore
+//! impl Boot for Article {
+//!     type Req = Editor;
+//!
+//!     fn configure(&self, options: &mut ModelOptions) {
+//!         options.publishable = true;
+//!     }
+//!
+//!     fn preview(&self, req: &Option<Editor>) -> bool {
+//!         req.is_some()
+//!     }
+//! }
+//!
+//! let id = Article::new_model(&db).fill(article).draft().await?.inserted_id;
+//! Article::new_model(&db).r#where(doc! {"_id": id}).publish().await?;
+//! ```
+//!
+//! # Notes
+//! - Every create path sets `status` to draft when it isn't one of the two
+//!   states, so a document is never hidden for good; publish it afterwards
+//! - `aggregate` pipelines are the caller's and aren't scoped
+//! - Updates and deletes aren't scoped either, so `publish` finds drafts
+
+/// Field holding the state of a document
+pub const STATUS: &str = "status";
+/// Field holding when the document was last published, null for drafts
+pub const PUBLISHED_AT: &str = "published_at";
+/// Status of documents hidden from reads
+pub const DRAFT: &str = "draft";
+/// Status of documents returned by reads
+pub const PUBLISHED: &str = "published";
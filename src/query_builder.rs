@@ -1,14 +1,383 @@
-use mongodb::bson::Document;
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::options::{
+    AggregateOptions, CountOptions, CursorType, DeleteOptions, FindOneAndDeleteOptions,
+    FindOneAndUpdateOptions, FindOptions, Hint, UpdateOptions,
+};
+use std::fmt;
+use std::sync::Arc;
 
 #[derive(Debug, Default, Clone)]
 pub(crate) struct QueryBuilder {
     pub r#where: Vec<Document>,
+    pub or_where: Vec<Vec<Document>>,
     pub all: bool,
     pub upsert: bool,
+    /// Skips the `max_affected` guard
+    pub force: bool,
     pub select: Option<Document>,
     pub sort: Document,
+    /// Database names of the fields the query may sort on, see `Model::sortable_fields`
+    pub sortable: Option<Vec<String>>,
     pub skip: u32,
     pub limit: u32,
     pub batch_size: u32,
+    pub hint: Option<Hint>,
+    /// Server side time limit, sent as `maxTimeMS`
+    pub max_time: Option<std::time::Duration>,
+    pub allow_disk_use: Option<bool>,
+    /// Tailable cursor of capped collections, see `Model::tailable`
+    pub cursor_type: Option<CursorType>,
+    /// `arrayFilters` of updates, see `Model::update_array_element`
+    pub array_filters: Vec<Document>,
+    pub overrides: OptionOverrides,
     pub visible_fields: Vec<String>,
-}
\ No newline at end of file
+    /// Shows every hidden field, see `Model::visible_all`
+    pub visible_all: bool,
+    /// Fields hidden for this query only, see `Model::hide`
+    pub hide: Vec<String>,
+    /// Reads `heavy` columns, set by `Model::with_heavy` and single document reads
+    pub with_heavy: bool,
+    /// Reads drafts of publishable models, see `Model::with_drafts`
+    pub with_drafts: bool,
+    /// Pending expiry for the `expires_at` column, `Some(None)` clears it
+    pub expire: Option<Option<DateTime>>,
+    #[cfg(feature = "rt-tokio")]
+    pub cancel: Option<tokio_util::sync::CancellationToken>,
+    /// Client side time limit of long operations
+    #[cfg(feature = "rt-tokio")]
+    pub timeout: Option<std::time::Duration>,
+}
+
+impl QueryBuilder {
+    /// Whether any condition was added
+    pub fn has_filter(&self) -> bool {
+        !self.r#where.is_empty() || self.or_where.iter().any(|g| !g.is_empty())
+    }
+
+    /// Final filter document
+    pub fn filter(&self) -> Document {
+        compile(&self.r#where, &self.or_where)
+    }
+
+    /// ANDs `condition` with the whole filter, not only the alternative `or_where` opened last
+    pub fn and_all(&mut self, condition: Document) {
+        let filter = and_condition(self.filter(), condition);
+        self.or_where.clear();
+        self.r#where = match filter.get_array("$and") {
+            Ok(conditions) if filter.len() == 1 => {
+                conditions.iter().filter_map(|c| c.as_document().cloned()).collect()
+            }
+            _ => vec![filter],
+        };
+    }
+}
+
+pub(crate) type Override<T> = Arc<dyn Fn(&mut T) + Send + Sync>;
+
+/// Driver option adjustments set with the `Model::with_*_options` methods
+///
+/// They run in order after the options built from the query settings.
+#[derive(Clone, Default)]
+pub(crate) struct OptionOverrides {
+    pub find: Vec<Override<FindOptions>>,
+    pub count: Vec<Override<CountOptions>>,
+    pub aggregate: Vec<Override<AggregateOptions>>,
+    pub update: Vec<Override<UpdateOptions>>,
+    pub find_one_and_update: Vec<Override<FindOneAndUpdateOptions>>,
+    pub delete: Vec<Override<DeleteOptions>>,
+    pub find_one_and_delete: Vec<Override<FindOneAndDeleteOptions>>,
+}
+
+impl fmt::Debug for OptionOverrides {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OptionOverrides")
+            .field("find", &self.find.len())
+            .field("count", &self.count.len())
+            .field("aggregate", &self.aggregate.len())
+            .field("update", &self.update.len())
+            .field("find_one_and_update", &self.find_one_and_update.len())
+            .field("delete", &self.delete.len())
+            .field("find_one_and_delete", &self.find_one_and_delete.len())
+            .finish()
+    }
+}
+
+pub(crate) fn apply<T>(overrides: &[Override<T>], mut options: T) -> T {
+    for f in overrides {
+        f(&mut options);
+    }
+    options
+}
+
+/// Sort direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+impl Order {
+    pub fn value(&self) -> i32 {
+        match self {
+            Order::Asc => 1,
+            Order::Desc => -1,
+        }
+    }
+}
+
+/// Final find command of a model query, see `Model::build_find`
+#[derive(Debug, Clone)]
+pub struct FindQuery {
+    pub filter: Document,
+    pub options: FindOptions,
+}
+
+impl FindQuery {
+    /// Equivalent `find` command document, as run by `Model::explain`
+    pub fn command(&self, collection: &str) -> Document {
+        let mut command = doc! {"find": collection, "filter": self.filter.clone()};
+        let options = &self.options;
+        if let Some(sort) = options.sort.clone().filter(|s| !s.is_empty()) {
+            command.insert("sort", sort);
+        }
+        if let Some(projection) = options.projection.clone() {
+            command.insert("projection", projection);
+        }
+        if let Some(skip) = options.skip {
+            command.insert("skip", skip as i64);
+        }
+        if let Some(limit) = options.limit {
+            command.insert("limit", limit);
+        }
+        match &options.hint {
+            Some(Hint::Keys(keys)) => {
+                command.insert("hint", keys.clone());
+            }
+            Some(Hint::Name(name)) => {
+                command.insert("hint", name.clone());
+            }
+            _ => {}
+        }
+        if let Some(max_time) = options.max_time {
+            command.insert("maxTimeMS", max_time.as_millis() as i64);
+        }
+        if let Some(allow) = options.allow_disk_use {
+            command.insert("allowDiskUse", allow);
+        }
+        match options.cursor_type {
+            Some(CursorType::Tailable) => {
+                command.insert("tailable", true);
+            }
+            Some(CursorType::TailableAwait) => {
+                command.insert("tailable", true);
+                command.insert("awaitData", true);
+            }
+            _ => {}
+        }
+        if let Some(comment) = options.comment.clone() {
+            command.insert("comment", comment);
+        }
+        command
+    }
+}
+
+/// Detail level of `Model::explain`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    /// The winning plan, without running the query
+    #[default]
+    QueryPlanner,
+    /// Also runs the winning plan and reports its statistics
+    ExecutionStats,
+    /// Also reports partial statistics of the rejected plans
+    AllPlansExecution,
+}
+
+impl Verbosity {
+    pub fn value(&self) -> &'static str {
+        match self {
+            Verbosity::QueryPlanner => "queryPlanner",
+            Verbosity::ExecutionStats => "executionStats",
+            Verbosity::AllPlansExecution => "allPlansExecution",
+        }
+    }
+}
+
+/// Final update command of a model query, see `Model::build_update`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateQuery {
+    pub filter: Document,
+    pub update: Document,
+    pub upsert: bool,
+    /// `update_many` when true, `find_one_and_update` otherwise
+    pub many: bool,
+    pub sort: Document,
+}
+
+/// Nested group of conditions used by `Model::where_group`
+///
+/// Conditions added with `r#where` are ANDed, `or_where` starts a new
+/// alternative, so `a.where(b).or_where(c).where(d)` means `(a AND b) OR (c AND d)`.
+#[derive(Debug, Default, Clone)]
+pub struct WhereGroup {
+    r#where: Vec<Document>,
+    or_where: Vec<Vec<Document>>,
+}
+
+impl WhereGroup {
+    /// Adds a condition to the current alternative
+    pub fn r#where(mut self, data: Document) -> WhereGroup {
+        self.r#where.push(data);
+        self
+    }
+
+    /// Starts a new alternative with the given condition
+    pub fn or_where(mut self, data: Document) -> WhereGroup {
+        push_or(&mut self.r#where, &mut self.or_where, data);
+        self
+    }
+
+    /// Adds a nested group to the current alternative
+    pub fn where_group(self, f: impl FnOnce(WhereGroup) -> WhereGroup) -> WhereGroup {
+        let group = f(WhereGroup::default()).into_document();
+        if group.is_empty() {
+            return self;
+        }
+        self.r#where(group)
+    }
+
+    /// Compiles the group into a filter document
+    pub fn into_document(self) -> Document {
+        compile(&self.r#where, &self.or_where)
+    }
+}
+
+/// Array items updated by `Model::update_array_element` and their changes
+///
+/// Field names are relative to the item, `""` stands for the item itself in
+/// arrays of scalars.
+#[derive(Debug, Clone, Default)]
+pub struct ArrayElement {
+    conditions: Document,
+    changes: Document,
+}
+
+impl ArrayElement {
+    /// Only updates the items whose `field` matches `condition`, a value or a query document
+    pub fn where_(mut self, field: &str, condition: impl Into<Bson>) -> ArrayElement {
+        self.conditions.insert(field, condition.into());
+        self
+    }
+
+    /// Sets `field` of the items
+    pub fn set(self, field: &str, value: impl Into<Bson>) -> ArrayElement {
+        self.change("$set", field, value.into())
+    }
+
+    /// Adds `n` to `field` of the items
+    pub fn inc(self, field: &str, n: impl Into<Bson>) -> ArrayElement {
+        self.change("$inc", field, n.into())
+    }
+
+    /// Removes `field` from the items
+    pub fn unset(self, field: &str) -> ArrayElement {
+        self.change("$unset", field, Bson::String(String::new()))
+    }
+
+    fn change(mut self, op: &str, field: &str, value: Bson) -> ArrayElement {
+        if let Ok(fields) = self.changes.get_document_mut(op) {
+            fields.insert(field, value);
+        } else {
+            self.changes.insert(op, doc! {field: value});
+        }
+        self
+    }
+
+    /// Update document on the array at `path` and its array filter, `None` to update every item
+    ///
+    /// # Arguments
+    /// * `ident` - Identifier of the array filter, unique in the update
+    pub(crate) fn build(self, path: &str, ident: &str) -> (Document, Option<Document>) {
+        let (position, filter) = if self.conditions.is_empty() {
+            ("$[]".to_string(), None)
+        } else {
+            let filter = self
+                .conditions
+                .into_iter()
+                .map(|(field, condition)| (item_path(ident, &field), condition))
+                .collect::<Document>();
+            (format!("$[{ident}]"), Some(filter))
+        };
+        let mut update = Document::new();
+        for (op, fields) in self.changes {
+            let Bson::Document(fields) = fields else {
+                continue;
+            };
+            let fields = fields
+                .into_iter()
+                .map(|(field, value)| (item_path(&format!("{path}.{position}"), &field), value))
+                .collect::<Document>();
+            update.insert(op, fields);
+        }
+        (update, filter)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+fn item_path(item: &str, field: &str) -> String {
+    if field.is_empty() {
+        item.to_string()
+    } else {
+        format!("{item}.{field}")
+    }
+}
+
+pub(crate) fn push_or(current: &mut Vec<Document>, closed: &mut Vec<Vec<Document>>, data: Document) {
+    let group = std::mem::take(current);
+    if !group.is_empty() {
+        closed.push(group);
+    }
+    current.push(data);
+}
+
+/// `filter` AND `condition`, appended to the top level `$and` when there is one
+pub(crate) fn and_condition(mut filter: Document, condition: Document) -> Document {
+    if filter.is_empty() {
+        return condition;
+    }
+    if filter.len() == 1
+        && let Ok(conditions) = filter.get_array_mut("$and")
+    {
+        conditions.push(Bson::Document(condition));
+        return filter;
+    }
+    doc! {"$and": [filter, condition]}
+}
+
+fn and(group: &[Document]) -> Document {
+    if group.len() == 1 {
+        group[0].clone()
+    } else {
+        doc! {"$and": group}
+    }
+}
+
+fn compile(current: &[Document], closed: &[Vec<Document>]) -> Document {
+    let mut groups = closed
+        .iter()
+        .filter(|g| !g.is_empty())
+        .map(|g| g.as_slice())
+        .collect::<Vec<_>>();
+    if !current.is_empty() {
+        groups.push(current);
+    }
+    match groups.len() {
+        0 => doc! {},
+        1 if closed.is_empty() => doc! {"$and": groups[0]},
+        1 => and(groups[0]),
+        _ => doc! {"$or": groups.into_iter().map(and).collect::<Vec<_>>()},
+    }
+}
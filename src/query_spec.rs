@@ -0,0 +1,373 @@
+//! Validated list queries from untrusted input
+//!
+//! A [`QuerySpec`] holds the filters, sort and page of a list request. It is
+//! parsed from query string pairs against per-model [`QueryRules`], which
+//! whitelist the filterable fields with their value types and operators, and
+//! the sortable fields, then applied to a model with `Model::apply_spec`.
+//!
+//! ```ignore
+//! let rules = QueryRules::new()
+//!     .filterable("status", ValueKind::String)
+//!     .filterable("age", ValueKind::Int)
+//!     .filterable_with("name", ValueKind::String, &[Allow::Eq, Allow::RegexPrefix])
+//!     .sortable(&["age", "created_at"]);
+//! // ?status[in]=active,trial&age[gte]=18&sort=-age&page=2&per_page=20
+//! let spec = QuerySpec::parse(query_pairs, &rules)?;
+//! let page = User::new_model(&db)
+//!     .apply_spec(&spec)
+//!     .paginate(spec.page, spec.per_page)
+//!     .await?;
+//! ```
+
+use crate::error::SpecError;
+use crate::query_builder::Order;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::error::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Type the string values of a filterable field are parsed into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ValueKind {
+    String,
+    Int,
+    Float,
+    Bool,
+    ObjectId,
+    /// RFC 3339 date
+    Date,
+}
+
+impl ValueKind {
+    fn parse(&self, field: &str, value: &str) -> Result<Bson> {
+        let parsed = match self {
+            ValueKind::String => Some(Bson::String(value.to_string())),
+            ValueKind::Int => value.parse().ok().map(Bson::Int64),
+            ValueKind::Float => value.parse().ok().map(Bson::Double),
+            ValueKind::Bool => value.parse().ok().map(Bson::Boolean),
+            ValueKind::ObjectId => ObjectId::parse_str(value).ok().map(Bson::ObjectId),
+            ValueKind::Date => DateTime::parse_rfc3339_str(value).ok().map(Bson::DateTime),
+        };
+        parsed.ok_or_else(|| {
+            SpecError::InvalidValue {
+                field: field.to_string(),
+                value: value.to_string(),
+            }
+            .into()
+        })
+    }
+}
+
+/// Comparison of a filter condition, written `field[op]=value`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    /// Comma separated values
+    In,
+    /// Strings starting with the value, `field[prefix]=ab`
+    Prefix,
+}
+
+impl Op {
+    fn from_name(name: &str) -> Option<Op> {
+        Some(match name {
+            "eq" => Op::Eq,
+            "ne" => Op::Ne,
+            "gt" => Op::Gt,
+            "gte" => Op::Gte,
+            "lt" => Op::Lt,
+            "lte" => Op::Lte,
+            "in" => Op::In,
+            "prefix" => Op::Prefix,
+            _ => return None,
+        })
+    }
+
+    fn operator(&self) -> &'static str {
+        match self {
+            Op::Eq => "$eq",
+            Op::Ne => "$ne",
+            Op::Gt => "$gt",
+            Op::Gte => "$gte",
+            Op::Lt => "$lt",
+            Op::Lte => "$lte",
+            Op::In => "$in",
+            Op::Prefix => "$regex",
+        }
+    }
+
+    fn allowed_by(&self) -> Allow {
+        match self {
+            Op::Eq | Op::Ne => Allow::Eq,
+            Op::In => Allow::In,
+            Op::Gt | Op::Gte | Op::Lt | Op::Lte => Allow::Range,
+            Op::Prefix => Allow::RegexPrefix,
+        }
+    }
+}
+
+/// Operator families a filterable field accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Allow {
+    /// `eq` and `ne`
+    Eq,
+    In,
+    /// `gt`, `gte`, `lt` and `lte`
+    Range,
+    /// `prefix`, an anchored regex that can use an index
+    RegexPrefix,
+}
+
+/// How a filterable field may be filtered
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldRule {
+    pub kind: ValueKind,
+    pub allow: Vec<Allow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Condition {
+    /// Model field name
+    pub field: String,
+    pub op: Op,
+    pub value: Bson,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    Asc,
+    Desc,
+}
+
+impl From<Direction> for Order {
+    fn from(value: Direction) -> Self {
+        match value {
+            Direction::Asc => Order::Asc,
+            Direction::Desc => Order::Desc,
+        }
+    }
+}
+
+/// Whitelist of what a list request may filter and sort on
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryRules {
+    pub filterable: BTreeMap<String, FieldRule>,
+    pub sortable: Vec<String>,
+    pub default_per_page: u32,
+    pub max_per_page: u32,
+}
+
+impl Default for QueryRules {
+    fn default() -> Self {
+        QueryRules {
+            filterable: BTreeMap::new(),
+            sortable: vec![],
+            default_per_page: 20,
+            max_per_page: 100,
+        }
+    }
+}
+
+impl QueryRules {
+    pub fn new() -> QueryRules {
+        QueryRules::default()
+    }
+
+    /// Allows filtering on `field` with `eq`, `in` and range operators, parsing its values as `kind`
+    pub fn filterable(self, field: &str, kind: ValueKind) -> QueryRules {
+        self.filterable_with(field, kind, &[Allow::Eq, Allow::In, Allow::Range])
+    }
+
+    /// Allows filtering on `field` with the `allow` operators only
+    pub fn filterable_with(mut self, field: &str, kind: ValueKind, allow: &[Allow]) -> QueryRules {
+        let rule = FieldRule {
+            kind,
+            allow: allow.to_vec(),
+        };
+        self.filterable.insert(field.to_string(), rule);
+        self
+    }
+
+    /// Allows sorting on `fields`
+    pub fn sortable(mut self, fields: &[&str]) -> QueryRules {
+        self.sortable.extend(fields.iter().map(|f| f.to_string()));
+        self
+    }
+
+    /// Page size used when the request doesn't set `per_page`
+    pub fn default_per_page(mut self, per_page: u32) -> QueryRules {
+        self.default_per_page = per_page.max(1);
+        self
+    }
+
+    /// Larger `per_page` values are capped to `per_page`
+    pub fn max_per_page(mut self, per_page: u32) -> QueryRules {
+        self.max_per_page = per_page.max(1);
+        self
+    }
+}
+
+/// Filters, sort and page of a list request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuerySpec {
+    pub conditions: Vec<Condition>,
+    pub sort: Vec<(String, Direction)>,
+    /// Page number, starting at 1
+    pub page: u32,
+    pub per_page: u32,
+}
+
+impl QuerySpec {
+    /// Parses query string pairs, rejecting anything `rules` doesn't allow
+    ///
+    /// # Notes
+    /// - `field=v` and `field[op]=v` add conditions, ops are `eq ne gt gte lt lte in prefix`
+    /// - `sort=-age,name` sorts by age descending then name
+    /// - `page` starts at 1, `per_page` is capped to the rules' maximum
+    /// - Unknown keys fail with [`SpecError`]
+    pub fn parse<K, V>(pairs: impl IntoIterator<Item = (K, V)>, rules: &QueryRules) -> Result<QuerySpec>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut spec = QuerySpec {
+            conditions: vec![],
+            sort: vec![],
+            page: 1,
+            per_page: rules.default_per_page,
+        };
+        for (key, value) in pairs {
+            let (key, value) = (key.as_ref(), value.as_ref());
+            match key {
+                "page" => spec.page = parse_number(key, value)?.max(1),
+                "per_page" => spec.per_page = parse_number(key, value)?.max(1),
+                "sort" => {
+                    for field in value.split(',').filter(|f| !f.is_empty()) {
+                        let sort = match field.strip_prefix('-') {
+                            Some(field) => (field.to_string(), Direction::Desc),
+                            None => (field.to_string(), Direction::Asc),
+                        };
+                        spec.sort.push(sort);
+                    }
+                }
+                _ => {
+                    let (field, op) = match key.strip_suffix(']').and_then(|k| k.split_once('[')) {
+                        Some((field, op)) => (
+                            field,
+                            Op::from_name(op).ok_or_else(|| SpecError::UnknownOperator {
+                                field: field.to_string(),
+                                op: op.to_string(),
+                            })?,
+                        ),
+                        None => (key, Op::Eq),
+                    };
+                    let rule = rules
+                        .filterable
+                        .get(field)
+                        .ok_or_else(|| SpecError::NotFilterable { field: field.to_string() })?;
+                    let value = match op {
+                        Op::In => Bson::Array(
+                            value
+                                .split(',')
+                                .map(|v| rule.kind.parse(field, v))
+                                .collect::<Result<Vec<_>>>()?,
+                        ),
+                        Op::Prefix => Bson::String(value.to_string()),
+                        _ => rule.kind.parse(field, value)?,
+                    };
+                    spec.conditions.push(Condition {
+                        field: field.to_string(),
+                        op,
+                        value,
+                    });
+                }
+            }
+        }
+        spec.validate(rules)?;
+        Ok(spec)
+    }
+
+    /// Checks a spec built elsewhere, e.g. deserialized, against `rules`
+    ///
+    /// Caps `per_page` like `parse`.
+    pub fn validate(&mut self, rules: &QueryRules) -> Result<()> {
+        for condition in &self.conditions {
+            let Some(rule) = rules.filterable.get(&condition.field) else {
+                return Err(SpecError::NotFilterable {
+                    field: condition.field.clone(),
+                }
+                .into());
+            };
+            let allowed = rule.allow.contains(&condition.op.allowed_by());
+            let is_string = matches!(condition.value, Bson::String(_));
+            if !allowed || (condition.op == Op::Prefix && (!is_string || rule.kind != ValueKind::String)) {
+                return Err(SpecError::OperatorNotAllowed {
+                    field: condition.field.clone(),
+                    op: condition.op,
+                }
+                .into());
+            }
+        }
+        for (field, _) in &self.sort {
+            if !rules.sortable.contains(field) {
+                return Err(SpecError::NotSortable { field: field.clone() }.into());
+            }
+        }
+        self.page = self.page.max(1);
+        self.per_page = self.per_page.clamp(1, rules.max_per_page);
+        Ok(())
+    }
+
+    /// Filter document of the conditions, with model field names
+    pub fn filter(&self) -> Document {
+        let mut conditions = vec![];
+        for c in &self.conditions {
+            let op = c.op.operator();
+            let value = match (&c.op, &c.value) {
+                (Op::Prefix, Bson::String(prefix)) => Bson::String(format!("^{}", escape_regex(prefix))),
+                _ => c.value.clone(),
+            };
+            conditions.push(doc! {&c.field: {op: value}});
+        }
+        match conditions.len() {
+            0 => doc! {},
+            _ => doc! {"$and": conditions},
+        }
+    }
+
+    /// Sort document, with model field names
+    pub fn sort_document(&self) -> Document {
+        let mut sort = Document::new();
+        for (field, direction) in &self.sort {
+            sort.insert(field.clone(), Order::from(*direction).value());
+        }
+        sort
+    }
+}
+
+fn escape_regex(value: &str) -> String {
+    let mut r = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            r.push('\\');
+        }
+        r.push(c);
+    }
+    r
+}
+
+fn parse_number(key: &str, value: &str) -> Result<u32> {
+    value.parse().map_err(|_| {
+        SpecError::InvalidValue {
+            field: key.to_string(),
+            value: value.to_string(),
+        }
+        .into()
+    })
+}
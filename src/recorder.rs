@@ -0,0 +1,231 @@
+//! Recording the commands models send, for tests
+//!
+//! A [`QueryRecorder`] attached to the client options captures every command
+//! as the server receives it, after renames, timestamps and options were
+//! applied. Tests can then check the queries of repository code without
+//! looking at the database state.
+//!
+//! ```ignore
+//! let recorder = QueryRecorder::new();
+//! let mut options = ClientOptions::parse("mongodb://localhost:27017").await?;
+//! recorder.attach(&mut options);
+//! let db = Client::with_options(options)?.database("test");
+//!
+//! deactivate_user(&db, "a").await?;
+//! recorder.assert_filter_contains("user", doc! {"name": "a"});
+//! recorder.assert_snapshot(include_str!("snapshots/deactivate_user.txt"));
+//! ```
+
+use mongodb::bson::{Bson, Document};
+use mongodb::event::command::CommandEvent;
+use mongodb::event::EventHandler;
+use mongodb::options::ClientOptions;
+use std::sync::{Arc, Mutex};
+
+/// Commands of the connection handshake, server detection, cursor and session
+/// bookkeeping, never recorded
+const IGNORED_COMMANDS: [&str; 8] = [
+    "hello",
+    "isMaster",
+    "buildInfo",
+    "saslStart",
+    "saslContinue",
+    "getMore",
+    "killCursors",
+    "endSessions",
+];
+
+/// Fields that change from one run to the other, left out of the recorded commands
+const VOLATILE_FIELDS: [&str; 6] = [
+    "lsid",
+    "$clusterTime",
+    "$db",
+    "txnNumber",
+    "$readPreference",
+    "recoveryToken",
+];
+
+/// A command captured by a [`QueryRecorder`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedCommand {
+    /// Command name, e.g. `find` or `update`
+    pub name: String,
+    pub db: String,
+    /// Command document, without the session and cluster time fields
+    pub body: Document,
+}
+
+impl RecordedCommand {
+    /// Collection the command runs on, `None` for database commands
+    pub fn collection(&self) -> Option<&str> {
+        self.body.get_str(&self.name).ok()
+    }
+
+    /// Filters of the command, one per statement of updates and deletes
+    pub fn filters(&self) -> Vec<&Document> {
+        let body = &self.body;
+        let statements = |key: &str, field: &str| -> Vec<&Document> {
+            body.get_array(key)
+                .map(|s| {
+                    s.iter()
+                        .filter_map(Bson::as_document)
+                        .filter_map(|s| s.get_document(field).ok())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        match self.name.as_str() {
+            "find" => body.get_document("filter").into_iter().collect(),
+            "findAndModify" | "count" | "distinct" => body.get_document("query").into_iter().collect(),
+            "update" => statements("updates", "q"),
+            "delete" => statements("deletes", "q"),
+            "aggregate" => statements("pipeline", "$match").into_iter().take(1).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// Captures the commands of a client, see the [module docs](self)
+#[derive(Debug, Clone, Default)]
+pub struct QueryRecorder {
+    commands: Arc<Mutex<Vec<RecordedCommand>>>,
+}
+
+impl QueryRecorder {
+    pub fn new() -> QueryRecorder {
+        QueryRecorder::default()
+    }
+
+    /// Records the commands of the clients built from `options`
+    ///
+    /// # Notes
+    /// - Replaces the command event handler already set on `options`
+    pub fn attach(&self, options: &mut ClientOptions) {
+        let recorder = self.clone();
+        options.command_event_handler = Some(EventHandler::callback(move |event| {
+            if let CommandEvent::Started(started) = event {
+                recorder.record(&started.command_name, &started.db, started.command);
+            }
+        }));
+    }
+
+    /// Adds a command, as `attach` does for every command sent
+    pub fn record(&self, name: &str, db: &str, mut command: Document) {
+        if IGNORED_COMMANDS.contains(&name) {
+            return;
+        }
+        normalize(&mut command);
+        self.commands.lock().unwrap().push(RecordedCommand {
+            name: name.to_string(),
+            db: db.to_string(),
+            body: command,
+        });
+    }
+
+    /// Recorded commands, oldest first
+    pub fn commands(&self) -> Vec<RecordedCommand> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    /// Recorded commands on `collection`, oldest first
+    pub fn commands_on(&self, collection: &str) -> Vec<RecordedCommand> {
+        self.commands()
+            .into_iter()
+            .filter(|c| c.collection() == Some(collection))
+            .collect()
+    }
+
+    /// Forgets the recorded commands, e.g. after the setup of a test
+    pub fn clear(&self) {
+        self.commands.lock().unwrap().clear();
+    }
+
+    /// Panics unless a command on `collection` filtered on every field of `expected`
+    ///
+    /// # Notes
+    /// - Fields are looked up in the filter and in its nested `$and` conditions
+    pub fn assert_filter_contains(&self, collection: &str, expected: Document) {
+        let commands = self.commands_on(collection);
+        let found = commands.iter().any(|c| {
+            c.filters()
+                .into_iter()
+                .any(|f| expected.iter().all(|(k, v)| contains(f, k, v)))
+        });
+        if !found {
+            let filters = commands.iter().flat_map(|c| c.filters()).collect::<Vec<_>>();
+            panic!("no filter on `{collection}` contains {expected}, recorded filters: {filters:?}");
+        }
+    }
+
+    /// One line of relaxed extended JSON per recorded command
+    ///
+    /// ObjectIds, dates and cursor ids are masked, so snapshots are stable across runs.
+    pub fn snapshot(&self) -> String {
+        self.commands()
+            .into_iter()
+            .map(|mut c| {
+                mask_cursor_ids(&mut c.body);
+                let mut body = Bson::Document(c.body);
+                mask(&mut body);
+                body.into_relaxed_extjson().to_string() + "\n"
+            })
+            .collect()
+    }
+
+    /// Panics unless [`QueryRecorder::snapshot`] equals `expected`, ignoring surrounding whitespace
+    pub fn assert_snapshot(&self, expected: &str) {
+        let snapshot = self.snapshot();
+        assert_eq!(snapshot.trim(), expected.trim(), "recorded commands differ from the snapshot");
+    }
+}
+
+/// Whether `filter`, or one of its `$and` conditions, has `value` for `key`
+fn contains(filter: &Document, key: &str, value: &Bson) -> bool {
+    if filter.get(key) == Some(value) {
+        return true;
+    }
+    filter
+        .get_array("$and")
+        .map(|conditions| {
+            conditions
+                .iter()
+                .filter_map(Bson::as_document)
+                .any(|c| contains(c, key, value))
+        })
+        .unwrap_or(false)
+}
+
+/// Drops the fields that change from one run to the other
+fn normalize(command: &mut Document) {
+    for field in VOLATILE_FIELDS {
+        command.remove(field);
+    }
+    if let Ok(read_concern) = command.get_document_mut("readConcern") {
+        read_concern.remove("afterClusterTime");
+        if read_concern.is_empty() {
+            command.remove("readConcern");
+        }
+    }
+}
+
+/// Replaces generated ids and dates with placeholders
+fn mask(value: &mut Bson) {
+    match value {
+        Bson::ObjectId(_) => *value = Bson::String("<ObjectId>".to_string()),
+        Bson::DateTime(_) => *value = Bson::String("<DateTime>".to_string()),
+        Bson::Document(d) => d.iter_mut().for_each(|(_, v)| mask(v)),
+        Bson::Array(items) => items.iter_mut().for_each(mask),
+        _ => {}
+    }
+}
+
+/// Replaces the cursor ids picked by the server with placeholders
+fn mask_cursor_ids(command: &mut Document) {
+    let placeholder = || Bson::String("<CursorId>".to_string());
+    if let Some(id @ Bson::Int64(_)) = command.get_mut("getMore") {
+        *id = placeholder();
+    }
+    if let Ok(ids) = command.get_array_mut("cursors") {
+        ids.iter_mut().for_each(|id| *id = placeholder());
+    }
+}
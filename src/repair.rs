@@ -0,0 +1,70 @@
+//! Healing legacy documents on read
+//!
+//! When a read has to fill in missing fields, or replace undecodable ones under
+//! [`DecodeErrorPolicy::UseDefaultAndLog`](crate::options::DecodeErrorPolicy),
+//! the model offers the fix to [`Boot::repair`](crate::event::Boot::repair).
+//! Accepted fixes are written back in the background, so legacy data heals as
+//! it's read instead of in one big migration.
+//!
+//! ```ignore
+//! impl Boot for User {
+//!     type Req = ();
+//!
+//!     fn repair(&self, repair: &Repair) -> bool {
+//!         log::info!("repairing {} {}: {:?}", repair.collection, repair.id, repair.fixes);
+//!         true
+//!     }
+//! }
+//! ```
+//!
+//! # Notes
+//! - Write-behind needs the `rt-tokio` feature
+//! - Writes are throttled per collection, see `ModelOptions::max_repairs_per_second`,
+//!   skipped repairs are offered again on the next read
+//! - A repair only applies while the fixed fields still hold the values read
+
+use mongodb::bson::{Bson, Document};
+#[cfg(feature = "rt-tokio")]
+use std::collections::HashMap;
+#[cfg(feature = "rt-tokio")]
+use std::sync::Mutex;
+#[cfg(feature = "rt-tokio")]
+use std::time::{Duration, Instant};
+
+/// Fixes a read applied to a stored document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repair {
+    pub collection: String,
+    pub id: Bson,
+    /// Model fields missing from the document, filled with their default
+    pub missing: Vec<String>,
+    /// Model fields that didn't decode, replaced with their default
+    pub defaulted: Vec<String>,
+    /// Stored form of the fixed fields, the `$set` of the write-behind
+    pub fixes: Document,
+    /// Filter of the write-behind, the `_id` and the values read
+    pub filter: Document,
+}
+
+/// Writes per second window of each collection
+#[cfg(feature = "rt-tokio")]
+static WINDOWS: Mutex<Option<HashMap<String, (Instant, u32)>>> = Mutex::new(None);
+
+/// Whether one more repair of `collection` fits in the current second
+#[cfg(feature = "rt-tokio")]
+pub(crate) fn allow(collection: &str, per_second: u32) -> bool {
+    let mut windows = WINDOWS.lock().unwrap();
+    let windows = windows.get_or_insert_with(HashMap::new);
+    let (start, count) = windows
+        .entry(collection.to_string())
+        .or_insert((Instant::now(), 0));
+    if start.elapsed() >= Duration::from_secs(1) {
+        *start = Instant::now();
+        *count = 0;
+    }
+    if *count >= per_second {
+        return false;
+    }
+    *count += 1;
+    true
+}
@@ -0,0 +1,224 @@
+//! Replaying past changes to rebuild derived data
+//!
+//! A [`Replay`] reads the change events of a collection from a point in the
+//! past up to now, in order, and hands them to a handler, e.g. to rebuild a
+//! search index or a cache after a bug. The position is saved as a checkpoint
+//! so an interrupted replay continues where it stopped.
+//!
+//! ```ignore
+//! let report = Order::new_model(&db)
+//!     .replay([])
+//!     .from_time(DateTime::parse_rfc3339_str("2024-05-01T00:00:00Z")?)
+//!     .checkpoint("replay_checkpoints", "orders_search")
+//!     .max_per_second(500)
+//!     .run(|event| async move { search.index(event).await })
+//!     .await?;
+//! ```
+//!
+//! Starting from a recorded revision of [`crate::history`] replays what
+//! happened after that version of a document, see [`Replay::from_version`].
+//!
+//! # Notes
+//! - Changes are read from the oplog, only those still in its window can be replayed
+//! - The replay ends once it has caught up, changes made after that aren't handled
+
+use mongodb::bson::{doc, Bson, DateTime, Document, Timestamp};
+use mongodb::change_stream::event::{ChangeStreamEvent, ResumeToken};
+use mongodb::error::{Error, Result};
+use mongodb::options::{ChangeStreamOptions, FullDocumentType};
+use mongodb::Database;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Outcome of a [`Replay::run`]
+#[derive(Debug, Clone)]
+pub struct ReplayReport {
+    /// Number of events handled
+    pub events: u64,
+    /// Token of the last handled event, `None` if there was none
+    pub last_token: Option<ResumeToken>,
+}
+
+/// Replay of the change events of a collection
+pub struct Replay {
+    db: Database,
+    collection: String,
+    pipeline: Vec<Document>,
+    full_document: Option<FullDocumentType>,
+    token: Option<ResumeToken>,
+    time: Option<Timestamp>,
+    checkpoint: Option<(String, String)>,
+    checkpoint_every: u64,
+    max_per_second: Option<u32>,
+}
+
+impl Replay {
+    pub fn new(db: &Database, collection: &str, pipeline: Vec<Document>) -> Replay {
+        Replay {
+            db: db.clone(),
+            collection: collection.to_string(),
+            pipeline,
+            full_document: None,
+            token: None,
+            time: None,
+            checkpoint: None,
+            checkpoint_every: 100,
+            max_per_second: None,
+        }
+    }
+
+    /// Starts after the event of `token`
+    pub fn from_token(mut self, token: ResumeToken) -> Replay {
+        self.token = Some(token);
+        self
+    }
+
+    /// Starts with the events at or after `time`
+    pub fn from_time(mut self, time: DateTime) -> Replay {
+        let seconds = time.timestamp_millis().div_euclid(1000);
+        self.time = Some(Timestamp {
+            time: seconds.clamp(0, u32::MAX as i64) as u32,
+            increment: 0,
+        });
+        self
+    }
+
+    /// Starts after the change recorded as `revision`, a document of a
+    /// [`Revisions`](crate::history::Revisions) collection
+    ///
+    /// # Notes
+    /// - Fails when `revision` has no resume token, revisions keep the token of their event as `_id`
+    pub fn from_version(mut self, revision: &Document) -> Result<Replay> {
+        let token = match revision.get("_id") {
+            Some(token @ Bson::Document(_)) => mongodb::bson::from_bson(token.clone())?,
+            _ => {
+                return Err(Error::from(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "revision has no resume token.",
+                )));
+            }
+        };
+        self.token = Some(token);
+        Ok(self)
+    }
+
+    /// Includes the current document in update events
+    pub fn full_document(mut self, full_document: FullDocumentType) -> Replay {
+        self.full_document = Some(full_document);
+        self
+    }
+
+    /// Saves the position in the `name` document of `collection`
+    ///
+    /// # Notes
+    /// - A saved position takes precedence over `from_token`, `from_version` and `from_time`
+    pub fn checkpoint(mut self, collection: &str, name: &str) -> Replay {
+        self.checkpoint = Some((collection.to_string(), name.to_string()));
+        self
+    }
+
+    /// Number of handled events between two saves of the checkpoint, 100 by default
+    pub fn checkpoint_every(mut self, events: u64) -> Replay {
+        self.checkpoint_every = events.max(1);
+        self
+    }
+
+    /// Limits how many events are handled per second
+    pub fn max_per_second(mut self, events: u32) -> Replay {
+        self.max_per_second = Some(events);
+        self
+    }
+
+    async fn load_checkpoint(&self) -> Result<Option<ResumeToken>> {
+        let Some((collection, name)) = &self.checkpoint else {
+            return Ok(None);
+        };
+        let saved = self
+            .db
+            .collection::<Document>(collection)
+            .find_one(doc! {"_id": name})
+            .await?;
+        match saved.and_then(|mut d| d.remove("token")) {
+            Some(token) => Ok(Some(mongodb::bson::from_bson(token)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn save_checkpoint(&self, token: &ResumeToken) -> Result<()> {
+        let Some((collection, name)) = &self.checkpoint else {
+            return Ok(());
+        };
+        let token = mongodb::bson::to_bson(token)?;
+        self.db
+            .collection::<Document>(collection)
+            .update_one(
+                doc! {"_id": name},
+                doc! {"$set": {"token": token, "updated_at": DateTime::now()}},
+            )
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    /// Handles the events in order until the replay has caught up
+    ///
+    /// # Notes
+    /// - Stops at the first error of `handler`, after saving the checkpoint of
+    ///   the last handled event, so the next run retries the failed one
+    pub async fn run<F, Fut>(self, mut handler: F) -> Result<ReplayReport>
+    where
+        F: FnMut(ChangeStreamEvent<Document>) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let mut options = ChangeStreamOptions::default();
+        options.full_document = self.full_document.clone();
+        match self.load_checkpoint().await? {
+            Some(token) => options.start_after = Some(token),
+            None if self.token.is_some() => options.start_after = self.token.clone(),
+            None => options.start_at_operation_time = self.time,
+        }
+        if options.start_after.is_none() && options.start_at_operation_time.is_none() {
+            return Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "replay needs a starting token, time or checkpoint.",
+            )));
+        }
+        let mut stream = self
+            .db
+            .collection::<Document>(&self.collection)
+            .watch()
+            .pipeline(self.pipeline.clone())
+            .with_options(options)
+            .await?;
+
+        let mut report = ReplayReport {
+            events: 0,
+            last_token: None,
+        };
+        let started = Instant::now();
+        while let Some(event) = stream.next_if_any().await? {
+            if let Some(rate) = self.max_per_second.filter(|r| *r > 0) {
+                let due = Duration::from_secs_f64(report.events as f64 / rate as f64);
+                if let Some(wait) = due.checked_sub(started.elapsed()) {
+                    tokio::time::sleep(wait).await;
+                }
+            }
+            let token = event.id.clone();
+            if let Err(e) = handler(event).await {
+                if let Some(last) = &report.last_token {
+                    self.save_checkpoint(last).await?;
+                }
+                return Err(e);
+            }
+            report.events += 1;
+            if report.events.is_multiple_of(self.checkpoint_every) {
+                self.save_checkpoint(&token).await?;
+            }
+            report.last_token = Some(token);
+        }
+        if let Some(last) = &report.last_token {
+            self.save_checkpoint(last).await?;
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,151 @@
+//! Materialized reporting collections
+//!
+//! A [`ReportModel`] runs an aggregation pipeline on a source collection and
+//! replaces a target collection with its output (`$out`). Every refresh is
+//! recorded in the `report_refreshes` collection so staleness can be checked
+//! from any process.
+
+use futures_util::StreamExt;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::error::Result;
+use mongodb::Database;
+use std::time::Duration;
+
+const META_COLLECTION: &str = "report_refreshes";
+
+/// Metadata of the last refresh of a report
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportStatus {
+    pub target: String,
+    pub refreshed_at: DateTime,
+    /// Time the pipeline took, in milliseconds
+    pub took_ms: i64,
+}
+
+impl ReportStatus {
+    /// Time elapsed since the last refresh
+    pub fn age(&self) -> Duration {
+        let elapsed = DateTime::now().timestamp_millis() - self.refreshed_at.timestamp_millis();
+        Duration::from_millis(elapsed.max(0) as u64)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReportModel {
+    db: Database,
+    source: String,
+    target: String,
+    pipeline: Vec<Document>,
+    interval: Duration,
+}
+
+impl ReportModel {
+    /// # Arguments
+    /// * `source` - Collection the pipeline runs on
+    /// * `target` - Collection replaced with the pipeline output
+    /// * `interval` - Maximum age before the report is considered stale
+    pub fn new(
+        db: &Database,
+        source: &str,
+        target: &str,
+        pipeline: Vec<Document>,
+        interval: Duration,
+    ) -> ReportModel {
+        ReportModel {
+            db: db.clone(),
+            source: source.to_string(),
+            target: target.to_string(),
+            pipeline,
+            interval,
+        }
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Runs the pipeline and replaces the target collection
+    pub async fn refresh_now(&self) -> Result<ReportStatus> {
+        let started = DateTime::now();
+        let mut pipeline = self.pipeline.clone();
+        pipeline.push(doc! {"$out": &self.target});
+        let mut cursor = self
+            .db
+            .collection::<Document>(&self.source)
+            .aggregate(pipeline)
+            .await?;
+        while let Some(d) = cursor.next().await {
+            d?;
+        }
+
+        let status = ReportStatus {
+            target: self.target.clone(),
+            refreshed_at: DateTime::now(),
+            took_ms: DateTime::now().timestamp_millis() - started.timestamp_millis(),
+        };
+        self.db
+            .collection::<Document>(META_COLLECTION)
+            .update_one(
+                doc! {"_id": &self.target},
+                doc! {"$set": {
+                    "source": &self.source,
+                    "refreshed_at": status.refreshed_at,
+                    "took_ms": status.took_ms,
+                }},
+            )
+            .upsert(true)
+            .await?;
+        Ok(status)
+    }
+
+    /// Metadata of the last refresh, `None` if the report was never built
+    pub async fn status(&self) -> Result<Option<ReportStatus>> {
+        let meta = self
+            .db
+            .collection::<Document>(META_COLLECTION)
+            .find_one(doc! {"_id": &self.target})
+            .await?;
+        Ok(meta.and_then(|m| {
+            Some(ReportStatus {
+                target: self.target.clone(),
+                refreshed_at: *m.get_datetime("refreshed_at").ok()?,
+                took_ms: m.get_i64("took_ms").unwrap_or_default(),
+            })
+        }))
+    }
+
+    /// Whether the report is older than its refresh interval
+    pub async fn is_stale(&self) -> Result<bool> {
+        Ok(match self.status().await? {
+            None => true,
+            Some(status) => status.age() >= self.interval,
+        })
+    }
+
+    /// Spawns a task refreshing the report whenever it becomes stale
+    ///
+    /// # Notes
+    /// - Staleness is read from the shared metadata, so several processes can run a refresher
+    /// - Failed refreshes are logged and retried on the next tick
+    #[cfg(feature = "rt-tokio")]
+    pub fn spawn_refresher(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let wait = match self.status().await {
+                    Ok(Some(status)) if status.age() < self.interval => self.interval - status.age(),
+                    Ok(_) => {
+                        if let Err(e) = self.refresh_now().await {
+                            log::error!("Can't refresh report {} : {:?}", self.target, e);
+                        }
+                        self.interval
+                    }
+                    Err(e) => {
+                        log::error!("Can't read report status {} : {:?}", self.target, e);
+                        self.interval
+                    }
+                };
+                tokio::time::sleep(wait.max(Duration::from_secs(1))).await;
+            }
+        })
+    }
+}
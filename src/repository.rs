@@ -0,0 +1,132 @@
+//! Mockable data access per model
+//!
+//! [`Repository`] is the small CRUD surface services usually need. Code that
+//! takes a repository instead of building models can be unit tested with a
+//! mock, while production uses [`ModelRepository`], backed by [`Model`].
+//!
+//! ```ignore
+//! mongodb_ro::repository!(pub UserRepository for User);
+//!
+//! struct Signup<R: UserRepository> { users: R }
+//!
+//! // production
+//! let service = Signup { users: User::repository(&db) };
+//! // tests
+//! let service = Signup { users: InMemoryUsers::default() };
+//! ```
+
+use crate::event::Boot;
+use crate::model::Model;
+use crate::page::Page;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::Document;
+use mongodb::error::{Error, Result};
+use mongodb::Database;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// CRUD operations on the documents of one model
+#[allow(async_fn_in_trait)]
+pub trait Repository<M> {
+    async fn find(&self, id: ObjectId) -> Result<Option<M>>;
+
+    /// Page of the documents matching `filter`, `page` starting at 1
+    async fn list(&self, filter: Document, page: u32, per_page: u32) -> Result<Page<M>>;
+
+    /// Inserts `item`, returning its `_id`
+    async fn create(&self, item: M) -> Result<ObjectId>;
+
+    /// Applies `data` to the document, returning it as it is after the update
+    async fn update(&self, id: ObjectId, data: Document) -> Result<Option<M>>;
+
+    /// Deletes the document, returning it
+    async fn delete(&self, id: ObjectId) -> Result<Option<M>>;
+}
+
+/// [`Repository`] running the queries with [`Model`]
+///
+/// Renames, hidden fields, timestamps, hooks and options of the model all apply.
+pub struct ModelRepository<M>
+where
+    M: Boot + Default + Serialize + DeserializeOwned + Send + Sync + Unpin,
+{
+    db: Database,
+    new_model: fn(&Database) -> Model<'static, M>,
+}
+
+impl<M> Clone for ModelRepository<M>
+where
+    M: Boot + Default + Serialize + DeserializeOwned + Send + Sync + Unpin,
+{
+    fn clone(&self) -> Self {
+        ModelRepository {
+            db: self.db.clone(),
+            new_model: self.new_model,
+        }
+    }
+}
+
+impl<M> ModelRepository<M>
+where
+    M: Boot + Default + Serialize + DeserializeOwned + Send + Sync + Unpin,
+{
+    /// # Arguments
+    /// * `new_model` - The model constructor generated by the derive, e.g. `User::new_model`
+    pub fn new(db: &Database, new_model: fn(&Database) -> Model<'static, M>) -> ModelRepository<M> {
+        ModelRepository {
+            db: db.clone(),
+            new_model,
+        }
+    }
+}
+
+impl<M> Repository<M> for ModelRepository<M>
+where
+    M: Boot + Default + Serialize + DeserializeOwned + Send + Sync + Unpin,
+{
+    async fn find(&self, id: ObjectId) -> Result<Option<M>> {
+        (self.new_model)(&self.db).find_by_id(id).await
+    }
+
+    async fn list(&self, filter: Document, page: u32, per_page: u32) -> Result<Page<M>> {
+        (self.new_model)(&self.db)
+            .r#where(filter)
+            .paginate(page, per_page)
+            .await
+    }
+
+    async fn create(&self, item: M) -> Result<ObjectId> {
+        let r = (self.new_model)(&self.db).fill(item).create().await?;
+        r.inserted_id
+            .as_object_id()
+            .ok_or_else(|| Error::custom("inserted id is not an ObjectId"))
+    }
+
+    async fn update(&self, id: ObjectId, data: Document) -> Result<Option<M>> {
+        (self.new_model)(&self.db).update_by_id(id, data).await
+    }
+
+    async fn delete(&self, id: ObjectId) -> Result<Option<M>> {
+        (self.new_model)(&self.db).delete_by_id(id).await
+    }
+}
+
+/// Declares a repository trait for a model
+///
+/// Generates `$name`, implemented by every [`Repository`] of `$model`, and a
+/// `$model::repository(&db)` constructor of its [`ModelRepository`].
+#[macro_export]
+macro_rules! repository {
+    ($vis:vis $name:ident for $model:ident) => {
+        $vis trait $name: $crate::repository::Repository<$model> {}
+
+        impl<T: $crate::repository::Repository<$model>> $name for T {}
+
+        #[allow(dead_code)]
+        impl $model {
+            pub fn repository(db: &$crate::mongodb::Database) -> $crate::repository::ModelRepository<$model> {
+                $crate::repository::ModelRepository::new(db, $model::new_model)
+            }
+        }
+    };
+}
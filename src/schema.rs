@@ -0,0 +1,92 @@
+//! Field metadata of models at runtime
+//!
+//! `Model::schema` lists the fields of a model with their column attributes
+//! and descriptions, e.g. for admin UIs, and `Model::openapi_schema` turns them
+//! into an OpenAPI schema object for API docs.
+//!
+//! ```ignore
+//! impl Boot for User {
+//!     type Req = ();
+//!
+//!     fn configure_columns(&self, columns: &mut HashMap<&str, ColumnAttr>) {
+//!         columns.get_mut("phone").unwrap().doc = Some("User's primary phone in E.164".into());
+//!     }
+//! }
+//!
+//! let components = json!({"schemas": {"User": User::new_model(&db).openapi_schema()}});
+//! ```
+
+use mongodb::bson::Bson;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// Description of one field of a model
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldSchema {
+    /// Name in the struct and in the JSON output
+    pub name: String,
+    /// Name in the database
+    pub db_name: String,
+    pub doc: Option<String>,
+    /// JSON schema type, `None` when the default value doesn't tell, e.g. a `None` option
+    pub kind: Option<&'static str>,
+    /// JSON schema format, e.g. `date-time`
+    pub format: Option<&'static str>,
+    pub hidden: bool,
+    pub unique: bool,
+    pub indexed: bool,
+    pub heavy: bool,
+}
+
+/// JSON schema type and format of a value
+pub(crate) fn kind_of(value: &Bson) -> (Option<&'static str>, Option<&'static str>) {
+    match value {
+        Bson::String(_) => (Some("string"), None),
+        Bson::ObjectId(_) => (Some("string"), Some("objectid")),
+        Bson::DateTime(_) => (Some("string"), Some("date-time")),
+        Bson::Boolean(_) => (Some("boolean"), None),
+        Bson::Int32(_) => (Some("integer"), Some("int32")),
+        Bson::Int64(_) => (Some("integer"), Some("int64")),
+        Bson::Double(_) | Bson::Decimal128(_) => (Some("number"), None),
+        Bson::Array(_) => (Some("array"), None),
+        Bson::Document(_) => (Some("object"), None),
+        _ => (None, None),
+    }
+}
+
+/// `$jsonSchema` bsonType of a value
+pub(crate) fn bson_type_of(value: &Bson) -> Option<Bson> {
+    let kind = match value {
+        Bson::String(_) => "string",
+        Bson::ObjectId(_) => "objectId",
+        Bson::DateTime(_) => "date",
+        Bson::Boolean(_) => "bool",
+        Bson::Int32(_) | Bson::Int64(_) => return Some(Bson::from(vec!["int", "long"])),
+        // other writers store whole numbers as integers
+        Bson::Double(_) | Bson::Decimal128(_) => "number",
+        Bson::Array(_) => "array",
+        Bson::Document(_) => "object",
+        Bson::Binary(_) => "binData",
+        _ => return None,
+    };
+    Some(Bson::from(kind))
+}
+
+/// OpenAPI schema object of `fields`, hidden fields left out
+pub fn openapi(fields: &[FieldSchema]) -> Value {
+    let mut properties = Map::new();
+    for field in fields.iter().filter(|f| !f.hidden) {
+        let mut property = Map::new();
+        if let Some(kind) = field.kind {
+            property.insert("type".to_string(), json!(kind));
+        }
+        if let Some(format) = field.format {
+            property.insert("format".to_string(), json!(format));
+        }
+        if let Some(doc) = &field.doc {
+            property.insert("description".to_string(), json!(doc));
+        }
+        properties.insert(field.name.clone(), Value::Object(property));
+    }
+    json!({"type": "object", "properties": properties})
+}
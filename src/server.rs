@@ -0,0 +1,137 @@
+//! Detection of server capabilities
+//!
+//! [`ServerInfo::detect`] runs `hello` and `buildInfo`, so features can be
+//! skipped or degraded on older or simpler deployments (e.g. no transactions
+//! on a standalone server). A [`ServerInfoCache`] kept next to the `Client`,
+//! as [`SessionPool`](crate::session::SessionPool) does, reuses the result.
+
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::Result;
+use mongodb::Database;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// How long a [`ServerInfoCache`] reuses a detected [`ServerInfo`]
+pub const CACHE_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    Standalone,
+    ReplicaSet,
+    Sharded,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    /// Version string reported by `buildInfo`, e.g. `7.0.12`
+    pub version: String,
+    /// `[major, minor, patch]`
+    pub version_array: [u32; 3],
+    pub topology: Topology,
+    /// Hosted on MongoDB Atlas, detected from the host names
+    pub atlas: bool,
+    pub max_wire_version: i32,
+}
+
+impl ServerInfo {
+    /// Builds the info from the `hello` and `buildInfo` command responses
+    pub fn from_responses(hello: &Document, build_info: &Document) -> ServerInfo {
+        let version = build_info.get_str("version").unwrap_or_default().to_string();
+        let mut version_array = [0; 3];
+        for (i, part) in version.split(['.', '-']).take(3).enumerate() {
+            version_array[i] = part.parse().unwrap_or(0);
+        }
+        let topology = if hello.get_str("msg") == Ok("isdbgrid") {
+            Topology::Sharded
+        } else if hello.contains_key("setName") {
+            Topology::ReplicaSet
+        } else {
+            Topology::Standalone
+        };
+        let atlas = hello
+            .get_array("hosts")
+            .map(|h| h.iter().filter_map(Bson::as_str).collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .chain(hello.get_str("me").ok())
+            .any(|host| host.split(':').next().unwrap_or_default().ends_with(".mongodb.net"));
+        let max_wire_version = match hello.get("maxWireVersion") {
+            Some(Bson::Int32(v)) => *v,
+            Some(Bson::Int64(v)) => *v as i32,
+            _ => 0,
+        };
+        ServerInfo {
+            version,
+            version_array,
+            topology,
+            atlas,
+            max_wire_version,
+        }
+    }
+
+    /// Detects the server behind `db`, running both commands on every call
+    pub async fn detect(db: &Database) -> Result<ServerInfo> {
+        let hello = db.run_command(doc! {"hello": 1}).await?;
+        let build_info = db.run_command(doc! {"buildInfo": 1}).await?;
+        Ok(ServerInfo::from_responses(&hello, &build_info))
+    }
+
+    pub fn at_least(&self, major: u32, minor: u32) -> bool {
+        self.version_array[0] > major
+            || (self.version_array[0] == major && self.version_array[1] >= minor)
+    }
+
+    /// Multi-document transactions need a replica set (4.0+) or a sharded cluster (4.2+)
+    pub fn supports_transactions(&self) -> bool {
+        match self.topology {
+            Topology::Standalone => false,
+            Topology::ReplicaSet => self.at_least(4, 0),
+            Topology::Sharded => self.at_least(4, 2),
+        }
+    }
+
+    /// `$vectorSearch` is only available on Atlas 6.0.11+
+    pub fn supports_vector_search(&self) -> bool {
+        self.atlas && (self.at_least(6, 1) || self.version_array >= [6, 0, 11])
+    }
+
+    /// Change streams need a replica set or a sharded cluster
+    pub fn supports_change_streams(&self) -> bool {
+        self.topology != Topology::Standalone
+    }
+}
+
+/// [`ServerInfo`] of one client, reused for [`CACHE_TTL`]
+///
+/// The driver doesn't expose the identity of a client, so the cache belongs
+/// next to it, e.g. in the application state holding the `Client`.
+#[derive(Debug, Default)]
+pub struct ServerInfoCache {
+    info: RwLock<Option<(ServerInfo, Instant)>>,
+}
+
+impl ServerInfoCache {
+    pub fn new() -> ServerInfoCache {
+        ServerInfoCache::default()
+    }
+
+    /// Cached info, detected with `db` when missing or expired
+    ///
+    /// # Notes
+    /// - `db` must belong to the client the cache was made for
+    pub async fn get(&self, db: &Database) -> Result<ServerInfo> {
+        if let Some((info, at)) = self.info.read().unwrap().as_ref()
+            && at.elapsed() < CACHE_TTL
+        {
+            return Ok(info.clone());
+        }
+        let info = ServerInfo::detect(db).await?;
+        *self.info.write().unwrap() = Some((info.clone(), Instant::now()));
+        Ok(info)
+    }
+
+    /// Drops the cached info, e.g. after an upgrade
+    pub fn invalidate(&self) {
+        *self.info.write().unwrap() = None;
+    }
+}
@@ -0,0 +1,191 @@
+//! Sessions shared by every model of a request
+//!
+//! [`SessionPool::scope`] starts a causally consistent session and runs a
+//! future with it as the current session. Models used inside the future run
+//! their queries in it without being passed the session, so a request reads
+//! its own writes even on secondaries.
+//!
+//! ```ignore
+//! let pool = SessionPool::new(client.clone());
+//! // e.g. in a middleware
+//! let response = pool.scope(handler(request)).await?;
+//!
+//! // in the handler, both queries use the request's session
+//! User::new_model(&db).r#where(doc! {"_id": id}).update(doc! {"$set": {"name": "a"}}).await?;
+//! let user = User::new_model(&db).find_by_id(id).await?;
+//! ```
+//!
+//! [`SessionPool::transaction`] does the same inside a transaction, committed
+//! when the future succeeds:
+//!
+//! ```ignore
+//! let pool = SessionPool::new(client.clone()).fallback(TransactionFallback::RunWithoutTransaction);
+//! pool.transaction(async {
+//!     Account::new_model(&db).r#where(doc! {"_id": from}).update(doc! {"$inc": {"balance": -10}}).await?;
+//!     Account::new_model(&db).r#where(doc! {"_id": to}).update(doc! {"$inc": {"balance": 10}}).await?;
+//!     Ok(())
+//! })
+//! .await?;
+//! ```
+//!
+//! # Notes
+//! - The session lives in a tokio task local, so it doesn't follow `tokio::spawn`
+//! - Concurrent queries of one request wait for each other, a session can't run two at once
+//! - `*_with_session` methods keep using the session they are given
+//! - Streams, cursors and `*_via` methods don't use the current session, nor do
+//!   `estimated_count`, `explain*` and the maintenance jobs (`backfill_defaults`,
+//!   `refresh_index_exprs`, `copy_to`, `export_jsonl`, `import_jsonl`)
+
+use crate::error::TransactionsUnsupported;
+use crate::server::{ServerInfo, ServerInfoCache};
+use mongodb::error::Result;
+use mongodb::options::SessionOptions;
+use mongodb::{Client, ClientSession};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Session shared by the models of a scope
+pub type SharedSession = Arc<Mutex<ClientSession>>;
+
+tokio::task_local! {
+    static CURRENT: Option<SharedSession>;
+}
+
+/// What [`SessionPool::transaction`] does on a deployment without transactions,
+/// e.g. a standalone server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransactionFallback {
+    /// Fails with [`TransactionsUnsupported`]
+    #[default]
+    Fail,
+    /// Runs the future in a session without transaction, logging a warning
+    RunWithoutTransaction,
+}
+
+/// Starts the sessions of request scopes
+#[derive(Debug, Clone)]
+pub struct SessionPool {
+    client: Client,
+    options: SessionOptions,
+    fallback: TransactionFallback,
+    server_info: Arc<ServerInfoCache>,
+}
+
+impl SessionPool {
+    /// Pool of causally consistent sessions of `client`
+    pub fn new(client: Client) -> SessionPool {
+        let options = SessionOptions::builder().causal_consistency(true).build();
+        SessionPool {
+            client,
+            options,
+            fallback: TransactionFallback::default(),
+            server_info: Arc::default(),
+        }
+    }
+
+    /// Replaces the options of the started sessions
+    pub fn options(mut self, options: SessionOptions) -> SessionPool {
+        self.options = options;
+        self
+    }
+
+    /// Sets what transactions do on deployments without transactions
+    pub fn fallback(mut self, fallback: TransactionFallback) -> SessionPool {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Capabilities of the pool's server, detected once per [`CACHE_TTL`](crate::server::CACHE_TTL)
+    pub async fn server_info(&self) -> Result<ServerInfo> {
+        let db = self
+            .client
+            .default_database()
+            .unwrap_or_else(|| self.client.database("admin"));
+        self.server_info.get(&db).await
+    }
+
+    /// Cache behind [`SessionPool::server_info`], to share with the models of the same client
+    pub fn server_info_cache(&self) -> Arc<ServerInfoCache> {
+        self.server_info.clone()
+    }
+
+    /// Starts a session that can be passed to [`SessionPool::scope_with`]
+    pub async fn session(&self) -> Result<SharedSession> {
+        let session = self
+            .client
+            .start_session()
+            .with_options(self.options.clone())
+            .await?;
+        Ok(Arc::new(Mutex::new(session)))
+    }
+
+    /// Runs `f` with a new session as the current session
+    pub async fn scope<F: Future>(&self, f: F) -> Result<F::Output> {
+        let session = self.session().await?;
+        Ok(scope_with(session, f).await)
+    }
+
+    /// Runs `f` in a transaction of a new current session
+    ///
+    /// Commits when `f` returns `Ok`, aborts when it returns an error.
+    ///
+    /// # Notes
+    /// - Transactions need a replica set or a sharded cluster, on other deployments
+    ///   the pool's [`TransactionFallback`] applies
+    /// - Writes of a run without transaction aren't rolled back on error
+    pub async fn transaction<F, T>(&self, f: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let info = self.server_info().await?;
+        let session = self.session().await?;
+        if !info.supports_transactions() {
+            if self.fallback == TransactionFallback::Fail {
+                return Err(TransactionsUnsupported {
+                    topology: info.topology,
+                    version: info.version,
+                }
+                .into());
+            }
+            log::warn!(
+                "transactions are not supported on this {:?} server {}, running without transaction",
+                info.topology,
+                info.version
+            );
+            return scope_with(session, f).await;
+        }
+
+        session.lock().await.start_transaction().await?;
+        match scope_with(session.clone(), f).await {
+            Ok(r) => {
+                session.lock().await.commit_transaction().await?;
+                Ok(r)
+            }
+            Err(e) => {
+                if let Err(abort) = session.lock().await.abort_transaction().await {
+                    log::warn!("transaction abort failed : {:?}", abort);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Runs `f` with `session` as the current session
+pub async fn scope_with<F: Future>(session: SharedSession, f: F) -> F::Output {
+    CURRENT.scope(Some(session), f).await
+}
+
+/// Session of the enclosing scope, if any
+pub fn current() -> Option<SharedSession> {
+    CURRENT.try_with(|s| s.clone()).ok().flatten()
+}
+
+/// Runs `f` outside of any scope
+///
+/// Used while the current session is locked, so hooks calling other models
+/// don't wait on it forever.
+pub(crate) async fn detached<F: Future>(f: F) -> F::Output {
+    CURRENT.scope(None, f).await
+}
@@ -0,0 +1,53 @@
+//! Sampled column statistics
+
+use mongodb::bson::{Bson, Document};
+
+/// Statistics of one field computed on a random sample
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldStats {
+    pub field: String,
+    /// Number of sampled documents
+    pub sampled: u64,
+    /// Distinct values seen in the sample
+    pub distinct: u64,
+    /// Share of sampled documents where the field is missing or null
+    pub null_ratio: f64,
+    pub min: Bson,
+    pub max: Bson,
+    /// Average encoded size of the value in bytes
+    pub avg_size: f64,
+}
+
+impl FieldStats {
+    pub(crate) fn from_document(field: &str, d: &Document) -> FieldStats {
+        let sampled = number(d.get("count")) as u64;
+        let nulls = number(d.get("nulls"));
+        FieldStats {
+            field: field.to_string(),
+            sampled,
+            distinct: number(d.get("distinct")) as u64,
+            null_ratio: if sampled == 0 { 0.0 } else { nulls / sampled as f64 },
+            min: d.get("min").cloned().unwrap_or(Bson::Null),
+            max: d.get("max").cloned().unwrap_or(Bson::Null),
+            avg_size: number(d.get("avg_size")),
+        }
+    }
+
+    /// Distinct values per sampled document, close to 1.0 for unique-like fields
+    pub fn selectivity(&self) -> f64 {
+        if self.sampled == 0 {
+            0.0
+        } else {
+            self.distinct as f64 / self.sampled as f64
+        }
+    }
+}
+
+fn number(value: Option<&Bson>) -> f64 {
+    match value {
+        Some(Bson::Int32(v)) => *v as f64,
+        Some(Bson::Int64(v)) => *v as f64,
+        Some(Bson::Double(v)) => *v,
+        _ => 0.0,
+    }
+}
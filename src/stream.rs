@@ -0,0 +1,106 @@
+//! Adapters for result streams and session cursors
+//!
+//! Session cursors borrow the session mutably for every read, so `StreamExt`
+//! combinators that also need the session for lookups do not compile.
+//! [`SessionChunks`] reads one chunk at a time and hands the session back
+//! between chunks, so lookups inside the same transaction stay possible.
+
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use mongodb::error::Result;
+use mongodb::{ClientSession, SessionCursor};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+
+/// Combinators for streams of `Result<T>`
+pub trait ModelStreamExt<T>: Stream<Item = Result<T>> + Sized {
+    /// Groups items into vectors of at most `size` items
+    ///
+    /// A chunk containing an error yields that error.
+    fn try_chunks(self, size: usize) -> impl Stream<Item = Result<Vec<T>>> {
+        self.chunks(size.max(1))
+            .map(|items| items.into_iter().collect::<Result<Vec<T>>>())
+    }
+
+    /// Resolves items in batches of `size` with a single lookup per batch
+    ///
+    /// `lookup` receives a whole chunk (e.g. to run one `$in` query) and the
+    /// items it returns are flattened back into the stream.
+    fn buffered_lookup<R, F, Fut>(self, size: usize, lookup: F) -> impl Stream<Item = Result<R>>
+    where
+        F: FnMut(Vec<T>) -> Fut,
+        Fut: Future<Output = Result<Vec<R>>>,
+    {
+        self.try_chunks(size)
+            .and_then(lookup)
+            .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    /// Runs `f` for every item with at most `limit` futures in flight
+    ///
+    /// Stops at the first error.
+    fn for_each_concurrent_limited<F, Fut>(
+        self,
+        limit: usize,
+        f: F,
+    ) -> impl Future<Output = Result<()>>
+    where
+        F: FnMut(T) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        self.try_for_each_concurrent(limit.max(1), f)
+    }
+}
+
+impl<T, S: Stream<Item = Result<T>>> ModelStreamExt<T> for S {}
+
+/// Chunked reader over a [`SessionCursor`] that only borrows the session per call
+pub struct SessionChunks<'c, T> {
+    cursor: &'c mut SessionCursor<T>,
+    size: usize,
+}
+
+impl<'c, T: DeserializeOwned> SessionChunks<'c, T> {
+    pub fn new(cursor: &'c mut SessionCursor<T>, size: usize) -> Self {
+        Self {
+            cursor,
+            size: size.max(1),
+        }
+    }
+
+    /// Reads the next chunk, `None` once the cursor is exhausted
+    pub async fn next(&mut self, session: &mut ClientSession) -> Result<Option<Vec<T>>> {
+        let mut r = Vec::with_capacity(self.size);
+        while r.len() < self.size {
+            match self.cursor.next(session).await {
+                Some(item) => r.push(item?),
+                None => break,
+            }
+        }
+        if r.is_empty() { Ok(None) } else { Ok(Some(r)) }
+    }
+
+    /// Reads the next chunk and resolves it with `lookup` using the same session
+    pub async fn next_with_lookup<R>(
+        &mut self,
+        session: &mut ClientSession,
+        lookup: impl AsyncFnOnce(Vec<T>, &mut ClientSession) -> Result<R>,
+    ) -> Result<Option<R>> {
+        match self.next(session).await? {
+            Some(chunk) => Ok(Some(lookup(chunk, session).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Runs `f` for every chunk, passing the session along
+    pub async fn for_each(
+        mut self,
+        session: &mut ClientSession,
+        mut f: impl AsyncFnMut(Vec<T>, &mut ClientSession) -> Result<()>,
+    ) -> Result<()> {
+        while let Some(chunk) = self.next(session).await? {
+            f(chunk, session).await?;
+        }
+        Ok(())
+    }
+}
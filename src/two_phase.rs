@@ -0,0 +1,158 @@
+//! Two-phase writes across databases or clusters
+//!
+//! A single MongoDB transaction can't span two clusters. [`TwoPhase`] first
+//! records an intent document in a coordinator collection, then applies each
+//! step in order. When a step fails, the compensation of every applied step
+//! runs in reverse order. The intent document tracks the state so that
+//! interrupted runs can be found with [`TwoPhase::unfinished`] and repaired.
+//!
+//! ```ignore
+//! let id = TwoPhase::new(&coordinator_db, "transfers")
+//!     .step("debit", || debit(&db_a), || credit(&db_a))
+//!     .step("credit", || credit(&db_b), || debit(&db_b))
+//!     .run(doc! {"amount": 10})
+//!     .await?;
+//! ```
+
+use futures::future::LocalBoxFuture;
+use futures_util::StreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::error::Result;
+use mongodb::{Collection, Database};
+use std::future::Future;
+
+pub const STATE_PENDING: &str = "pending";
+pub const STATE_COMMITTED: &str = "committed";
+pub const STATE_ROLLED_BACK: &str = "rolled_back";
+/// A step failed and at least one compensation failed too, needs manual repair
+pub const STATE_COMPENSATION_FAILED: &str = "compensation_failed";
+
+type Action<'a> = Box<dyn FnOnce() -> LocalBoxFuture<'a, Result<()>> + 'a>;
+
+struct Step<'a> {
+    name: String,
+    apply: Action<'a>,
+    compensate: Action<'a>,
+}
+
+pub struct TwoPhase<'a> {
+    log: Collection<Document>,
+    steps: Vec<Step<'a>>,
+}
+
+impl<'a> TwoPhase<'a> {
+    /// # Arguments
+    /// * `db` - Coordinator database holding the intent documents
+    /// * `collection` - Collection of the intent documents
+    pub fn new(db: &Database, collection: &str) -> TwoPhase<'a> {
+        TwoPhase {
+            log: db.collection(collection),
+            steps: vec![],
+        }
+    }
+
+    /// Adds a step and the action undoing it
+    pub fn step<A, AF, C, CF>(mut self, name: &str, apply: A, compensate: C) -> TwoPhase<'a>
+    where
+        A: FnOnce() -> AF + 'a,
+        AF: Future<Output = Result<()>> + 'a,
+        C: FnOnce() -> CF + 'a,
+        CF: Future<Output = Result<()>> + 'a,
+    {
+        self.steps.push(Step {
+            name: name.to_string(),
+            apply: Box::new(move || Box::pin(apply())),
+            compensate: Box::new(move || Box::pin(compensate())),
+        });
+        self
+    }
+
+    /// Records the intent and applies every step
+    ///
+    /// # Arguments
+    /// * `payload` - Stored in the intent document for auditing and recovery
+    ///
+    /// # Notes
+    /// - Returns the intent id once every step is applied
+    /// - On failure, returns the error of the failed step after compensation
+    pub async fn run(self, payload: Document) -> Result<ObjectId> {
+        let id = ObjectId::new();
+        let names = self.steps.iter().map(|s| s.name.clone()).collect::<Vec<_>>();
+        self.log
+            .insert_one(doc! {
+                "_id": id,
+                "state": STATE_PENDING,
+                "steps": names,
+                "applied": [],
+                "payload": payload,
+                "created_at": DateTime::now(),
+                "updated_at": DateTime::now(),
+            })
+            .await?;
+
+        let mut applied = vec![];
+        for step in self.steps {
+            match (step.apply)().await {
+                Ok(()) => {
+                    self.log
+                        .update_one(
+                            doc! {"_id": id},
+                            doc! {
+                                "$push": {"applied": &step.name},
+                                "$set": {"updated_at": DateTime::now()},
+                            },
+                        )
+                        .await?;
+                    applied.push((step.name, step.compensate));
+                }
+                Err(e) => {
+                    let mut state = STATE_ROLLED_BACK;
+                    let mut compensation_errors = vec![];
+                    for (name, compensate) in applied.into_iter().rev() {
+                        if let Err(ce) = compensate().await {
+                            log::error!("compensation of step {name} failed: {ce}");
+                            state = STATE_COMPENSATION_FAILED;
+                            compensation_errors.push(doc! {"step": name, "error": ce.to_string()});
+                        }
+                    }
+                    self.log
+                        .update_one(
+                            doc! {"_id": id},
+                            doc! {"$set": {
+                                "state": state,
+                                "failed_step": &step.name,
+                                "error": e.to_string(),
+                                "compensation_errors": compensation_errors,
+                                "updated_at": DateTime::now(),
+                            }},
+                        )
+                        .await?;
+                    return Err(e);
+                }
+            }
+        }
+
+        self.log
+            .update_one(
+                doc! {"_id": id},
+                doc! {"$set": {"state": STATE_COMMITTED, "updated_at": DateTime::now()}},
+            )
+            .await?;
+        Ok(id)
+    }
+
+    /// Intent documents left pending or with failed compensations
+    pub async fn unfinished(&self) -> Result<Vec<Document>> {
+        let mut cursor = self
+            .log
+            .find(doc! {"state": {"$in": [STATE_PENDING, STATE_COMPENSATION_FAILED]}})
+            .sort(doc! {"created_at": 1})
+            .await?;
+        let mut r = vec![];
+        while let Some(d) = cursor.next().await {
+            r.push(d?);
+        }
+        Ok(r)
+    }
+}
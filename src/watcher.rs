@@ -0,0 +1,219 @@
+//! Alerts on data conditions
+//!
+//! A [`Watcher`] evaluates registered checks, built from model queries, and
+//! calls the alert handlers for every check whose condition holds.
+//!
+//! ```ignore
+//! let failed = Job::new_model(&db).r#where(doc! {"status": "failed"});
+//! let pending = Job::new_model(&db).r#where(doc! {"status": "pending"});
+//! Watcher::new(&db)
+//!     .check(Check::count_above("failed jobs", &failed, 100))
+//!     .check(Check::oldest_older_than("stuck jobs", &pending, "created_at", Duration::from_secs(600)))
+//!     .on_alert(|alert| log::warn!("{alert}"))
+//!     .spawn(Duration::from_secs(60));
+//! ```
+
+use crate::event::Boot;
+use crate::model::Model;
+use futures_util::StreamExt;
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::error::Result;
+use mongodb::Database;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// More than this many documents match
+    CountAbove(u64),
+    /// The oldest matching document has `field` older than `age`
+    OldestOlderThan { field: String, age: Duration },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub collection: String,
+    pub filter: Document,
+    pub condition: Condition,
+}
+
+impl Check {
+    /// Alerts when more than `threshold` documents match the model's query
+    pub fn count_above<M>(name: &str, model: &Model<'_, M>, threshold: u64) -> Check
+    where
+        M: Boot + Default + Serialize + DeserializeOwned + Send + Sync + Unpin,
+    {
+        Check {
+            name: name.to_string(),
+            collection: model.collection_name().to_string(),
+            filter: model.build_find().filter,
+            condition: Condition::CountAbove(threshold),
+        }
+    }
+
+    /// Alerts when the oldest document matching the model's query has `field` older than `age`
+    pub fn oldest_older_than<M>(name: &str, model: &Model<'_, M>, field: &str, age: Duration) -> Check
+    where
+        M: Boot + Default + Serialize + DeserializeOwned + Send + Sync + Unpin,
+    {
+        Check {
+            name: name.to_string(),
+            collection: model.collection_name().to_string(),
+            filter: model.build_find().filter,
+            condition: Condition::OldestOlderThan {
+                field: model.db_name(field),
+                age,
+            },
+        }
+    }
+
+    /// Evaluates the check, returning an alert when its condition holds
+    pub async fn evaluate(&self, db: &Database) -> Result<Option<Alert>> {
+        let collection = db.collection::<Document>(&self.collection);
+        let value = match &self.condition {
+            Condition::CountAbove(threshold) => {
+                let count = collection.count_documents(self.filter.clone()).await?;
+                if count <= *threshold {
+                    return Ok(None);
+                }
+                Bson::Int64(count as i64)
+            }
+            Condition::OldestOlderThan { field, age } => {
+                let oldest = collection
+                    .find_one(self.filter.clone())
+                    .sort(doc! {field: 1})
+                    .projection(doc! {field: 1})
+                    .await?;
+                let Some(at) = oldest.and_then(|d| d.get_datetime(field).ok().copied()) else {
+                    return Ok(None);
+                };
+                let elapsed = DateTime::now().timestamp_millis() - at.timestamp_millis();
+                if elapsed < age.as_millis() as i64 {
+                    return Ok(None);
+                }
+                Bson::DateTime(at)
+            }
+        };
+        Ok(Some(Alert {
+            check: self.name.clone(),
+            collection: self.collection.clone(),
+            value,
+            at: DateTime::now(),
+        }))
+    }
+}
+
+/// A check whose condition holds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub check: String,
+    pub collection: String,
+    /// Matching count, or the oldest date
+    pub value: Bson,
+    pub at: DateTime,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "alert `{}` on `{}`: {}", self.check, self.collection, self.value)
+    }
+}
+
+type Handler = Arc<dyn Fn(&Alert) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Watcher {
+    db: Database,
+    checks: Vec<Check>,
+    handlers: Vec<Handler>,
+}
+
+impl Watcher {
+    pub fn new(db: &Database) -> Watcher {
+        Watcher {
+            db: db.clone(),
+            checks: vec![],
+            handlers: vec![],
+        }
+    }
+
+    pub fn check(mut self, check: Check) -> Watcher {
+        self.checks.push(check);
+        self
+    }
+
+    /// Adds a handler called for every alert
+    pub fn on_alert(mut self, handler: impl Fn(&Alert) + Send + Sync + 'static) -> Watcher {
+        self.handlers.push(Arc::new(handler));
+        self
+    }
+
+    fn notify(&self, alert: &Alert) {
+        for handler in &self.handlers {
+            handler(alert);
+        }
+    }
+
+    /// Evaluates every check once and calls the handlers
+    ///
+    /// # Notes
+    /// - A failing check is logged and skipped, the others still run
+    pub async fn evaluate(&self) -> Vec<Alert> {
+        let mut alerts = vec![];
+        for check in &self.checks {
+            match check.evaluate(&self.db).await {
+                Ok(Some(alert)) => {
+                    self.notify(&alert);
+                    alerts.push(alert);
+                }
+                Ok(None) => {}
+                Err(e) => log::error!("Can't evaluate check {} : {:?}", check.name, e),
+            }
+        }
+        alerts
+    }
+
+    /// Spawns a task evaluating the checks every `interval`
+    #[cfg(feature = "rt-tokio")]
+    pub fn spawn(self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                self.evaluate().await;
+                tokio::time::sleep(interval.max(Duration::from_secs(1))).await;
+            }
+        })
+    }
+
+    /// Evaluates the checks of a collection whenever it changes, until the change stream ends
+    ///
+    /// # Notes
+    /// - Change streams need a replica set or a sharded cluster, use `spawn` otherwise
+    pub async fn run_on_changes(&self) -> Result<()> {
+        let mut collections = self.checks.iter().map(|c| c.collection.clone()).collect::<Vec<_>>();
+        collections.sort();
+        collections.dedup();
+        let mut stream = self
+            .db
+            .watch()
+            .pipeline([doc! {"$match": {"ns.coll": {"$in": &collections}}}])
+            .await?;
+        while let Some(event) = stream.next().await {
+            let event = event?;
+            let Some(collection) = event.ns.and_then(|ns| ns.coll) else {
+                continue;
+            };
+            for check in self.checks.iter().filter(|c| c.collection == collection) {
+                match check.evaluate(&self.db).await {
+                    Ok(Some(alert)) => self.notify(&alert),
+                    Ok(None) => {}
+                    Err(e) => log::error!("Can't evaluate check {} : {:?}", check.name, e),
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -2,8 +2,13 @@ use futures_util::StreamExt;
 use mongodb::bson::oid::ObjectId;
 use mongodb::bson::{doc, Bson, DateTime};
 use mongodb::{Client, Database};
-use mongodb_ro::event::Boot;
+use mongodb_ro::backend::{DataApiBackend, HttpTransport};
+use mongodb_ro::event::{Boot, Hooks};
+use mongodb_ro::ext_json::ExtJsonMode;
 use mongodb_ro::model::Model;
+use mongodb_ro::query_builder::Verbosity;
+use mongodb_ro::repository::Repository;
+use mongodb_ro::session::{self, SessionPool};
 use mongodb_ro::Model;
 use serde::{Deserialize, Serialize};
 
@@ -25,6 +30,16 @@ struct User {
 
 impl Boot for User {
     type Req = bool;
+
+    fn configure(&self, options: &mut mongodb_ro::options::ModelOptions) {
+        options
+            .scope("active", doc! {"block": false})
+            .scope("with_password", doc! {"password": {"$ne": ""}});
+    }
+
+    fn configure_columns(&self, columns: &mut std::collections::HashMap<&str, mongodb_ro::column::ColumnAttr>) {
+        columns.get_mut("phone").unwrap().doc = Some("User's primary phone in E.164".to_string());
+    }
 }
 
 async fn get_db() -> Database {
@@ -61,463 +76,3508 @@ async fn test_all() {
     test_find_and_collect_multiple().await;
     test_transaction_with_session().await;
     test_select().await;
+    test_rebuild_indexes().await;
+    test_add_unique_index().await;
+    test_declared_unique_index().await;
+    test_backfill_defaults().await;
+    test_paginate_after().await;
+    test_paginate().await;
+    test_first_or_create().await;
+    test_report_model().await;
+    test_copy_to().await;
+    test_by_id().await;
+    test_two_phase().await;
+    test_save().await;
+    test_preflight().await;
+    test_server_info_cache().await;
+    test_replace().await;
+    test_increment().await;
+    test_finish_images().await;
+    test_array_updates().await;
+    test_bulk_writer().await;
+    test_watcher().await;
+    test_consistency().await;
+    test_decode_error_policy().await;
+    test_get_with().await;
+    test_views().await;
+    test_invariants().await;
+    test_watch_typed().await;
+    test_recorded_queries().await;
+    test_tail_stream().await;
+    test_graph_lookup().await;
+    test_repository().await;
+    test_index_expr().await;
+    test_debug_stages().await;
+    test_provision_collection().await;
+    test_read_repair().await;
+    test_as_of().await;
+    test_partial_index().await;
+    test_publish().await;
 }
 
-async fn test_select() {
+async fn test_rebuild_indexes() {
     let db = get_db().await;
     cleanup_users(&db).await;
 
-    let mut user_model = User::new_model(&db);
-    user_model.name = "test_visibility".to_string();
-    user_model.phone = "555555555".to_string();
-    user_model.password = "secret".to_string();
-    user_model.create().await.unwrap();
-
-
-    let hidden_user = User::new_model(&db)
-        .select(doc!{"name":1})
-        .r#where(doc! {"name": "test_visibility"})
-        .first()
+    let model = User::new_model(&db);
+    model.register_indexes().await;
+    let compound = mongodb::IndexModel::builder()
+        .keys(doc! {"age": 1, "name": 1})
+        .build();
+    model.collection().create_index(compound).await.unwrap();
+
+    let mut rebuilt = vec![];
+    model
+        .rebuild_indexes(std::time::Duration::from_millis(10), |p| {
+            rebuilt.push((p.field.clone(), p.done, p.total))
+        })
         .await
-        .unwrap()
         .unwrap();
 
     assert_eq!(
-        hidden_user.phone, "",
-        "phone should be hidden"
+        rebuilt,
+        vec![("age".to_string(), 1, 2), ("phone".to_string(), 2, 2)]
     );
-    let hidden_user = User::new_model(&db)
-        .select(doc!{"name":1})
-        .r#where(doc! {"name": "test_visibility"})
-        .first_doc()
-        .await
-        .unwrap()
-        .unwrap();
+    let names = model.collection().list_index_names().await.unwrap();
+    assert!(names.contains(&"age_1_name_1".to_string()));
+    model.collection().drop_index("age_1_name_1").await.unwrap();
+
+    mongodb_ro::assert_indexes!(User, &db);
 
-    assert!(
-        hidden_user.get("phone").is_none(),
-        "phone should be hidden"
-    );
     cleanup_users(&db).await;
 }
-async fn test_count_documents() {
+
+async fn test_add_unique_index() {
+    use mongodb_ro::error::IndexMigrationError;
+    use mongodb_ro::index::{DuplicatePolicy, UniqueIndexStep};
+
     let db = get_db().await;
     cleanup_users(&db).await;
+    setup_test_user(&db, "twin", "555555551", 1).await;
+    setup_test_user(&db, "twin", "555555552", 2).await;
+    setup_test_user(&db, "single", "555555553", 3).await;
 
-    // Insert test data
-    for i in 0..5 {
-        setup_test_user(&db, "test_count_user", &format!("12345678{i}"), i as u8).await;
-    }
+    let model = User::new_model(&db);
+    let e = model
+        .add_unique_index(&["name"], DuplicatePolicy::Abort, |_| {})
+        .await
+        .unwrap_err();
+    assert_eq!(
+        e.get_custom::<IndexMigrationError>(),
+        Some(&IndexMigrationError::Duplicates { index: "name_1".to_string(), groups: 1 })
+    );
 
-    // Test basic count
-    let total_count = User::new_model(&db)
-        .r#where(doc! {"name": "test_count_user"})
-        .count_documents()
+    let mut steps = vec![];
+    let report = model
+        .add_unique_index(&["name"], DuplicatePolicy::KeepNewest, |s| steps.push(s.clone()))
         .await
         .unwrap();
-    assert_eq!(total_count, 5, "Should count all matching documents");
-
-    // Test count with limit
-    let limited_count = User::new_model(&db)
-        .r#where(doc! {"name": "test_count_user"})
-        .limit(3)
-        .count_documents()
+    assert_eq!(report.removed, 1);
+    assert_eq!(steps.first(), Some(&UniqueIndexStep::Scanning));
+    assert_eq!(steps.last(), Some(&UniqueIndexStep::Done));
+    let twin = User::new_model(&db).r#where(doc! {"name": "twin"}).first().await.unwrap().unwrap();
+    assert_eq!(twin.age, 2);
+
+    let raw = model.collection().clone_with_type::<mongodb::bson::Document>();
+    raw.insert_many([doc! {"name": "no_nick"}, doc! {"name": "null_nick", "nick": null}])
         .await
         .unwrap();
-    assert_eq!(limited_count, 3, "Should respect limit");
+    let e = model
+        .add_unique_index(&["nick"], DuplicatePolicy::KeepOldest, |_| {})
+        .await
+        .unwrap_err();
+    assert_eq!(
+        e.get_custom::<IndexMigrationError>(),
+        Some(&IndexMigrationError::NullKeys { index: "nick_1".to_string(), groups: 1 })
+    );
+    assert_eq!(raw.count_documents(doc! {"name": {"$in": ["no_nick", "null_nick"]}}).await.unwrap(), 2);
+    cleanup_users(&db).await;
+}
 
-    // Test count with skip
-    let skipped_count = User::new_model(&db)
-        .r#where(doc! {"name": "test_count_user"})
-        .skip(2)
-        .count_documents()
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "seats")]
+struct Seat {
+    _id: Option<ObjectId>,
+    #[model(name("r"))]
+    row: String,
+    number: i32,
+}
+
+impl Boot for Seat {
+    type Req = ();
+
+    fn configure(&self, options: &mut mongodb_ro::options::ModelOptions) {
+        options.unique_index(&["row", "number"]);
+    }
+}
+
+async fn test_declared_unique_index() {
+    use mongodb_ro::index::DuplicatePolicy;
+
+    let db = get_db().await;
+    let model = Seat::new_model(&db);
+    model.collection().drop().await.unwrap();
+    for _ in 0..2 {
+        let mut seat = Seat::new_model(&db);
+        seat.row = "A".to_string();
+        seat.number = 1;
+        seat.create().await.unwrap();
+    }
+    let report = model
+        .add_unique_index(&["row", "number"], DuplicatePolicy::KeepOldest, |_| {})
         .await
         .unwrap();
-    assert_eq!(skipped_count, 3, "Should respect skip");
+    assert_eq!((report.name.as_str(), report.removed), ("r_1_number_1", 1));
 
-    // Test count with skip and limit
-    let skip_limit_count = User::new_model(&db)
-        .r#where(doc! {"name": "test_count_user"})
-        .skip(1)
-        .limit(2)
-        .count_documents()
+    model.register_indexes().await;
+    let names = model.collection().list_index_names().await.unwrap();
+    assert!(names.contains(&"r_1_number_1".to_string()), "{names:?}");
+    let drift = model.index_drift().await.unwrap();
+    assert!(drift.is_empty(), "{drift}");
+
+    let mut rebuilt = vec![];
+    model
+        .rebuild_indexes(std::time::Duration::ZERO, |p| rebuilt.push(p.field.clone()))
         .await
         .unwrap();
-    assert_eq!(skip_limit_count, 2, "Should respect both skip and limit");
+    assert_eq!(rebuilt, vec!["r_1_number_1".to_string()]);
+    assert!(model.index_drift().await.unwrap().is_empty());
+    model.collection().drop().await.unwrap();
+}
 
-    // Test count with age filter
-    let age_filter_count = User::new_model(&db)
-        .r#where(doc! {
-            "name": "test_count_user",
-            "age": { "$gt": 2 }
-        })
-        .count_documents()
+async fn test_backfill_defaults() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+    let raw = db.collection::<mongodb::bson::Document>("user");
+    raw.insert_many([
+        doc! {"name": "old", "phone": "666666661", "pswd": ""},
+        doc! {"name": "old", "phone": "666666662", "pswd": "", "age": 7},
+    ])
+    .await
+    .unwrap();
+    setup_test_user(&db, "new", "666666663", 3).await;
+
+    let report = User::new_model(&db)
+        .batch_size(1)
+        .backfill_defaults(&["age", "block"])
         .await
         .unwrap();
-    assert_eq!(
-        age_filter_count, 2,
-        "Should count only documents matching age filter"
-    );
-
+    assert_eq!(report.fields, vec![("age".to_string(), 1), ("block".to_string(), 2)]);
+    assert_eq!(report.total(), 3);
+    let old = User::new_model(&db).r#where(doc! {"name": "old"}).get().await.unwrap();
+    assert_eq!(old.iter().map(|u| u.age).collect::<Vec<_>>(), vec![0, 7]);
+    assert!(User::new_model(&db).backfill_defaults(&["missing"]).await.is_err());
     cleanup_users(&db).await;
 }
 
-async fn test_upsert() {
+async fn test_paginate_after() {
     let db = get_db().await;
     cleanup_users(&db).await;
 
-    // First upsert (create)
-    User::new_model(&db)
-        .set_request(true)
-        .r#where(doc! {"name": "test_upsert"})
-        .upsert()
-        .update(doc! {"$set": {"phone": "123456789"}})
+    for i in 0..5 {
+        setup_test_user(&db, "test_keyset", &format!("77777777{i}"), i as u8).await;
+    }
+
+    let first = User::new_model(&db)
+        .sort(doc! {"age": -1})
+        .paginate_after(None, 2)
         .await
         .unwrap();
+    assert_eq!(first.items.iter().map(|u| u.age).collect::<Vec<_>>(), vec![4, 3]);
 
-    let user = User::new_model(&db)
-        .r#where(doc! {"name": "test_upsert"})
-        .first()
+    let second = User::new_model(&db)
+        .sort(doc! {"age": -1})
+        .paginate_after(first.next.as_deref(), 2)
         .await
-        .unwrap()
         .unwrap();
+    assert_eq!(second.items.iter().map(|u| u.age).collect::<Vec<_>>(), vec![2, 1]);
 
-    assert!(user.created_at.is_some());
-    assert_eq!(user.name, "test_upsert");
-    assert_eq!(user.phone, "123456789");
-
-    // Second upsert (update)
-    User::new_model(&db)
-        .set_request(true)
-        .r#where(doc! {"name": "test_upsert"})
-        .upsert()
-        .update(doc! {"$set": {"phone": "987654321"}})
+    let last = User::new_model(&db)
+        .sort(doc! {"age": -1})
+        .paginate_after(second.next.as_deref(), 2)
         .await
         .unwrap();
+    assert_eq!(last.items.len(), 1);
+    assert!(last.next.is_none());
 
-    let updated_user = User::new_model(&db)
-        .r#where(doc! {"name": "test_upsert"})
-        .first()
+    let descending = User::new_model(&db)
+        .sort(doc! {"age": -1.0})
+        .paginate_after(first.next.as_deref(), 2)
         .await
-        .unwrap()
         .unwrap();
-
-    assert_eq!(updated_user.phone, "987654321");
-    assert_eq!(
-        user._id, updated_user._id,
-        "Should update existing document"
-    );
+    assert_eq!(descending.items.iter().map(|u| u.age).collect::<Vec<_>>(), vec![2, 1]);
 
     cleanup_users(&db).await;
 }
 
-async fn test_save_fill() {
+#[tokio::test]
+async fn test_paginate_after_crafted_token() {
+    let db = get_db().await;
+    let token = |value: Bson| {
+        let token = doc! {"k": "age", "v": value, "id": ObjectId::new()};
+        let bytes = mongodb::bson::to_vec(&token).unwrap();
+        bytes.iter().map(|b| format!("{b:02x}")).collect::<String>()
+    };
+    for value in [
+        Bson::Document(doc! {"$ne": Bson::Null}),
+        Bson::Array(vec![]),
+        Bson::RegularExpression(mongodb::bson::Regex { pattern: ".*".to_string(), options: String::new() }),
+    ] {
+        let e = User::new_model(&db)
+            .sort(doc! {"age": -1})
+            .paginate_after(Some(&token(value)), 2)
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("invalid pagination cursor."), "{e}");
+    }
+}
+
+async fn test_paginate() {
     let db = get_db().await;
     cleanup_users(&db).await;
 
-    let user = User {
-        _id: None,
-        name: "test_save_fill".to_string(),
-        phone: "912".to_string(),
-        age: 25,
-        password: "".to_string(),
-        block: false,
-        updated_at: None,
-        created_at: None,
-    };
+    for i in 0..5 {
+        setup_test_user(&db, "test_page", &format!("88888888{i}"), i as u8).await;
+    }
 
-    User::new_model(&db).fill(user).create().await.unwrap();
+    let page = User::new_model(&db)
+        .r#where(doc! {"name": "test_page"})
+        .sort(doc! {"age": 1})
+        .paginate(2, 2)
+        .await
+        .unwrap();
 
-    let fetched_user = User::new_model(&db)
-        .r#where(doc! {"name": "test_save_fill"})
-        .first()
+    assert_eq!(page.items.iter().map(|u| u.age).collect::<Vec<_>>(), vec![2, 3]);
+    assert_eq!(page.total, 5);
+    assert_eq!(page.page, 2);
+    assert_eq!(page.total_pages, 3);
+    assert!(page.has_next());
+
+    let sample = User::new_model(&db).r#where(doc! {"name": "test_page"}).sample(3).await.unwrap();
+    assert_eq!(sample.len(), 3);
+    assert!(sample.iter().all(|u| u.name == "test_page" && u.password.is_empty()));
+    let mut ages = sample.iter().map(|u| u.age).collect::<Vec<_>>();
+    ages.sort();
+    ages.dedup();
+    assert_eq!(ages.len(), 3, "Should not repeat documents");
+    assert_eq!(User::new_model(&db).r#where(doc! {"name": "test_page"}).sample(50).await.unwrap().len(), 5);
+
+    #[derive(Deserialize)]
+    struct Age {
+        age: i32,
+    }
+    let page = User::new_model(&db)
+        .facet_paginate::<Age>(
+            vec![
+                doc! {"$match": {"name": "test_page", "age": {"$gte": 1}}},
+                doc! {"$project": {"_id": 0, "age": 1}},
+                doc! {"$sort": {"age": -1}},
+            ],
+            1,
+            3,
+        )
         .await
-        .unwrap()
         .unwrap();
+    assert_eq!(page.items.iter().map(|a| a.age).collect::<Vec<_>>(), vec![4, 3, 2]);
+    assert_eq!((page.total, page.total_pages), (4, 2));
 
-    assert_eq!("test_save_fill".to_string(), fetched_user.name);
-    assert_eq!("912".to_string(), fetched_user.phone);
-    assert_eq!(25, fetched_user.age);
-    assert!(fetched_user.created_at.is_some());
+    let plan = User::new_model(&db)
+        .r#where(doc! {"name": "test_page"})
+        .explain(Verbosity::ExecutionStats)
+        .await
+        .unwrap();
+    assert!(plan.contains_key("queryPlanner"));
+    assert_eq!(plan.get_document("executionStats").unwrap().get_i32("nReturned").unwrap(), 5);
 
     cleanup_users(&db).await;
 }
-async fn test_create_many() {
+
+async fn test_first_or_create() {
     let db = get_db().await;
     cleanup_users(&db).await;
 
-    User::new_model(&db).create_many_doc(vec![doc!{"name":"test1","phone":"123"},doc!{"name":"test2","phone":"124"}]).await.unwrap();
+    let mut model = User::new_model(&db).r#where(doc! {"name": "test_first_or_create"});
+    model.phone = "999999991".to_string();
+    model.age = 40;
+    let created = model.first_or_create().await.unwrap();
+    assert!(created._id.is_some());
+    assert_eq!(created.name, "test_first_or_create");
+    assert_eq!(created.age, 40);
+    assert!(created.created_at.is_some());
+
+    let mut model = User::new_model(&db).r#where(doc! {"name": "test_first_or_create"});
+    model.age = 50;
+    let found = model.first_or_create().await.unwrap();
+    assert_eq!(found._id, created._id);
+    assert_eq!(found.age, 40);
+
+    let id = ObjectId::new();
+    let mut model = User::new_model(&db).r#where(doc! {"_id": id});
+    model._id = Some(ObjectId::new());
+    model.phone = "999999992".to_string();
+    let created = model.first_or_create().await.unwrap();
+    assert_eq!(created._id, Some(id));
+    let mut model = User::new_model(&db)
+        .r#where(doc! {"_id": id})
+        .r#where(doc! {"phone": "999999992"});
+    model.phone = "999999993".to_string();
+    assert_eq!(model.first_or_create().await.unwrap().phone, "999999992");
 
-    let fetched_user = User::new_model(&db)
-        .count_documents()
+    cleanup_users(&db).await;
+}
+
+async fn test_copy_to() {
+    use mongodb_ro::copy::CopyOptions;
+
+    let db = get_db().await;
+    cleanup_users(&db).await;
+    User::new_model(&db).register_indexes().await;
+    for i in 0..5 {
+        setup_test_user(&db, "copy", &format!("55555555{i}"), i as u8).await;
+    }
+
+    let options = CopyOptions::default()
+        .collection("user_copy")
+        .batch_size(2)
+        .transform(|mut d| {
+            let age = d.get_i32("age").unwrap_or_default();
+            d.insert("copied", true);
+            (age != 4).then_some(d)
+        });
+    let report = User::new_model(&db)
+        .r#where(doc! {"name": "copy"})
+        .copy_to(&db, options)
         .await
         .unwrap();
+    assert_eq!(report.copied, 4);
+    assert_eq!(report.skipped, 1);
+    assert!(report.indexes.contains(&"phone_1".to_string()));
+
+    let copy = db.collection::<mongodb::bson::Document>("user_copy");
+    assert_eq!(copy.count_documents(doc! {"copied": true}).await.unwrap(), 4);
+    copy.drop().await.unwrap();
+
+    let mut dump = vec![];
+    let exported = User::new_model(&db)
+        .r#where(doc! {"name": "copy"})
+        .export_jsonl(&mut dump, ExtJsonMode::Canonical)
+        .await
+        .unwrap();
+    assert_eq!(exported, 5);
+    assert_eq!(String::from_utf8_lossy(&dump).lines().count(), 5);
+    cleanup_users(&db).await;
+    let imported = User::new_model(&db).import_jsonl(dump.as_slice(), 2).await.unwrap();
+    assert_eq!(imported, 5);
+    assert_eq!(User::new_model(&db).r#where(doc! {"name": "copy"}).count_documents().await.unwrap(), 5);
+    cleanup_users(&db).await;
+}
 
+#[test]
+fn test_ext_json_lines() {
+    use mongodb_ro::ext_json::{from_line, to_line};
 
-    assert_eq!(2,fetched_user);
+    let id = ObjectId::parse_str("65f0a1b2c3d4e5f601234567").unwrap();
+    let d = doc! {"_id": id, "n": 1, "at": DateTime::from_millis(0)};
+    assert_eq!(
+        to_line(d.clone(), ExtJsonMode::Relaxed),
+        r#"{"_id":{"$oid":"65f0a1b2c3d4e5f601234567"},"n":1,"at":{"$date":"1970-01-01T00:00:00Z"}}"#
+    );
+    let canonical = to_line(d.clone(), ExtJsonMode::Canonical);
+    assert_eq!(
+        canonical,
+        r#"{"_id":{"$oid":"65f0a1b2c3d4e5f601234567"},"n":{"$numberInt":"1"},"at":{"$date":{"$numberLong":"0"}}}"#
+    );
+    assert_eq!(from_line(&canonical).unwrap(), d);
+    assert!(from_line("[1]").is_err());
+}
 
-    let fetched_user = User::new_model(&db)
-        .r#where(doc! {"name": "test1"})
+async fn test_by_id() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+
+    setup_test_user(&db, "by_id", "444444441", 20).await;
+    let id = User::new_model(&db)
+        .r#where(doc! {"name": "by_id"})
         .first()
         .await
         .unwrap()
+        .unwrap()
+        ._id
         .unwrap();
+    let found = User::new_model(&db).find_by_id(&id.to_hex()).await.unwrap().unwrap();
+    assert_eq!(found.name, "by_id");
+
+    let missing = ObjectId::new();
+    let existing = User::new_model(&db).exists_many([id, missing, id]).await.unwrap();
+    assert_eq!(existing, std::collections::HashSet::from([id]));
+    let blocked = User::new_model(&db).r#where(doc! {"block": true}).exists_many([id]).await.unwrap();
+    assert!(blocked.is_empty());
+    assert!(User::new_model(&db).exists_many([]).await.unwrap().is_empty());
+
+    let updated = User::new_model(&db)
+        .update_by_id(id, doc! {"age": 21})
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.age, 21);
 
-    assert_eq!("test1".to_string(), fetched_user.name);
-    assert_eq!("123".to_string(), fetched_user.phone);
-
-    cleanup_users(&db).await;
+    setup_test_user(&db, "by_id_other", "444444442", 30).await;
+    let other = || {
+        User::new_model(&db)
+            .r#where(doc! {"name": "by_id_other"})
+            .or_where(doc! {"name": "nobody"})
+    };
+    assert!(other().find_by_id(id).await.unwrap().is_none());
+    assert!(other().delete_by_id(ObjectId::new()).await.unwrap().is_none());
+    assert!(other().update_by_id(id, doc! {"age": 40}).await.unwrap().is_none());
+    assert!(User::new_model(&db).r#where(doc! {"name": "by_id_other"}).exists().await.unwrap());
+
+    let deleted = User::new_model(&db).delete_by_id(id).await.unwrap().unwrap();
+    assert_eq!(deleted._id, Some(id));
+    assert!(User::new_model(&db).find_by_id(id).await.unwrap().is_none());
+    assert!(User::new_model(&db).delete_by_id(id).await.unwrap().is_none());
 }
 
-async fn test_save_and_retrieve() {
+async fn test_two_phase() {
+    use mongodb_ro::two_phase::{TwoPhase, STATE_COMMITTED, STATE_ROLLED_BACK};
+    use std::cell::Cell;
+
     let db = get_db().await;
-    cleanup_users(&db).await;
+    let log = db.collection::<mongodb::bson::Document>("two_phase_log");
+    log.drop().await.unwrap();
 
-    let mut user_model = User::new_model(&db);
-    user_model.name = "test_save".to_string();
-    user_model.phone = "123456789".to_string();
-    user_model.password = "1234".to_string();
-    user_model.age = 30;
-    user_model.create().await.unwrap();
+    let id = TwoPhase::new(&db, "two_phase_log")
+        .step("a", || async { Ok(()) }, || async { Ok(()) })
+        .step("b", || async { Ok(()) }, || async { Ok(()) })
+        .run(doc! {"amount": 10})
+        .await
+        .unwrap();
+    let intent = log.find_one(doc! {"_id": id}).await.unwrap().unwrap();
+    assert_eq!(intent.get_str("state").unwrap(), STATE_COMMITTED);
+    assert_eq!(intent.get_array("applied").unwrap().len(), 2);
+
+    let compensated = Cell::new(false);
+    let r = TwoPhase::new(&db, "two_phase_log")
+        .step("a", || async { Ok(()) }, || async {
+            compensated.set(true);
+            Ok(())
+        })
+        .step(
+            "b",
+            || async { Err(mongodb::error::Error::custom("boom")) },
+            || async { Ok(()) },
+        )
+        .run(doc! {})
+        .await;
+    assert!(r.is_err());
+    assert!(compensated.get());
+    let intent = log.find_one(doc! {"failed_step": "b"}).await.unwrap().unwrap();
+    assert_eq!(intent.get_str("state").unwrap(), STATE_ROLLED_BACK);
+    assert!(TwoPhase::new(&db, "two_phase_log").unfinished().await.unwrap().is_empty());
+    log.drop().await.unwrap();
+}
 
-    let distinct_names = User::new_model(&db).distinct("name").await.unwrap();
-    assert!(distinct_names.contains(&Bson::String("test_save".to_string())));
+async fn test_save() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
 
+    let mut model = User::new_model(&db);
+    model.name = "test_save".to_string();
+    model.phone = "333333331".to_string();
+    model.password = "secret".to_string();
+    let id = model.save().await.unwrap();
+    assert_eq!(model._id, Some(id));
+
+    let mut model = User::new_model(&db);
+    let user = model.find_by_id(id).await.unwrap().unwrap();
+    let created_at = user.created_at;
+    let mut model = User::new_model(&db).fill(user);
+    model.age = 33;
+    assert_eq!(model.save().await.unwrap(), id);
+
+    let saved = User::new_model(&db)
+        .visible(vec!["password"])
+        .find_by_id(id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(saved.age, 33);
+    assert_eq!(saved.password, "secret");
+    assert_eq!(saved.created_at, created_at);
+    assert_eq!(User::new_model(&db).count_documents().await.unwrap(), 1);
     cleanup_users(&db).await;
 }
 
-async fn test_find_one_with_visibility() {
+async fn test_preflight() {
     let db = get_db().await;
     cleanup_users(&db).await;
+    let users = User::new_model(&db);
+    users.register_indexes().await;
 
-    let mut user_model = User::new_model(&db);
-    user_model.name = "test_visibility".to_string();
-    user_model.phone = "555555555".to_string();
-    user_model.password = "secret".to_string();
-    user_model.create().await.unwrap();
+    let report = mongodb_ro::preflight(&db, &[&users]).await;
+    assert!(report.is_ok(), "{report}");
+    assert!(report.collections[0].exists);
 
-    let visible_user = User::new_model(&db)
-        .r#where(doc! {"name": "test_visibility"})
+    let invites = Invite::new_model(&db);
+    let report = mongodb_ro::preflight(&db, &[&users, &invites]).await;
+    assert!(!report.is_ok());
+    assert_eq!(report.collections[1].index_drift.missing.len(), 1);
+    cleanup_users(&db).await;
+}
+
+async fn test_replace() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+    setup_test_user(&db, "replace", "222222221", 10).await;
+
+    let new = User {
+        name: "replaced".to_string(),
+        phone: "222222221".to_string(),
+        age: 11,
+        password: "pw".to_string(),
+        ..Default::default()
+    };
+    let old = User::new_model(&db)
+        .r#where(doc! {"name": "replace"})
+        .replace(new)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(old.age, 10);
+    let stored = User::new_model(&db)
         .visible(vec!["password"])
+        .r#where(doc! {"name": "replaced"})
         .first()
         .await
         .unwrap()
         .unwrap();
+    assert_eq!(stored.password, "");
+    assert_eq!(stored._id, old._id);
+    assert_eq!(stored.created_at, old.created_at);
+    assert!(stored.updated_at.is_some());
 
-    assert_eq!(visible_user.password, "secret");
-
-    let hidden_user = User::new_model(&db)
-        .r#where(doc! {"name": "test_visibility"})
+    User::new_model(&db)
+        .visible(vec!["password"])
+        .r#where(doc! {"name": "replaced"})
+        .replace(User {
+            name: "replaced".to_string(),
+            phone: "222222221".to_string(),
+            password: "pw".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let stored = User::new_model(&db)
+        .visible(vec!["password"])
+        .r#where(doc! {"name": "replaced"})
         .first()
         .await
         .unwrap()
         .unwrap();
+    assert_eq!(stored.password, "pw");
+    assert_eq!(stored.created_at, old.created_at);
 
-    assert_eq!(
-        hidden_user.password, "",
-        "Password should be hidden by default"
-    );
-
+    let inserted = User::new_model(&db)
+        .r#where(doc! {"name": "missing"})
+        .upsert()
+        .replace(User {
+            name: "missing".to_string(),
+            phone: "222222222".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(inserted._id.is_some());
+    assert_eq!(User::new_model(&db).count_documents().await.unwrap(), 2);
     cleanup_users(&db).await;
 }
 
-async fn test_cursor_iteration() {
+async fn test_increment() {
     let db = get_db().await;
     cleanup_users(&db).await;
+    setup_test_user(&db, "counter", "111111121", 10).await;
 
-    for i in 0..10 {
-        User::new_model(&db)
-            .fill(User {
-                _id: None,
-                name: format!("test_cursor_user_{}", i),
-                phone: i.to_string(),
-                age: i as u8,
-                password: "".to_string(),
-                block: false,
-                updated_at: None,
-                created_at: None,
-            })
-            .create()
-            .await
-            .unwrap();
-    }
+    let user = User::new_model(&db)
+        .r#where(doc! {"name": "counter"})
+        .increment("age", 5)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.age, 15);
 
-    let mut cursor = User::new_model(&db).cursor().await.unwrap();
+    let user = User::new_model(&db)
+        .r#where(doc! {"name": "counter"})
+        .decrement("age", 3)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.age, 12);
+    cleanup_users(&db).await;
+}
 
-    let mut count = 0;
-    while let Some(doc) = cursor.next().await {
-        let doc = doc.unwrap();
-        assert!(
-            doc.get_str("name")
-                .unwrap()
-                .starts_with("test_cursor_user_")
-        );
-        count += 1;
-    }
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "tallies")]
+struct Tally {
+    _id: Option<ObjectId>,
+    name: String,
+    count: i32,
+}
 
-    assert_eq!(count, 10);
+static FINISHED: std::sync::Mutex<Vec<(String, mongodb::bson::Document, mongodb::bson::Document)>> =
+    std::sync::Mutex::new(Vec::new());
+
+impl Boot for Tally {
+    type Req = ();
+
+    async fn finish(
+        &self,
+        _req: &Option<()>,
+        typ: &str,
+        old: mongodb::bson::Document,
+        new: mongodb::bson::Document,
+        _session: Option<&mut mongodb::ClientSession>,
+    ) {
+        FINISHED.lock().unwrap().push((typ.to_string(), old, new));
+    }
+}
 
-    cleanup_users(&db).await;
+async fn test_finish_images() {
+    let db = get_db().await;
+    let model = Tally::new_model(&db);
+    model.collection().drop().await.unwrap();
+    let mut tally = Tally::new_model(&db);
+    tally.name = "a".to_string();
+    tally.count = 1;
+    tally.create().await.unwrap();
+    let finished = || std::mem::take(&mut *FINISHED.lock().unwrap());
+    finished();
+
+    let updated = Tally::new_model(&db)
+        .r#where(doc! {"name": "a"})
+        .update_returning(doc! {"$set": {"count": 2}})
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.count, 2);
+    let calls = finished();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].0, "update");
+    assert_eq!(calls[0].1.get_i32("count").unwrap(), 1);
+
+    Tally::new_model(&db)
+        .r#where(doc! {"name": "a"})
+        .increment("count", 3)
+        .await
+        .unwrap()
+        .unwrap();
+    let calls = finished();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].1.get_i32("count").unwrap(), 2);
+    assert_eq!(calls[0].2.get_i32("count").unwrap(), 5);
+
+    let id = updated._id.unwrap();
+    let updated = Tally::new_model(&db)
+        .update_by_id(id, doc! {"$set": {"count": 9}})
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.count, 9);
+    let calls = finished();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].1.get_i32("count").unwrap(), 5);
+    model.collection().drop().await.unwrap();
 }
 
-async fn test_update_operations() {
+async fn test_array_updates() {
     let db = get_db().await;
     cleanup_users(&db).await;
+    setup_test_user(&db, "arrays", "111111131", 10).await;
+    let model = || User::new_model(&db).r#where(doc! {"name": "arrays"});
 
-    let mut user_model = User::new_model(&db);
-    user_model.name = "test_update".to_string();
-    user_model.phone = "111111111".to_string();
-    user_model.age = 20;
-    user_model.create().await.unwrap();
+    model().push("tags", "a").await.unwrap();
+    model().push("tags", doc! {"$each": ["b", "c"]}).await.unwrap();
+    model().add_to_set("tags", "a").await.unwrap();
+    model().add_to_set("tags", "d").await.unwrap();
+    model().pull("tags", doc! {"$in": ["b", "c"]}).await.unwrap();
 
-    // Simple update
-    User::new_model(&db)
-        .r#where(doc! {"name": "test_update"})
-        .update(doc! {"$set": {"age": 25}})
+    let stored = model().first_doc().await.unwrap().unwrap();
+    let tags = stored.get_array("tags").unwrap();
+    assert_eq!(tags, &vec![Bson::from("a"), Bson::from("d")]);
+
+    model()
+        .update_array_element("tags", |tag| tag.where_("", "d").set("", "e"))
         .await
         .unwrap();
+    let stored = model().first_doc().await.unwrap().unwrap();
+    let tags = stored.get_array("tags").unwrap();
+    assert_eq!(tags, &vec![Bson::from("a"), Bson::from("e")]);
+    cleanup_users(&db).await;
+}
 
-    let updated_user = User::new_model(&db)
-        .r#where(doc! {"name": "test_update"})
-        .first()
+#[tokio::test]
+async fn test_update_array_element_needs_change() {
+    let db = Client::with_uri_str("mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=100")
         .await
         .unwrap()
-        .unwrap();
+        .database("test");
+    let err = User::new_model(&db)
+        .update_array_element("tags", |tag| tag.where_("", "a"))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("no change set"));
+}
 
-    assert_eq!(updated_user.age, 25);
+async fn test_bulk_writer() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+    User::new_model(&db).register_indexes().await;
+    setup_test_user(&db, "bulk_existing", "101010101", 10).await;
 
-    // Increment operation
-    User::new_model(&db)
-        .r#where(doc! {"name": "test_update"})
-        .update(doc! {"$inc": {"age": 1}})
+    let new_user = |name: &str, phone: &str| User {
+        name: name.to_string(),
+        phone: phone.to_string(),
+        ..Default::default()
+    };
+    let cache = std::sync::Arc::new(mongodb_ro::server::ServerInfoCache::new());
+    let model = User::new_model(&db).server_info_cache(cache);
+    let outcome = model
+        .bulk()
+        .ordered(false)
+        .insert(&new_user("bulk_a", "101010102"))
+        .insert(&new_user("bulk_dup", "101010101"))
+        .update(doc! {"name": "bulk_existing"}, doc! {"password": "pw"})
+        .upsert(doc! {"name": "bulk_upserted"}, doc! {"phone": "101010103"})
+        .delete(doc! {"name": "bulk_a"})
+        .submit()
         .await
         .unwrap();
+    assert_eq!(outcome.total, 5);
+    assert_eq!(outcome.failed_indexes(), vec![1]);
+    assert_eq!(outcome.matched_count, 1);
+    assert_eq!(outcome.deleted_count, 1);
+    assert!(outcome.upserted_ids.contains_key(&3));
 
-    let incremented_user = User::new_model(&db)
-        .r#where(doc! {"name": "test_update"})
+    let stored = User::new_model(&db)
+        .visible(vec!["password"])
+        .r#where(doc! {"name": "bulk_existing"})
         .first()
         .await
         .unwrap()
         .unwrap();
+    assert_eq!(stored.password, "pw");
+    cleanup_users(&db).await;
+}
 
-    assert_eq!(incremented_user.age, 26);
-    assert!(incremented_user.updated_at.is_some());
+async fn test_watcher() {
+    use mongodb_ro::watcher::{Check, Watcher};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
 
+    let db = get_db().await;
+    cleanup_users(&db).await;
+    for i in 0..3 {
+        setup_test_user(&db, "blocked", &format!("12121212{i}"), i).await;
+    }
+    User::new_model(&db).r#where(doc! {"name": "blocked"}).all().update(doc! {"block": true}).await.unwrap();
+
+    let blocked = User::new_model(&db).r#where(doc! {"block": true});
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let alerts = Watcher::new(&db)
+        .check(Check::count_above("too many blocked", &blocked, 2))
+        .check(Check::count_above("any blocked", &blocked, 5))
+        .check(Check::oldest_older_than("old blocked", &blocked, "created_at", std::time::Duration::ZERO))
+        .on_alert(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .evaluate()
+        .await;
+    assert_eq!(alerts.len(), 2);
+    assert_eq!(alerts[0].value, Bson::Int64(3));
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
     cleanup_users(&db).await;
 }
 
-async fn test_delete_operation() {
+async fn test_consistency() {
+    use mongodb_ro::consistency::{self, Rule};
+
     let db = get_db().await;
     cleanup_users(&db).await;
+    setup_test_user(&db, "owner", "131313131", 40).await;
+    let owner = User::new_model(&db).r#where(doc! {"name": "owner"}).first().await.unwrap().unwrap();
+    let orders = db.collection::<mongodb::bson::Document>("order");
+    orders.drop().await.unwrap();
+    let missing = ObjectId::new();
+    orders
+        .insert_many(vec![
+            doc! {"user_id": owner._id},
+            doc! {"user_id": missing},
+            doc! {"note": "no user"},
+        ])
+        .await
+        .unwrap();
 
-    let mut user_model = User::new_model(&db);
-    user_model.name = "test_delete".to_string();
-    user_model.phone = "222222222".to_string();
-    user_model.create().await.unwrap();
+    let users = User::new_model(&db);
+    let rules = [Rule::references("order user", &User::new_model(&db).set_collection("order"), "user_id", &users, "_id")];
+    let reports = consistency::check(&db, &rules).await.unwrap();
+    assert_eq!(reports[0].checked, 2);
+    assert_eq!(reports[0].orphans.len(), 1);
+    assert_eq!(reports[0].orphans[0].value, Bson::ObjectId(missing));
+
+    let reports = consistency::check_and_fix(&db, &rules, async |_, orphan| {
+        orders.delete_one(doc! {"_id": orphan.id.clone()}).await?;
+        Ok(())
+    })
+    .await
+    .unwrap();
+    assert!(!reports[0].is_consistent());
+    assert!(consistency::check(&db, &rules).await.unwrap()[0].is_consistent());
+
+    orders.drop().await.unwrap();
+    cleanup_users(&db).await;
+}
 
-    // Verify exists
-    let exists_before = User::new_model(&db)
-        .r#where(doc! {"name": "test_delete"})
-        .first()
+async fn test_decode_error_policy() {
+    use mongodb_ro::options::DecodeErrorPolicy;
+
+    let db = get_db().await;
+    cleanup_users(&db).await;
+    setup_test_user(&db, "decodable", "141414141", 20).await;
+    db.collection::<mongodb::bson::Document>("user")
+        .insert_one(doc! {"name": "legacy", "phone": "141414142", "age": "twenty"})
         .await
         .unwrap();
-    assert!(exists_before.is_some());
 
-    // Delete
-    User::new_model(&db)
-        .r#where(doc! {"name": "test_delete"})
-        .delete()
+    assert!(User::new_model(&db).get().await.is_err());
+    let users = User::new_model(&db)
+        .on_decode_error(DecodeErrorPolicy::SkipDocument)
+        .get()
         .await
         .unwrap();
-
-    // Verify deleted
-    let exists_after = User::new_model(&db)
-        .r#where(doc! {"name": "test_delete"})
-        .first()
+    assert_eq!(users.len(), 1);
+    let mut users = User::new_model(&db)
+        .on_decode_error(DecodeErrorPolicy::UseDefaultAndLog)
+        .sort(doc! {"name": 1})
+        .get()
         .await
         .unwrap();
-    assert!(exists_after.is_none());
+    assert_eq!(users.len(), 2);
+    let legacy = users.pop().unwrap();
+    assert_eq!((legacy.name.as_str(), legacy.age), ("legacy", 0));
+    cleanup_users(&db).await;
 }
 
-async fn test_find_and_collect_multiple() {
+async fn test_get_with() {
     let db = get_db().await;
     cleanup_users(&db).await;
-
-    // Create multiple users
-    for i in 0..5 {
-        setup_test_user(&db, "test_collect", &format!("33333333{i}"), i as u8 + 20).await;
+    for i in 0..2 {
+        let mut user = User::new_model(&db);
+        user.name = "join".to_string();
+        user.phone = format!("15151515{i}");
+        user.password = "secret".to_string();
+        user.create().await.unwrap();
     }
+    setup_test_user(&db, "alone", "151515159", 1).await;
 
-    let users = User::new_model(&db)
-        .r#where(doc! {"name": "test_collect"})
-        .get()
+    let joined = User::new_model(&db)
+        .sort(doc! {"phone": 1})
+        .get_with(&User::new_model(&db), "name", "name")
+        .await
+        .unwrap();
+    assert_eq!(joined.len(), 3);
+    assert_eq!(joined[0].joined.len(), 2);
+    assert_eq!(joined[2].joined.len(), 1);
+    assert!(joined[0].joined.iter().all(|u| u.password.is_empty()));
+
+    let joined = User::new_model(&db)
+        .r#where(doc! {"name": "alone"})
+        .get_with(&User::new_model(&db).visible(vec!["password"]), "phone", "name")
         .await
         .unwrap();
+    assert!(joined[0].joined.is_empty());
+    cleanup_users(&db).await;
+}
 
-    assert_eq!(users.len(), 5);
-    for user in users {
-        assert!(user.age >= 20 && user.age < 25);
+async fn test_graph_lookup() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+    // each user points to the next one through name -> phone
+    for (name, phone) in [("g2", "g1"), ("g3", "g2"), ("end", "g3")] {
+        setup_test_user(&db, name, phone, 1).await;
     }
 
+    let graph = |max_depth| {
+        let db = db.clone();
+        async move {
+            User::new_model(&db)
+                .r#where(doc! {"phone": "g1"})
+                .graph_lookup(&User::new_model(&db), "name", "name", "phone", max_depth)
+                .await
+                .unwrap()
+        }
+    };
+    let all = graph(None).await;
+    assert_eq!(all.len(), 1);
+    let mut phones: Vec<_> = all[0].joined.iter().map(|u| u.phone.clone()).collect();
+    phones.sort();
+    assert_eq!(phones, vec!["g2", "g3"]);
+    assert_eq!(graph(Some(0)).await[0].joined.len(), 1);
     cleanup_users(&db).await;
 }
 
-async fn test_transaction_with_session() {
+async fn test_report_model() {
+    use mongodb_ro::report::ReportModel;
+
     let db = get_db().await;
     cleanup_users(&db).await;
 
-    let mut session = db.client().start_session().await.unwrap();
+    for i in 0..4 {
+        setup_test_user(&db, if i % 2 == 0 { "even" } else { "odd" }, &format!("66666666{i}"), i as u8).await;
+    }
 
-    // Start transaction
-    session.start_transaction().await.unwrap();
+    let report = ReportModel::new(
+        &db,
+        "user",
+        "user_report",
+        vec![doc! {"$group": {"_id": "$name", "count": {"$sum": 1}}}],
+        std::time::Duration::from_secs(3600),
+    );
+    report.refresh_now().await.unwrap();
+    assert!(!report.is_stale().await.unwrap());
+
+    let rows = db
+        .collection::<mongodb::bson::Document>("user_report")
+        .count_documents(doc! {})
+        .await
+        .unwrap();
+    assert_eq!(rows, 2);
+
+    db.collection::<mongodb::bson::Document>("user_report").drop().await.unwrap();
+    cleanup_users(&db).await;
+}
+
+async fn test_select() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
 
-    // Create a user within the transaction
     let mut user_model = User::new_model(&db);
-    user_model.name = "test_transaction".to_string();
-    user_model.phone = "444444444".to_string();
-    user_model.password = "txn_pass".to_string();
-    user_model.create_with_session(&mut session).await.unwrap();
+    user_model.name = "test_visibility".to_string();
+    user_model.phone = "555555555".to_string();
+    user_model.password = "secret".to_string();
+    user_model.create().await.unwrap();
 
-    // Verify the user exists within the transaction
-    let user_in_txn = User::new_model(&db)
-        .r#where(doc! {"name": "test_transaction"})
-        .first_with_session(&mut session)
+
+    let hidden_user = User::new_model(&db)
+        .select(doc!{"name":1})
+        .r#where(doc! {"name": "test_visibility"})
+        .first()
         .await
+        .unwrap()
         .unwrap();
-    assert!(
-        user_in_txn.is_some(),
-        "User should exist within transaction"
-    );
 
-    // Verify the user doesn't exist outside transaction
-    let user_outside_txn = User::new_model(&db)
-        .r#where(doc! {"name": "test_transaction"})
-        .first()
+    assert_eq!(
+        hidden_user.phone, "",
+        "phone should be hidden"
+    );
+    let hidden_user = User::new_model(&db)
+        .select(doc!{"name":1})
+        .r#where(doc! {"name": "test_visibility"})
+        .first_doc()
         .await
+        .unwrap()
         .unwrap();
+
     assert!(
-        user_outside_txn.is_none(),
-        "User should not exist outside transaction before commit"
+        hidden_user.get("phone").is_none(),
+        "phone should be hidden"
     );
+    cleanup_users(&db).await;
+}
+async fn test_count_documents() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
 
-    // Commit the transaction
-    session.commit_transaction().await.unwrap();
+    // Insert test data
+    for i in 0..5 {
+        setup_test_user(&db, "test_count_user", &format!("12345678{i}"), i as u8).await;
+    }
 
-    // Verify the user exists after commit
-    let user_after_commit = User::new_model(&db)
-        .r#where(doc! {"name": "test_transaction"})
-        .first()
+    // Test basic count
+    let total_count = User::new_model(&db)
+        .r#where(doc! {"name": "test_count_user"})
+        .count_documents()
         .await
         .unwrap();
-    assert!(
-        user_after_commit.is_some(),
-        "User should exist after commit"
-    );
+    assert_eq!(total_count, 5, "Should count all matching documents");
 
-    // Cleanup
-    User::new_model(&db)
-        .r#where(doc! {"name": "test_transaction"})
-        .delete()
+    // Test count with limit
+    let limited_count = User::new_model(&db)
+        .r#where(doc! {"name": "test_count_user"})
+        .limit(3)
+        .count_documents()
+        .await
+        .unwrap();
+    assert_eq!(limited_count, 3, "Should respect limit");
+
+    // Test count with skip
+    let skipped_count = User::new_model(&db)
+        .r#where(doc! {"name": "test_count_user"})
+        .skip(2)
+        .count_documents()
         .await
         .unwrap();
+    assert_eq!(skipped_count, 3, "Should respect skip");
+
+    // Test count with skip and limit
+    let skip_limit_count = User::new_model(&db)
+        .r#where(doc! {"name": "test_count_user"})
+        .skip(1)
+        .limit(2)
+        .count_documents()
+        .await
+        .unwrap();
+    assert_eq!(skip_limit_count, 2, "Should respect both skip and limit");
+
+    // Test count with age filter
+    let age_filter_count = User::new_model(&db)
+        .r#where(doc! {
+            "name": "test_count_user",
+            "age": { "$gt": 2 }
+        })
+        .count_documents()
+        .await
+        .unwrap();
+    assert_eq!(
+        age_filter_count, 2,
+        "Should count only documents matching age filter"
+    );
+
+    // Test cached count
+    let ttl = std::time::Duration::from_secs(60);
+    let cached = || User::new_model(&db).r#where(doc! {"name": "test_count_user"}).count_cached(ttl);
+    assert_eq!(cached().await.unwrap(), 5);
+    setup_test_user(&db, "test_count_user", "123456785", 5).await;
+    assert_eq!(cached().await.unwrap(), 5, "Should reuse the cached count");
+    mongodb_ro::cache::invalidate_counts("user");
+    assert_eq!(cached().await.unwrap(), 6);
+
+    // Test estimated count
+    let estimated = User::new_model(&db).estimated_count().await.unwrap();
+    assert_eq!(estimated, User::new_model(&db).count_documents().await.unwrap());
+    let filtered = User::new_model(&db)
+        .r#where(doc! {"age": 5})
+        .estimated_count()
+        .await
+        .unwrap();
+    assert_eq!(filtered, 1, "Should count exactly with a filter");
+
+    cleanup_users(&db).await;
+}
+
+async fn test_upsert() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+
+    // First upsert (create)
+    User::new_model(&db)
+        .set_request(true)
+        .r#where(doc! {"name": "test_upsert"})
+        .upsert()
+        .update(doc! {"$set": {"phone": "123456789"}})
+        .await
+        .unwrap();
+
+    let user = User::new_model(&db)
+        .r#where(doc! {"name": "test_upsert"})
+        .first()
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert!(user.created_at.is_some());
+    assert_eq!(user.name, "test_upsert");
+    assert_eq!(user.phone, "123456789");
+
+    // Second upsert (update)
+    User::new_model(&db)
+        .set_request(true)
+        .r#where(doc! {"name": "test_upsert"})
+        .upsert()
+        .update(doc! {"$set": {"phone": "987654321"}})
+        .await
+        .unwrap();
+
+    let updated_user = User::new_model(&db)
+        .r#where(doc! {"name": "test_upsert"})
+        .first()
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(updated_user.phone, "987654321");
+    assert_eq!(
+        user._id, updated_user._id,
+        "Should update existing document"
+    );
+
+    // Typed upsert returning the updated document
+    let returned = User::new_model(&db)
+        .r#where(doc! {"name": "test_upsert"})
+        .update_or_create(doc! {"$set": {"phone": "555000111"}})
+        .await
+        .unwrap();
+    assert_eq!(returned.phone, "555000111");
+    assert_eq!(returned._id, user._id);
+
+    let created = User::new_model(&db)
+        .r#where(doc! {"name": "test_upsert_new"})
+        .update_or_create(doc! {"$set": {"phone": "555000222"}})
+        .await
+        .unwrap();
+    assert_eq!(created.name, "test_upsert_new");
+    assert!(created.created_at.is_some());
+
+    cleanup_users(&db).await;
+}
+
+async fn test_save_fill() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+
+    let user = User {
+        _id: None,
+        name: "test_save_fill".to_string(),
+        phone: "912".to_string(),
+        age: 25,
+        password: "".to_string(),
+        block: false,
+        updated_at: None,
+        created_at: None,
+    };
+
+    User::new_model(&db).fill(user).create().await.unwrap();
+
+    let fetched_user = User::new_model(&db)
+        .r#where(doc! {"name": "test_save_fill"})
+        .first()
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!("test_save_fill".to_string(), fetched_user.name);
+    assert_eq!("912".to_string(), fetched_user.phone);
+    assert_eq!(25, fetched_user.age);
+    assert!(fetched_user.created_at.is_some());
+
+    cleanup_users(&db).await;
+}
+async fn test_create_many() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+
+    User::new_model(&db).create_many_doc(vec![doc!{"name":"test1","phone":"123"},doc!{"name":"test2","phone":"124"}]).await.unwrap();
+
+    let fetched_user = User::new_model(&db)
+        .count_documents()
+        .await
+        .unwrap();
+
+
+    assert_eq!(2,fetched_user);
+
+    let fetched_user = User::new_model(&db)
+        .r#where(doc! {"name": "test1"})
+        .first()
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!("test1".to_string(), fetched_user.name);
+    assert_eq!("123".to_string(), fetched_user.phone);
+
+    let users = vec![
+        User { name: "typed1".to_string(), phone: "125".to_string(), password: "pw".to_string(), ..Default::default() },
+        User { name: "typed2".to_string(), phone: "126".to_string(), ..Default::default() },
+    ];
+    let r = User::new_model(&db).create_many(users).await.unwrap();
+    assert_eq!(r.inserted_ids.len(), 2);
+    let typed = User::new_model(&db)
+        .visible(vec!["password"])
+        .r#where(doc! {"_id": r.inserted_ids.get(&0).unwrap()})
+        .first()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(typed.name, "typed1");
+    assert_eq!(typed.password, "pw");
+    assert!(typed.created_at.is_some());
+
+    cleanup_users(&db).await;
+}
+
+async fn test_save_and_retrieve() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+
+    let mut user_model = User::new_model(&db);
+    user_model.name = "test_save".to_string();
+    user_model.phone = "123456789".to_string();
+    user_model.password = "1234".to_string();
+    user_model.age = 30;
+    user_model.create().await.unwrap();
+
+    let distinct_names = User::new_model(&db).distinct("name").await.unwrap();
+    assert!(distinct_names.contains(&Bson::String("test_save".to_string())));
+    let passwords = User::new_model(&db).distinct_as::<String>("password").await.unwrap();
+    assert_eq!(passwords, vec!["1234".to_string()]);
+    let ages = User::new_model(&db).distinct_as::<u8>("age").await.unwrap();
+    assert_eq!(ages, vec![30]);
+
+    setup_test_user(&db, "test_save", "123456780", 31).await;
+    let distinct = User::new_model(&db)
+        .distinct_many(&["name", "password", "age"])
+        .await
+        .unwrap();
+    assert_eq!(distinct["name"], vec![Bson::String("test_save".to_string())]);
+    assert_eq!(distinct["password"].len(), 2);
+    assert_eq!(distinct["age"].len(), 2);
+
+    let counts = User::new_model(&db).group_count("name").await.unwrap();
+    assert_eq!(counts, vec![(Bson::String("test_save".to_string()), 2)]);
+    let sums = User::new_model(&db).group_sum("password", "age").await.unwrap();
+    assert_eq!(sums.len(), 2);
+    assert_eq!(sums[0].1, 31.0);
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct AgeTotal {
+        _id: String,
+        total: i64,
+    }
+    let totals: Vec<AgeTotal> = User::new_model(&db)
+        .aggregate_as(vec![doc! {"$group": {"_id": "$name", "total": {"$sum": "$age"}}}])
+        .await
+        .unwrap();
+    assert_eq!(totals, vec![AgeTotal { _id: "test_save".to_string(), total: 61 }]);
+
+    cleanup_users(&db).await;
+}
+
+async fn test_find_one_with_visibility() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+
+    let mut user_model = User::new_model(&db);
+    user_model.name = "test_visibility".to_string();
+    user_model.phone = "555555555".to_string();
+    user_model.password = "secret".to_string();
+    user_model.create().await.unwrap();
+
+    let visible_user = User::new_model(&db)
+        .r#where(doc! {"name": "test_visibility"})
+        .visible(vec!["password"])
+        .first()
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(visible_user.password, "secret");
+
+    let hidden_user = User::new_model(&db)
+        .r#where(doc! {"name": "test_visibility"})
+        .first()
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        hidden_user.password, "",
+        "Password should be hidden by default"
+    );
+
+    cleanup_users(&db).await;
+}
+
+async fn test_cursor_iteration() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+
+    for i in 0..10 {
+        User::new_model(&db)
+            .fill(User {
+                _id: None,
+                name: format!("test_cursor_user_{}", i),
+                phone: i.to_string(),
+                age: i as u8,
+                password: "".to_string(),
+                block: false,
+                updated_at: None,
+                created_at: None,
+            })
+            .create()
+            .await
+            .unwrap();
+    }
+
+    let mut cursor = User::new_model(&db).cursor().await.unwrap();
+
+    let mut count = 0;
+    while let Some(doc) = cursor.next().await {
+        let doc = doc.unwrap();
+        assert!(
+            doc.get_str("name")
+                .unwrap()
+                .starts_with("test_cursor_user_")
+        );
+        count += 1;
+    }
+
+    assert_eq!(count, 10);
+
+    let model = User::new_model(&db);
+    let names = model
+        .stream()
+        .await
+        .unwrap()
+        .map(|u| u.unwrap().name)
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(names.len(), 10);
+
+    let mut batches = vec![];
+    let processed = User::new_model(&db)
+        .chunk(4, async |users| {
+            batches.push(users.len());
+            Ok(())
+        })
+        .await
+        .unwrap();
+    assert_eq!(processed, 10);
+    assert_eq!(batches, vec![4, 4, 2]);
+
+    let mut ids = 0;
+    let processed = User::new_model(&db)
+        .select(doc! {"_id": 0, "name": 1})
+        .chunk(4, async |users| {
+            ids += users.iter().filter(|u| u._id.is_some()).count();
+            Ok(())
+        })
+        .await
+        .unwrap();
+    assert_eq!((processed, ids), (10, 10));
+
+    cleanup_users(&db).await;
+}
+
+async fn test_update_operations() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+
+    let mut user_model = User::new_model(&db);
+    user_model.name = "test_update".to_string();
+    user_model.phone = "111111111".to_string();
+    user_model.age = 20;
+    user_model.create().await.unwrap();
+
+    // Simple update
+    User::new_model(&db)
+        .r#where(doc! {"name": "test_update"})
+        .update(doc! {"$set": {"age": 25}})
+        .await
+        .unwrap();
+
+    let updated_user = User::new_model(&db)
+        .r#where(doc! {"name": "test_update"})
+        .first()
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(updated_user.age, 25);
+
+    // Increment operation
+    User::new_model(&db)
+        .r#where(doc! {"name": "test_update"})
+        .update(doc! {"$inc": {"age": 1}})
+        .await
+        .unwrap();
+
+    let incremented_user = User::new_model(&db)
+        .r#where(doc! {"name": "test_update"})
+        .first()
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(incremented_user.age, 26);
+    assert!(incremented_user.updated_at.is_some());
+
+    let returned = User::new_model(&db)
+        .r#where(doc! {"name": "test_update"})
+        .update_returning(doc! {"$set": {"age": 30, "password": "secret"}})
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(returned.age, 30);
+    assert_eq!(returned.password, "");
+    assert!(User::new_model(&db)
+        .r#where(doc! {"name": "missing"})
+        .update_returning(doc! {"age": 1})
+        .await
+        .unwrap()
+        .is_none());
+
+    cleanup_users(&db).await;
+}
+
+async fn test_delete_operation() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+
+    let mut user_model = User::new_model(&db);
+    user_model.name = "test_delete".to_string();
+    user_model.phone = "222222222".to_string();
+    user_model.create().await.unwrap();
+
+    // Verify exists
+    let exists_before = User::new_model(&db)
+        .r#where(doc! {"name": "test_delete"})
+        .first()
+        .await
+        .unwrap();
+    assert!(exists_before.is_some());
+    assert!(
+        User::new_model(&db)
+            .r#where(doc! {"name": "test_delete"})
+            .exists()
+            .await
+            .unwrap()
+    );
+
+    // Delete
+    User::new_model(&db)
+        .r#where(doc! {"name": "test_delete"})
+        .delete()
+        .await
+        .unwrap();
+
+    // Verify deleted
+    let exists_after = User::new_model(&db)
+        .r#where(doc! {"name": "test_delete"})
+        .first()
+        .await
+        .unwrap();
+    assert!(exists_after.is_none());
+    assert!(
+        !User::new_model(&db)
+            .r#where(doc! {"name": "test_delete"})
+            .exists()
+            .await
+            .unwrap()
+    );
+
+    setup_test_user(&db, "test_delete_typed", "222222223", 30).await;
+    let deleted = User::new_model(&db)
+        .r#where(doc! {"name": "test_delete_typed"})
+        .delete_typed()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(deleted.age, 30);
+    assert!(User::new_model(&db)
+        .r#where(doc! {"name": "test_delete_typed"})
+        .delete_typed()
+        .await
+        .unwrap()
+        .is_none());
+    assert!(User::new_model(&db)
+        .r#where(doc! {"name": "test_delete_typed"})
+        .all()
+        .delete_typed()
+        .await
+        .is_err());
+}
+
+async fn test_find_and_collect_multiple() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+
+    // Create multiple users
+    for i in 0..5 {
+        setup_test_user(&db, "test_collect", &format!("33333333{i}"), i as u8 + 20).await;
+    }
+
+    let users = User::new_model(&db)
+        .r#where(doc! {"name": "test_collect"})
+        .get()
+        .await
+        .unwrap();
+
+    assert_eq!(users.len(), 5);
+    for user in users {
+        assert!(user.age >= 20 && user.age < 25);
+    }
+
+    let latest = User::new_model(&db)
+        .r#where(doc! {"name": "test_collect"})
+        .latest()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(latest.age, 24);
+    let oldest = User::new_model(&db)
+        .r#where(doc! {"name": "test_collect"})
+        .oldest()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(oldest.age, 20);
+
+    let ages = User::new_model(&db)
+        .r#where(doc! {"name": "test_collect"})
+        .sort(doc! {"age": 1})
+        .pluck::<u8>("age")
+        .await
+        .unwrap();
+    assert_eq!(ages, vec![20, 21, 22, 23, 24]);
+
+    let stats = User::new_model(&db)
+        .r#where(doc! {"name": "test_collect"})
+        .field_stats("age", 100)
+        .await
+        .unwrap();
+    assert_eq!(stats.sampled, 5);
+    assert_eq!(stats.distinct, 5);
+    assert_eq!(stats.null_ratio, 0.0);
+    assert_eq!(stats.min, Bson::Int32(20));
+    assert_eq!(stats.max, Bson::Int32(24));
+
+    cleanup_users(&db).await;
+}
+
+async fn test_transaction_with_session() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+
+    let mut session = db.client().start_session().await.unwrap();
+
+    // Start transaction
+    session.start_transaction().await.unwrap();
+
+    // Create a user within the transaction
+    let mut user_model = User::new_model(&db);
+    user_model.name = "test_transaction".to_string();
+    user_model.phone = "444444444".to_string();
+    user_model.password = "txn_pass".to_string();
+    user_model.create_with_session(&mut session).await.unwrap();
+
+    // Verify the user exists within the transaction
+    let user_in_txn = User::new_model(&db)
+        .r#where(doc! {"name": "test_transaction"})
+        .first_with_session(&mut session)
+        .await
+        .unwrap();
+    assert!(
+        user_in_txn.is_some(),
+        "User should exist within transaction"
+    );
+
+    // Verify the user doesn't exist outside transaction
+    let user_outside_txn = User::new_model(&db)
+        .r#where(doc! {"name": "test_transaction"})
+        .first()
+        .await
+        .unwrap();
+    assert!(
+        user_outside_txn.is_none(),
+        "User should not exist outside transaction before commit"
+    );
+
+    // Commit the transaction
+    session.commit_transaction().await.unwrap();
+
+    // Verify the user exists after commit
+    let user_after_commit = User::new_model(&db)
+        .r#where(doc! {"name": "test_transaction"})
+        .first()
+        .await
+        .unwrap();
+    assert!(
+        user_after_commit.is_some(),
+        "User should exist after commit"
+    );
+
+    // Cleanup
+    User::new_model(&db)
+        .r#where(doc! {"name": "test_transaction"})
+        .delete()
+        .await
+        .unwrap();
+
+    // Models inside a scope share its session without being passed it
+    let pool = SessionPool::new(db.client().clone());
+    let shared = pool.session().await.unwrap();
+    shared.lock().await.start_transaction().await.unwrap();
+    let found = session::scope_with(shared.clone(), async {
+        setup_test_user(&db, "test_scope", "444444445", 20).await;
+        User::new_model(&db).r#where(doc! {"name": "test_scope"}).first().await.unwrap()
+    })
+    .await;
+    assert!(found.is_some(), "User should exist within the scope");
+    let outside = User::new_model(&db).r#where(doc! {"name": "test_scope"}).first().await.unwrap();
+    assert!(outside.is_none(), "User should not exist outside the scope before commit");
+    shared.lock().await.commit_transaction().await.unwrap();
+    let count = pool
+        .scope(User::new_model(&db).r#where(doc! {"name": "test_scope"}).count_documents())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(count, 1);
+
+    // A failing transaction leaves nothing behind
+    let failed: mongodb::error::Result<()> = pool
+        .transaction(async {
+            setup_test_user(&db, "test_rollback", "444444446", 20).await;
+            Err(mongodb::error::Error::custom("rollback"))
+        })
+        .await;
+    assert!(failed.is_err());
+    let rolled_back = User::new_model(&db).r#where(doc! {"name": "test_rollback"}).first().await.unwrap();
+    assert!(rolled_back.is_none(), "User should not exist after a failed transaction");
+    pool.transaction(async {
+        setup_test_user(&db, "test_commit", "444444447", 20).await;
+        Ok(())
+    })
+    .await
+    .unwrap();
+    let committed = User::new_model(&db).r#where(doc! {"name": "test_commit"}).first().await.unwrap();
+    assert!(committed.is_some(), "User should exist after the transaction");
+
+    cleanup_users(&db).await;
+}
+
+struct Tag(&'static str);
+
+impl Boot for Tag {
+    type Req = bool;
+
+    fn cast(&self, mut data: mongodb::bson::Document, _req: &Option<Self::Req>) -> mongodb::bson::Document {
+        data.insert(self.0, true);
+        data
+    }
+}
+
+#[test]
+fn test_hooks_registry() {
+    let mut hooks = Hooks::<bool>::new();
+    hooks.register(Box::new(Tag("first"))).register(Box::new(Tag("second")));
+    assert_eq!(hooks.len(), 2);
+
+    let data = hooks.cast(doc! {"name": "hooks"}, &None);
+    assert_eq!(data, doc! {"name": "hooks", "first": true, "second": true});
+}
+
+struct CannedTransport {
+    response: String,
+    requests: std::sync::Mutex<Vec<(String, String)>>,
+}
+
+impl HttpTransport for CannedTransport {
+    async fn post(&self, url: &str, _headers: &[(&str, &str)], body: String) -> mongodb::error::Result<String> {
+        self.requests.lock().unwrap().push((url.to_string(), body));
+        Ok(self.response.clone())
+    }
+}
+
+#[tokio::test]
+async fn test_data_api_backend() {
+    let db = get_db().await;
+    let transport = CannedTransport {
+        response: r#"{"documents":[{"_id":{"$oid":"65a1b2c3d4e5f60718293a4b"},"name":"api","phone":"1","age":3,"pswd":"x","block":false}]}"#.to_string(),
+        requests: Default::default(),
+    };
+    let backend = DataApiBackend::new(transport, "https://example.com/data/v1/", "key", "Cluster0", "test");
+
+    let users = User::new_model(&db)
+        .r#where(doc! {"name": "api"})
+        .get_via(&backend)
+        .await
+        .unwrap();
+
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].name, "api");
+    assert_eq!(users[0].password, "", "Hidden fields are stripped");
+
+    let requests = backend_requests(&backend);
+    assert_eq!(requests[0].0, "https://example.com/data/v1/action/find");
+    assert!(requests[0].1.contains(r#""collection":"user""#));
+}
+
+#[tokio::test]
+async fn test_backend_guards() {
+    use mongodb_ro::error::GuardError;
+    use mongodb_ro::options::Guards;
+
+    let db = get_db().await;
+    let transport = CannedTransport {
+        response: r#"{"documents":[{"count":3}]}"#.to_string(),
+        requests: Default::default(),
+    };
+    let backend = DataApiBackend::new(transport, "https://example.com/data/v1/", "key", "Cluster0", "test");
+    let guards = Guards {
+        max_affected: Some(2),
+        ..Default::default()
+    };
+    let guarded = || User::new_model(&db).guards(guards.clone()).r#where(doc! {"name": "api"}).all();
+
+    let e = guarded().update_via(&backend, doc! {"block": true}).await.unwrap_err();
+    assert_eq!(
+        e.get_custom::<GuardError>(),
+        Some(&GuardError::TooManyAffected { operation: "update_many".to_string(), max_affected: 2 })
+    );
+    let e = guarded().delete_via(&backend).await.unwrap_err();
+    assert!(matches!(e.get_custom::<GuardError>(), Some(GuardError::TooManyAffected { .. })));
+    let requests = backend_requests(&backend);
+    assert_eq!(requests.len(), 2);
+    assert!(requests.iter().all(|(url, _)| url.ends_with("/action/aggregate")));
+}
+
+fn backend_requests(backend: &DataApiBackend<CannedTransport>) -> Vec<(String, String)> {
+    backend.transport().requests.lock().unwrap().clone()
+}
+
+mongodb_ro::model_fields!(UserFields for User {
+    name: String,
+    age: u8,
+    password: String,
+});
+
+#[test]
+fn test_typed_filter() {
+    let filter = User::fields()
+        .age
+        .gt(18)
+        .unwrap()
+        .and(User::fields().name.is_in(["a".to_string(), "b".to_string()]).unwrap());
+
+    assert_eq!(
+        filter.into_document(),
+        doc! {"$and": [{"age": {"$gt": 18}}, {"name": {"$in": ["a", "b"]}}]}
+    );
+    assert_eq!(
+        User::fields().name.regex("^sm", "i").into_document(),
+        doc! {"name": {"$regex": "^sm", "$options": "i"}}
+    );
+    let big = mongodb_ro::filter::Field::<u64>::new("big");
+    assert!(big.gt(u64::MAX).is_err());
+    assert!(big.is_in([1, u64::MAX]).is_err());
+}
+
+#[tokio::test]
+async fn test_stream_adapters() {
+    use mongodb_ro::stream::ModelStreamExt;
+
+    let items = futures::stream::iter((1..=5).map(Ok::<i32, mongodb::error::Error>));
+    let chunks = items.try_chunks(2).collect::<Vec<_>>().await;
+    let chunks = chunks.into_iter().map(|c| c.unwrap()).collect::<Vec<_>>();
+    assert_eq!(chunks, vec![vec![1, 2], vec![3, 4], vec![5]]);
+
+    let items = futures::stream::iter((1..=5).map(Ok::<i32, mongodb::error::Error>));
+    let doubled = items
+        .buffered_lookup(2, |chunk| async move { Ok(chunk.into_iter().map(|i| i * 2).collect()) })
+        .collect::<Vec<_>>()
+        .await;
+    let doubled = doubled.into_iter().map(|c| c.unwrap()).collect::<Vec<_>>();
+    assert_eq!(doubled, vec![2, 4, 6, 8, 10]);
+
+    let sum = std::sync::atomic::AtomicI32::new(0);
+    futures::stream::iter((1..=5).map(Ok::<i32, mongodb::error::Error>))
+        .for_each_concurrent_limited(2, |i| {
+            sum.fetch_add(i, std::sync::atomic::Ordering::SeqCst);
+            async { Ok(()) }
+        })
+        .await
+        .unwrap();
+    assert_eq!(sum.load(std::sync::atomic::Ordering::SeqCst), 15);
+}
+
+#[test]
+fn test_where_group() {
+    use mongodb_ro::query_builder::WhereGroup;
+
+    let group = WhereGroup::default()
+        .r#where(doc! {"a": 1})
+        .r#where(doc! {"b": 1})
+        .or_where(doc! {"c": 1})
+        .r#where(doc! {"d": 1})
+        .into_document();
+    assert_eq!(
+        group,
+        doc! {"$or": [{"$and": [{"a": 1}, {"b": 1}]}, {"$and": [{"c": 1}, {"d": 1}]}]}
+    );
+
+    let nested = WhereGroup::default()
+        .r#where(doc! {"block": false})
+        .where_group(|q| q.r#where(doc! {"age": 1}).or_where(doc! {"age": 2}))
+        .into_document();
+    assert_eq!(
+        nested,
+        doc! {"$and": [{"block": false}, {"$or": [{"age": 1}, {"age": 2}]}]}
+    );
+}
+
+#[tokio::test]
+async fn test_declared_indexes() {
+    use mongodb_ro::index::IndexDrift;
+
+    let db = get_db().await;
+    let declared = User::new_model(&db).declared_indexes();
+    let names = declared.iter().map(|i| i.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["age_-1", "phone_1"]);
+    assert!(declared[1].unique);
+
+    let mut live = declared.clone();
+    live[1].unique = false;
+    let drift = IndexDrift::compare("user", declared, live);
+    assert_eq!(drift.mismatched.len(), 1);
+    assert!(drift.to_string().contains("~ expected   phone_1"));
+
+    let declared = Booking::new_model(&db).declared_indexes();
+    assert_eq!(declared[0].partial, Some(doc! {"s.end": {"$gt": 0}}));
+    let mut live = declared.clone();
+    live[0].partial = None;
+    let drift = IndexDrift::compare("bookings", declared, live);
+    assert!(drift.to_string().contains("room_1 { \"room\": 1 } partial="));
+}
+
+#[tokio::test]
+async fn test_build_queries() {
+    let db = get_db().await;
+
+    let find = User::new_model(&db)
+        .filter(User::fields().password.eq("secret".to_string()).unwrap())
+        .sort(doc! {"age": 1})
+        .limit(10)
+        .batch_size(500)
+        .build_find();
+    assert_eq!(find.filter, doc! {"$and": [{"pswd": "secret"}]});
+    assert_eq!(find.options.sort, Some(doc! {"age": 1}));
+    assert_eq!(find.options.limit, Some(10));
+    assert_eq!(find.options.batch_size, Some(500));
+    assert!(find.options.hint.is_none());
+
+    let find = User::new_model(&db).hint(doc! {"age": -1}).build_find();
+    assert_eq!(find.options.hint, Some(mongodb::options::Hint::Keys(doc! {"age": -1})));
+    let find = User::new_model(&db).hint_name("phone_1").build_find();
+    assert_eq!(find.options.hint, Some(mongodb::options::Hint::Name("phone_1".to_string())));
+
+    let find = User::new_model(&db)
+        .limit(10)
+        .with_find_options(|o| o.allow_disk_use = Some(true))
+        .with_find_options(|o| o.limit = Some(5))
+        .build_find();
+    assert_eq!(find.options.allow_disk_use, Some(true));
+    let find = User::new_model(&db)
+        .r#where(doc! {"age": {"$gt": 1}})
+        .sort(doc! {"age": -1})
+        .hint_name("age_-1")
+        .limit(5)
+        .build_find();
+    assert_eq!(
+        find.command("user"),
+        doc! {
+            "find": "user",
+            "filter": {"$and": [{"age": {"$gt": 1}}]},
+            "sort": {"age": -1},
+            "limit": 5_i64,
+            "hint": "age_-1",
+        }
+    );
+    assert_eq!(find.options.limit, Some(5));
+
+    let find = User::new_model(&db).max_time(std::time::Duration::from_millis(1500)).build_find();
+    assert_eq!(find.options.max_time, Some(std::time::Duration::from_millis(1500)));
+    let find = User::new_model(&db).allow_disk_use(true).build_find();
+    assert_eq!(find.options.allow_disk_use, Some(true));
+
+    let update = User::new_model(&db)
+        .r#where(doc! {"name": "build"})
+        .build_update(doc! {"password": "new"})
+        .unwrap();
+    assert_eq!(update.filter, doc! {"$and": [{"name": "build"}]});
+    let set = update.update.get_document("$set").unwrap();
+    assert_eq!(set.get_str("pswd").unwrap(), "new");
+    assert!(set.get_datetime("updated_at").is_ok());
+    assert!(!update.many);
+
+    assert!(User::new_model(&db).build_update(doc! {"age": 1}).is_err());
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "invite")]
+struct Invite {
+    _id: Option<ObjectId>,
+    code: String,
+    expires_at: Option<DateTime>,
+}
+
+impl Boot for Invite {
+    type Req = ();
+
+    fn configure_columns(&self, columns: &mut std::collections::HashMap<&str, mongodb_ro::column::ColumnAttr>) {
+        columns.get_mut("expires_at").unwrap().expires_at = true;
+    }
+
+    fn configure(&self, options: &mut mongodb_ro::options::ModelOptions) {
+        options.public_id = Some("invite-salt".to_string());
+        options.write_concern = Some(mongodb::options::WriteConcern::majority());
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "contacts")]
+struct Contact {
+    _id: Option<ObjectId>,
+    name: String,
+    phone: String,
+}
+
+impl Boot for Contact {
+    type Req = String;
+
+    fn configure(&self, options: &mut mongodb_ro::options::ModelOptions) {
+        options.visible_to("phone", &["admin"]);
+    }
+
+    fn role(&self, req: &Option<Self::Req>) -> Option<String> {
+        req.clone()
+    }
+}
+
+#[tokio::test]
+async fn test_field_roles() {
+    let db = get_db().await;
+    Contact::new_model(&db).collection().drop().await.unwrap();
+    let mut contact = Contact::new_model(&db);
+    contact.name = "roles".to_string();
+    contact.phone = "0912".to_string();
+    contact.create().await.unwrap();
+
+    let read = |role: Option<&str>| {
+        let mut model = Contact::new_model(&db);
+        if let Some(role) = role {
+            model = model.set_request(role.to_string());
+        }
+        async move { model.first().await.unwrap().unwrap().phone }
+    };
+    assert_eq!(read(Some("admin")).await, "0912");
+    assert_eq!(read(Some("support")).await, "");
+    assert_eq!(read(None).await, "");
+    let visible = Contact::new_model(&db)
+        .set_request("support".to_string())
+        .visible(vec!["phone"])
+        .first()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(visible.phone, "0912");
+    let all = Contact::new_model(&db).visible_all().first().await.unwrap().unwrap();
+    assert_eq!(all.phone, "0912");
+    let hidden = Contact::new_model(&db)
+        .set_request("admin".to_string())
+        .visible_all()
+        .hide(&["name", "phone"])
+        .first()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((hidden.name.as_str(), hidden.phone.as_str()), ("", ""));
+
+    Contact::new_model(&db).collection().drop().await.unwrap();
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "articles")]
+struct Article {
+    _id: Option<ObjectId>,
+    #[model(text = "english")]
+    body: String,
+}
+
+impl Boot for Article {
+    type Req = bool;
+}
+
+#[tokio::test]
+async fn test_search_text() {
+    let db = get_db().await;
+    Article::new_model(&db).collection().drop().await.unwrap();
+    Article::new_model(&db).register_indexes().await;
+    for body in ["rust mongodb", "rust rust rust", "go"] {
+        let mut article = Article::new_model(&db);
+        article.body = body.to_string();
+        article.create().await.unwrap();
+    }
+
+    let found = Article::new_model(&db)
+        .search_text("rust", true)
+        .unwrap()
+        .get()
+        .await
+        .unwrap();
+    assert_eq!(found.iter().map(|a| a.body.as_str()).collect::<Vec<_>>(), vec!["rust rust rust", "rust mongodb"]);
+    let docs = Article::new_model(&db).search_text("go", true).unwrap().get_doc().await.unwrap();
+    assert!(docs[0].get_f64("text_score").unwrap() > 0.0);
+    assert!(User::new_model(&db).search_text("rust", false).is_err());
+
+    Article::new_model(&db).collection().drop().await.unwrap();
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "places")]
+struct Place {
+    _id: Option<ObjectId>,
+    name: String,
+    #[model(sphere2d)]
+    location: mongodb::bson::Document,
+}
+
+impl Boot for Place {
+    type Req = bool;
+}
+
+#[tokio::test]
+async fn test_geo_queries() {
+    let db = get_db().await;
+    Place::new_model(&db).collection().drop().await.unwrap();
+    Place::new_model(&db).register_indexes().await;
+    for (name, lng, lat) in [("tehran", 51.389, 35.689), ("karaj", 50.991, 35.840), ("paris", 2.352, 48.856)] {
+        let mut place = Place::new_model(&db);
+        place.name = name.to_string();
+        place.location = doc! {"type": "Point", "coordinates": [lng, lat]};
+        place.create().await.unwrap();
+    }
+    let names = |places: Vec<Place>| places.into_iter().map(|p| p.name).collect::<Vec<_>>();
+
+    let near = Place::new_model(&db).near("location", 51.0, 35.8, 100_000.0).unwrap().get().await.unwrap();
+    assert_eq!(names(near), vec!["karaj", "tehran"]);
+    let within = Place::new_model(&db)
+        .geo_within_polygon("location", &[(50.0, 35.0), (52.0, 35.0), (52.0, 36.0), (50.0, 36.0)])
+        .unwrap()
+        .sort(doc! {"name": 1})
+        .get()
+        .await
+        .unwrap();
+    assert_eq!(names(within), vec!["karaj", "tehran"]);
+    let line = doc! {"type": "LineString", "coordinates": [[2.0, 48.0], [3.0, 49.5]]};
+    let crossing = Place::new_model(&db).geo_intersects("location", line).unwrap().get().await.unwrap();
+    assert!(crossing.is_empty());
+    assert!(Place::new_model(&db).near("name", 0.0, 0.0, 1.0).is_err());
+
+    Place::new_model(&db).collection().drop().await.unwrap();
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "attachments")]
+struct Attachment {
+    _id: Option<ObjectId>,
+    name: String,
+    content: String,
+}
+
+impl Boot for Attachment {
+    type Req = bool;
+
+    fn configure_columns(&self, columns: &mut std::collections::HashMap<&str, mongodb_ro::column::ColumnAttr>) {
+        columns.get_mut("content").unwrap().heavy = true;
+    }
+}
+
+#[tokio::test]
+async fn test_heavy_columns() {
+    let db = get_db().await;
+    let find = Attachment::new_model(&db).build_find();
+    assert_eq!(find.options.projection, Some(doc! {"content": 0}));
+    assert_eq!(Attachment::new_model(&db).with_heavy().build_find().options.projection, None);
+    let find = Attachment::new_model(&db).select(doc! {"name": 1}).build_find();
+    assert_eq!(find.options.projection, Some(doc! {"name": 1}));
+
+    Attachment::new_model(&db).collection().drop().await.unwrap();
+    let mut attachment = Attachment::new_model(&db);
+    attachment.name = "report".to_string();
+    attachment.content = "x".repeat(1000);
+    attachment.create().await.unwrap();
+    let listed = Attachment::new_model(&db).get().await.unwrap();
+    assert_eq!((listed[0].name.as_str(), listed[0].content.as_str()), ("report", ""));
+    let listed = Attachment::new_model(&db).with_heavy().get().await.unwrap();
+    assert_eq!(listed[0].content.len(), 1000);
+    let first = Attachment::new_model(&db).first().await.unwrap().unwrap();
+    assert_eq!(first.content.len(), 1000);
+    Attachment::new_model(&db).collection().drop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_expires_at() {
+    let db = get_db().await;
+    let declared = Invite::new_model(&db).declared_indexes();
+    assert_eq!(declared.len(), 1);
+    assert_eq!(declared[0].name, "expires_at_1");
+    assert_eq!(declared[0].expire_after_secs, Some(0));
+
+    let update = Invite::new_model(&db)
+        .r#where(doc! {"code": "abc"})
+        .expire_in(std::time::Duration::from_secs(3600))
+        .build_update(doc! {"code": "abc"})
+        .unwrap();
+    let at = update.update.get_document("$set").unwrap().get_datetime("expires_at").unwrap();
+    assert!(at.timestamp_millis() > DateTime::now().timestamp_millis());
+
+    let update = Invite::new_model(&db)
+        .r#where(doc! {"code": "abc"})
+        .never_expire()
+        .build_update(doc! {"code": "abc"})
+        .unwrap();
+    assert!(update.update.get_document("$unset").unwrap().contains_key("expires_at"));
+}
+
+#[test]
+fn test_bulk_outcome() {
+    use mongodb::error::{Error, ErrorKind, InsertManyError};
+    use mongodb_ro::bulk::BulkOutcome;
+
+    let insert_error: InsertManyError = mongodb::bson::from_document(doc! {
+        "writeErrors": [{
+            "index": 1,
+            "code": 11000,
+            "errmsg": "E11000 duplicate key error collection: test.user index: phone_1 dup key: { phone: \"123\" }",
+        }],
+    })
+    .unwrap();
+    let error = Error::from(ErrorKind::InsertMany(insert_error));
+
+    let outcome = BulkOutcome::from_insert_many(4, true, Err(error.clone())).unwrap();
+    assert_eq!(outcome.succeeded, vec![0]);
+    assert_eq!(outcome.not_attempted, vec![2, 3]);
+    assert_eq!(outcome.write_errors[0].key_value.as_deref(), Some("{ phone: \"123\" }"));
+    assert_eq!(outcome.retry_subset(&["a", "b", "c", "d"]), vec!["b", "c", "d"]);
+
+    let mut outcome = BulkOutcome::from_insert_many(4, false, Err(error)).unwrap();
+    assert_eq!(outcome.succeeded, vec![0, 2, 3]);
+    assert_eq!(outcome.failed_indexes(), vec![1]);
+    assert!(!outcome.is_success());
+    assert!(outcome.inserted_ids.is_empty());
+    let ids = (0..4).map(|_| Bson::ObjectId(ObjectId::new())).collect::<Vec<_>>();
+    outcome.fill_inserted_ids(&ids);
+    assert_eq!(outcome.inserted_ids.keys().copied().collect::<Vec<_>>(), vec![0, 2, 3]);
+    assert_eq!(outcome.inserted_ids[&2], ids[2]);
+}
+
+#[test]
+fn test_bulk_write_outcome() {
+    use mongodb::error::{BulkWriteError, Error, ErrorKind, WriteError};
+    use mongodb_ro::bulk::BulkOutcome;
+
+    let write_error: WriteError = mongodb::bson::from_document(doc! {
+        "code": 11000,
+        "errmsg": "E11000 duplicate key error dup key: { phone: \"1\" }",
+    })
+    .unwrap();
+    let mut bulk_error = BulkWriteError::default();
+    bulk_error.write_errors.insert(1, write_error);
+
+    let outcome = BulkOutcome::from_bulk_write(3, Err(Error::from(ErrorKind::BulkWrite(bulk_error)))).unwrap();
+    assert!(outcome.succeeded.is_empty());
+    assert_eq!(outcome.write_errors[0].index, 1);
+    assert_eq!(outcome.write_errors[0].key_value.as_deref(), Some("{ phone: \"1\" }"));
+    assert_eq!(outcome.not_attempted, vec![0, 2]);
+    assert_eq!(outcome.failed_indexes(), vec![0, 1, 2]);
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "log_entry")]
+struct LogEntry {
+    _id: Option<ObjectId>,
+    payload: String,
+}
+
+impl Boot for LogEntry {
+    type Req = ();
+
+    fn configure_columns(&self, columns: &mut std::collections::HashMap<&str, mongodb_ro::column::ColumnAttr>) {
+        columns.get_mut("payload").unwrap().compress = Some("reverse".to_string());
+    }
+
+    fn configure(&self, options: &mut mongodb_ro::options::ModelOptions) {
+        options.object_id_json = mongodb_ro::public_id::ObjectIdJson::Hex;
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "blobs")]
+struct Blob {
+    _id: Option<ObjectId>,
+    data: String,
+}
+
+impl Boot for Blob {
+    type Req = ();
+
+    fn configure_columns(&self, columns: &mut std::collections::HashMap<&str, mongodb_ro::column::ColumnAttr>) {
+        columns.get_mut("data").unwrap().compress = Some("unregistered".to_string());
+    }
+}
+
+struct Reverse;
+
+impl mongodb_ro::compress::Codec for Reverse {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        Ok(data.iter().rev().copied().collect())
+    }
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        Ok(data.iter().rev().copied().collect())
+    }
+}
+
+#[tokio::test]
+async fn test_compressed_field() {
+    use mongodb_ro::compress;
+
+    compress::register_codec("reverse", Reverse);
+    let db = get_db().await;
+
+    let update = LogEntry::new_model(&db)
+        .r#where(doc! {"_id": 1})
+        .build_update(doc! {"payload": "hello"})
+        .unwrap();
+    let stored = update.update.get_document("$set").unwrap().get("payload").unwrap().clone();
+    assert!(compress::is_compressed(&stored));
+    assert_eq!(compress::decompress(stored).unwrap(), Bson::String("hello".to_string()));
+
+    let e = Blob::new_model(&db)
+        .r#where(doc! {"_id": 1})
+        .build_update(doc! {"data": "hello"})
+        .unwrap_err();
+    assert!(e.to_string().contains("can't compress data"));
+    let mut blob = Blob::new_model(&db);
+    blob.data = "hello".to_string();
+    assert!(blob.create().await.is_err());
+}
+
+#[tokio::test]
+async fn test_where_date() {
+    use mongodb_ro::date::{period, DatePart};
+
+    // 2024-02-29 22:30 UTC is 2024-03-01 02:00 in UTC+03:30
+    let at = DateTime::from_millis(1_709_245_800_000);
+    let (start, end) = period(at, DatePart::Day, 210);
+    assert_eq!(start.try_to_rfc3339_string().unwrap(), "2024-02-29T20:30:00Z");
+    assert_eq!(end.try_to_rfc3339_string().unwrap(), "2024-03-01T20:30:00Z");
+
+    let (start, end) = period(at, DatePart::Month, 0);
+    assert_eq!(start.try_to_rfc3339_string().unwrap(), "2024-02-01T00:00:00Z");
+    assert_eq!(end.try_to_rfc3339_string().unwrap(), "2024-03-01T00:00:00Z");
+
+    let (start, _) = period(at, DatePart::Week, 0);
+    assert_eq!(start.try_to_rfc3339_string().unwrap(), "2024-02-26T00:00:00Z");
+
+    let db = get_db().await;
+    let find = User::new_model(&db)
+        .where_date("created_at", DatePart::Year, at, 0)
+        .build_find();
+    let range = find.filter.get_array("$and").unwrap()[0].as_document().unwrap().get_document("created_at").unwrap().clone();
+    assert_eq!(range.get_datetime("$gte").unwrap().try_to_rfc3339_string().unwrap(), "2024-01-01T00:00:00Z");
+    assert_eq!(range.get_datetime("$lt").unwrap().try_to_rfc3339_string().unwrap(), "2025-01-01T00:00:00Z");
+}
+
+#[tokio::test]
+async fn test_guards() {
+    use mongodb_ro::error::GuardError;
+    use mongodb_ro::options::Guards;
+
+    let db = get_db().await;
+    let guards = Guards {
+        max_limit: Some(100),
+        max_skip: Some(1000),
+        require_filter_for_all: true,
+        max_affected: None,
+    };
+    let guard_error = |e: mongodb::error::Error| e.get_custom::<GuardError>().cloned();
+
+    let e = User::new_model(&db).guards(guards.clone()).get().await.unwrap_err();
+    assert_eq!(guard_error(e), Some(GuardError::UnboundedQuery { max_limit: 100 }));
+
+    let e = User::new_model(&db).guards(guards.clone()).limit(500).get_doc().await.unwrap_err();
+    assert_eq!(guard_error(e), Some(GuardError::LimitExceeded { limit: 500, max_limit: 100 }));
+
+    let e = User::new_model(&db).guards(guards.clone()).limit(10).skip(5000).get().await.unwrap_err();
+    assert_eq!(guard_error(e), Some(GuardError::SkipExceeded { skip: 5000, max_skip: 1000 }));
+
+    let e = User::new_model(&db).guards(guards.clone()).paginate(200, 10).await.unwrap_err();
+    assert_eq!(guard_error(e), Some(GuardError::SkipExceeded { skip: 1990, max_skip: 1000 }));
+
+    let e = User::new_model(&db).guards(guards.clone()).r#where(doc! {}).all().delete().await.unwrap_err();
+    assert!(matches!(guard_error(e), Some(GuardError::FilterRequired { .. })));
+
+    let e = User::new_model(&db).guards(guards.clone()).r#where(doc! {}).all().update(doc! {"block": true}).await.unwrap_err();
+    assert!(matches!(guard_error(e), Some(GuardError::FilterRequired { .. })));
+
+    let e = User::new_model(&db).guards(guards).bulk().delete_many(doc! {}).submit().await.unwrap_err();
+    assert_eq!(guard_error(e), Some(GuardError::FilterRequired { operation: "delete_many".to_string() }));
+
+    let e = User::new_model(&db).sortable_fields(&["age"]).sort(doc! {"name": 1}).get().await.unwrap_err();
+    assert_eq!(guard_error(e), Some(GuardError::SortNotAllowed { field: "name".to_string() }));
+    let find = User::new_model(&db).sortable_fields(&["age", "password"]).sort(doc! {"age": -1}).build_find();
+    assert_eq!(find.options.sort, Some(doc! {"age": -1, "_id": -1}));
+    let find = User::new_model(&db).sort(doc! {"age": -1}).build_find();
+    assert_eq!(find.options.sort, Some(doc! {"age": -1}));
+
+    cleanup_users(&db).await;
+    for i in 0..3 {
+        setup_test_user(&db, "guarded", &format!("33333333{i}"), i as u8).await;
+    }
+    let guards = Guards {
+        max_affected: Some(2),
+        ..Default::default()
+    };
+    let guarded = || User::new_model(&db).guards(guards.clone()).r#where(doc! {"name": "guarded"}).all();
+    let e = guarded().update(doc! {"block": true}).await.unwrap_err();
+    assert_eq!(
+        guard_error(e),
+        Some(GuardError::TooManyAffected { operation: "update_many".to_string(), max_affected: 2 })
+    );
+    let e = guarded().delete().await.unwrap_err();
+    assert!(matches!(guard_error(e), Some(GuardError::TooManyAffected { .. })));
+    guarded().r#where(doc! {"age": {"$gt": 0}}).update(doc! {"block": true}).await.unwrap();
+    let model = User::new_model(&db).guards(guards.clone());
+    let e = model.bulk().update_many(doc! {"name": "guarded"}, doc! {"age": 1}).submit().await.unwrap_err();
+    assert!(matches!(guard_error(e), Some(GuardError::TooManyAffected { .. })));
+    let deleted = guarded().force().delete().await.unwrap();
+    assert_eq!(deleted.get_str("deleted_count").unwrap(), "3");
+    cleanup_users(&db).await;
+}
+
+#[tokio::test]
+async fn test_invalid_id() {
+    let db = get_db().await;
+    let e = User::new_model(&db).find_by_id("not-an-id").await.unwrap_err();
+    assert!(e.to_string().contains("invalid id."));
+}
+
+#[tokio::test]
+async fn test_preflight_unreachable() {
+    let db = Client::with_uri_str("mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=100")
+        .await
+        .unwrap()
+        .database("test");
+    let users = User::new_model(&db);
+    let report = mongodb_ro::preflight(&db, &[&users]).await;
+    assert!(!report.is_ok());
+    assert!(report.connection_error.is_some());
+    assert!(report.collections.is_empty());
+}
+
+#[tokio::test]
+async fn test_increment_amount() {
+    let db = get_db().await;
+    let e = User::new_model(&db)
+        .r#where(doc! {"name": "counter"})
+        .decrement("age", "one")
+        .await
+        .unwrap_err();
+    assert!(e.to_string().contains("must be numeric"));
+    let e = User::new_model(&db)
+        .r#where(doc! {"name": "counter"})
+        .decrement("age", i32::MIN)
+        .await
+        .unwrap_err();
+    assert!(e.to_string().contains("overflows"));
+    let e = User::new_model(&db)
+        .r#where(doc! {"name": "counter"})
+        .decrement("age", i64::MIN)
+        .await
+        .unwrap_err();
+    assert!(e.to_string().contains("overflows"));
+}
+
+async fn test_server_info_cache() {
+    use mongodb_ro::server::ServerInfoCache;
+
+    let db = get_db().await;
+    let cache = std::sync::Arc::new(ServerInfoCache::new());
+    let model = User::new_model(&db).server_info_cache(cache.clone());
+    let info = model.server_info().await.unwrap();
+    assert_eq!(model.server_info().await.unwrap(), info);
+    assert_eq!(cache.get(&db).await.unwrap(), info);
+}
+
+#[test]
+fn test_server_info() {
+    use mongodb_ro::server::{ServerInfo, Topology};
+
+    let info = ServerInfo::from_responses(
+        &doc! {"isWritablePrimary": true, "maxWireVersion": 21},
+        &doc! {"version": "7.0.12"},
+    );
+    assert_eq!(info.version_array, [7, 0, 12]);
+    assert_eq!(info.topology, Topology::Standalone);
+    assert!(!info.supports_transactions());
+    assert!(!info.supports_vector_search());
+
+    let info = ServerInfo::from_responses(
+        &doc! {"setName": "atlas-abc", "hosts": ["ac-1.x.mongodb.net:27017"], "maxWireVersion": 21},
+        &doc! {"version": "6.0.11"},
+    );
+    assert_eq!(info.topology, Topology::ReplicaSet);
+    assert!(info.atlas);
+    assert!(info.supports_transactions());
+    assert!(info.supports_vector_search());
+
+    let info = ServerInfo::from_responses(&doc! {"msg": "isdbgrid"}, &doc! {"version": "4.0.3"});
+    assert_eq!(info.topology, Topology::Sharded);
+    assert!(!info.supports_transactions());
+}
+
+#[cfg(feature = "blocking")]
+#[test]
+fn test_blocking() {
+    let db = mongodb_ro::blocking::connect("mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=100")
+        .unwrap()
+        .database("test");
+    let mut users = User::new_model(&db).r#where(doc! {"name": "blocking"}).blocking();
+    users.name = "blocking".to_string();
+    assert_eq!(users.name, "blocking");
+    assert!(users.first().is_err());
+    assert!(User::new_model(&db).blocking().find_by_id("bad").is_err());
+}
+
+#[tokio::test]
+async fn test_cancellation() {
+    use mongodb_ro::error::Interrupted;
+    use tokio_util::sync::CancellationToken;
+
+    let db = Client::with_uri_str("mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=30000")
+        .await
+        .unwrap()
+        .database("test");
+    let interrupted = |e: mongodb::error::Error| e.get_custom::<Interrupted>().copied();
+
+    let token = CancellationToken::new();
+    token.cancel();
+    let e = User::new_model(&db).cancel_on(token.clone()).get().await.unwrap_err();
+    assert_eq!(interrupted(e), Some(Interrupted::Cancelled));
+    let users = User::new_model(&db).cancel_on(token.clone());
+    let Err(e) = users.stream().await else {
+        panic!("stream was not cancelled");
+    };
+    assert_eq!(interrupted(e), Some(Interrupted::Cancelled));
+    let e = User::new_model(&db)
+        .cancel_on(token)
+        .backfill_defaults(&["age"])
+        .await
+        .unwrap_err();
+    assert_eq!(interrupted(e), Some(Interrupted::Cancelled));
+
+    let e = User::new_model(&db)
+        .timeout(std::time::Duration::from_millis(50))
+        .aggregate_doc(vec![doc! {"$match": {}}])
+        .await
+        .unwrap_err();
+    assert_eq!(interrupted(e), Some(Interrupted::TimedOut));
+}
+
+#[tokio::test]
+async fn test_public_id() {
+    use mongodb_ro::public_id;
+
+    let id = ObjectId::parse_str("65f1a2b3c4d5e6f708091a2b").unwrap();
+    let encoded = public_id::encode("salt", &id);
+    assert_eq!(public_id::decode("salt", &encoded), Some(id));
+    assert_ne!(public_id::decode("other", &encoded), Some(id));
+    assert_ne!(public_id::encode("salt", &ObjectId::parse_str("65f1a2b3c4d5e6f708091a2c").unwrap())[..4], encoded[..4]);
+    assert_eq!(public_id::decode("salt", "0OIl"), None);
+
+    let db = get_db().await;
+    let invites = Invite::new_model(&db);
+    let invite = Invite { _id: Some(id), code: "abc".to_string(), expires_at: None };
+    let json = invites.to_json(&invite).unwrap();
+    assert_eq!(json["_id"], invites.public_id(&id).unwrap());
+    assert_eq!(json["code"], "abc");
+    assert!(Invite::new_model(&db).find_by_public_id("0OIl").await.unwrap().is_none());
+
+    assert!(User::new_model(&db).public_id(&id).is_none());
+    assert!(User::new_model(&db).find_by_public_id(&encoded).await.is_err());
+}
+
+#[test]
+fn test_datetime_json() {
+    use mongodb_ro::date::{format_json_dates, DateTimeJson};
+
+    let at = DateTime::parse_rfc3339_str("2024-05-01T10:00:00Z").unwrap();
+    let json = || serde_json::json!({"at": serde_json::to_value(at).unwrap(), "list": [serde_json::to_value(at).unwrap()]});
+
+    let mut value = json();
+    format_json_dates(&mut value, DateTimeJson::Extended);
+    assert_eq!(value, json());
+    format_json_dates(&mut value, DateTimeJson::Rfc3339);
+    assert_eq!(value["at"], "2024-05-01T10:00:00Z");
+    assert_eq!(value["list"][0], "2024-05-01T10:00:00Z");
+
+    let mut value = json();
+    format_json_dates(&mut value, DateTimeJson::Millis);
+    assert_eq!(value["at"], at.timestamp_millis());
+}
+
+#[test]
+fn test_from_json() {
+    use mongodb_ro::date::{format_json_dates, DateTimeJson};
+    use mongodb_ro::json::from_json;
+    use mongodb_ro::public_id::{format_json_ids, ObjectIdJson};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Stamped {
+        at: DateTime,
+        seen: Option<Vec<DateTime>>,
+        owner: ObjectId,
+        label: String,
+        count: i64,
+        raw: mongodb::bson::Document,
+    }
+
+    let at = DateTime::parse_rfc3339_str("2024-05-01T10:00:00Z").unwrap();
+    let owner = ObjectId::parse_str("65f1a2b3c4d5e6f708091a2b").unwrap();
+    let stamped = Stamped {
+        at,
+        seen: Some(vec![at]),
+        owner,
+        label: "2024-05-01T10:00:00Z".to_string(),
+        count: 1_714_557_600_000,
+        raw: doc! {"at": at, "owner": owner},
+    };
+    for dates in [DateTimeJson::Extended, DateTimeJson::Rfc3339, DateTimeJson::Millis] {
+        let mut value = serde_json::to_value(&stamped).unwrap();
+        format_json_dates(&mut value, dates);
+        format_json_ids(&mut value, ObjectIdJson::Hex);
+        let read: Stamped = from_json(value, ObjectIdJson::Hex, dates).unwrap();
+        assert_eq!(read.at, at);
+        assert_eq!(read.seen, Some(vec![at]));
+        assert_eq!(read.owner, owner);
+        assert_eq!(read.label, stamped.label);
+        assert_eq!(read.count, stamped.count);
+        assert_eq!(read.raw.get_datetime("at"), Ok(&at));
+        assert_eq!(read.raw.get_object_id("owner"), Ok(owner));
+    }
+}
+
+#[tokio::test]
+async fn test_object_id_json() {
+    let db = get_db().await;
+    let id = ObjectId::parse_str("65f1a2b3c4d5e6f708091a2b").unwrap();
+    let entry = LogEntry { _id: Some(id), payload: "p".to_string() };
+    let json = LogEntry::new_model(&db).to_json(&entry).unwrap();
+    assert_eq!(json["_id"], "65f1a2b3c4d5e6f708091a2b");
+
+    let filled = LogEntry::new_model(&db).fill_from_json(json).unwrap();
+    assert_eq!(filled._id, Some(id));
+    assert_eq!(filled.payload, "p");
+    assert!(LogEntry::new_model(&db).fill_from_json(serde_json::json!({"_id": "bad", "payload": ""})).is_err());
+
+    let invites = Invite::new_model(&db);
+    let json = serde_json::json!({"_id": invites.public_id(&id).unwrap(), "code": "c", "expires_at": null});
+    assert_eq!(invites.fill_from_json(json).unwrap()._id, Some(id));
+    let json = User::new_model(&db).to_json(&User { _id: Some(id), ..Default::default() }).unwrap();
+    assert_eq!(json["_id"]["$oid"], "65f1a2b3c4d5e6f708091a2b");
+}
+
+#[tokio::test]
+async fn test_query_spec() {
+    use mongodb_ro::error::SpecError;
+    use mongodb_ro::query_spec::{Allow, Direction, Op, QueryRules, QuerySpec, ValueKind};
+
+    let rules = QueryRules::new()
+        .filterable_with("name", ValueKind::String, &[Allow::In, Allow::RegexPrefix])
+        .filterable("age", ValueKind::Int)
+        .filterable("password", ValueKind::String)
+        .sortable(&["age"])
+        .max_per_page(50);
+    let spec = QuerySpec::parse(
+        [("name[in]", "a,b"), ("age[gte]", "18"), ("sort", "-age"), ("page", "3"), ("per_page", "500")],
+        &rules,
+    )
+    .unwrap();
+    assert_eq!(spec.sort, vec![("age".to_string(), Direction::Desc)]);
+    assert_eq!((spec.page, spec.per_page), (3, 50));
+
+    let db = get_db().await;
+    let find = User::new_model(&db).apply_spec(&spec).build_find();
+    assert_eq!(
+        find.filter,
+        doc! {"$and": [{"$and": [{"name": {"$in": ["a", "b"]}}, {"age": {"$gte": 18_i64}}]}]}
+    );
+    assert_eq!(find.options.sort, Some(doc! {"age": -1}));
+    assert_eq!((find.options.skip, find.options.limit), (Some(100), Some(50)));
+    let find = User::new_model(&db)
+        .apply_spec(&QuerySpec::parse([("password", "x")], &rules).unwrap())
+        .build_find();
+    assert_eq!(find.filter, doc! {"$and": [{"$and": [{"pswd": {"$eq": "x"}}]}]});
+
+    let spec_error = |pairs: &[(&str, &str)]| {
+        QuerySpec::parse(pairs.iter().copied(), &rules)
+            .unwrap_err()
+            .get_custom::<SpecError>()
+            .cloned()
+    };
+    assert_eq!(spec_error(&[("phone", "1")]), Some(SpecError::NotFilterable { field: "phone".to_string() }));
+    assert_eq!(spec_error(&[("sort", "name")]), Some(SpecError::NotSortable { field: "name".to_string() }));
+    assert!(matches!(spec_error(&[("age[regex]", "1")]), Some(SpecError::UnknownOperator { .. })));
+    assert!(matches!(spec_error(&[("age", "old")]), Some(SpecError::InvalidValue { .. })));
+    assert_eq!(
+        spec_error(&[("name", "a")]),
+        Some(SpecError::OperatorNotAllowed { field: "name".to_string(), op: Op::Eq })
+    );
+    assert_eq!(
+        spec_error(&[("age[prefix]", "1")]),
+        Some(SpecError::OperatorNotAllowed { field: "age".to_string(), op: Op::Prefix })
+    );
+    let spec = QuerySpec::parse([("name[prefix]", "a.b")], &rules).unwrap();
+    assert_eq!(spec.filter(), doc! {"$and": [{"name": {"$regex": "^a\\.b"}}]});
+}
+
+#[test]
+fn test_change_stream_backoff() {
+    use mongodb_ro::change_stream::Backoff;
+    use std::time::Duration;
+
+    let backoff = Backoff::default()
+        .initial(Duration::from_millis(100))
+        .max(Duration::from_secs(1));
+    assert_eq!(backoff.delay(1), Duration::from_millis(100));
+    assert_eq!(backoff.delay(3), Duration::from_millis(400));
+    assert_eq!(backoff.delay(10), Duration::from_secs(1));
+    assert_eq!(backoff.delay(100), Duration::from_secs(1));
+}
+
+mongodb_ro::repository!(UserRepository for User);
+
+#[derive(Default)]
+struct InMemoryUsers {
+    users: std::sync::Mutex<Vec<User>>,
+}
+
+impl mongodb_ro::repository::Repository<User> for InMemoryUsers {
+    async fn find(&self, id: ObjectId) -> mongodb::error::Result<Option<User>> {
+        let users = self.users.lock().unwrap();
+        let found = users.iter().find(|u| u._id == Some(id));
+        Ok(found.map(|u| User {
+            _id: u._id,
+            name: u.name.clone(),
+            ..Default::default()
+        }))
+    }
+
+    async fn list(
+        &self,
+        _: mongodb::bson::Document,
+        page: u32,
+        per_page: u32,
+    ) -> mongodb::error::Result<mongodb_ro::page::Page<User>> {
+        let total = self.users.lock().unwrap().len() as u64;
+        Ok(mongodb_ro::page::Page::new(vec![], total, page, per_page))
+    }
+
+    async fn create(&self, mut item: User) -> mongodb::error::Result<ObjectId> {
+        let id = ObjectId::new();
+        item._id = Some(id);
+        self.users.lock().unwrap().push(item);
+        Ok(id)
+    }
+
+    async fn update(&self, _: ObjectId, _: mongodb::bson::Document) -> mongodb::error::Result<Option<User>> {
+        Ok(None)
+    }
+
+    async fn delete(&self, id: ObjectId) -> mongodb::error::Result<Option<User>> {
+        let mut users = self.users.lock().unwrap();
+        let index = users.iter().position(|u| u._id == Some(id));
+        Ok(index.map(|i| users.remove(i)))
+    }
+}
+
+async fn signup<R: UserRepository>(users: &R, name: &str) -> mongodb::error::Result<ObjectId> {
+    let user = User {
+        name: name.to_string(),
+        ..Default::default()
+    };
+    users.create(user).await
+}
+
+#[tokio::test]
+async fn test_repository_mock() {
+    let users = InMemoryUsers::default();
+    let id = signup(&users, "mocked").await.unwrap();
+    assert_eq!(users.find(id).await.unwrap().unwrap().name, "mocked");
+    assert_eq!(users.list(doc! {}, 1, 10).await.unwrap().total, 1);
+    assert!(users.delete(id).await.unwrap().is_some());
+    assert!(users.find(id).await.unwrap().is_none());
+}
+
+async fn test_repository() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+
+    let users = User::repository(&db);
+    let id = signup(&users, "repository").await.unwrap();
+    assert_eq!(users.find(id).await.unwrap().unwrap().name, "repository");
+
+    let updated = users.update(id, doc! {"age": 30}).await.unwrap().unwrap();
+    assert_eq!(updated.age, 30);
+    let page = users.list(doc! {"age": 30}, 1, 10).await.unwrap();
+    assert_eq!(page.total, 1);
+
+    assert!(users.delete(id).await.unwrap().is_some());
+    assert!(users.find(id).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_replay_needs_start() {
+    let db = get_db().await;
+    let result = User::new_model(&db).replay([]).run(|_| async { Ok(()) }).await;
+    assert!(result.is_err());
+
+    let revision = doc! {"_id": {"_data": "8263"}, "doc_id": 1, "op": "insert"};
+    assert!(User::new_model(&db).replay([]).from_version(&revision).is_ok());
+    assert!(User::new_model(&db).replay([]).from_version(&doc! {"doc_id": 1}).is_err());
+}
+
+#[tokio::test]
+async fn test_concerns() {
+    use mongodb::options::{ReadConcern, WriteConcern};
+
+    let db = get_db().await;
+    let invites = Invite::new_model(&db).collection();
+    assert_eq!(invites.write_concern(), Some(&WriteConcern::majority()));
+    assert_eq!(invites.read_concern(), None);
+
+    let users = User::new_model(&db)
+        .read_concern(ReadConcern::majority())
+        .write_concern(WriteConcern::nodes(2))
+        .collection();
+    assert_eq!(users.read_concern(), Some(&ReadConcern::majority()));
+    assert_eq!(users.write_concern(), Some(&WriteConcern::nodes(2)));
+    assert_eq!(User::new_model(&db).collection().write_concern(), None);
+}
+
+#[tokio::test]
+async fn test_schema() {
+    let db = get_db().await;
+    let schema = User::new_model(&db).schema();
+    let phone = schema.iter().find(|f| f.name == "phone").unwrap();
+    assert_eq!(phone.doc.as_deref(), Some("User's primary phone in E.164"));
+    assert!(phone.unique && phone.indexed);
+    assert_eq!(phone.kind, Some("string"));
+    let password = schema.iter().find(|f| f.name == "password").unwrap();
+    assert_eq!((password.db_name.as_str(), password.hidden), ("pswd", true));
+    assert_eq!(schema.iter().find(|f| f.name == "age").unwrap().kind, Some("integer"));
+
+    let openapi = User::new_model(&db).openapi_schema();
+    assert_eq!(
+        openapi["properties"]["phone"],
+        serde_json::json!({"type": "string", "description": "User's primary phone in E.164"})
+    );
+    assert!(openapi["properties"].get("password").is_none());
+
+    let json_schema = User::new_model(&db).json_schema();
+    let properties = json_schema.get_document("properties").unwrap();
+    assert_eq!(properties.get_document("pswd"), Ok(&doc! {"bsonType": "string"}));
+    assert_eq!(properties.get_document("age"), Ok(&doc! {"bsonType": ["int", "long"]}));
+    assert_eq!(properties.get_document("created_at"), Ok(&doc! {"bsonType": "date"}));
+    let required = json_schema.get_array("required").unwrap();
+    assert!(required.contains(&Bson::from("pswd")) && !required.contains(&Bson::from("_id")));
+}
+
+#[test]
+fn test_transactions_unsupported() {
+    use mongodb_ro::error::TransactionsUnsupported;
+    use mongodb_ro::server::{ServerInfo, Topology};
+
+    let info = ServerInfo::from_responses(&doc! {"maxWireVersion": 21}, &doc! {"version": "7.0.2"});
+    assert!(!info.supports_transactions());
+    let e: mongodb::error::Error = TransactionsUnsupported {
+        topology: info.topology,
+        version: info.version,
+    }
+    .into();
+    assert_eq!(
+        e.get_custom::<TransactionsUnsupported>().map(|e| e.topology),
+        Some(Topology::Standalone)
+    );
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "log_lines")]
+struct LogLine {
+    _id: Option<ObjectId>,
+    message: String,
+}
+
+impl Boot for LogLine {
+    type Req = ();
+
+    fn configure(&self, options: &mut mongodb_ro::options::ModelOptions) {
+        options.capped(4096, Some(100));
+    }
+}
+
+#[tokio::test]
+async fn test_tailable_flags() {
+    use mongodb::options::CursorType;
+
+    let db = get_db().await;
+    let find = LogLine::new_model(&db).tailable().build_find();
+    assert!(matches!(find.options.cursor_type, Some(CursorType::Tailable)));
+    let find = LogLine::new_model(&db).await_data().build_find();
+    assert!(matches!(find.options.cursor_type, Some(CursorType::TailableAwait)));
+    let command = find.command("log_lines");
+    assert_eq!((command.get_bool("tailable"), command.get_bool("awaitData")), (Ok(true), Ok(true)));
+}
+
+async fn test_tail_stream() {
+    let db = get_db().await;
+    LogLine::new_model(&db).collection().drop().await.unwrap();
+    assert!(LogLine::new_model(&db).provision().await.unwrap());
+    assert!(!LogLine::new_model(&db).provision().await.unwrap());
+    let spec = db.list_collections().filter(doc! {"name": "log_lines"}).await.unwrap();
+    let spec = spec.collect::<Vec<_>>().await.remove(0).unwrap();
+    assert_eq!((spec.options.capped, spec.options.max), (Some(true), Some(100)));
+    let write = |message: &str| {
+        let mut line = LogLine::new_model(&db);
+        line.message = message.to_string();
+        async move { line.create().await.unwrap() }
+    };
+    write("first").await;
+
+    let model = LogLine::new_model(&db);
+    let mut tail = std::pin::pin!(model.tail_stream());
+    assert_eq!(tail.next().await.unwrap().unwrap().message, "first");
+    write("second").await;
+    assert_eq!(tail.next().await.unwrap().unwrap().message, "second");
+    LogLine::new_model(&db).collection().drop().await.unwrap();
+}
+
+#[test]
+fn test_query_recorder() {
+    use mongodb_ro::recorder::QueryRecorder;
+
+    let recorder = QueryRecorder::new();
+    let id = ObjectId::new();
+    recorder.record("hello", "admin", doc! {"hello": 1});
+    recorder.record("buildInfo", "admin", doc! {"buildInfo": 1});
+    recorder.record("getMore", "test", doc! {"getMore": 7_i64, "collection": "user"});
+    recorder.record("killCursors", "test", doc! {"killCursors": "user", "cursors": [7_i64]});
+    recorder.record(
+        "update",
+        "test",
+        doc! {
+            "update": "user",
+            "updates": [{"q": {"$and": [{"_id": id}, {"name": "a"}]}, "u": {"$set": {"age": 1}}}],
+            "lsid": {"id": 1},
+            "$db": "test",
+        },
+    );
+    assert_eq!(recorder.commands().len(), 1);
+    recorder.assert_filter_contains("user", doc! {"_id": id, "name": "a"});
+    recorder.assert_snapshot(
+        r#"{"update":"user","updates":[{"q":{"$and":[{"_id":"<ObjectId>"},{"name":"a"}]},"u":{"$set":{"age":1}}}]}"#,
+    );
+    let missing = std::panic::catch_unwind(|| recorder.assert_filter_contains("user", doc! {"name": "b"}));
+    assert!(missing.is_err());
+    recorder.clear();
+    assert!(recorder.commands().is_empty());
+}
+
+async fn test_recorded_queries() {
+    use mongodb::options::ClientOptions;
+    use mongodb_ro::recorder::QueryRecorder;
+
+    let recorder = QueryRecorder::new();
+    let mut options = ClientOptions::parse("mongodb://localhost:27017").await.unwrap();
+    recorder.attach(&mut options);
+    let db = Client::with_options(options).unwrap().database("test");
+    cleanup_users(&db).await;
+    setup_test_user(&db, "recorded", "777777771", 1).await;
+    recorder.clear();
+
+    User::new_model(&db)
+        .r#where(doc! {"password": "x"})
+        .update(doc! {"age": 2})
+        .await
+        .unwrap();
+    recorder.assert_filter_contains("user", doc! {"pswd": "x"});
+    assert_eq!(recorder.commands_on("user")[0].name, "update");
+    cleanup_users(&db).await;
+}
+
+async fn test_watch_typed() {
+    use mongodb_ro::change_stream::ModelEvent;
+
+    let db = get_db().await;
+    cleanup_users(&db).await;
+    let model = User::new_model(&db);
+    let mut events = std::pin::pin!(model.watch_typed([]));
+    let writer = db.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        setup_test_user(&writer, "watched", "888888881", 1).await;
+        User::new_model(&writer)
+            .r#where(doc! {"name": "watched"})
+            .update(doc! {"age": 2, "password": "secret"})
+            .await
+            .unwrap();
+        User::new_model(&writer).r#where(doc! {"name": "watched"}).delete().await.unwrap();
+    });
+
+    let inserted = match events.next().await.unwrap().unwrap() {
+        ModelEvent::Insert(user) => user,
+        other => panic!("expected an insert, got {other:?}"),
+    };
+    assert_eq!(inserted.name, "watched");
+    match events.next().await.unwrap().unwrap() {
+        ModelEvent::Update { changes, .. } => {
+            assert_eq!(changes.get_i32("age"), Ok(2));
+            assert!(!changes.contains_key("pswd") && !changes.contains_key("password"));
+        }
+        other => panic!("expected an update, got {other:?}"),
+    }
+    assert!(matches!(events.next().await.unwrap().unwrap(), ModelEvent::Delete { .. }));
+    cleanup_users(&db).await;
+}
+
+async fn test_invariants() {
+    use mongodb_ro::error::InvariantViolated;
+
+    let db = get_db().await;
+    cleanup_users(&db).await;
+    let pool = SessionPool::new(db.client().clone());
+    let add = |phone: &'static str| {
+        let db = db.clone();
+        pool.transaction(async move {
+            User::new_model(&db).ensure_invariant(doc! {"name": "limited"}, 2).await?;
+            setup_test_user(&db, "limited", phone, 1).await;
+            Ok(())
+        })
+    };
+    add("999999991").await.unwrap();
+    add("999999992").await.unwrap();
+    let e = add("999999993").await.unwrap_err();
+    assert_eq!(
+        e.get_custom::<InvariantViolated>(),
+        Some(&InvariantViolated { collection: "user".to_string(), count: 2, max_count: 2 })
+    );
+    let count = User::new_model(&db).r#where(doc! {"name": "limited"}).count_documents().await.unwrap();
+    assert_eq!(count, 2);
+    cleanup_users(&db).await;
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "adult_users")]
+struct AdultUser {
+    _id: Option<ObjectId>,
+    name: String,
+    age: u8,
+}
+
+impl Boot for AdultUser {
+    type Req = ();
+
+    fn configure(&self, options: &mut mongodb_ro::options::ModelOptions) {
+        options.view_of("user", [doc! {"$match": {"age": {"$gte": 18}}}]);
+    }
+}
+
+async fn test_views() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+    let _ = AdultUser::new_model(&db).collection().drop().await;
+    setup_test_user(&db, "minor", "121212121", 12).await;
+    setup_test_user(&db, "adult", "121212122", 30).await;
+
+    assert!(AdultUser::new_model(&db).provision_view().await.unwrap());
+    assert!(!AdultUser::new_model(&db).provision_view().await.unwrap());
+    let adults = AdultUser::new_model(&db).get().await.unwrap();
+    assert_eq!(adults.iter().map(|u| u.name.as_str()).collect::<Vec<_>>(), vec!["adult"]);
+    assert!(User::new_model(&db).provision_view().await.is_err());
+
+    User::new_model(&db)
+        .create_view("user_names", [doc! {"$project": {"name": 1}}])
+        .await
+        .unwrap();
+    assert_eq!(User::new_model(&db).views().await.unwrap(), vec!["adult_users", "user_names"]);
+    User::new_model(&db).drop_view("user_names").await.unwrap();
+    assert!(User::new_model(&db).drop_view("user").await.is_err());
+    User::new_model(&db).drop_view("adult_users").await.unwrap();
+    assert!(User::new_model(&db).views().await.unwrap().is_empty());
+    cleanup_users(&db).await;
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "subscribers")]
+struct Subscriber {
+    _id: Option<ObjectId>,
+    email: String,
+}
+
+impl Boot for Subscriber {
+    type Req = ();
+
+    fn configure(&self, options: &mut mongodb_ro::options::ModelOptions) {
+        options
+            .index_expr("email_lower", "toLower(email)")
+            .versioned("subscriber_revisions");
+    }
+}
+
+#[test]
+fn test_index_expr_parse() {
+    use mongodb_ro::options::{IndexExpr, Normalize};
+
+    let e = IndexExpr::parse("email_key", "toLower(trim(email))").unwrap();
+    assert_eq!((e.source.as_str(), e.steps.clone()), ("email", vec![Normalize::Trim, Normalize::Lower]));
+    assert_eq!(e.apply(&Bson::from(" Foo@X.io ")), Some(Bson::from("foo@x.io")));
+    assert_eq!(e.apply(&Bson::Int32(1)), None);
+    assert_eq!(e.apply(&Bson::from("ÉMILE@X.io")), Some(Bson::from("Émile@x.io")));
+    let upper = IndexExpr::parse("email_key", "toUpper(email)").unwrap();
+    assert_eq!(upper.apply(&Bson::from("straße")), Some(Bson::from("STRAßE")));
+    assert_eq!(e.aggregation("mail"), Bson::Document(doc! {"$toLower": {"$trim": {"input": "$mail"}}}));
+    assert!(IndexExpr::parse("x", "email").is_none());
+    assert!(IndexExpr::parse("x", "lower(email)").is_none());
+    assert!(IndexExpr::parse("x", "toLower(email").is_none());
+}
+
+async fn test_index_expr() {
+    let db = get_db().await;
+    let model = || Subscriber::new_model(&db);
+    model().collection().drop().await.unwrap();
+    model().register_indexes().await;
+    let names = model().collection().list_index_names().await.unwrap();
+    assert!(names.contains(&"email_lower".to_string()));
+    assert!(model().declared_indexes().iter().any(|i| i.name == "email_lower"));
+    let drift = model().index_drift().await.unwrap();
+    assert!(drift.is_empty(), "{drift}");
+    let mut rebuilt = vec![];
+    model()
+        .rebuild_indexes(std::time::Duration::ZERO, |p| rebuilt.push(p.field.clone()))
+        .await
+        .unwrap();
+    assert_eq!(rebuilt, vec!["email_lower".to_string()]);
+
+    model()
+        .fill(Subscriber {
+            email: "Ada@Example.com".to_string(),
+            ..Default::default()
+        })
+        .create()
+        .await
+        .unwrap();
+    let found = model().r#where(doc! {"email_lower": "ADA@example.COM"}).first().await.unwrap();
+    assert_eq!(found.unwrap().email, "Ada@Example.com");
+
+    model()
+        .r#where(doc! {"email_lower": "ada@example.com"})
+        .update(doc! {"email": "Grace@Example.com"})
+        .await
+        .unwrap();
+    let stored = model().first_doc().await.unwrap().unwrap();
+    assert_eq!(stored.get_str("email_lower"), Ok("grace@example.com"));
+
+    model()
+        .collection()
+        .clone_with_type::<mongodb::bson::Document>()
+        .insert_one(doc! {"email": "Old@Example.com"})
+        .await
+        .unwrap();
+    assert_eq!(model().refresh_index_exprs().await.unwrap(), 1);
+    let found = model().r#where(doc! {"email_lower": {"$in": ["OLD@example.com"]}}).count_documents().await.unwrap();
+    assert_eq!(found, 1);
+    let found = model()
+        .r#where(doc! {"email": "nobody"})
+        .or_where(doc! {"$and": [{"email_lower": {"$eq": "GRACE@example.com"}}]})
+        .count_documents()
+        .await
+        .unwrap();
+    assert_eq!(found, 1);
+    model().collection().drop().await.unwrap();
+}
+
+async fn test_provision_collection() {
+    let db = get_db().await;
+    let model = || Subscriber::new_model(&db);
+    model().collection().drop().await.unwrap();
+    assert!(model().provision_collection().await.unwrap());
+    assert!(!model().provision_collection().await.unwrap());
+
+    let raw = model().collection().clone_with_type::<mongodb::bson::Document>();
+    assert!(raw.insert_one(doc! {"email": 5}).await.is_err());
+    assert!(raw.insert_one(doc! {"name": "no email"}).await.is_err());
+    model()
+        .fill(Subscriber {
+            email: "valid@example.com".to_string(),
+            ..Default::default()
+        })
+        .create()
+        .await
+        .unwrap();
+    model().collection().drop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_debug_stages_rejects_writes() {
+    let db = Client::with_uri_str("mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=100")
+        .await
+        .unwrap()
+        .database("test");
+    let err = User::new_model(&db)
+        .debug_stages([doc! {"$match": {}}, doc! {"$out": "copy"}], 3)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("writing its output"));
+}
+
+async fn test_debug_stages() {
+    let db = get_db().await;
+    cleanup_users(&db).await;
+    setup_test_user(&db, "stage_a", "131313131", 20).await;
+    setup_test_user(&db, "stage_b", "131313132", 40).await;
+
+    let stages = User::new_model(&db)
+        .debug_stages(
+            [
+                doc! {"$match": {"name": {"$regex": "^stage_"}}},
+                doc! {"$match": {"age": {"$gt": 30}}},
+                doc! {"$project": {"name": 1}},
+            ],
+            1,
+        )
+        .await
+        .unwrap();
+    let counts = stages
+        .iter()
+        .map(|s| (s.stage, s.operator.as_str(), s.count, s.sample.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(counts, vec![(1, "$match", 2, 1), (2, "$match", 1, 1), (3, "$project", 1, 1)]);
+    assert_eq!(stages[2].sample[0].get_str("name"), Ok("stage_b"));
+    assert!(!stages[2].sample[0].contains_key("age"));
+    cleanup_users(&db).await;
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "legacy_accounts")]
+struct LegacyAccount {
+    _id: Option<ObjectId>,
+    name: String,
+    #[model(name("pts"))]
+    points: i32,
+}
+
+impl Boot for LegacyAccount {
+    type Req = ();
+
+    fn repair(&self, repair: &mongodb_ro::repair::Repair) -> bool {
+        repair.collection == "legacy_accounts"
+    }
+
+    fn configure(&self, options: &mut mongodb_ro::options::ModelOptions) {
+        options.on_decode_error = mongodb_ro::options::DecodeErrorPolicy::UseDefaultAndLog;
+    }
+}
+
+async fn test_read_repair() {
+    let db = get_db().await;
+    let raw = db.collection::<mongodb::bson::Document>("legacy_accounts");
+    raw.drop().await.unwrap();
+    raw.insert_many([
+        doc! {"name": "missing"},
+        doc! {"name": "wrong", "pts": "ten"},
+        doc! {"name": "fine", "pts": 3},
+    ])
+    .await
+    .unwrap();
+
+    let accounts = LegacyAccount::new_model(&db).sort(doc! {"name": 1}).get().await.unwrap();
+    let points = accounts.iter().map(|a| (a.name.as_str(), a.points)).collect::<Vec<_>>();
+    assert_eq!(points, vec![("fine", 3), ("missing", 0), ("wrong", 0)]);
+
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    let repaired = raw.count_documents(doc! {"pts": {"$type": "int"}}).await.unwrap();
+    assert_eq!(repaired, 3);
+    raw.drop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_scopes() {
+    let db = get_db().await;
+    let find = User::new_model(&db)
+        .scope("active")
+        .and_then(|m| m.scope("with_password"))
+        .unwrap()
+        .build_find();
+    assert_eq!(
+        find.filter,
+        doc! {"$and": [{"block": false}, {"pswd": {"$ne": ""}}]}
+    );
+}
+
+#[tokio::test]
+async fn test_unknown_scope() {
+    let db = get_db().await;
+    let e = User::new_model(&db).scope("blocked").err().unwrap();
+    assert!(e.to_string().contains("user has no scope blocked"), "{e}");
+}
+
+async fn test_as_of() {
+    use mongodb::bson::Timestamp;
+    use mongodb::change_stream::event::ChangeStreamEvent;
+
+    let db = get_db().await;
+    let model = || Subscriber::new_model(&db);
+    db.collection::<mongodb::bson::Document>("subscriber_revisions").drop().await.unwrap();
+    let revisions = model().revisions().unwrap();
+    revisions.ensure_index().await.unwrap();
+
+    let (ada, bob) = (ObjectId::new(), ObjectId::new());
+    let event = |n: u32, op: &str, id: ObjectId, email: Option<&str>| {
+        let mut event = doc! {
+            "_id": {"_data": n.to_string()},
+            "operationType": op,
+            "documentKey": {"_id": id},
+            "clusterTime": Timestamp { time: n, increment: 0 },
+            "wallTime": DateTime::from_millis(n as i64 * 1000),
+        };
+        if let Some(email) = email {
+            event.insert("fullDocument", doc! {"_id": id, "email": email});
+        }
+        mongodb::bson::from_document::<ChangeStreamEvent<mongodb::bson::Document>>(event).unwrap()
+    };
+    assert!(revisions.record(event(10, "insert", ada, Some("ada@v1"))).await.unwrap());
+    assert!(revisions.record(event(20, "insert", bob, Some("bob@v1"))).await.unwrap());
+    assert!(revisions.record(event(30, "update", ada, Some("ada@v2"))).await.unwrap());
+    assert!(revisions.record(event(40, "delete", bob, None)).await.unwrap());
+    assert!(revisions.record(event(40, "delete", bob, None)).await.unwrap());
+    assert!(revisions.record(event(50, "update", ada, None)).await.is_err());
+    let version = revisions.version(ada, DateTime::from_millis(25_000)).await.unwrap().unwrap();
+    assert_eq!(version.get_document("doc").unwrap().get_str("email"), Ok("ada@v1"));
+    assert!(model().replay([]).from_version(&version).is_ok());
+
+    let emails = |at: i64| {
+        let model = model().sort(doc! {"email": 1});
+        async move {
+            let items = model.as_of(DateTime::from_millis(at * 1000)).await.unwrap();
+            items.into_iter().map(|s| s.email).collect::<Vec<_>>()
+        }
+    };
+    assert!(emails(5).await.is_empty());
+    assert_eq!(emails(25).await, vec!["ada@v1", "bob@v1"]);
+    assert_eq!(emails(45).await, vec!["ada@v2"]);
+    let filtered = model()
+        .r#where(doc! {"email": "bob@v1"})
+        .as_of(DateTime::from_millis(25_000))
+        .await
+        .unwrap();
+    assert_eq!(filtered.len(), 1);
+    for email in ["ada@v1", "bob@v1"] {
+        let stale = model().r#where(doc! {"email": email});
+        assert!(stale.as_of(DateTime::from_millis(45_000)).await.unwrap().is_empty(), "{email}");
+    }
+    let guards = mongodb_ro::options::Guards {
+        max_limit: Some(10),
+        ..Default::default()
+    };
+    assert!(model().guards(guards).as_of(DateTime::now()).await.is_err());
+    assert!(User::new_model(&db).as_of(DateTime::now()).await.is_err());
+}
+
+mongodb_ro::model_builder!(UserBuilder for User {
+    required { name: String, phone: String }
+    optional { age: u8, updated_at: Option<DateTime> }
+});
+
+#[test]
+fn test_model_builder() {
+    let at = DateTime::from_millis(1_000);
+    let user = User::builder().name("a").phone("b").age(3).updated_at(at).build().unwrap();
+    assert_eq!(
+        user,
+        User {
+            name: "a".to_string(),
+            phone: "b".to_string(),
+            age: 3,
+            updated_at: Some(at),
+            ..Default::default()
+        }
+    );
+    let err = User::builder().name("a").build().unwrap_err();
+    assert!(err.to_string().contains("phone of User is required."));
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "bookings")]
+struct Booking {
+    _id: Option<ObjectId>,
+    #[model(asc)]
+    room: String,
+    #[model(name("s"))]
+    slot: mongodb_ro::interval::Interval,
+}
+
+impl Boot for Booking {
+    type Req = ();
+
+    fn configure_columns(&self, columns: &mut std::collections::HashMap<&str, mongodb_ro::column::ColumnAttr>) {
+        columns.get_mut("room").unwrap().partial = Some(doc! {"slot.end": {"$gt": 0}});
+    }
+
+    fn configure(&self, options: &mut mongodb_ro::options::ModelOptions) {
+        options.comment = Some("svc-rooms".to_string());
+    }
+}
+
+mongodb_ro::model_fields!(BookingFields for Booking {
+    room: String,
+    slot: mongodb_ro::interval::Interval,
+});
+
+#[tokio::test]
+async fn test_interval() {
+    use mongodb_ro::interval::Interval;
+
+    let at = |millis: i64| DateTime::from_millis(millis);
+    let slot = Interval::new(at(1_000), at(2_000));
+    assert_eq!(slot.duration(), std::time::Duration::from_secs(1));
+    assert_eq!(Interval::starting_at(at(1_000), std::time::Duration::from_secs(1)), slot);
+    assert!(slot.contains(at(1_000)) && !slot.contains(at(2_000)));
+    assert!(slot.overlaps(&Interval::new(at(1_500), at(3_000))));
+    assert!(!slot.overlaps(&Interval::new(at(2_000), at(3_000))));
+    assert!(Interval::new(at(2_000), at(1_000)).is_empty());
+
+    let db = get_db().await;
+    let find = Booking::new_model(&db)
+        .filter(Booking::fields().slot.overlaps(at(1_500), at(3_000)))
+        .build_find();
+    assert_eq!(
+        find.filter,
+        doc! {"$and": [{"s.start": {"$lt": 3_000_i64}, "s.end": {"$gt": 1_500_i64}}]}
+    );
+    let contains = Booking::fields().slot.contains(at(1_500)).into_document();
+    assert_eq!(contains, doc! {"slot.start": {"$lte": 1_500_i64}, "slot.end": {"$gt": 1_500_i64}});
+}
+
+async fn test_partial_index() {
+    let db = get_db().await;
+    let model = Booking::new_model(&db);
+    model.collection().drop().await.unwrap();
+    model
+        .collection()
+        .create_index(mongodb::IndexModel::builder().keys(doc! {"room": 1}).build())
+        .await
+        .unwrap();
+    assert_eq!(model.index_drift().await.unwrap().mismatched.len(), 1);
+    model.register_indexes().await;
+    let drift = model.index_drift().await.unwrap();
+    assert!(drift.is_empty(), "{drift}");
+    model.collection().drop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_comment() {
+    let db = get_db().await;
+    assert!(Invite::new_model(&db).build_find().options.comment.is_none());
+    let find = Booking::new_model(&db).build_find();
+    assert_eq!(find.options.comment, Some(Bson::from("svc-rooms")));
+    let find = Booking::new_model(&db).comment("svc-billing").build_find();
+    assert_eq!(find.command("bookings").get_str("comment"), Ok("svc-billing"));
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Model)]
+#[model(collection = "posts")]
+struct Post {
+    _id: Option<ObjectId>,
+    title: String,
+    #[model(name("st"))]
+    status: String,
+    published_at: Option<DateTime>,
+}
+
+impl Boot for Post {
+    /// Whether the reader is an editor
+    type Req = bool;
+
+    fn configure(&self, options: &mut mongodb_ro::options::ModelOptions) {
+        options.publishable = true;
+    }
+
+    fn preview(&self, req: &Option<Self::Req>) -> bool {
+        req.unwrap_or(false)
+    }
+}
+
+#[tokio::test]
+async fn test_publish_filter() {
+    let db = get_db().await;
+    let find = Post::new_model(&db).build_find();
+    assert_eq!(find.filter, doc! {"st": "published"});
+    let find = Post::new_model(&db)
+        .r#where(doc! {"title": "a"})
+        .build_find();
+    assert_eq!(find.filter, doc! {"$and": [{"title": "a"}, {"st": "published"}]});
+    assert!(Post::new_model(&db).with_drafts().build_find().filter.is_empty());
+    assert!(Post::new_model(&db).set_request(true).build_find().filter.is_empty());
+    assert_eq!(
+        Post::new_model(&db).set_request(false).build_find().filter,
+        doc! {"st": "published"}
+    );
+}
+
+async fn test_publish() {
+    let db = get_db().await;
+    Post::new_model(&db).collection().drop().await.unwrap();
+
+    let mut model = Post::new_model(&db);
+    model.title = "launch".to_string();
+    let id = model.draft().await.unwrap().inserted_id;
+    assert_eq!(Post::new_model(&db).count_documents().await.unwrap(), 0);
+    assert_eq!(Post::new_model(&db).estimated_count().await.unwrap(), 0);
+    let draft = Post::new_model(&db).with_drafts().get().await.unwrap();
+    assert_eq!(draft[0].status, "draft");
+    assert!(draft[0].published_at.is_none());
+    assert_eq!(
+        Post::new_model(&db).set_request(true).count_documents().await.unwrap(),
+        1
+    );
+
+    Post::new_model(&db)
+        .r#where(doc! {"_id": id.clone()})
+        .publish()
+        .await
+        .unwrap();
+    let published = Post::new_model(&db).first().await.unwrap().unwrap();
+    assert_eq!(published.status, "published");
+    assert!(published.published_at.is_some());
+
+    Post::new_model(&db)
+        .r#where(doc! {"_id": id})
+        .unpublish()
+        .await
+        .unwrap();
+    assert!(!Post::new_model(&db).exists().await.unwrap());
+
+    let mut model = Post::new_model(&db);
+    model.title = "plain create".to_string();
+    let id = model.create().await.unwrap().inserted_id;
+    let created = Post::new_model(&db)
+        .with_drafts()
+        .r#where(doc! {"_id": id})
+        .first()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(created.status, "draft");
+    Post::new_model(&db).collection().drop().await.unwrap();
 }